@@ -0,0 +1,46 @@
+#version 430 core
+
+#include "pbr.glsl"
+
+//G-buffer, see GBuffer
+uniform sampler2D albedo_texture;
+uniform sampler2D normal_texture;
+uniform sampler2D material_texture;
+uniform sampler2D emissive_texture;
+uniform sampler2D depth_texture;
+
+uniform mat4 inverse_view_projection;
+uniform vec3 camera_pos;
+//The light being drawn, see Light::packed. With ambient set the ambient and emissive terms are drawn
+//instead
+uniform vec4 light_position;
+uniform vec4 light_color;
+uniform bool ambient;
+
+uniform sampler2D ao_map;
+uniform bool ssao_enabled;
+
+out vec4 o_color;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / vec2(textureSize(depth_texture, 0));
+    float depth = texture(depth_texture, uv).r;
+    //The background keeps the clear color
+    if (depth >= 1.0) {
+        discard;
+    }
+    vec3 albedo = texture(albedo_texture, uv).rgb;
+    if (ambient) {
+        //Same as the forward shader's ambient
+        float ao = ssao_enabled ? texture(ao_map, uv).r : 1.0;
+        o_color = vec4(vec3(0.1) * albedo * ao + texture(emissive_texture, uv).rgb, 1.0);
+        return;
+    }
+
+    vec4 world = inverse_view_projection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    vec3 pos = world.xyz / world.w;
+    vec2 material = texture(material_texture, uv).rg;
+    pbr_material mat = make_pbr_material(albedo, material.g, material.r);
+    vec3 normal = texture(normal_texture, uv).xyz;
+    o_color = vec4(shade(mat, pos, normal, camera_pos - pos, light_position, light_color), 1.0);
+}
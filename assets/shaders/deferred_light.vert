@@ -0,0 +1,17 @@
+#version 430 core
+
+//Light volume of a point light (a sphere scaled to its range), or a full-screen triangle for lights
+//that reach everywhere
+layout(location = 0) in vec3 pos;
+
+uniform mat4 M,V,P;
+uniform bool fullscreen;
+
+void main() {
+    if (fullscreen) {
+        vec2 uv = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+        gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+    } else {
+        gl_Position = P * V * M * vec4(pos, 1.0);
+    }
+}
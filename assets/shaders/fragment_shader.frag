@@ -1,11 +1,9 @@
-//This was used as a reference: https://github.com/adrianderstroff/pbr/tree/master/assets/shaders/pbr/simple
-
 #version 430 core
 
-#define DirectionalPointLight 0x00000001
-#define PointPointLight       0x00000002
+#include "pbr.glsl"
 
-#define M_PI 3.1415926535897932384626433832795
+//Lights beyond this are ignored, the deferred path has no limit. Matches MAX_FORWARD_LIGHTS
+#define MAX_LIGHTS 16
 
 
 ///////////////////////////////////////////////////////////////////////////////////////////
@@ -15,6 +13,14 @@ uniform vec3 camera_pos;
 uniform vec3 albedo;
 uniform float roughness;
 uniform float metallic;
+uniform float alpha;
+uniform float alpha_cutoff; //0 unless the material uses alpha testing
+uniform vec3 emissive;
+
+//See Light::packed
+uniform int light_count;
+uniform vec4 light_position[MAX_LIGHTS];
+uniform vec4 light_color[MAX_LIGHTS];
 
 //Texture maps
 uniform sampler2D diffuse_map;
@@ -24,29 +30,19 @@ uniform sampler2D metallic_map;
 
 uniform sampler2D equirectangular_map;
 
+//Screen space ambient occlusion, same size as the framebuffer (see Ssao)
+uniform sampler2D ao_map;
+uniform bool ssao_enabled;
+
 in Vertex{
     vec3 pos;
     vec3 norm;
     vec2 uv;
     mat3 TBN;
+    vec4 color;
+    vec4 data; //InstanceData::user_data, zero when not instanced
 } i;
 
-struct pbr_material {
-    vec3  albedo;
-    float metallic;
-    float roughness;
-    vec3  f0;
-    float a;
-    float k;
-};
-
-struct micro_surface {
-    vec3 n;
-    vec3 l;
-    vec3 v;
-    vec3 h;
-};
-
 const vec2 invAtan = vec2(0.1591, 0.3183);
 
 vec2 sample_spherical_map(vec3 v) {
@@ -58,81 +54,11 @@ vec2 sample_spherical_map(vec3 v) {
 
 
 pbr_material make_pbr_material() {
-    pbr_material mat;
-
-    mat.albedo=albedo;
-    mat.metallic=metallic * texture(metallic_map,i.uv*4).r;
-    mat.roughness=roughness * texture(roughness_map,i.uv*4).r;
-    mat.f0=mix(vec3(0.04),mat.albedo,mat.metallic);
-    mat.a=mat.roughness*mat.roughness;
-    mat.k=((mat.roughness+1) * (mat.roughness+1))/8;
-
-    return mat;
-}
-
-micro_surface make_micro_surface(pbr_material mat, vec3 pos, vec3 normal) {
-    micro_surface ms;
-    ms.n=normalize(normal);
-    ms.v=normalize(camera_pos-pos);
-    ms.l= normalize(vec3(0.0,0.0,1.0) - pos);//Hardcoded light direction for now
-    ms.h=normalize(ms.l+ms.v);
-
-    return ms;
-}
-
-vec3 fresnel_schlick(pbr_material mat, micro_surface ms) {
-    float vdotn = max(dot(ms.v, ms.n), 0.0);
-    return mat.f0 + (1.0 - mat.f0) * pow(1.0 - vdotn, 5.0);
-}
-
-float normal_distribution_ggx(micro_surface ms, float a) {
-    float a2 = a * a;
-    float NdotH = max(dot(ms.n, ms.h), 0.0);
-    float NdotH2 = NdotH * NdotH;
-
-    float nom = a2;
-    float denom = (NdotH2 * (a2 - 1.0) + 1.0);
-    denom = M_PI * denom * denom;
-
-    return nom / denom;
-}
-
-float geometry_smith(micro_surface ms, float a, float k) {
-    float NdotV = max(dot(ms.n, ms.v), 0.0);
-    float NdotL = max(dot(ms.n, ms.l), 0.0);
-    float ggx1 = NdotL/(NdotL * (1.0 - k) + k);
-    float ggx2 = NdotV/(NdotV * (1.0 - k) + k);
-
-    return (ggx1 * ggx2);
-}
-
-vec3 specular(pbr_material mat, micro_surface ms){
-    vec3 v = ms.v;
-    vec3 n = ms.n;
-    vec3 l = ms.l;
-    vec3 h = ms.h;
-
-    float d = normal_distribution_ggx(ms, mat.a);
-    float g = geometry_smith(ms, mat.a, mat.k);
-    vec3 f = fresnel_schlick(mat, ms);
-
-    float ndotl = max(dot(n,l),0.0);
-    float ndotv = max(dot(n,v),0.0);
-    float denom = max(4.0*ndotl*ndotv,0.0001);
-
-    return (f * d * g) / denom;
-}
-
-
-
-vec3 brdf(pbr_material mat, micro_surface ms) {
-    vec3 Ks = fresnel_schlick(mat, ms);
-    vec3 kD = (vec3(1.0)-Ks) * (1.0-mat.metallic);
-
-    vec3 diffuse_color = mix(mat.albedo / M_PI,vec3(0),mat.metallic);
-    vec3 specular_color = specular(mat, ms);
-
-    return M_PI * kD * diffuse_color + mat.metallic * specular_color;
+    return make_pbr_material(
+        albedo*i.color.rgb,
+        metallic * texture(metallic_map,i.uv*4).r,
+        roughness * texture(roughness_map,i.uv*4).r
+    );
 }
 
 
@@ -140,19 +66,26 @@ vec3 brdf(pbr_material mat, micro_surface ms) {
 out vec4 o_color;
 
 void main(){
-
+    float opacity = alpha * i.color.a;
+    if (opacity < alpha_cutoff) {
+        discard;
+    }
 
 
     vec3 normal = normalize(texture(normal_map,i.uv*4).rgb*2.0-1.0);
     normal = normalize(i.TBN*normal);
     //normal = i.norm; //This is just a hack to disable normal mapping for testing purposes
     pbr_material mat = make_pbr_material();
-    micro_surface ms = make_micro_surface(mat,i.pos,normal);
-
-    float NdotL = max(dot(ms.n,ms.l),0.0);
-    vec3 Lo = M_PI * brdf(mat,ms) * NdotL * vec3(1.0,1.0,1.0);
-    vec3 ambient = vec3(0.1)*mat.albedo;
-    vec3 color_HDR = ambient + Lo;
+    vec3 view = camera_pos - i.pos;
+
+    vec3 Lo = vec3(0.0);
+    for (int l = 0; l < min(light_count, MAX_LIGHTS); l++) {
+        Lo += shade(mat, i.pos, normal, view, light_position[l], light_color[l]);
+    }
+    //Occlusion only applies to indirect light, the flat ambient stands in for image based lighting
+    float ao = ssao_enabled ? texture(ao_map, gl_FragCoord.xy / vec2(textureSize(ao_map, 0))).r : 1.0;
+    vec3 ambient = vec3(0.1)*mat.albedo*ao;
+    vec3 color_HDR = ambient + Lo + emissive;
     vec3 final = color_HDR; //* texture(diffuse_map,i.uv*4).rgb;
-    o_color = vec4(final,1.0);
+    o_color = vec4(final,opacity);
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+#version 430 core
+
+//Geometry pass of the deferred path, drawn with vertex_shader.vert. Writes the surface instead of lighting
+//it, with the same material inputs as fragment_shader.frag
+uniform vec3 albedo;
+uniform float roughness;
+uniform float metallic;
+uniform float alpha;
+uniform float alpha_cutoff;
+uniform vec3 emissive;
+
+uniform sampler2D roughness_map;
+uniform sampler2D normal_map;
+uniform sampler2D metallic_map;
+
+in Vertex{
+    vec3 pos;
+    vec3 norm;
+    vec2 uv;
+    mat3 TBN;
+    vec4 color;
+    vec4 data; //InstanceData::user_data, zero when not instanced
+} i;
+
+layout(location = 0) out vec4 o_albedo;
+layout(location = 1) out vec4 o_normal; //World space
+layout(location = 2) out vec4 o_material; //Roughness, metallic
+layout(location = 3) out vec4 o_emissive;
+
+void main() {
+    if (alpha * i.color.a < alpha_cutoff) {
+        discard;
+    }
+    vec3 normal = normalize(texture(normal_map, i.uv * 4).rgb * 2.0 - 1.0);
+    o_albedo = vec4(albedo * i.color.rgb, 1.0);
+    o_normal = vec4(normalize(i.TBN * normal), 0.0);
+    o_material = vec4(roughness * texture(roughness_map, i.uv * 4).r, metallic * texture(metallic_map, i.uv * 4).r, 0.0, 0.0);
+    o_emissive = vec4(emissive, 0.0);
+}
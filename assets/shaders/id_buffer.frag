@@ -0,0 +1,10 @@
+#version 430 core
+
+//Object arena slot + 1, 0 is left for the cleared background
+uniform int object_id;
+
+layout(location = 0) out uint id;
+
+void main() {
+    id = uint(object_id);
+}
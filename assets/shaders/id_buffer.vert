@@ -0,0 +1,9 @@
+#version 430 core
+
+layout(location = 0) in vec3 pos;
+
+uniform mat4 M,V,P;
+
+void main() {
+    gl_Position = P*V*M*vec4(pos,1);
+}
@@ -0,0 +1,9 @@
+#version 430 core
+
+uniform vec3 color;
+
+out vec4 o_color;
+
+void main() {
+    o_color = vec4(color, 1.0);
+}
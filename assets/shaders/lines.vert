@@ -0,0 +1,9 @@
+#version 430 core
+
+layout(location = 0) in vec3 pos; //World space
+
+uniform mat4 V,P;
+
+void main() {
+    gl_Position = P*V*vec4(pos,1);
+}
@@ -0,0 +1,25 @@
+#version 430 core
+
+//Depth and view space normals for screen space effects, drawn with vertex_shader.vert
+uniform mat4 V;
+uniform float alpha;
+uniform float alpha_cutoff;
+
+in Vertex{
+    vec3 pos;
+    vec3 norm;
+    vec2 uv;
+    mat3 TBN;
+    vec4 color;
+    vec4 data; //InstanceData::user_data, zero when not instanced
+} i;
+
+out vec4 o_normal;
+
+void main() {
+    //Alpha tested holes mustn't occlude
+    if (alpha * i.color.a < alpha_cutoff) {
+        discard;
+    }
+    o_normal = vec4(normalize(mat3(V) * i.norm), 1.0);
+}
@@ -0,0 +1,11 @@
+#version 430 core
+
+//Screen space motion since last frame, in uv units like post/motion_vectors.frag
+in vec4 current;
+in vec4 previous;
+
+layout(location = 0) out vec4 o_motion;
+
+void main() {
+    o_motion = vec4((current.xy/current.w - previous.xy/previous.w)*0.5, 0.0, 0.0);
+}
@@ -0,0 +1,19 @@
+#version 430 core
+
+layout(location = 0) in vec3 pos;
+
+uniform mat4 M,V,P;
+uniform mat4 previous_M;
+//Unjittered, the jitter isn't motion
+uniform mat4 view_projection;
+uniform mat4 previous_view_projection;
+
+out vec4 current;
+out vec4 previous;
+
+void main() {
+    vec4 world_pos = M*vec4(pos,1);
+    gl_Position = P*V*world_pos;
+    current = view_projection*world_pos;
+    previous = previous_view_projection*previous_M*vec4(pos,1);
+}
@@ -0,0 +1,114 @@
+//Cook-Torrance BRDF and scene lights, shared by the forward and deferred shaders through #include.
+//This was used as a reference: https://github.com/adrianderstroff/pbr/tree/master/assets/shaders/pbr/simple
+
+#define M_PI 3.1415926535897932384626433832795
+
+struct pbr_material {
+    vec3  albedo;
+    float metallic;
+    float roughness;
+    vec3  f0;
+    float a;
+    float k;
+};
+
+struct micro_surface {
+    vec3 n;
+    vec3 l;
+    vec3 v;
+    vec3 h;
+};
+
+pbr_material make_pbr_material(vec3 albedo, float metallic, float roughness) {
+    pbr_material mat;
+
+    mat.albedo=albedo;
+    mat.metallic=metallic;
+    mat.roughness=roughness;
+    mat.f0=mix(vec3(0.04),mat.albedo,mat.metallic);
+    mat.a=mat.roughness*mat.roughness;
+    mat.k=((mat.roughness+1) * (mat.roughness+1))/8;
+
+    return mat;
+}
+
+micro_surface make_micro_surface(vec3 normal, vec3 view, vec3 light) {
+    micro_surface ms;
+    ms.n=normalize(normal);
+    ms.v=normalize(view);
+    ms.l=normalize(light);
+    ms.h=normalize(ms.l+ms.v);
+
+    return ms;
+}
+
+vec3 fresnel_schlick(pbr_material mat, micro_surface ms) {
+    float vdotn = max(dot(ms.v, ms.n), 0.0);
+    return mat.f0 + (1.0 - mat.f0) * pow(1.0 - vdotn, 5.0);
+}
+
+float normal_distribution_ggx(micro_surface ms, float a) {
+    float a2 = a * a;
+    float NdotH = max(dot(ms.n, ms.h), 0.0);
+    float NdotH2 = NdotH * NdotH;
+
+    float nom = a2;
+    float denom = (NdotH2 * (a2 - 1.0) + 1.0);
+    denom = M_PI * denom * denom;
+
+    return nom / denom;
+}
+
+float geometry_smith(micro_surface ms, float a, float k) {
+    float NdotV = max(dot(ms.n, ms.v), 0.0);
+    float NdotL = max(dot(ms.n, ms.l), 0.0);
+    float ggx1 = NdotL/(NdotL * (1.0 - k) + k);
+    float ggx2 = NdotV/(NdotV * (1.0 - k) + k);
+
+    return (ggx1 * ggx2);
+}
+
+vec3 specular(pbr_material mat, micro_surface ms){
+    vec3 v = ms.v;
+    vec3 n = ms.n;
+    vec3 l = ms.l;
+    vec3 h = ms.h;
+
+    float d = normal_distribution_ggx(ms, mat.a);
+    float g = geometry_smith(ms, mat.a, mat.k);
+    vec3 f = fresnel_schlick(mat, ms);
+
+    float ndotl = max(dot(n,l),0.0);
+    float ndotv = max(dot(n,v),0.0);
+    float denom = max(4.0*ndotl*ndotv,0.0001);
+
+    return (f * d * g) / denom;
+}
+
+vec3 brdf(pbr_material mat, micro_surface ms) {
+    vec3 Ks = fresnel_schlick(mat, ms);
+    vec3 kD = (vec3(1.0)-Ks) * (1.0-mat.metallic);
+
+    vec3 diffuse_color = mix(mat.albedo / M_PI,vec3(0),mat.metallic);
+    vec3 specular_color = specular(mat, ms);
+
+    return M_PI * kD * diffuse_color + mat.metallic * specular_color;
+}
+
+//Lights as packed by Light::packed: light_position.w is 1 for point lights and 0 for directional ones,
+//whose xyz is the direction the light travels. light_color is color times intensity with a point
+//light's range in a
+vec3 shade(pbr_material mat, vec3 pos, vec3 normal, vec3 view, vec4 light_position, vec4 light_color) {
+    vec3 l = -light_position.xyz;
+    float attenuation = 1.0;
+    if (light_position.w > 0.5) {
+        l = light_position.xyz - pos;
+        //Inverse square, windowed to reach 0 at the range
+        float d = length(l);
+        float window = clamp(1.0 - pow(d / light_color.a, 4.0), 0.0, 1.0);
+        attenuation = window * window / (d * d + 1.0);
+    }
+    micro_surface ms = make_micro_surface(normal, view, l);
+    float NdotL = max(dot(ms.n,ms.l),0.0);
+    return M_PI * brdf(mat,ms) * NdotL * light_color.rgb * attenuation;
+}
@@ -0,0 +1,14 @@
+#version 430 core
+
+uniform sampler2D input_texture;
+uniform sampler2D bloom_texture;
+uniform float intensity;
+
+in vec2 uv;
+out vec4 o_color;
+
+void main() {
+    vec4 color = texture(input_texture, uv);
+    vec3 bloom = texture(bloom_texture, uv).rgb;
+    o_color = vec4(color.rgb + bloom * intensity, color.a);
+}
@@ -0,0 +1,66 @@
+#version 430 core
+
+//13 tap downsample from Jimenez, "Next Generation Post Processing in Call of Duty: Advanced Warfare"
+uniform sampler2D input_texture;
+uniform vec2 texel_size; //Of the input
+uniform bool prefilter; //First pass, applies the threshold and Karis average
+uniform float threshold;
+uniform float knee;
+
+in vec2 uv;
+out vec4 o_color;
+
+float brightness(vec3 c) {
+    return max(c.r, max(c.g, c.b));
+}
+
+//Soft knee threshold: 0 below threshold - knee, a quadratic ramp up to threshold + knee, then linear
+vec3 apply_threshold(vec3 c) {
+    float b = brightness(c);
+    float soft = clamp(b - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-5);
+    return c * max(soft, b - threshold) / max(b, 1e-5);
+}
+
+//Weighs a 2x2 block by inverse luma so single very bright pixels don't flicker
+vec3 karis_average(vec3 a, vec3 b, vec3 c, vec3 d) {
+    vec4 weights = 1.0 / (1.0 + vec4(brightness(a), brightness(b), brightness(c), brightness(d)));
+    return (a * weights.x + b * weights.y + c * weights.z + d * weights.w) / dot(weights, vec4(1.0));
+}
+
+vec3 tap(float x, float y) {
+    return texture(input_texture, uv + vec2(x, y) * texel_size).rgb;
+}
+
+void main() {
+    vec3 a = tap(-2.0, 2.0);
+    vec3 b = tap(0.0, 2.0);
+    vec3 c = tap(2.0, 2.0);
+    vec3 d = tap(-2.0, 0.0);
+    vec3 e = tap(0.0, 0.0);
+    vec3 f = tap(2.0, 0.0);
+    vec3 g = tap(-2.0, -2.0);
+    vec3 h = tap(0.0, -2.0);
+    vec3 i = tap(2.0, -2.0);
+    vec3 j = tap(-1.0, 1.0);
+    vec3 k = tap(1.0, 1.0);
+    vec3 l = tap(-1.0, -1.0);
+    vec3 m = tap(1.0, -1.0);
+
+    vec3 color;
+    if (prefilter) {
+        //Five overlapping 2x2 blocks, the center one counting for half
+        color = karis_average(j, k, l, m) * 0.5
+            + karis_average(a, b, d, e) * 0.125
+            + karis_average(b, c, e, f) * 0.125
+            + karis_average(d, e, g, h) * 0.125
+            + karis_average(e, f, h, i) * 0.125;
+        color = apply_threshold(color);
+    } else {
+        color = e * 0.125
+            + (a + c + g + i) * 0.03125
+            + (b + d + f + h) * 0.0625
+            + (j + k + l + m) * 0.125;
+    }
+    o_color = vec4(max(color, 0.0), 1.0);
+}
@@ -0,0 +1,23 @@
+#version 430 core
+
+//3x3 tent filter, added onto the next larger mip with additive blending
+uniform sampler2D input_texture;
+uniform vec2 texel_size; //Of the input
+uniform float filter_radius; //In input texels
+
+in vec2 uv;
+out vec4 o_color;
+
+void main() {
+    vec2 r = texel_size * filter_radius;
+    vec3 color = texture(input_texture, uv).rgb * 4.0;
+    color += (texture(input_texture, uv + vec2(-r.x, 0.0)).rgb
+        + texture(input_texture, uv + vec2(r.x, 0.0)).rgb
+        + texture(input_texture, uv + vec2(0.0, -r.y)).rgb
+        + texture(input_texture, uv + vec2(0.0, r.y)).rgb) * 2.0;
+    color += texture(input_texture, uv + vec2(-r.x, -r.y)).rgb
+        + texture(input_texture, uv + vec2(r.x, -r.y)).rgb
+        + texture(input_texture, uv + vec2(-r.x, r.y)).rgb
+        + texture(input_texture, uv + vec2(r.x, r.y)).rgb;
+    o_color = vec4(color / 16.0, 1.0);
+}
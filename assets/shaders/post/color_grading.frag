@@ -0,0 +1,17 @@
+#version 430 core
+
+uniform sampler2D input_texture;
+uniform sampler3D lut;
+uniform float lut_size;
+uniform float strength;
+
+in vec2 uv;
+out vec4 o_color;
+
+void main() {
+    vec4 color = texture(input_texture, uv);
+    //Sample between the centers of the first and last texels so 0 and 1 map exactly onto the table's ends
+    vec3 coord = clamp(color.rgb, 0.0, 1.0) * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+    vec3 graded = texture(lut, coord).rgb;
+    o_color = vec4(mix(color.rgb, graded, strength), color.a);
+}
@@ -0,0 +1,22 @@
+#version 430 core
+
+uniform sampler2D input_texture;
+uniform float intensity;
+uniform float time;
+
+in vec2 uv;
+out vec4 o_color;
+
+float hash(vec3 p) {
+    p = fract(p * 0.1031);
+    p += dot(p, p.zyx + 31.32);
+    return fract((p.x + p.y) * p.z);
+}
+
+void main() {
+    vec4 color = texture(input_texture, uv);
+    float noise = hash(vec3(gl_FragCoord.xy, fract(time) * 1000.0)) - 0.5;
+    //Grain shows most in the shadows and midtones
+    float luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    o_color = vec4(color.rgb + noise * intensity * (1.0 - 0.5 * luminance), color.a);
+}
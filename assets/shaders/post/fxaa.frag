@@ -0,0 +1,114 @@
+#version 430 core
+
+//FXAA, after Timothy Lottes' FXAA 3.11 quality preset: finds edges by luma contrast, walks along them to
+//their ends and blends across by how far the pixel is from the nearer end. Expects gamma corrected input
+uniform sampler2D input_texture;
+uniform float edge_threshold; //Contrast needed relative to the brightest neighbour
+uniform float edge_threshold_min; //Absolute contrast needed, skips dark areas
+uniform float subpixel; //Blur amount for single pixel details, 0 keeps them sharp
+
+in vec2 uv;
+out vec4 o_color;
+
+#define ITERATIONS 12
+const float QUALITY[ITERATIONS] = float[](1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0);
+
+float luma(vec2 coords) {
+    return dot(texture(input_texture, coords).rgb, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(input_texture, 0));
+    vec4 center = texture(input_texture, uv);
+    float m = dot(center.rgb, vec3(0.299, 0.587, 0.114));
+    float n = luma(uv + vec2(0.0, texel.y));
+    float s = luma(uv - vec2(0.0, texel.y));
+    float e = luma(uv + vec2(texel.x, 0.0));
+    float w = luma(uv - vec2(texel.x, 0.0));
+    float lowest = min(m, min(min(n, s), min(e, w)));
+    float highest = max(m, max(max(n, s), max(e, w)));
+    float range = highest - lowest;
+    if (range < max(edge_threshold_min, highest * edge_threshold)) {
+        o_color = center;
+        return;
+    }
+    float ne = luma(uv + texel);
+    float sw = luma(uv - texel);
+    float nw = luma(uv + vec2(-texel.x, texel.y));
+    float se = luma(uv + vec2(texel.x, -texel.y));
+
+    //Thin features get blurred by how much they stand out from their neighbourhood
+    float average = (2.0 * (n + s + e + w) + ne + nw + se + sw) / 12.0;
+    float subpixel_blend = smoothstep(0.0, 1.0, clamp(abs(average - m) / range, 0.0, 1.0));
+    subpixel_blend = subpixel_blend * subpixel_blend * subpixel;
+
+    float horizontal = abs(n + s - 2.0 * m) * 2.0 + abs(ne + se - 2.0 * e) + abs(nw + sw - 2.0 * w);
+    float vertical = abs(e + w - 2.0 * m) * 2.0 + abs(ne + nw - 2.0 * n) + abs(se + sw - 2.0 * s);
+    bool is_horizontal = horizontal >= vertical;
+
+    //Which side of the pixel the edge is on
+    float step_length = is_horizontal ? texel.y : texel.x;
+    float luma1 = is_horizontal ? s : w;
+    float luma2 = is_horizontal ? n : e;
+    float gradient1 = abs(luma1 - m);
+    float gradient2 = abs(luma2 - m);
+    float gradient_scaled = 0.25 * max(gradient1, gradient2);
+    float local_average;
+    if (gradient1 >= gradient2) {
+        step_length = -step_length;
+        local_average = 0.5 * (luma1 + m);
+    } else {
+        local_average = 0.5 * (luma2 + m);
+    }
+
+    //Walk along the edge, halfway between the pixel and its neighbour, until the contrast changes
+    vec2 edge_uv = uv;
+    vec2 offset;
+    if (is_horizontal) {
+        edge_uv.y += step_length * 0.5;
+        offset = vec2(texel.x, 0.0);
+    } else {
+        edge_uv.x += step_length * 0.5;
+        offset = vec2(0.0, texel.y);
+    }
+    vec2 uv1 = edge_uv - offset;
+    vec2 uv2 = edge_uv + offset;
+    float end1 = 0.0;
+    float end2 = 0.0;
+    bool reached1 = false;
+    bool reached2 = false;
+    for (int step = 0; step < ITERATIONS && !(reached1 && reached2); step++) {
+        if (!reached1) {
+            end1 = luma(uv1) - local_average;
+            reached1 = abs(end1) >= gradient_scaled;
+            if (!reached1) {
+                uv1 -= offset * QUALITY[step];
+            }
+        }
+        if (!reached2) {
+            end2 = luma(uv2) - local_average;
+            reached2 = abs(end2) >= gradient_scaled;
+            if (!reached2) {
+                uv2 += offset * QUALITY[step];
+            }
+        }
+    }
+
+    float distance1 = is_horizontal ? uv.x - uv1.x : uv.y - uv1.y;
+    float distance2 = is_horizontal ? uv2.x - uv.x : uv2.y - uv.y;
+    bool nearer1 = distance1 < distance2;
+    float edge_length = distance1 + distance2;
+    float pixel_offset = 0.5 - min(distance1, distance2) / edge_length;
+    //Only blend if the pixel is on the side of the edge the nearer end is heading to
+    bool center_smaller = m < local_average;
+    bool correct = ((nearer1 ? end1 : end2) < 0.0) != center_smaller;
+    float final_offset = max(correct ? pixel_offset : 0.0, subpixel_blend);
+
+    vec2 final_uv = uv;
+    if (is_horizontal) {
+        final_uv.y += final_offset * step_length;
+    } else {
+        final_uv.x += final_offset * step_length;
+    }
+    o_color = vec4(texture(input_texture, final_uv).rgb, center.a);
+}
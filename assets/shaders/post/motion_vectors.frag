@@ -0,0 +1,24 @@
+#version 430 core
+
+//Screen space motion of every pixel since last frame. Where Taa::render_object_motion drew an object
+//its own motion is used, the rest is reconstructed from the depth and the two frames' camera matrices
+uniform sampler2D input_texture; //Scene depth
+uniform sampler2D object_motion_texture;
+uniform bool object_motion;
+uniform mat4 inverse_view_projection;
+uniform mat4 previous_view_projection;
+
+in vec2 uv;
+out vec4 o_motion;
+
+void main() {
+    float depth = texture(input_texture, uv).r;
+    if (object_motion && depth < 1.0) {
+        o_motion = vec4(texture(object_motion_texture, uv).xy, 0.0, 0.0);
+        return;
+    }
+    vec4 world = inverse_view_projection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    vec4 previous = previous_view_projection * vec4(world.xyz / world.w, 1.0);
+    vec2 previous_uv = previous.xy / previous.w * 0.5 + 0.5;
+    o_motion = vec4(uv - previous_uv, 0.0, 0.0);
+}
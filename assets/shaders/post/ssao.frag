@@ -0,0 +1,51 @@
+#version 430 core
+
+#define MAX_SAMPLES 64
+
+uniform sampler2D input_texture; //Prepass depth
+uniform sampler2D normal_texture; //View space normals
+uniform sampler2D noise_texture; //Tiled random rotations around the normal
+uniform vec3 samples[MAX_SAMPLES]; //Hemisphere kernel, z along the normal
+uniform int sample_count;
+uniform float radius;
+uniform float bias;
+uniform float power;
+uniform vec2 noise_scale; //Screen size over noise texture size
+uniform mat4 P;
+uniform mat4 inverse_P;
+
+in vec2 uv;
+out vec4 o_color;
+
+vec3 view_position(vec2 coords) {
+    float depth = texture(input_texture, coords).r;
+    vec4 view = inverse_P * vec4(vec3(coords, depth) * 2.0 - 1.0, 1.0);
+    return view.xyz / view.w;
+}
+
+void main() {
+    //Nothing to occlude on the background
+    if (texture(input_texture, uv).r >= 1.0) {
+        o_color = vec4(1.0);
+        return;
+    }
+    vec3 pos = view_position(uv);
+    vec3 normal = normalize(texture(normal_texture, uv).xyz);
+    vec3 random = texture(noise_texture, uv * noise_scale).xyz;
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    mat3 TBN = mat3(tangent, cross(normal, tangent), normal);
+
+    float occlusion = 0.0;
+    int count = min(sample_count, MAX_SAMPLES);
+    for (int s = 0; s < count; s++) {
+        vec3 sample_pos = pos + TBN * samples[s] * radius;
+        vec4 offset = P * vec4(sample_pos, 1.0);
+        vec2 coords = offset.xy / offset.w * 0.5 + 0.5;
+        float sample_depth = view_position(coords).z;
+        //Geometry far in front of the sample is a different surface, it fades out instead of leaving halos
+        float range = smoothstep(0.0, 1.0, radius / abs(pos.z - sample_depth));
+        occlusion += (sample_depth >= sample_pos.z + bias ? 1.0 : 0.0) * range;
+    }
+    float ao = 1.0 - occlusion / float(max(count, 1));
+    o_color = vec4(vec3(pow(ao, power)), 1.0);
+}
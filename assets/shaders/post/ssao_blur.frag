@@ -0,0 +1,34 @@
+#version 430 core
+
+//One direction of a separable bilateral blur: a gaussian whose weights fall off with the difference
+//in view depth, so occlusion doesn't bleed across silhouettes
+uniform sampler2D input_texture; //AO
+uniform sampler2D depth_texture;
+uniform vec2 direction; //One texel along the blur axis
+uniform int blur_radius;
+uniform float sharpness;
+uniform mat4 inverse_P;
+
+in vec2 uv;
+out vec4 o_color;
+
+float view_depth(vec2 coords) {
+    float depth = texture(depth_texture, coords).r;
+    vec4 view = inverse_P * vec4(vec3(coords, depth) * 2.0 - 1.0, 1.0);
+    return view.z / view.w;
+}
+
+void main() {
+    float center = view_depth(uv);
+    float sigma = max(float(blur_radius), 1.0) * 0.5;
+    float total = 0.0;
+    float weights = 0.0;
+    for (int t = -blur_radius; t <= blur_radius; t++) {
+        vec2 coords = uv + direction * float(t);
+        float difference = (view_depth(coords) - center) / abs(center);
+        float weight = exp(-float(t * t) / (2.0 * sigma * sigma) - difference * difference * sharpness * sharpness);
+        total += texture(input_texture, coords).r * weight;
+        weights += weight;
+    }
+    o_color = vec4(vec3(total / weights), 1.0);
+}
@@ -0,0 +1,10 @@
+#version 430 core
+
+uniform sampler2D input_texture;
+
+in vec2 uv;
+out vec4 o_color;
+
+void main() {
+    o_color = vec4(vec3(texture(input_texture, uv).r), 1.0);
+}
@@ -0,0 +1,44 @@
+#version 430 core
+
+//Blends the jittered current frame with last frame's result, fetched where the pixel was last frame.
+//History outside the colors around the current pixel is from a surface that's no longer there and gets
+//clamped, which keeps ghosting down
+uniform sampler2D input_texture;
+uniform sampler2D history_texture;
+uniform sampler2D motion_texture;
+uniform float feedback; //Weight of the history
+uniform bool history_valid;
+
+in vec2 uv;
+out vec4 o_color;
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+void main() {
+    vec4 current = texture(input_texture, uv);
+    vec2 previous_uv = uv - texture(motion_texture, uv).xy;
+    if (!history_valid || any(lessThan(previous_uv, vec2(0.0))) || any(greaterThan(previous_uv, vec2(1.0)))) {
+        o_color = current;
+        return;
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(input_texture, 0));
+    vec3 lowest = current.rgb;
+    vec3 highest = current.rgb;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec3 neighbour = texture(input_texture, uv + vec2(x, y) * texel).rgb;
+            lowest = min(lowest, neighbour);
+            highest = max(highest, neighbour);
+        }
+    }
+    vec3 history = clamp(texture(history_texture, previous_uv).rgb, lowest, highest);
+
+    //Weighted by inverse luminance so single bright HDR samples don't flicker
+    float current_weight = (1.0 - feedback) / (1.0 + luma(current.rgb));
+    float history_weight = feedback / (1.0 + luma(history));
+    vec3 color = (current.rgb * current_weight + history * history_weight) / (current_weight + history_weight);
+    o_color = vec4(color, current.a);
+}
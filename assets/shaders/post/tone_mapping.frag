@@ -0,0 +1,32 @@
+#version 430 core
+
+uniform sampler2D input_texture;
+uniform float exposure;
+uniform int tone_map_operator; //ToneMapOperator: 0 Reinhard, 1 ACES, 2 clamp
+uniform float gamma;
+
+in vec2 uv;
+out vec4 o_color;
+
+//Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+void main() {
+    vec4 hdr = texture(input_texture, uv);
+    vec3 color = hdr.rgb * exposure;
+    if (tone_map_operator == 0) {
+        color = color / (1.0 + color);
+    } else if (tone_map_operator == 1) {
+        color = aces(color);
+    } else {
+        color = clamp(color, 0.0, 1.0);
+    }
+    o_color = vec4(pow(color, vec3(1.0 / gamma)), hdr.a);
+}
@@ -0,0 +1,17 @@
+#version 430 core
+
+uniform sampler2D input_texture;
+uniform float intensity;
+uniform float radius;
+uniform float softness;
+
+in vec2 uv;
+out vec4 o_color;
+
+void main() {
+    vec4 color = texture(input_texture, uv);
+    //0 in the center, 1 in the corners
+    float distance_to_center = length(uv - 0.5) * sqrt(2.0);
+    float falloff = smoothstep(radius, radius - softness, distance_to_center);
+    o_color = vec4(color.rgb * mix(1.0, falloff, intensity), color.a);
+}
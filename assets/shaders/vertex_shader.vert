@@ -4,26 +4,50 @@
 layout(location = 0) in vec3 pos;
 layout(location = 1) in vec3 normal;
 layout(location = 2) in vec2 uv;
-layout(location = 3) in vec3 tangent;
-layout(location = 4) in vec3 bitangent;
+layout(location = 3) in vec4 tangent; //w is the handedness of the tangent frame
+//Per-instance attributes (see InstanceData), only read when instanced is set
+layout(location = 5) in mat4 instance_model;
+layout(location = 9) in vec4 instance_color;
+layout(location = 10) in vec4 instance_data;
 
 uniform mat4 M,V,P;
+uniform mat3 N; //Normal matrix, inverse transpose of M's upper 3x3
+uniform bool instanced;
 
 
+//Everything in world space, same as camera_pos
 out Vertex{
     vec3 pos;
     vec3 norm;
     vec2 uv;
     mat3 TBN;
+    vec4 color;
+    vec4 data;
 } o;
 
 void main() {
-    gl_Position = P*V*M*vec4(pos,1);
-    vec3 T = normalize(vec3(M * vec4(tangent,   0.0)));
-    vec3 B = normalize(vec3(M * vec4(bitangent, 0.0)));
-    vec3 N = normalize(vec3(M * vec4(normal,    0.0)));
-    o.pos = pos;
-    o.norm = normal;
+    mat4 model = instanced ? instance_model : M;
+    //Instances have no attribute location left for a normal matrix (see InstanceData). The cofactor matrix
+    //is the inverse transpose scaled by the determinant, so it gives the same directions for three cross
+    //products instead of an inverse
+    mat3 normal_matrix = N;
+    if (instanced) {
+        mat3 m = mat3(instance_model);
+        normal_matrix = mat3(cross(m[1], m[2]), cross(m[2], m[0]), cross(m[0], m[1]));
+        normal_matrix *= sign(dot(m[0], normal_matrix[0]));
+    }
+    vec4 world_pos = model*vec4(pos,1);
+    gl_Position = P*V*world_pos;
+    //Tangents lie in the surface so they follow the model matrix, normals need the normal matrix
+    vec3 world_normal = normalize(normal_matrix * normal);
+    vec3 T = normalize(mat3(model) * tangent.xyz);
+    T = normalize(T - dot(T, world_normal) * world_normal);
+    //MikkTSpace: the bitangent is rebuilt per vertex from the normal, tangent and handedness
+    vec3 B = tangent.w * cross(world_normal, T);
+    o.pos = world_pos.xyz;
+    o.norm = world_normal;
     o.uv = uv;
-    o.TBN = (mat3(T,B,N));
+    o.TBN = (mat3(T,B,world_normal));
+    o.color = instanced ? instance_color : vec4(1.0);
+    o.data = instanced ? instance_data : vec4(0.0);
 }
\ No newline at end of file
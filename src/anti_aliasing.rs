@@ -0,0 +1,437 @@
+//Anti-aliasing: MSAA through PostStack's multisampled scene target, FXAA and TAA as post effects.
+//AntiAliasing picks one of them for a stack
+use crate::debug_output::DebugGroup;
+use crate::gl_state;
+use crate::post_process::{post_program, PostContext, PostEffect, PostStack, RenderTarget};
+use crate::sampling::halton;
+use crate::{shader_from_file, Camera, Scene, ShaderProgram, ShaderProgramBuilder, ShaderType};
+use cgmath::{Matrix4, SquareMatrix, Vector2};
+use cstr::cstr;
+use ogl33::*;
+
+use std::any::Any;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    #[default]
+    None,
+    //Number of samples per pixel
+    Msaa(i32),
+    Fxaa,
+    Taa,
+}
+
+impl AntiAliasing {
+    //The mode after this one, for cycling through them with a key
+    pub fn next(self) -> Self {
+        match self {
+            AntiAliasing::None => AntiAliasing::Msaa(4),
+            AntiAliasing::Msaa(_) => AntiAliasing::Fxaa,
+            AntiAliasing::Fxaa => AntiAliasing::Taa,
+            AntiAliasing::Taa => AntiAliasing::None,
+        }
+    }
+}
+
+impl PostStack {
+    //Sets the MSAA samples and enables the "fxaa" and "taa" passes to match mode, other passes are left
+    //alone. With TAA, call Taa::prepare on the camera every frame
+    pub unsafe fn set_anti_aliasing(&mut self, mode: AntiAliasing) {
+        let samples = match mode {
+            AntiAliasing::Msaa(samples) => samples,
+            _ => 0,
+        };
+        let used = self.set_msaa_samples(samples);
+        if used != samples {
+            log::warn!(
+                "MSAA with {} samples isn't supported, using {}",
+                samples,
+                used
+            );
+        }
+        self.set_enabled("fxaa", mode == AntiAliasing::Fxaa);
+        self.set_enabled("taa", mode == AntiAliasing::Taa);
+    }
+
+    pub fn anti_aliasing(&self) -> AntiAliasing {
+        if self.msaa_samples() > 1 {
+            AntiAliasing::Msaa(self.msaa_samples())
+        } else if self.is_enabled("taa") {
+            AntiAliasing::Taa
+        } else if self.is_enabled("fxaa") {
+            AntiAliasing::Fxaa
+        } else {
+            AntiAliasing::None
+        }
+    }
+}
+
+//Goes after tone mapping, it needs colors in display range to judge contrast like the eye does
+pub struct Fxaa {
+    pub edge_threshold: f32,
+    pub edge_threshold_min: f32,
+    pub subpixel: f32,
+    program: Option<ShaderProgram>,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            subpixel: 0.75,
+            program: None,
+        }
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn name(&self) -> &'static str {
+        "fxaa"
+    }
+
+    unsafe fn apply(&mut self, context: &PostContext) {
+        let program = self.program.get_or_insert_with(|| {
+            post_program(
+                "fxaa.frag",
+                &[
+                    cstr!("edge_threshold"),
+                    cstr!("edge_threshold_min"),
+                    cstr!("subpixel"),
+                ],
+            )
+        });
+        gl_state::use_program(program.0);
+        gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, context.input);
+        program.set_int("input_texture", 0);
+        program.set_float("edge_threshold", self.edge_threshold);
+        program.set_float("edge_threshold_min", self.edge_threshold_min);
+        program.set_float("subpixel", self.subpixel);
+        context.draw_fullscreen();
+    }
+
+    unsafe fn delete(&mut self) {
+        if let Some(program) = self.program.take() {
+            program.delete();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+//Temporal anti-aliasing: the camera is jittered by a different subpixel offset every frame and the
+//frames are accumulated, reprojected with motion vectors. Goes first in the stack, on the HDR scene.
+//Without render_object_motion the motion vectors only cover the camera
+pub struct Taa {
+    //How much of the accumulated history is kept each frame, higher is smoother but ghosts more
+    pub feedback: f32,
+    //Length of the jitter sequence
+    pub sample_count: usize,
+    history: Vec<RenderTarget>,
+    motion: Option<RenderTarget>,
+    //Velocity of the opaque objects, from render_object_motion
+    object_motion: Option<RenderTarget>,
+    //Which history target holds last frame's result
+    current: usize,
+    history_valid: bool,
+    view_projection: Matrix4<f32>,
+    previous_view_projection: Matrix4<f32>,
+    jitter_index: usize,
+    prepared: bool,
+    object_motion_rendered: bool,
+    last_frame: Option<u64>,
+    programs: Option<[ShaderProgram; 2]>, //Motion vectors, resolve
+    object_motion_program: Option<ShaderProgram>,
+}
+
+impl Default for Taa {
+    fn default() -> Self {
+        Self {
+            feedback: 0.9,
+            sample_count: 8,
+            history: Vec::new(),
+            motion: None,
+            object_motion: None,
+            current: 0,
+            history_valid: false,
+            view_projection: Matrix4::identity(),
+            previous_view_projection: Matrix4::identity(),
+            jitter_index: 0,
+            prepared: false,
+            object_motion_rendered: false,
+            last_frame: None,
+            programs: None,
+            object_motion_program: None,
+        }
+    }
+}
+
+impl Taa {
+    //Subpixel offset for frame index of the sequence, in pixels within -0.5..0.5. Halton (2, 3) covers
+    //the pixel evenly for any sequence length
+    pub fn jitter_offset(index: usize, sample_count: usize) -> Vector2<f32> {
+        let index = index % sample_count.max(1) + 1;
+        Vector2::new(halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+    }
+
+    //Jitters the camera for this frame and remembers its matrices for reprojection. Call every frame
+    //before rendering the scene, width and height are the scene target's size
+    pub fn prepare(&mut self, camera: &mut Camera, width: i32, height: i32) {
+        let offset = Self::jitter_offset(self.jitter_index, self.sample_count);
+        self.jitter_index = (self.jitter_index + 1) % self.sample_count.max(1);
+        camera.jitter = Vector2::new(
+            offset.x * 2.0 / width as f32,
+            offset.y * 2.0 / height as f32,
+        );
+        self.previous_view_projection = self.view_projection;
+        self.view_projection = camera.get_unjittered_projection_matrix() * camera.get_view_matrix();
+        self.prepared = true;
+    }
+
+    //Renders the motion of the opaque objects from their previous_model_matrix, so moving objects are
+    //reprojected to where they were instead of where the camera alone would put them. Call every frame
+    //after prepare and Scene::update_transforms, before the scene pass. Leaves its target bound, bind the
+    //scene target afterwards
+    pub unsafe fn render_object_motion(
+        &mut self,
+        scene: &mut Scene,
+        camera: &Camera,
+        width: i32,
+        height: i32,
+    ) {
+        let _group = DebugGroup::new("Object motion vectors");
+        let resized = self
+            .object_motion
+            .as_ref()
+            .is_none_or(|target| (target.width, target.height) != (width, height));
+        if resized {
+            if let Some(target) = self.object_motion.take() {
+                target.delete();
+            }
+            let target = RenderTarget::new(width, height, GL_RG16F, true)
+                .expect("Couldn't create object motion vectors");
+            target.set_label("Object motion vectors");
+            self.object_motion = Some(target);
+        }
+        let program = self
+            .object_motion_program
+            .get_or_insert_with(|| object_motion_program());
+        let target = self
+            .object_motion
+            .as_ref()
+            .expect("Target was just created");
+
+        target.bind();
+        gl_state::enable(GL_DEPTH_TEST);
+        gl_state::depth_mask(true);
+        glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+        gl_state::use_program(program.0);
+        program.set_mat4("view_projection", &self.view_projection);
+        program.set_mat4("previous_view_projection", &self.previous_view_projection);
+        scene.draw_opaque(program, camera);
+        self.object_motion_rendered = true;
+    }
+
+    //Drops the history, e.g. after the camera jumped somewhere else
+    pub fn reset(&mut self) {
+        self.history_valid = false;
+    }
+
+    unsafe fn create_targets(&mut self, width: i32, height: i32) {
+        self.delete_targets();
+        for i in 0..2 {
+            let target = RenderTarget::new(width, height, GL_RGBA16F, false)
+                .expect("Couldn't create TAA history");
+            target.set_label(&format!("TAA history {}", i));
+            self.history.push(target);
+        }
+        let motion = RenderTarget::new(width, height, GL_RG16F, false)
+            .expect("Couldn't create motion vectors");
+        motion.set_label("Motion vectors");
+        self.motion = Some(motion);
+        self.history_valid = false;
+    }
+
+    unsafe fn delete_targets(&mut self) {
+        for target in self.history.drain(..).chain(self.motion.take()) {
+            target.delete();
+        }
+    }
+}
+
+impl PostEffect for Taa {
+    fn name(&self) -> &'static str {
+        "taa"
+    }
+
+    unsafe fn apply(&mut self, context: &PostContext) {
+        let resized = self
+            .motion
+            .as_ref()
+            .is_none_or(|motion| (motion.width, motion.height) != (context.width, context.height));
+        if resized {
+            self.create_targets(context.width, context.height);
+        }
+        //Without prepare this frame the matrices are stale, after a gap the history is
+        let history_valid = self.history_valid
+            && self.prepared
+            && self
+                .last_frame
+                .is_some_and(|frame| frame + 1 == context.frame);
+        if !self.prepared {
+            log::warn!("Taa::prepare wasn't called this frame, the image won't be accumulated");
+        }
+        self.prepared = false;
+        let object_motion = self
+            .object_motion
+            .as_ref()
+            .filter(|_| self.object_motion_rendered);
+        self.object_motion_rendered = false;
+        self.last_frame = Some(context.frame);
+
+        let [motion_program, resolve_program] = self.programs.get_or_insert_with(|| {
+            [
+                post_program(
+                    "motion_vectors.frag",
+                    &[
+                        cstr!("object_motion_texture"),
+                        cstr!("object_motion"),
+                        cstr!("inverse_view_projection"),
+                        cstr!("previous_view_projection"),
+                    ],
+                ),
+                post_program(
+                    "taa_resolve.frag",
+                    &[
+                        cstr!("history_texture"),
+                        cstr!("motion_texture"),
+                        cstr!("feedback"),
+                        cstr!("history_valid"),
+                    ],
+                ),
+            ]
+        });
+        let motion = self.motion.as_ref().expect("TAA targets were just created");
+
+        if let Some(depth_texture) = context.scene.depth_texture {
+            let _group = DebugGroup::new("Motion vectors");
+            motion.bind();
+            gl_state::use_program(motion_program.0);
+            gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, depth_texture);
+            motion_program.set_int("input_texture", 0);
+            gl_state::bind_texture_unit(
+                GL_TEXTURE1,
+                GL_TEXTURE_2D,
+                object_motion.map_or(0, |target| target.color_texture),
+            );
+            motion_program.set_int("object_motion_texture", 1);
+            motion_program.set_int("object_motion", object_motion.is_some() as i32);
+            motion_program.set_mat4(
+                "inverse_view_projection",
+                &self
+                    .view_projection
+                    .invert()
+                    .unwrap_or_else(Matrix4::identity),
+            );
+            motion_program.set_mat4("previous_view_projection", &self.previous_view_projection);
+            context.draw_fullscreen();
+        }
+
+        //Resolved into the next history target, which is then copied to the output
+        let (history, target) = (&self.history[self.current], &self.history[1 - self.current]);
+        {
+            let _group = DebugGroup::new("TAA resolve");
+            target.bind();
+            gl_state::use_program(resolve_program.0);
+            gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, context.input);
+            gl_state::bind_texture_unit(GL_TEXTURE1, GL_TEXTURE_2D, history.color_texture);
+            gl_state::bind_texture_unit(GL_TEXTURE2, GL_TEXTURE_2D, motion.color_texture);
+            resolve_program.set_int("input_texture", 0);
+            resolve_program.set_int("history_texture", 1);
+            resolve_program.set_int("motion_texture", 2);
+            resolve_program.set_float("feedback", self.feedback.clamp(0.0, 1.0));
+            resolve_program.set_int("history_valid", history_valid as i32);
+            context.draw_fullscreen();
+        }
+
+        glBindFramebuffer(GL_READ_FRAMEBUFFER, target.framebuffer);
+        glBindFramebuffer(GL_DRAW_FRAMEBUFFER, context.output_framebuffer);
+        glBlitFramebuffer(
+            0,
+            0,
+            target.width,
+            target.height,
+            0,
+            0,
+            context.width,
+            context.height,
+            GL_COLOR_BUFFER_BIT,
+            GL_NEAREST,
+        );
+        context.bind_output();
+        self.current = 1 - self.current;
+        self.history_valid = true;
+    }
+
+    unsafe fn resize(&mut self, _width: i32, _height: i32) {
+        //Recreated at the right size on the next apply and render_object_motion
+        self.delete_targets();
+        if let Some(target) = self.object_motion.take() {
+            target.delete();
+        }
+    }
+
+    unsafe fn delete(&mut self) {
+        self.delete_targets();
+        if let Some(target) = self.object_motion.take() {
+            target.delete();
+        }
+        for program in self.programs.take().into_iter().flatten() {
+            program.delete();
+        }
+        if let Some(program) = self.object_motion_program.take() {
+            program.delete();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+//Draws the scene's velocity, see assets/shaders/object_motion.vert. Has everything Scene::draw sets, the
+//ones it doesn't use just have no location
+unsafe fn object_motion_program() -> ShaderProgram {
+    let mut program = ShaderProgramBuilder::new()
+        .create_shader(
+            ShaderType::Vertex,
+            &shader_from_file(Path::new("assets/shaders/object_motion.vert")),
+        )
+        .create_shader(
+            ShaderType::Fragment,
+            &shader_from_file(Path::new("assets/shaders/object_motion.frag")),
+        )
+        .link()
+        .expect("Failed to link the object motion program");
+    program.set_label("Object motion vectors");
+    for uniform in [
+        cstr!("M"),
+        cstr!("N"),
+        cstr!("V"),
+        cstr!("P"),
+        cstr!("previous_M"),
+        cstr!("view_projection"),
+        cstr!("previous_view_projection"),
+        cstr!("camera_pos"),
+        cstr!("instanced"),
+        cstr!("albedo"),
+        cstr!("roughness"),
+        cstr!("metallic"),
+    ] {
+        program.create_uniform(uniform);
+    }
+    program
+}
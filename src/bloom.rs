@@ -0,0 +1,185 @@
+//Bloom on the HDR image: a thresholded copy is downsampled through a chain of half sized mips, then
+//upsampled back with a tent filter, each level adding onto the next larger one. Goes before tone mapping
+use crate::debug_output::DebugGroup;
+use crate::gl_state;
+use crate::post_process::{post_program, PostContext, PostEffect, RenderTarget};
+use crate::ShaderProgram;
+use cgmath::Vector2;
+use cstr::cstr;
+use ogl33::*;
+
+use std::any::Any;
+
+pub struct Bloom {
+    //Brightness where bloom starts, 0 makes everything bloom a little (the physically based look)
+    pub threshold: f32,
+    //Width of the soft transition around threshold, 0 for a hard cut
+    pub knee: f32,
+    //How much of the blurred light is added back
+    pub intensity: f32,
+    //Upsample filter size in texels, larger values spread the glow
+    pub filter_radius: f32,
+    //Number of downsampled levels, each half the size of the previous
+    pub mip_count: usize,
+    mips: Vec<RenderTarget>,
+    mip_size: (i32, i32),
+    programs: Option<[ShaderProgram; 3]>, //Downsample, upsample, composite
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.8,
+            filter_radius: 1.0,
+            mip_count: 6,
+            mips: Vec::new(),
+            mip_size: (0, 0),
+            programs: None,
+        }
+    }
+}
+
+impl Bloom {
+    //Sizes of the mips for a source of the given size, stops early once a level is a single pixel
+    pub fn mip_sizes(width: i32, height: i32, mip_count: usize) -> Vec<(i32, i32)> {
+        let mut sizes = Vec::new();
+        let (mut width, mut height) = (width, height);
+        while sizes.len() < mip_count && (width > 1 || height > 1) {
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            sizes.push((width, height));
+        }
+        sizes
+    }
+
+    //Fraction of a color that passes the threshold, what the prefilter applies on the GPU
+    pub fn contribution(&self, brightness: f32) -> f32 {
+        let soft = (brightness - self.threshold + self.knee).clamp(0.0, 2.0 * self.knee);
+        let soft = soft * soft / (4.0 * self.knee + 1e-5);
+        soft.max(brightness - self.threshold) / brightness.max(1e-5)
+    }
+
+    unsafe fn create_mips(&mut self, width: i32, height: i32) {
+        self.delete_mips();
+        for (i, (width, height)) in Self::mip_sizes(width, height, self.mip_count)
+            .into_iter()
+            .enumerate()
+        {
+            let mip = RenderTarget::new(width, height, GL_RGBA16F, false)
+                .expect("Couldn't create bloom mip");
+            mip.set_label(&format!("Bloom mip {}", i));
+            self.mips.push(mip);
+        }
+        self.mip_size = (width, height);
+    }
+
+    unsafe fn delete_mips(&mut self) {
+        for mip in self.mips.drain(..) {
+            mip.delete();
+        }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &'static str {
+        "bloom"
+    }
+
+    unsafe fn apply(&mut self, context: &PostContext) {
+        let wanted = Self::mip_sizes(context.width, context.height, self.mip_count).len();
+        if self.mip_size != (context.width, context.height) || self.mips.len() != wanted {
+            self.create_mips(context.width, context.height);
+        }
+        let [downsample, upsample, composite] = self.programs.get_or_insert_with(|| {
+            [
+                post_program(
+                    "bloom_downsample.frag",
+                    &[
+                        cstr!("texel_size"),
+                        cstr!("prefilter"),
+                        cstr!("threshold"),
+                        cstr!("knee"),
+                    ],
+                ),
+                post_program(
+                    "bloom_upsample.frag",
+                    &[cstr!("texel_size"), cstr!("filter_radius")],
+                ),
+                post_program(
+                    "bloom_composite.frag",
+                    &[cstr!("bloom_texture"), cstr!("intensity")],
+                ),
+            ]
+        });
+
+        if !self.mips.is_empty() {
+            let _group = DebugGroup::new("Bloom downsample");
+            gl_state::use_program(downsample.0);
+            downsample.set_int("input_texture", 0);
+            downsample.set_float("threshold", self.threshold);
+            downsample.set_float("knee", self.knee);
+            let mut source = (context.input, context.width, context.height);
+            for (i, mip) in self.mips.iter().enumerate() {
+                mip.bind();
+                gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, source.0);
+                downsample.set_int("prefilter", (i == 0) as i32);
+                set_texel_size(downsample, source.1, source.2);
+                context.draw_fullscreen();
+                source = (mip.color_texture, mip.width, mip.height);
+            }
+        }
+
+        if self.mips.len() > 1 {
+            let _group = DebugGroup::new("Bloom upsample");
+            gl_state::use_program(upsample.0);
+            upsample.set_int("input_texture", 0);
+            upsample.set_float("filter_radius", self.filter_radius);
+            gl_state::enable(GL_BLEND);
+            gl_state::blend_func(GL_ONE, GL_ONE);
+            for pair in self.mips.windows(2).rev() {
+                let (target, source) = (&pair[0], &pair[1]);
+                target.bind();
+                gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, source.color_texture);
+                set_texel_size(upsample, source.width, source.height);
+                context.draw_fullscreen();
+            }
+            gl_state::disable(GL_BLEND);
+        }
+
+        context.bind_output();
+        gl_state::use_program(composite.0);
+        gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, context.input);
+        composite.set_int("input_texture", 0);
+        //No mips (a 1x1 output) means nothing to add, the composite then just copies
+        let bloom = self.mips.first().map_or(0, |mip| mip.color_texture);
+        gl_state::bind_texture_unit(GL_TEXTURE1, GL_TEXTURE_2D, bloom);
+        composite.set_int("bloom_texture", 1);
+        composite.set_float("intensity", if bloom == 0 { 0.0 } else { self.intensity });
+        context.draw_fullscreen();
+    }
+
+    unsafe fn resize(&mut self, _width: i32, _height: i32) {
+        //Recreated at the right size on the next apply
+        self.delete_mips();
+    }
+
+    unsafe fn delete(&mut self) {
+        self.delete_mips();
+        for program in self.programs.take().into_iter().flatten() {
+            program.delete();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+unsafe fn set_texel_size(program: &ShaderProgram, width: i32, height: i32) {
+    program.set_vec2(
+        "texel_size",
+        &Vector2::new(1.0 / width as f32, 1.0 / height as f32),
+    );
+}
@@ -0,0 +1,337 @@
+use crate::Vertex;
+use cgmath::{InnerSpace, Matrix4, Vector3};
+
+//Axis aligned bounding box, min > max on any axis means empty
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vector3<f32>>) -> Self {
+        let mut aabb = Self::empty();
+        for point in points {
+            aabb.extend(point);
+        }
+        aabb
+    }
+
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        Self::from_points(vertices.iter().map(|v| Vector3::new(v[0], v[1], v[2])))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn extend(&mut self, point: Vector3<f32>) {
+        self.min = Vector3::new(
+            self.min.x.min(point.x),
+            self.min.y.min(point.y),
+            self.min.z.min(point.z),
+        );
+        self.max = Vector3::new(
+            self.max.x.max(point.x),
+            self.max.y.max(point.y),
+            self.max.z.max(point.z),
+        );
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut result = *self;
+        if !other.is_empty() {
+            result.extend(other.min);
+            result.extend(other.max);
+        }
+        result
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        if self.is_empty() {
+            Vector3::new(0.0, 0.0, 0.0)
+        } else {
+            self.max - self.min
+        }
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        self.size() * 0.5
+    }
+
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vector3::new(a.x, a.y, a.z),
+            Vector3::new(b.x, a.y, a.z),
+            Vector3::new(a.x, b.y, a.z),
+            Vector3::new(b.x, b.y, a.z),
+            Vector3::new(a.x, a.y, b.z),
+            Vector3::new(b.x, a.y, b.z),
+            Vector3::new(a.x, b.y, b.z),
+            Vector3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    pub fn contains(&self, point: Vector3<f32>) -> bool {
+        point.x >= self.min.x
+            && point.y >= self.min.y
+            && point.z >= self.min.z
+            && point.x <= self.max.x
+            && point.y <= self.max.y
+            && point.z <= self.max.z
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    //Squared distance from point to the closest point of the box, 0 inside and infinite for empty boxes
+    pub fn distance2(&self, point: Vector3<f32>) -> f32 {
+        if self.is_empty() {
+            return f32::INFINITY;
+        }
+        let clamped = Vector3::new(
+            point.x.clamp(self.min.x, self.max.x),
+            point.y.clamp(self.min.y, self.max.y),
+            point.z.clamp(self.min.z, self.max.z),
+        );
+        (point - clamped).magnitude2()
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.distance2(sphere.center) <= sphere.radius * sphere.radius
+    }
+
+    //Slab test, returns the distance along the ray where it enters the box (0 when it starts inside)
+    pub fn intersect_ray(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut near = 0.0f32;
+        let mut far = max_distance;
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            //NaN (0 * inf for rays in the slab's plane) compares false, so it leaves near/far alone
+            if t0 > near {
+                near = t0;
+            }
+            if t1 < far {
+                far = t1;
+            }
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    //Smallest AABB around the transformed box (Arvo's method, no need to transform all 8 corners)
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = (matrix * self.center().extend(1.0)).truncate();
+        let e = self.half_extents();
+        let half = Vector3::new(
+            matrix.x.x.abs() * e.x + matrix.y.x.abs() * e.y + matrix.z.x.abs() * e.z,
+            matrix.x.y.abs() * e.x + matrix.y.y.abs() * e.y + matrix.z.y.abs() * e.z,
+            matrix.x.z.abs() * e.x + matrix.y.z.abs() * e.y + matrix.z.z.abs() * e.z,
+        );
+        Aabb::new(center - half, center + half)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    //Unit length, distances along the ray are in world units
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vector3<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    //Ritter's approximation, within a few percent of the minimal sphere
+    pub fn from_points(points: &[Vector3<f32>]) -> Self {
+        let first = match points.first() {
+            Some(&first) => first,
+            None => return Self::new(Vector3::new(0.0, 0.0, 0.0), 0.0),
+        };
+        let farthest_from = |from: Vector3<f32>| {
+            *points
+                .iter()
+                .max_by(|a, b| (*a - from).magnitude2().total_cmp(&(*b - from).magnitude2()))
+                .unwrap()
+        };
+        let a = farthest_from(first);
+        let b = farthest_from(a);
+        let mut center = (a + b) * 0.5;
+        let mut radius = (b - a).magnitude() * 0.5;
+        for &point in points.iter() {
+            let distance = (point - center).magnitude();
+            if distance > radius {
+                let grown = (radius + distance) * 0.5;
+                center += (point - center) * ((grown - radius) / distance);
+                radius = grown;
+            }
+        }
+        Self::new(center, radius)
+    }
+
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        let points: Vec<Vector3<f32>> = vertices
+            .iter()
+            .map(|v| Vector3::new(v[0], v[1], v[2]))
+            .collect();
+        Self::from_points(&points)
+    }
+
+    pub fn contains(&self, point: Vector3<f32>) -> bool {
+        (point - self.center).magnitude2() <= self.radius * self.radius
+    }
+
+    pub fn intersects(&self, other: &BoundingSphere) -> bool {
+        let radius = self.radius + other.radius;
+        (other.center - self.center).magnitude2() <= radius * radius
+    }
+
+    pub fn union(&self, other: &BoundingSphere) -> BoundingSphere {
+        let offset = other.center - self.center;
+        let distance = offset.magnitude();
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+        let radius = (self.radius + distance + other.radius) * 0.5;
+        Self::new(
+            self.center + offset * ((radius - self.radius) / distance),
+            radius,
+        )
+    }
+
+    //Scales the radius by the largest axis scale, so non-uniform scales give a conservative sphere
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> BoundingSphere {
+        let scale = matrix
+            .x
+            .truncate()
+            .magnitude()
+            .max(matrix.y.truncate().magnitude())
+            .max(matrix.z.truncate().magnitude());
+        Self::new(
+            (matrix * self.center.extend(1.0)).truncate(),
+            self.radius * scale,
+        )
+    }
+}
+
+//Oriented bounding box, axes are unit length and half_extents are along them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Obb {
+    pub center: Vector3<f32>,
+    pub axes: [Vector3<f32>; 3],
+    pub half_extents: Vector3<f32>,
+}
+
+impl Obb {
+    //The local AABB carried through the matrix, tight for rotations and scales unlike Aabb::transformed
+    pub fn from_aabb(aabb: &Aabb, matrix: &Matrix4<f32>) -> Self {
+        let center = (matrix * aabb.center().extend(1.0)).truncate();
+        let e = aabb.half_extents();
+        let columns = [matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate()];
+        let lengths = columns.map(|c| c.magnitude());
+        let axes = [0, 1, 2].map(|i| {
+            if lengths[i] > 0.0 {
+                columns[i] / lengths[i]
+            } else {
+                [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()][i]
+            }
+        });
+        Self {
+            center,
+            axes,
+            half_extents: Vector3::new(e.x * lengths[0], e.y * lengths[1], e.z * lengths[2]),
+        }
+    }
+
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let [x, y, z] = [
+            self.axes[0] * self.half_extents.x,
+            self.axes[1] * self.half_extents.y,
+            self.axes[2] * self.half_extents.z,
+        ];
+        let c = self.center;
+        [
+            c - x - y - z,
+            c + x - y - z,
+            c - x + y - z,
+            c + x + y - z,
+            c - x - y + z,
+            c + x - y + z,
+            c - x + y + z,
+            c + x + y + z,
+        ]
+    }
+
+    pub fn contains(&self, point: Vector3<f32>) -> bool {
+        let d = point - self.center;
+        (0..3).all(|i| d.dot(self.axes[i]).abs() <= self.half_extents[i])
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(self.corners())
+    }
+}
@@ -0,0 +1,420 @@
+//Bounding volume hierarchy over a list of AABBs (one per scene object). Items are referred to by their index in the
+//list the tree was built from, the tree itself knows nothing about objects so it can be used without GL
+use crate::{Aabb, BoundingSphere, Containment, Frustum, Ray};
+use cgmath::Vector3;
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+};
+
+const MAX_LEAF_ITEMS: usize = 4;
+const SAH_BINS: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BvhNodeKind {
+    //Items items[first..first + count]
+    Leaf { first: usize, count: usize },
+    Internal { left: usize, right: usize },
+}
+
+#[derive(Clone, Debug)]
+pub struct BvhNode {
+    pub aabb: Aabb,
+    pub kind: BvhNodeKind,
+    pub parent: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    //Root first, children always come after their parent
+    pub nodes: Vec<BvhNode>,
+    //Item indices, grouped by leaf
+    pub items: Vec<usize>,
+    //Current bounds of each item
+    pub bounds: Vec<Aabb>,
+    //Leaf node holding each item
+    leaf_of: Vec<usize>,
+    //cost() right after the last build, refits that drift too far from it call for a rebuild
+    build_cost: f32,
+}
+
+//Entry of the k-nearest search queue, ordered so the closest pops first
+struct Candidate {
+    distance2: f32,
+    node: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance2 == other.distance2
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance2.total_cmp(&self.distance2)
+    }
+}
+
+impl Bvh {
+    //Top down build, splitting with the surface area heuristic over binned centroids
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            items: (0..bounds.len()).collect(),
+            bounds: bounds.to_vec(),
+            leaf_of: vec![0; bounds.len()],
+            build_cost: 0.0,
+        };
+        if bounds.is_empty() {
+            return bvh;
+        }
+
+        //Empty boxes (e.g. free slots) have no center, they get binned at the origin and are never hit
+        let centroids: Vec<Vector3<f32>> = bounds
+            .iter()
+            .map(|aabb| {
+                if aabb.is_empty() {
+                    Vector3::new(0.0, 0.0, 0.0)
+                } else {
+                    aabb.center()
+                }
+            })
+            .collect();
+        //(node, first, count) ranges still to split
+        let mut stack = vec![(0, 0, bounds.len())];
+        bvh.nodes.push(BvhNode {
+            aabb: Aabb::empty(),
+            kind: BvhNodeKind::Leaf {
+                first: 0,
+                count: bounds.len(),
+            },
+            parent: None,
+        });
+
+        while let Some((node, first, count)) = stack.pop() {
+            let range = &mut bvh.items[first..first + count];
+            let aabb = range
+                .iter()
+                .fold(Aabb::empty(), |aabb, &item| aabb.union(&bounds[item]));
+            bvh.nodes[node].aabb = aabb;
+
+            let split = if count > MAX_LEAF_ITEMS {
+                Self::find_split(range, bounds, &centroids)
+            } else {
+                None
+            };
+            match split {
+                Some(left_count) => {
+                    let left = bvh.nodes.len();
+                    for (child, child_first, child_count) in [
+                        (left, first, left_count),
+                        (left + 1, first + left_count, count - left_count),
+                    ] {
+                        bvh.nodes.push(BvhNode {
+                            aabb: Aabb::empty(),
+                            kind: BvhNodeKind::Leaf {
+                                first: child_first,
+                                count: child_count,
+                            },
+                            parent: Some(node),
+                        });
+                        stack.push((child, child_first, child_count));
+                    }
+                    bvh.nodes[node].kind = BvhNodeKind::Internal {
+                        left,
+                        right: left + 1,
+                    };
+                }
+                None => {
+                    for &item in bvh.items[first..first + count].iter() {
+                        bvh.leaf_of[item] = node;
+                    }
+                }
+            }
+        }
+        bvh.build_cost = bvh.cost();
+        bvh
+    }
+
+    //Partitions range in place and returns the size of the left half, None when splitting doesn't pay off
+    fn find_split(range: &mut [usize], bounds: &[Aabb], centroids: &[Vector3<f32>]) -> Option<usize> {
+        let centroid_bounds = Aabb::from_points(range.iter().map(|&item| centroids[item]));
+        let extent = centroid_bounds.size();
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        if extent[axis] <= 0.0 {
+            //All centroids on top of each other, split down the middle to keep leaves small
+            let half = range.len() / 2;
+            return Some(half);
+        }
+
+        let bin_of = |item: usize| {
+            let t = (centroids[item][axis] - centroid_bounds.min[axis]) / extent[axis];
+            ((t * SAH_BINS as f32) as usize).min(SAH_BINS - 1)
+        };
+        let mut bins = [(Aabb::empty(), 0usize); SAH_BINS];
+        for &item in range.iter() {
+            let bin = &mut bins[bin_of(item)];
+            bin.0 = bin.0.union(&bounds[item]);
+            bin.1 += 1;
+        }
+
+        //Cost of splitting after each bin: area * count on both sides
+        let mut best: Option<(f32, usize)> = None;
+        for split in 1..SAH_BINS {
+            let (left, left_count) = bins[..split]
+                .iter()
+                .fold((Aabb::empty(), 0), |(aabb, count), bin| (aabb.union(&bin.0), count + bin.1));
+            let (right, right_count) = bins[split..]
+                .iter()
+                .fold((Aabb::empty(), 0), |(aabb, count), bin| (aabb.union(&bin.0), count + bin.1));
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = left.surface_area() * left_count as f32 + right.surface_area() * right_count as f32;
+            if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+                best = Some((cost, split));
+            }
+        }
+        let (_, split) = best?;
+
+        //Partition items whose bin is left of the split to the front
+        let mut left_count = 0;
+        for i in 0..range.len() {
+            if bin_of(range[i]) < split {
+                range.swap(i, left_count);
+                left_count += 1;
+            }
+        }
+        Some(left_count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    //Takes new bounds for every item and refits all nodes bottom up, the tree structure stays the same
+    pub fn refit(&mut self, bounds: &[Aabb]) {
+        assert_eq!(bounds.len(), self.bounds.len(), "refit needs the same number of items, rebuild instead");
+        self.bounds.copy_from_slice(bounds);
+        for node in (0..self.nodes.len()).rev() {
+            self.nodes[node].aabb = self.node_bounds(node);
+        }
+    }
+
+    //Moves one item and refits the path from its leaf to the root
+    pub fn update(&mut self, item: usize, aabb: Aabb) {
+        self.bounds[item] = aabb;
+        let mut node = Some(self.leaf_of[item]);
+        while let Some(index) = node {
+            let refitted = self.node_bounds(index);
+            if refitted == self.nodes[index].aabb {
+                break;
+            }
+            self.nodes[index].aabb = refitted;
+            node = self.nodes[index].parent;
+        }
+    }
+
+    fn node_bounds(&self, node: usize) -> Aabb {
+        match self.nodes[node].kind {
+            BvhNodeKind::Leaf { first, count } => self.items[first..first + count]
+                .iter()
+                .fold(Aabb::empty(), |aabb, &item| aabb.union(&self.bounds[item])),
+            BvhNodeKind::Internal { left, right } => {
+                self.nodes[left].aabb.union(&self.nodes[right].aabb)
+            }
+        }
+    }
+
+    //Surface area heuristic cost of the tree relative to its root, grows as refits loosen the nodes
+    pub fn cost(&self) -> f32 {
+        let root = match self.nodes.first() {
+            Some(root) => root.aabb.surface_area(),
+            None => return 0.0,
+        };
+        if root <= 0.0 {
+            return 0.0;
+        }
+        self.nodes
+            .iter()
+            .map(|node| match node.kind {
+                BvhNodeKind::Leaf { count, .. } => node.aabb.surface_area() * count as f32,
+                BvhNodeKind::Internal { .. } => node.aabb.surface_area(),
+            })
+            .sum::<f32>()
+            / root
+    }
+
+    //True when refitting has made the tree noticeably worse than a fresh build would be
+    pub fn needs_rebuild(&self) -> bool {
+        self.cost() > self.build_cost * 1.5
+    }
+
+    //Depth first walk, visit decides per node whether to descend (Intersecting), take the whole subtree (Inside)
+    //or skip it (Outside). Leaf items are checked with item_test unless their node was fully inside
+    fn walk(
+        &self,
+        mut visit: impl FnMut(&Aabb) -> Containment,
+        mut item_test: impl FnMut(&Aabb) -> bool,
+    ) -> Vec<usize> {
+        let mut result = Vec::new();
+        if self.nodes.is_empty() {
+            return result;
+        }
+        let mut stack = vec![(0, false)];
+        while let Some((node, inside)) = stack.pop() {
+            let containment = if inside {
+                Containment::Inside
+            } else {
+                visit(&self.nodes[node].aabb)
+            };
+            if containment == Containment::Outside {
+                continue;
+            }
+            let inside = containment == Containment::Inside;
+            match self.nodes[node].kind {
+                BvhNodeKind::Leaf { first, count } => {
+                    for &item in self.items[first..first + count].iter() {
+                        if inside || item_test(&self.bounds[item]) {
+                            result.push(item);
+                        }
+                    }
+                }
+                BvhNodeKind::Internal { left, right } => {
+                    stack.push((right, inside));
+                    stack.push((left, inside));
+                }
+            }
+        }
+        result
+    }
+
+    //Items whose AABB isn't completely outside the frustum
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        self.walk(
+            |aabb| frustum.classify_aabb(aabb),
+            |aabb| frustum.classify_aabb(aabb) != Containment::Outside,
+        )
+    }
+
+    pub fn query_aabb(&self, query: &Aabb) -> Vec<usize> {
+        let classify = |aabb: &Aabb| {
+            if !query.intersects(aabb) {
+                Containment::Outside
+            } else if query.contains(aabb.min) && query.contains(aabb.max) {
+                Containment::Inside
+            } else {
+                Containment::Intersecting
+            }
+        };
+        self.walk(classify, |aabb| query.intersects(aabb))
+    }
+
+    pub fn query_sphere(&self, sphere: &BoundingSphere) -> Vec<usize> {
+        let classify = |aabb: &Aabb| {
+            if !aabb.intersects_sphere(sphere) {
+                Containment::Outside
+            } else if aabb.corners().iter().all(|&corner| sphere.contains(corner)) {
+                Containment::Inside
+            } else {
+                Containment::Intersecting
+            }
+        };
+        self.walk(classify, |aabb| aabb.intersects_sphere(sphere))
+    }
+
+    //Items whose AABB the ray hits within max_distance, with the entry distance, closest first
+    pub fn query_ray(&self, ray: &Ray, max_distance: f32) -> Vec<(usize, f32)> {
+        let mut hits = Vec::new();
+        if self.nodes.is_empty() {
+            return hits;
+        }
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            if self.nodes[node].aabb.intersect_ray(ray, max_distance).is_none() {
+                continue;
+            }
+            match self.nodes[node].kind {
+                BvhNodeKind::Leaf { first, count } => {
+                    for &item in self.items[first..first + count].iter() {
+                        if let Some(distance) = self.bounds[item].intersect_ray(ray, max_distance) {
+                            hits.push((item, distance));
+                        }
+                    }
+                }
+                BvhNodeKind::Internal { left, right } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+        hits
+    }
+
+    //The k items whose AABBs are closest to point (distance 0 when inside), closest first
+    pub fn nearest(&self, point: Vector3<f32>, k: usize) -> Vec<(usize, f32)> {
+        let mut result: Vec<(usize, f32)> = Vec::with_capacity(k);
+        if self.nodes.is_empty() || k == 0 {
+            return result;
+        }
+        //Best first: nodes and items share the queue, items are pushed as leaves with an index past the nodes
+        let mut queue = BinaryHeap::new();
+        queue.push(Candidate {
+            distance2: self.nodes[0].aabb.distance2(point),
+            node: 0,
+        });
+        while let Some(candidate) = queue.pop() {
+            //Only empty boxes are left
+            if candidate.distance2 == f32::INFINITY {
+                break;
+            }
+            if candidate.node >= self.nodes.len() {
+                result.push((candidate.node - self.nodes.len(), candidate.distance2.sqrt()));
+                if result.len() == k {
+                    break;
+                }
+                continue;
+            }
+            match self.nodes[candidate.node].kind {
+                BvhNodeKind::Leaf { first, count } => {
+                    for &item in self.items[first..first + count].iter() {
+                        queue.push(Candidate {
+                            distance2: self.bounds[item].distance2(point),
+                            node: self.nodes.len() + item,
+                        });
+                    }
+                }
+                BvhNodeKind::Internal { left, right } => {
+                    for child in [left, right] {
+                        queue.push(Candidate {
+                            distance2: self.nodes[child].aabb.distance2(point),
+                            node: child,
+                        });
+                    }
+                }
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,194 @@
+use crate::gl_ext::*;
+use log::Level;
+use ogl33::*;
+
+use std::{ffi::CStr, sync::RwLock};
+
+//Which KHR_debug messages get dropped before they reach the logger
+#[derive(Clone, Debug, Default)]
+pub struct DebugMessageFilter {
+    pub ignored_sources: Vec<GLenum>,
+    pub ignored_types: Vec<GLenum>,
+    pub ignored_ids: Vec<GLuint>,
+}
+
+impl DebugMessageFilter {
+    //Drops our own push/pop group notifications and NVIDIA's "buffer will use video memory" spam
+    pub fn noise_filtered() -> Self {
+        Self {
+            ignored_sources: Vec::new(),
+            ignored_types: vec![GL_DEBUG_TYPE_PUSH_GROUP, GL_DEBUG_TYPE_POP_GROUP],
+            ignored_ids: vec![131185],
+        }
+    }
+
+    pub fn ignore_source(mut self, source: GLenum) -> Self {
+        self.ignored_sources.push(source);
+        self
+    }
+
+    pub fn ignore_type(mut self, type_: GLenum) -> Self {
+        self.ignored_types.push(type_);
+        self
+    }
+
+    pub fn ignore_id(mut self, id: GLuint) -> Self {
+        self.ignored_ids.push(id);
+        self
+    }
+
+    pub fn accepts(&self, source: GLenum, type_: GLenum, id: GLuint) -> bool {
+        !self.ignored_sources.contains(&source)
+            && !self.ignored_types.contains(&type_)
+            && !self.ignored_ids.contains(&id)
+    }
+}
+
+static DEBUG_FILTER: RwLock<Option<DebugMessageFilter>> = RwLock::new(None);
+
+//Routes driver messages into the log facade, returns false when KHR_debug isn't available
+pub unsafe fn enable_debug_output(filter: DebugMessageFilter) -> bool {
+    if !is_loaded::glDebugMessageCallback() {
+        log::warn!("glDebugMessageCallback isn't available, GL debug output is disabled");
+        return false;
+    }
+    set_debug_filter(filter);
+
+    glEnable(GL_DEBUG_OUTPUT);
+    //Synchronous output makes the callback run inside the offending GL call, so backtraces point at it
+    glEnable(GL_DEBUG_OUTPUT_SYNCHRONOUS);
+    glDebugMessageCallback(Some(debug_message_callback), std::ptr::null());
+    glDebugMessageControl(
+        GL_DONT_CARE,
+        GL_DONT_CARE,
+        GL_DONT_CARE,
+        0,
+        std::ptr::null(),
+        GL_TRUE,
+    );
+
+    let mut flags = 0;
+    glGetIntegerv(GL_CONTEXT_FLAGS, &mut flags);
+    if flags & GL_CONTEXT_FLAG_DEBUG_BIT == 0 {
+        log::info!("GL context isn't a debug context, the driver may report fewer messages");
+    }
+    true
+}
+
+pub fn set_debug_filter(filter: DebugMessageFilter) {
+    *DEBUG_FILTER.write().unwrap() = Some(filter);
+}
+
+pub fn debug_severity_level(severity: GLenum) -> Level {
+    match severity {
+        GL_DEBUG_SEVERITY_HIGH => Level::Error,
+        GL_DEBUG_SEVERITY_MEDIUM => Level::Warn,
+        GL_DEBUG_SEVERITY_LOW => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+pub fn debug_source_name(source: GLenum) -> &'static str {
+    match source {
+        GL_DEBUG_SOURCE_API => "API",
+        GL_DEBUG_SOURCE_WINDOW_SYSTEM => "WINDOW_SYSTEM",
+        GL_DEBUG_SOURCE_SHADER_COMPILER => "SHADER_COMPILER",
+        GL_DEBUG_SOURCE_THIRD_PARTY => "THIRD_PARTY",
+        GL_DEBUG_SOURCE_APPLICATION => "APPLICATION",
+        _ => "OTHER",
+    }
+}
+
+pub fn debug_type_name(type_: GLenum) -> &'static str {
+    match type_ {
+        GL_DEBUG_TYPE_ERROR => "ERROR",
+        GL_DEBUG_TYPE_DEPRECATED_BEHAVIOR => "DEPRECATED_BEHAVIOR",
+        GL_DEBUG_TYPE_UNDEFINED_BEHAVIOR => "UNDEFINED_BEHAVIOR",
+        GL_DEBUG_TYPE_PORTABILITY => "PORTABILITY",
+        GL_DEBUG_TYPE_PERFORMANCE => "PERFORMANCE",
+        GL_DEBUG_TYPE_MARKER => "MARKER",
+        GL_DEBUG_TYPE_PUSH_GROUP => "PUSH_GROUP",
+        GL_DEBUG_TYPE_POP_GROUP => "POP_GROUP",
+        _ => "OTHER",
+    }
+}
+
+extern "system" fn debug_message_callback(
+    source: GLenum,
+    type_: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    _length: GLsizei,
+    message: *const GLchar,
+    _user_param: *mut c_void,
+) {
+    let accepted = match DEBUG_FILTER.read() {
+        Ok(filter) => filter
+            .as_ref()
+            .is_none_or(|filter| filter.accepts(source, type_, id)),
+        Err(_) => true,
+    };
+    if !accepted || message.is_null() {
+        return;
+    }
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    log::log!(
+        target: "gl",
+        debug_severity_level(severity),
+        "[GL {} {} #{}] {}",
+        debug_source_name(source),
+        debug_type_name(type_),
+        id,
+        message.trim_end()
+    );
+}
+
+//Names a GL object so it shows up by name in RenderDoc/apitrace captures and debug messages
+pub unsafe fn label_object(identifier: GLenum, name: GLuint, label: &str) {
+    if !is_loaded::glObjectLabel() {
+        return;
+    }
+    glObjectLabel(
+        identifier,
+        name,
+        label.len() as GLsizei,
+        label.as_ptr() as *const GLchar,
+    );
+}
+
+pub unsafe fn push_debug_group(message: &str) {
+    if !is_loaded::glPushDebugGroup() {
+        return;
+    }
+    glPushDebugGroup(
+        GL_DEBUG_SOURCE_APPLICATION,
+        0,
+        message.len() as GLsizei,
+        message.as_ptr() as *const GLchar,
+    );
+}
+
+pub unsafe fn pop_debug_group() {
+    if !is_loaded::glPopDebugGroup() {
+        return;
+    }
+    glPopDebugGroup();
+}
+
+//Pushes a debug group that gets popped when the guard is dropped
+pub struct DebugGroup(());
+
+impl DebugGroup {
+    pub unsafe fn new(message: &str) -> Self {
+        push_debug_group(message);
+        Self(())
+    }
+}
+
+impl Drop for DebugGroup {
+    fn drop(&mut self) {
+        unsafe {
+            pop_debug_group();
+        }
+    }
+}
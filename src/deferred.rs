@@ -0,0 +1,459 @@
+//Deferred shading: the opaque scene is drawn once into a G-buffer of surface attributes, then every light
+//is drawn as a volume covering just the pixels it reaches and shaded from the G-buffer, so the cost
+//of a light is the area it covers rather than the whole scene. Transparent objects can't be stored in
+//a G-buffer and go through the forward shader afterwards
+use crate::debug_output::{label_object, DebugGroup};
+use crate::frustum::{Containment, Frustum};
+use crate::gl_state;
+use crate::post_process::PostStack;
+use crate::ssao::Ssao;
+use crate::{
+    shader_from_file, BoundingSphere, Camera, Light, Mesh, Scene, ShaderProgram,
+    ShaderProgramBuilder, ShaderType, VertexArray,
+};
+use cgmath::{Matrix4, SquareMatrix};
+use cstr::cstr;
+use ogl33::*;
+
+use std::path::Path;
+
+//How the scene is lit, both take the same Scene, Material and Camera
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderPath {
+    //One pass through fragment_shader.frag with up to MAX_FORWARD_LIGHTS lights, supports MSAA
+    #[default]
+    Forward,
+    //G-buffer and light volumes, any number of lights but no MSAA
+    Deferred,
+}
+
+//The G-buffer is read on these units, after the material textures and the SSAO
+const GBUFFER_UNIT: GLenum = GL_TEXTURE6;
+const AO_UNIT: GLenum = GL_TEXTURE5;
+//Faces of a once subdivided icosphere come within 0.93 of its radius, the volume is scaled up to
+//still cover the whole range
+const VOLUME_SCALE: f32 = 1.1;
+
+//Surface attributes per pixel: albedo (RGBA8), world space normal (RGBA16F), roughness and metallic
+//(RGBA8), emissive (RGBA16F) and depth
+pub struct GBuffer {
+    pub framebuffer: GLuint,
+    pub albedo: GLuint,
+    pub normal: GLuint,
+    pub material: GLuint,
+    pub emissive: GLuint,
+    pub depth: GLuint,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl GBuffer {
+    const FORMATS: [(GLenum, GLenum, GLenum); 5] = [
+        (GL_RGBA8, GL_RGBA, GL_UNSIGNED_BYTE),
+        (GL_RGBA16F, GL_RGBA, GL_FLOAT),
+        (GL_RGBA8, GL_RGBA, GL_UNSIGNED_BYTE),
+        (GL_RGBA16F, GL_RGBA, GL_FLOAT),
+        //Same as RenderTarget's so it can be copied into one
+        (GL_DEPTH_COMPONENT24, GL_DEPTH_COMPONENT, GL_FLOAT),
+    ];
+
+    pub unsafe fn new(width: i32, height: i32) -> Option<Self> {
+        let mut framebuffer = 0;
+        glGenFramebuffers(1, &mut framebuffer);
+        let mut textures = [0; 5];
+        glGenTextures(5, textures.as_mut_ptr());
+        if framebuffer == 0 || textures.contains(&0) {
+            return None;
+        }
+        let [albedo, normal, material, emissive, depth] = textures;
+        let mut gbuffer = Self {
+            framebuffer,
+            albedo,
+            normal,
+            material,
+            emissive,
+            depth,
+            width: 0,
+            height: 0,
+        };
+        gbuffer.resize(width, height);
+
+        glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+        for (i, &texture) in gbuffer.color_textures().iter().enumerate() {
+            glFramebufferTexture2D(
+                GL_FRAMEBUFFER,
+                GL_COLOR_ATTACHMENT0 + i as GLenum,
+                GL_TEXTURE_2D,
+                texture,
+                0,
+            );
+        }
+        glFramebufferTexture2D(GL_FRAMEBUFFER, GL_DEPTH_ATTACHMENT, GL_TEXTURE_2D, depth, 0);
+        let draw_buffers = [
+            GL_COLOR_ATTACHMENT0,
+            GL_COLOR_ATTACHMENT1,
+            GL_COLOR_ATTACHMENT2,
+            GL_COLOR_ATTACHMENT3,
+        ];
+        glDrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
+        let status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
+        glBindFramebuffer(GL_FRAMEBUFFER, 0);
+        if status != GL_FRAMEBUFFER_COMPLETE {
+            log::error!("G-buffer framebuffer is incomplete: 0x{:x}", status);
+            gbuffer.delete();
+            return None;
+        }
+        Some(gbuffer)
+    }
+
+    //In attachment order
+    pub fn color_textures(&self) -> [GLuint; 4] {
+        [self.albedo, self.normal, self.material, self.emissive]
+    }
+
+    fn textures(&self) -> [GLuint; 5] {
+        [
+            self.albedo,
+            self.normal,
+            self.material,
+            self.emissive,
+            self.depth,
+        ]
+    }
+
+    //Reallocates the textures, their contents are undefined afterwards
+    pub unsafe fn resize(&mut self, width: i32, height: i32) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+        for (texture, (internal_format, format, type_)) in
+            self.textures().into_iter().zip(Self::FORMATS)
+        {
+            gl_state::bind_texture(GL_TEXTURE_2D, texture);
+            glTexImage2D(
+                GL_TEXTURE_2D,
+                0,
+                internal_format as GLint,
+                self.width,
+                self.height,
+                0,
+                format,
+                type_,
+                std::ptr::null(),
+            );
+            //Read one texel per pixel
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_NEAREST as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as GLint);
+        }
+        gl_state::bind_texture(GL_TEXTURE_2D, 0);
+    }
+
+    pub unsafe fn bind(&self) {
+        glBindFramebuffer(GL_FRAMEBUFFER, self.framebuffer);
+        glViewport(0, 0, self.width, self.height);
+    }
+
+    pub unsafe fn set_label(&self, label: &str) {
+        label_object(GL_FRAMEBUFFER, self.framebuffer, label);
+        let names = ["albedo", "normal", "material", "emissive", "depth"];
+        for (texture, name) in self.textures().into_iter().zip(names) {
+            label_object(GL_TEXTURE, texture, &format!("{} {}", label, name));
+        }
+    }
+
+    pub unsafe fn delete(&self) {
+        glDeleteFramebuffers(1, &self.framebuffer);
+        for texture in self.textures() {
+            glDeleteTextures(1, &texture);
+            gl_state::forget_texture(texture);
+        }
+    }
+}
+
+//Lights drawn and skipped by the last DeferredRenderer::draw
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LightStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+//Indices of the lights that can reach into the frustum. Directional lights reach everywhere, point
+//lights are culled by the sphere of their range
+pub fn visible_lights(lights: &[Light], frustum: &Frustum) -> Vec<usize> {
+    lights
+        .iter()
+        .enumerate()
+        .filter(|(_, light)| match **light {
+            Light::Point {
+                position, range, ..
+            } => {
+                frustum.classify_sphere(&BoundingSphere::new(position, range))
+                    != Containment::Outside
+            }
+            Light::Directional { .. } => true,
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+pub struct DeferredRenderer {
+    pub gbuffer: GBuffer,
+    pub light_stats: LightStats,
+    geometry_program: ShaderProgram,
+    light_program: ShaderProgram,
+    light_volume: Mesh,
+    fullscreen_vao: VertexArray,
+}
+
+impl DeferredRenderer {
+    pub unsafe fn new(width: i32, height: i32) -> Option<Self> {
+        let gbuffer = GBuffer::new(width, height)?;
+        gbuffer.set_label("G-buffer");
+
+        let mut geometry_program = ShaderProgramBuilder::new()
+            .create_shader(
+                ShaderType::Vertex,
+                &shader_from_file(Path::new("assets/shaders/vertex_shader.vert")),
+            )
+            .create_shader(
+                ShaderType::Fragment,
+                &shader_from_file(Path::new("assets/shaders/gbuffer.frag")),
+            )
+            .link()?;
+        geometry_program.set_label("G-buffer");
+        //Everything Scene::draw sets and the material maps, same names as the forward shader
+        for uniform in [
+            cstr!("M"),
+            cstr!("N"),
+            cstr!("V"),
+            cstr!("P"),
+            cstr!("camera_pos"),
+            cstr!("instanced"),
+            cstr!("albedo"),
+            cstr!("roughness"),
+            cstr!("metallic"),
+            cstr!("alpha"),
+            cstr!("alpha_cutoff"),
+            cstr!("emissive"),
+            cstr!("diffuse_map"),
+            cstr!("roughness_map"),
+            cstr!("normal_map"),
+            cstr!("metallic_map"),
+        ] {
+            geometry_program.create_uniform(uniform);
+        }
+
+        let mut light_program = ShaderProgramBuilder::new()
+            .create_shader(
+                ShaderType::Vertex,
+                &shader_from_file(Path::new("assets/shaders/deferred_light.vert")),
+            )
+            .create_shader(
+                ShaderType::Fragment,
+                &shader_from_file(Path::new("assets/shaders/deferred_light.frag")),
+            )
+            .link()?;
+        light_program.set_label("Deferred lighting");
+        for uniform in [
+            cstr!("M"),
+            cstr!("V"),
+            cstr!("P"),
+            cstr!("fullscreen"),
+            cstr!("albedo_texture"),
+            cstr!("normal_texture"),
+            cstr!("material_texture"),
+            cstr!("emissive_texture"),
+            cstr!("depth_texture"),
+            cstr!("inverse_view_projection"),
+            cstr!("camera_pos"),
+            cstr!("light_position"),
+            cstr!("light_color"),
+            cstr!("ambient"),
+            cstr!("ao_map"),
+            cstr!("ssao_enabled"),
+        ] {
+            light_program.create_uniform(uniform);
+        }
+
+        let mut light_volume = Mesh::icosphere(1.0, 1);
+        light_volume.setup();
+        light_volume.set_label("Light volume");
+
+        Some(Self {
+            gbuffer,
+            light_stats: LightStats::default(),
+            geometry_program,
+            light_program,
+            light_volume,
+            fullscreen_vao: VertexArray::new()?,
+        })
+    }
+
+    //Draws the G-buffer, it needs the same material texture units as the forward shader set on it
+    pub fn geometry_program(&self) -> &ShaderProgram {
+        &self.geometry_program
+    }
+
+    pub unsafe fn resize(&mut self, width: i32, height: i32) {
+        self.gbuffer.resize(width, height);
+    }
+
+    //Renders the scene into the stack's scene target, call between its begin and end. The G-buffer isn't
+    //multisampled, so MSAA is turned off if it's on (end would otherwise resolve the empty multisampled
+    //target over the result). Transparent objects are drawn on top with forward_program, which needs its
+    //uniforms set up as for Scene::draw. ssao darkens the ambient term like in the forward shader
+    pub unsafe fn draw(
+        &mut self,
+        scene: &mut Scene,
+        camera: &Camera,
+        stack: &mut PostStack,
+        forward_program: &ShaderProgram,
+        ssao: Option<&Ssao>,
+    ) {
+        let _group = DebugGroup::new("Deferred");
+        if stack.msaa_samples() > 1 {
+            log::warn!("MSAA doesn't work with deferred shading, turning it off");
+            stack.set_msaa_samples(0);
+            //begin cleared the multisampled target instead
+            stack.scene_target().bind();
+            glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+        }
+        let target = stack.scene_target();
+        if (self.gbuffer.width, self.gbuffer.height) != (target.width, target.height) {
+            self.gbuffer.resize(target.width, target.height);
+        }
+
+        {
+            let _group = DebugGroup::new("Geometry");
+            self.gbuffer.bind();
+            gl_state::enable(GL_DEPTH_TEST);
+            gl_state::depth_mask(true);
+            gl_state::disable(GL_BLEND);
+            glClear(GL_DEPTH_BUFFER_BIT);
+            scene.draw_opaque(&self.geometry_program, camera);
+        }
+
+        //The target gets the scene's depth for the light volumes' depth test and transparent objects
+        if target.depth_texture.is_some() {
+            glBindFramebuffer(GL_READ_FRAMEBUFFER, self.gbuffer.framebuffer);
+            glBindFramebuffer(GL_DRAW_FRAMEBUFFER, target.framebuffer);
+            glBlitFramebuffer(
+                0,
+                0,
+                self.gbuffer.width,
+                self.gbuffer.height,
+                0,
+                0,
+                target.width,
+                target.height,
+                GL_DEPTH_BUFFER_BIT,
+                GL_NEAREST,
+            );
+        }
+        target.bind();
+
+        let program = &self.light_program;
+        let view_projection = camera.get_projection_matrix() * camera.get_view_matrix();
+        gl_state::use_program(program.0);
+        let samplers = [
+            "albedo_texture",
+            "normal_texture",
+            "material_texture",
+            "emissive_texture",
+            "depth_texture",
+        ];
+        for (i, (texture, sampler)) in self
+            .gbuffer
+            .textures()
+            .into_iter()
+            .zip(samplers)
+            .enumerate()
+        {
+            gl_state::bind_texture_unit(GBUFFER_UNIT + i as GLenum, GL_TEXTURE_2D, texture);
+            program.set_int(sampler, (GBUFFER_UNIT - GL_TEXTURE0) as i32 + i as i32);
+        }
+        Scene::set_camera_uniforms(program, camera);
+        program.set_mat4(
+            "inverse_view_projection",
+            &view_projection.invert().unwrap_or_else(Matrix4::identity),
+        );
+        gl_state::disable(GL_DEPTH_TEST);
+        gl_state::depth_mask(false);
+
+        {
+            let _group = DebugGroup::new("Ambient");
+            match ssao {
+                Some(ssao) => ssao.bind(program, AO_UNIT),
+                None => program.set_int("ssao_enabled", 0),
+            }
+            program.set_int("ambient", 1);
+            program.set_int("fullscreen", 1);
+            self.draw_fullscreen();
+            program.set_int("ambient", 0);
+        }
+
+        {
+            let _group = DebugGroup::new("Lights");
+            gl_state::enable(GL_BLEND);
+            gl_state::blend_func(GL_ONE, GL_ONE);
+            //Back faces behind the surface: lights the pixels whose surface is inside the volume, and
+            //still works with the camera inside it
+            glDepthFunc(GL_GEQUAL);
+            gl_state::cull_face(GL_FRONT);
+            //Lights that reach into the frustum but not any object have nothing to shade
+            scene.assign_lights();
+            let mut visible = visible_lights(&scene.lights, &Frustum::from_camera(camera));
+            visible.retain(|&index| scene.light_reach(index) > 0);
+            self.light_stats = LightStats {
+                drawn: visible.len(),
+                culled: scene.lights.len() - visible.len(),
+            };
+            for index in visible {
+                let light = &scene.lights[index];
+                let (position, color) = light.packed();
+                program.set_vec4("light_position", &position);
+                program.set_vec4("light_color", &color);
+                match *light {
+                    Light::Point {
+                        position, range, ..
+                    } => {
+                        gl_state::enable(GL_DEPTH_TEST);
+                        gl_state::enable(GL_CULL_FACE);
+                        program.set_int("fullscreen", 0);
+                        program.set_mat4(
+                            "M",
+                            &(Matrix4::from_translation(position)
+                                * Matrix4::from_scale(range * VOLUME_SCALE)),
+                        );
+                        self.light_volume.draw();
+                    }
+                    Light::Directional { .. } => {
+                        gl_state::disable(GL_DEPTH_TEST);
+                        gl_state::disable(GL_CULL_FACE);
+                        program.set_int("fullscreen", 1);
+                        self.draw_fullscreen();
+                    }
+                }
+            }
+            glDepthFunc(GL_LESS);
+            gl_state::cull_face(GL_BACK);
+            gl_state::disable(GL_CULL_FACE);
+            gl_state::disable(GL_BLEND);
+        }
+
+        gl_state::enable(GL_DEPTH_TEST);
+        gl_state::depth_mask(true);
+        scene.draw_transparent(forward_program, camera);
+    }
+
+    unsafe fn draw_fullscreen(&self) {
+        self.fullscreen_vao.bind();
+        glDrawArrays(GL_TRIANGLES, 0, 3);
+        gl_state::draw_call();
+    }
+
+    pub unsafe fn delete(&self) {
+        self.gbuffer.delete();
+    }
+}
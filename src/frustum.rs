@@ -0,0 +1,244 @@
+use crate::debug_output::DebugGroup;
+use crate::gl_state;
+use crate::{
+    shader_from_file, Aabb, BoundingSphere, Buffer, Camera, ShaderProgram, ShaderProgramBuilder,
+    ShaderType, VertexArray,
+};
+use cgmath::{InnerSpace, Matrix, Matrix4, SquareMatrix, Vector3, Vector4};
+use cstr::cstr;
+use ogl33::*;
+
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Containment {
+    Outside,
+    Intersecting,
+    Inside,
+}
+
+//Six planes (left, right, bottom, top, near, far) as (normal, distance) with normals pointing inwards,
+//a point p is inside a plane when dot(normal, p) + distance >= 0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+    //World space corners, near plane first, for drawing the frustum when it's frozen (see FrustumLines).
+    //Bit 0 of the index picks the right side, bit 1 the top and bit 2 the far plane
+    pub corners: [Vector3<f32>; 8],
+}
+
+//Corner index pairs of the 12 edges: the near rectangle, the far rectangle, then near to far
+pub const FRUSTUM_EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [1, 3],
+    [3, 2],
+    [2, 0],
+    [4, 5],
+    [5, 7],
+    [7, 6],
+    [6, 4],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+impl Frustum {
+    //Gribb/Hartmann plane extraction from a projection * view matrix (GL clip space, z in [-w, w])
+    pub fn from_matrix(view_projection: &Matrix4<f32>) -> Self {
+        let rows = [
+            view_projection.row(0),
+            view_projection.row(1),
+            view_projection.row(2),
+            view_projection.row(3),
+        ];
+        let planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[3] + rows[2],
+            rows[3] - rows[2],
+        ]
+        .map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > 0.0 {
+                plane / length
+            } else {
+                plane
+            }
+        });
+
+        let inverse = view_projection.invert().unwrap_or_else(Matrix4::identity);
+        let mut corners = [Vector3::new(0.0, 0.0, 0.0); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let ndc = Vector4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+                1.0,
+            );
+            let world = inverse * ndc;
+            *corner = world.truncate() / world.w;
+        }
+
+        Self { planes, corners }
+    }
+
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self::from_matrix(&(camera.get_projection_matrix() * camera.get_view_matrix()))
+    }
+
+    //Both end points of every edge, for drawing with GL_LINES
+    pub fn edge_vertices(&self) -> Vec<Vector3<f32>> {
+        FRUSTUM_EDGES
+            .iter()
+            .flat_map(|edge| edge.map(|corner| self.corners[corner]))
+            .collect()
+    }
+
+    fn distance(plane: &Vector4<f32>, point: Vector3<f32>) -> f32 {
+        plane.truncate().dot(point) + plane.w
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Self::distance(plane, point) >= 0.0)
+    }
+
+    pub fn classify_sphere(&self, sphere: &BoundingSphere) -> Containment {
+        let mut result = Containment::Inside;
+        for plane in self.planes.iter() {
+            let distance = Self::distance(plane, sphere.center);
+            if distance < -sphere.radius {
+                return Containment::Outside;
+            }
+            if distance < sphere.radius {
+                result = Containment::Intersecting;
+            }
+        }
+        result
+    }
+
+    //Tests the box corner furthest along each plane normal (and the nearest one for full containment).
+    //Conservative: boxes near the frustum's edges can be reported as intersecting while outside
+    pub fn classify_aabb(&self, aabb: &Aabb) -> Containment {
+        if aabb.is_empty() {
+            return Containment::Outside;
+        }
+        let mut result = Containment::Inside;
+        for plane in self.planes.iter() {
+            let pick = |positive: bool, min: f32, max: f32| if positive { max } else { min };
+            let furthest = Vector3::new(
+                pick(plane.x >= 0.0, aabb.min.x, aabb.max.x),
+                pick(plane.y >= 0.0, aabb.min.y, aabb.max.y),
+                pick(plane.z >= 0.0, aabb.min.z, aabb.max.z),
+            );
+            if Self::distance(plane, furthest) < 0.0 {
+                return Containment::Outside;
+            }
+            let nearest = Vector3::new(
+                pick(plane.x < 0.0, aabb.min.x, aabb.max.x),
+                pick(plane.y < 0.0, aabb.min.y, aabb.max.y),
+                pick(plane.z < 0.0, aabb.min.z, aabb.max.z),
+            );
+            if Self::distance(plane, nearest) < 0.0 {
+                result = Containment::Intersecting;
+            }
+        }
+        result
+    }
+
+    //Sphere first since it's cheaper, the box only decides the cases the sphere can't
+    pub fn intersects(&self, aabb: &Aabb, sphere: &BoundingSphere) -> bool {
+        match self.classify_sphere(sphere) {
+            Containment::Outside => false,
+            Containment::Inside => true,
+            Containment::Intersecting => self.classify_aabb(aabb) != Containment::Outside,
+        }
+    }
+}
+
+//What the last culling pass did, triangles are counted for the meshes as drawn (after LOD selection)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub tested: usize, //Objects whose bounds were tested, with a BVH only those in nodes reaching into the frustum
+    pub visible: usize,
+    pub culled: usize,
+    pub visible_triangles: usize,
+    pub culled_triangles: usize,
+}
+
+//Draws a frustum's edges as lines, to look at a frozen culling frustum from outside
+pub struct FrustumLines {
+    vao: VertexArray,
+    vbo: Buffer,
+    program: ShaderProgram,
+}
+
+impl FrustumLines {
+    pub unsafe fn new() -> Option<Self> {
+        let vao = VertexArray::new()?;
+        let vbo = Buffer::new(GL_DYNAMIC_DRAW, GL_ARRAY_BUFFER)?;
+        vao.bind();
+        vbo.bind();
+        glVertexAttribPointer(
+            0,
+            3,
+            GL_FLOAT,
+            GL_FALSE,
+            std::mem::size_of::<[f32; 3]>() as GLsizei,
+            std::ptr::null(),
+        );
+        glEnableVertexAttribArray(0);
+        vao.unbind();
+        vbo.unbind();
+        vao.set_label("Frustum lines");
+
+        let mut program = ShaderProgramBuilder::new()
+            .create_shader(
+                ShaderType::Vertex,
+                &shader_from_file(Path::new("assets/shaders/lines.vert")),
+            )
+            .create_shader(
+                ShaderType::Fragment,
+                &shader_from_file(Path::new("assets/shaders/lines.frag")),
+            )
+            .link()?;
+        program.set_label("Frustum lines");
+        for uniform in [cstr!("V"), cstr!("P"), cstr!("color")] {
+            program.create_uniform(uniform);
+        }
+        Some(Self { vao, vbo, program })
+    }
+
+    //Draws into the bound framebuffer, depth tested against the scene
+    pub unsafe fn draw(&self, frustum: &Frustum, camera: &Camera, color: Vector3<f32>) {
+        let _group = DebugGroup::new("Frustum lines");
+        let vertices: Vec<[f32; 3]> = frustum
+            .edge_vertices()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        self.vbo.bind();
+        self.vbo.set_data(bytemuck::cast_slice(&vertices));
+        self.vbo.unbind();
+
+        gl_state::use_program(self.program.0);
+        self.program.set_mat4("V", &camera.get_view_matrix());
+        self.program.set_mat4("P", &camera.get_projection_matrix());
+        self.program.set_vec3("color", &color);
+        self.vao.bind();
+        glDrawArrays(GL_LINES, 0, vertices.len() as GLsizei);
+        gl_state::draw_call();
+        self.vao.unbind();
+    }
+
+    pub unsafe fn delete(&self) {
+        self.vao.delete();
+        glDeleteBuffers(1, &self.vbo.0);
+        gl_state::forget_buffer(self.vbo.0);
+        self.program.delete();
+    }
+}
@@ -0,0 +1,89 @@
+//Loader for the GL 4.x entry points that ogl33 doesn't provide (it only covers 3.3 core).
+//Call load_gl_extensions_with after load_gl_with, using the same proc address lookup.
+#![allow(non_snake_case)]
+#![allow(non_upper_case_globals)]
+
+use ogl33::*;
+use std::sync::atomic::Ordering;
+
+pub const GL_PROGRAM_BINARY_RETRIEVABLE_HINT: GLenum = 0x8257;
+pub const GL_PROGRAM_BINARY_LENGTH: GLenum = 0x8741;
+pub const GL_NUM_PROGRAM_BINARY_FORMATS: GLenum = 0x87FE;
+
+//KHR_debug
+pub const GL_DEBUG_OUTPUT: GLenum = 0x92E0;
+pub const GL_DEBUG_OUTPUT_SYNCHRONOUS: GLenum = 0x8242;
+pub const GL_CONTEXT_FLAG_DEBUG_BIT: GLint = 0x00000002;
+pub const GL_DEBUG_SOURCE_API: GLenum = 0x8246;
+pub const GL_DEBUG_SOURCE_WINDOW_SYSTEM: GLenum = 0x8247;
+pub const GL_DEBUG_SOURCE_SHADER_COMPILER: GLenum = 0x8248;
+pub const GL_DEBUG_SOURCE_THIRD_PARTY: GLenum = 0x8249;
+pub const GL_DEBUG_SOURCE_APPLICATION: GLenum = 0x824A;
+pub const GL_DEBUG_SOURCE_OTHER: GLenum = 0x824B;
+pub const GL_DEBUG_TYPE_ERROR: GLenum = 0x824C;
+pub const GL_DEBUG_TYPE_DEPRECATED_BEHAVIOR: GLenum = 0x824D;
+pub const GL_DEBUG_TYPE_UNDEFINED_BEHAVIOR: GLenum = 0x824E;
+pub const GL_DEBUG_TYPE_PORTABILITY: GLenum = 0x824F;
+pub const GL_DEBUG_TYPE_PERFORMANCE: GLenum = 0x8250;
+pub const GL_DEBUG_TYPE_OTHER: GLenum = 0x8251;
+pub const GL_DEBUG_TYPE_MARKER: GLenum = 0x8268;
+pub const GL_DEBUG_TYPE_PUSH_GROUP: GLenum = 0x8269;
+pub const GL_DEBUG_TYPE_POP_GROUP: GLenum = 0x826A;
+pub const GL_DEBUG_SEVERITY_HIGH: GLenum = 0x9146;
+pub const GL_DEBUG_SEVERITY_MEDIUM: GLenum = 0x9147;
+pub const GL_DEBUG_SEVERITY_LOW: GLenum = 0x9148;
+pub const GL_DEBUG_SEVERITY_NOTIFICATION: GLenum = 0x826B;
+pub const GL_BUFFER: GLenum = 0x82E0;
+pub const GL_SHADER: GLenum = 0x82E1;
+pub const GL_PROGRAM: GLenum = 0x82E2;
+pub const GL_VERTEX_ARRAY: GLenum = 0x8074;
+
+macro_rules! gl_extensions {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*) => {
+        mod storage {
+            use std::sync::atomic::AtomicPtr;
+            use ogl33::c_void;
+            $(pub static $name: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());)*
+        }
+
+        //Loads every extension function, entries the driver doesn't expose are left null
+        pub unsafe fn load_gl_extensions_with<F>(mut load_fn: F)
+        where
+            F: FnMut(*const c_char) -> *const c_void,
+        {
+            $(
+                let p = load_fn(concat!(stringify!($name), "\0").as_ptr().cast());
+                storage::$name.store(p as *mut c_void, Ordering::Relaxed);
+            )*
+        }
+
+        //is_loaded::glFoo() reports whether glFoo can be called
+        pub mod is_loaded {
+            use std::sync::atomic::Ordering;
+            $(pub fn $name() -> bool {
+                !super::storage::$name.load(Ordering::Relaxed).is_null()
+            })*
+        }
+
+        $(
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                let p = storage::$name.load(Ordering::Relaxed);
+                if p.is_null() {
+                    panic!(concat!(stringify!($name), " not loaded"));
+                }
+                std::mem::transmute::<*mut c_void, extern "system" fn($($ty),*) $(-> $ret)?>(p)($($arg),*)
+            }
+        )*
+    };
+}
+
+gl_extensions! {
+    fn glGetProgramBinary(program: GLuint, buf_size: GLsizei, length: *mut GLsizei, binary_format: *mut GLenum, binary: *mut c_void);
+    fn glProgramBinary(program: GLuint, binary_format: GLenum, binary: *const c_void, length: GLsizei);
+    fn glProgramParameteri(program: GLuint, pname: GLenum, value: GLint);
+    fn glDebugMessageCallback(callback: GLDEBUGPROC, user_param: *const c_void);
+    fn glDebugMessageControl(source: GLenum, type_: GLenum, severity: GLenum, count: GLsizei, ids: *const GLuint, enabled: GLboolean);
+    fn glObjectLabel(identifier: GLenum, name: GLuint, length: GLsizei, label: *const GLchar);
+    fn glPushDebugGroup(source: GLenum, id: GLuint, length: GLsizei, message: *const GLchar);
+    fn glPopDebugGroup();
+}
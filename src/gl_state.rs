@@ -0,0 +1,391 @@
+//Shadow copy of the GL binding and fixed function state of the context current on this thread, so binds
+//that wouldn't change anything are skipped. Everything that binds programs, VAOs, buffers or textures
+//should go through here; after calling into code that doesn't, call invalidate
+use ogl33::*;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+
+//Counters since the last end_frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GlStats {
+    pub draw_calls: usize,
+    pub program_binds: usize,
+    pub vertex_array_binds: usize,
+    pub buffer_binds: usize,
+    pub texture_binds: usize,
+    //Capabilities, depth mask, blend function, cull face and active texture unit changes
+    pub state_changes: usize,
+    //Calls the cache made unnecessary
+    pub skipped: usize,
+}
+
+//The cached state itself. Every setter returns whether the GL call has to be made, None fields are
+//unknown and never skipped
+#[derive(Clone, Debug, Default)]
+pub struct GlState {
+    program: Option<GLuint>,
+    vertex_array: Option<GLuint>,
+    buffers: HashMap<GLenum, GLuint>,
+    active_texture: Option<GLenum>,
+    //(unit, target) -> texture
+    textures: HashMap<(GLenum, GLenum), GLuint>,
+    capabilities: HashMap<GLenum, bool>,
+    depth_mask: Option<bool>,
+    blend_func: Option<(GLenum, GLenum)>,
+    cull_face: Option<GLenum>,
+    pub stats: GlStats,
+}
+
+impl GlState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Forgets everything, the next call of each kind goes through
+    pub fn invalidate(&mut self) {
+        *self = Self {
+            stats: self.stats,
+            ..Self::default()
+        };
+    }
+
+    fn changed<T: PartialEq>(cached: &mut Option<T>, value: T, skipped: &mut usize) -> bool {
+        if cached.as_ref() == Some(&value) {
+            *skipped += 1;
+            false
+        } else {
+            *cached = Some(value);
+            true
+        }
+    }
+
+    pub fn set_program(&mut self, program: GLuint) -> bool {
+        let changed = Self::changed(&mut self.program, program, &mut self.stats.skipped);
+        self.stats.program_binds += changed as usize;
+        changed
+    }
+
+    pub fn set_vertex_array(&mut self, vertex_array: GLuint) -> bool {
+        let changed = Self::changed(
+            &mut self.vertex_array,
+            vertex_array,
+            &mut self.stats.skipped,
+        );
+        if changed {
+            //The element buffer binding is part of the VAO
+            self.buffers.remove(&GL_ELEMENT_ARRAY_BUFFER);
+            self.stats.vertex_array_binds += 1;
+        }
+        changed
+    }
+
+    pub fn set_buffer(&mut self, target: GLenum, buffer: GLuint) -> bool {
+        let mut cached = self.buffers.get(&target).copied();
+        let changed = Self::changed(&mut cached, buffer, &mut self.stats.skipped);
+        if changed {
+            self.buffers.insert(target, buffer);
+            self.stats.buffer_binds += 1;
+        }
+        changed
+    }
+
+    //Unit is the enum, GL_TEXTURE0 + n
+    pub fn set_active_texture(&mut self, unit: GLenum) -> bool {
+        let changed = Self::changed(&mut self.active_texture, unit, &mut self.stats.skipped);
+        self.stats.state_changes += changed as usize;
+        changed
+    }
+
+    //Binds to the active unit, an unknown active unit makes every texture bind go through
+    pub fn set_texture(&mut self, target: GLenum, texture: GLuint) -> bool {
+        let unit = match self.active_texture {
+            Some(unit) => unit,
+            None => {
+                self.stats.texture_binds += 1;
+                return true;
+            }
+        };
+        let mut cached = self.textures.get(&(unit, target)).copied();
+        let changed = Self::changed(&mut cached, texture, &mut self.stats.skipped);
+        if changed {
+            self.textures.insert((unit, target), texture);
+            self.stats.texture_binds += 1;
+        }
+        changed
+    }
+
+    //glEnable/glDisable state such as GL_BLEND, GL_DEPTH_TEST or GL_CULL_FACE
+    pub fn set_capability(&mut self, capability: GLenum, enabled: bool) -> bool {
+        let mut cached = self.capabilities.get(&capability).copied();
+        let changed = Self::changed(&mut cached, enabled, &mut self.stats.skipped);
+        if changed {
+            self.capabilities.insert(capability, enabled);
+            self.stats.state_changes += 1;
+        }
+        changed
+    }
+
+    pub fn set_depth_mask(&mut self, write: bool) -> bool {
+        let changed = Self::changed(&mut self.depth_mask, write, &mut self.stats.skipped);
+        self.stats.state_changes += changed as usize;
+        changed
+    }
+
+    pub fn set_blend_func(&mut self, source: GLenum, destination: GLenum) -> bool {
+        let changed = Self::changed(
+            &mut self.blend_func,
+            (source, destination),
+            &mut self.stats.skipped,
+        );
+        self.stats.state_changes += changed as usize;
+        changed
+    }
+
+    pub fn set_cull_face(&mut self, face: GLenum) -> bool {
+        let changed = Self::changed(&mut self.cull_face, face, &mut self.stats.skipped);
+        self.stats.state_changes += changed as usize;
+        changed
+    }
+
+    //Deleting a bound object unbinds it, and its name can be handed out again
+    pub fn forget_texture(&mut self, texture: GLuint) {
+        self.textures.retain(|_, bound| *bound != texture);
+    }
+
+    pub fn forget_buffer(&mut self, buffer: GLuint) {
+        self.buffers.retain(|_, bound| *bound != buffer);
+    }
+
+    pub fn forget_program(&mut self, program: GLuint) {
+        if self.program == Some(program) {
+            self.program = None;
+        }
+    }
+
+    pub fn forget_vertex_array(&mut self, vertex_array: GLuint) {
+        if self.vertex_array == Some(vertex_array) {
+            self.vertex_array = None;
+        }
+    }
+
+    pub fn draw_call(&mut self) {
+        self.stats.draw_calls += 1;
+    }
+
+    //Returns the counters of the frame that just ended and starts counting from zero
+    pub fn end_frame(&mut self) -> GlStats {
+        std::mem::take(&mut self.stats)
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<GlState> = RefCell::new(GlState::new());
+    static VERIFY: Cell<bool> = const { Cell::new(false) };
+}
+
+fn with_state<R>(f: impl FnOnce(&mut GlState) -> R) -> R {
+    STATE.with(|state| f(&mut state.borrow_mut()))
+}
+
+//When enabled every skipped call first checks the cached value against glGet*, a mismatch is logged
+//and the call made anyway. Meant for tracking down code that changes state behind the cache's back
+pub fn set_verify(verify: bool) {
+    VERIFY.with(|v| v.set(verify));
+}
+
+pub fn is_verifying() -> bool {
+    VERIFY.with(|v| v.get())
+}
+
+//Skips the call if the cache says it's redundant (and verification, if on, agrees)
+unsafe fn apply(
+    changed: bool,
+    what: &str,
+    actual: impl FnOnce() -> Option<bool>,
+    call: impl FnOnce(),
+) {
+    if changed {
+        call();
+    } else if is_verifying() && actual() == Some(false) {
+        log::error!(
+            "GL state cache out of sync: {} was changed outside gl_state",
+            what
+        );
+        call();
+    }
+}
+
+unsafe fn get_integer(name: GLenum) -> GLint {
+    let mut value = 0;
+    glGetIntegerv(name, &mut value);
+    value
+}
+
+fn buffer_binding(target: GLenum) -> Option<GLenum> {
+    match target {
+        GL_ARRAY_BUFFER => Some(GL_ARRAY_BUFFER_BINDING),
+        GL_ELEMENT_ARRAY_BUFFER => Some(GL_ELEMENT_ARRAY_BUFFER_BINDING),
+        GL_UNIFORM_BUFFER => Some(GL_UNIFORM_BUFFER_BINDING),
+        //The copy targets double as their binding queries
+        GL_COPY_READ_BUFFER | GL_COPY_WRITE_BUFFER => Some(target),
+        GL_PIXEL_PACK_BUFFER => Some(GL_PIXEL_PACK_BUFFER_BINDING),
+        GL_PIXEL_UNPACK_BUFFER => Some(GL_PIXEL_UNPACK_BUFFER_BINDING),
+        GL_TEXTURE_BUFFER => Some(GL_TEXTURE_BINDING_BUFFER),
+        _ => None,
+    }
+}
+
+fn texture_binding(target: GLenum) -> Option<GLenum> {
+    match target {
+        GL_TEXTURE_1D => Some(GL_TEXTURE_BINDING_1D),
+        GL_TEXTURE_2D => Some(GL_TEXTURE_BINDING_2D),
+        GL_TEXTURE_3D => Some(GL_TEXTURE_BINDING_3D),
+        GL_TEXTURE_2D_ARRAY => Some(GL_TEXTURE_BINDING_2D_ARRAY),
+        GL_TEXTURE_CUBE_MAP => Some(GL_TEXTURE_BINDING_CUBE_MAP),
+        GL_TEXTURE_2D_MULTISAMPLE => Some(GL_TEXTURE_BINDING_2D_MULTISAMPLE),
+        _ => None,
+    }
+}
+
+pub unsafe fn use_program(program: GLuint) {
+    apply(
+        with_state(|state| state.set_program(program)),
+        "program",
+        || Some(get_integer(GL_CURRENT_PROGRAM) as GLuint == program),
+        || glUseProgram(program),
+    );
+}
+
+pub unsafe fn bind_vertex_array(vertex_array: GLuint) {
+    apply(
+        with_state(|state| state.set_vertex_array(vertex_array)),
+        "vertex array binding",
+        || Some(get_integer(GL_VERTEX_ARRAY_BINDING) as GLuint == vertex_array),
+        || glBindVertexArray(vertex_array),
+    );
+}
+
+pub unsafe fn bind_buffer(target: GLenum, buffer: GLuint) {
+    apply(
+        with_state(|state| state.set_buffer(target, buffer)),
+        "buffer binding",
+        || buffer_binding(target).map(|binding| get_integer(binding) as GLuint == buffer),
+        || glBindBuffer(target, buffer),
+    );
+}
+
+//Unit is the enum, GL_TEXTURE0 + n
+pub unsafe fn active_texture(unit: GLenum) {
+    apply(
+        with_state(|state| state.set_active_texture(unit)),
+        "active texture unit",
+        || Some(get_integer(GL_ACTIVE_TEXTURE) as GLenum == unit),
+        || glActiveTexture(unit),
+    );
+}
+
+//Binds to the active texture unit
+pub unsafe fn bind_texture(target: GLenum, texture: GLuint) {
+    apply(
+        with_state(|state| state.set_texture(target, texture)),
+        "texture binding",
+        || texture_binding(target).map(|binding| get_integer(binding) as GLuint == texture),
+        || glBindTexture(target, texture),
+    );
+}
+
+pub unsafe fn bind_texture_unit(unit: GLenum, target: GLenum, texture: GLuint) {
+    active_texture(unit);
+    bind_texture(target, texture);
+}
+
+pub unsafe fn set_enabled(capability: GLenum, enabled: bool) {
+    apply(
+        with_state(|state| state.set_capability(capability, enabled)),
+        "capability",
+        || Some((glIsEnabled(capability) == GL_TRUE) == enabled),
+        || {
+            if enabled {
+                glEnable(capability)
+            } else {
+                glDisable(capability)
+            }
+        },
+    );
+}
+
+pub unsafe fn enable(capability: GLenum) {
+    set_enabled(capability, true);
+}
+
+pub unsafe fn disable(capability: GLenum) {
+    set_enabled(capability, false);
+}
+
+pub unsafe fn depth_mask(write: bool) {
+    apply(
+        with_state(|state| state.set_depth_mask(write)),
+        "depth mask",
+        || Some((get_integer(GL_DEPTH_WRITEMASK) == GL_TRUE as GLint) == write),
+        || glDepthMask(if write { GL_TRUE } else { GL_FALSE }),
+    );
+}
+
+pub unsafe fn blend_func(source: GLenum, destination: GLenum) {
+    apply(
+        with_state(|state| state.set_blend_func(source, destination)),
+        "blend function",
+        || {
+            Some(
+                get_integer(GL_BLEND_SRC_RGB) as GLenum == source
+                    && get_integer(GL_BLEND_DST_RGB) as GLenum == destination,
+            )
+        },
+        || glBlendFunc(source, destination),
+    );
+}
+
+pub unsafe fn cull_face(face: GLenum) {
+    apply(
+        with_state(|state| state.set_cull_face(face)),
+        "cull face",
+        || Some(get_integer(GL_CULL_FACE_MODE) as GLenum == face),
+        || glCullFace(face),
+    );
+}
+
+pub fn forget_texture(texture: GLuint) {
+    with_state(|state| state.forget_texture(texture));
+}
+
+pub fn forget_buffer(buffer: GLuint) {
+    with_state(|state| state.forget_buffer(buffer));
+}
+
+pub fn forget_program(program: GLuint) {
+    with_state(|state| state.forget_program(program));
+}
+
+pub fn forget_vertex_array(vertex_array: GLuint) {
+    with_state(|state| state.forget_vertex_array(vertex_array));
+}
+
+//For GL code that bypasses the cache (other libraries, raw calls), makes no assumptions afterwards
+pub fn invalidate() {
+    with_state(|state| state.invalidate());
+}
+
+pub fn draw_call() {
+    with_state(|state| state.draw_call());
+}
+
+pub fn stats() -> GlStats {
+    with_state(|state| state.stats)
+}
+
+pub fn end_frame() -> GlStats {
+    with_state(|state| state.end_frame())
+}
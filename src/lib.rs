@@ -1,15 +1,72 @@
+//Nearly every GL wrapper is unsafe for the same reason: it needs a current context with the functions loaded
+#![allow(clippy::missing_safety_doc)]
+
 use bytemuck::*;
 use cgmath::{
-    Deg, InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation3, SquareMatrix, Vector2, Vector3,
+    Deg, InnerSpace, Matrix, Matrix3, Matrix4, Point3, Quaternion, Rad, Rotation3, SquareMatrix, Vector2, Vector3,
+    Vector4,
 };
 use ogl33::*;
+use serde::{Deserialize, Serialize};
 use stb_image::image::{LoadResult, Image};
 
 use std::{
     collections::HashMap,
     ffi::{CStr, CString},
+    rc::Rc,
+};
+use std::{fs::File, io::prelude::*, path::Path, path::PathBuf};
+
+pub mod anti_aliasing;
+pub mod arena;
+pub mod bloom;
+pub mod bounds;
+pub mod bvh;
+pub mod debug_output;
+pub mod deferred;
+pub mod frustum;
+pub mod gl_ext;
+pub mod gl_state;
+pub mod lod;
+pub mod logging;
+pub mod mesh_processing;
+pub mod picking;
+pub mod post_process;
+pub mod primitives;
+pub mod scene_file;
+pub mod scene_graph;
+pub mod program_cache;
+pub mod render_queue;
+pub mod sampling;
+pub mod simplify;
+pub mod ssao;
+pub mod vertex_format;
+
+pub use anti_aliasing::{AntiAliasing, Fxaa, Taa};
+pub use arena::{Arena, Handle};
+pub use bloom::Bloom;
+pub use bounds::{Aabb, BoundingSphere, Obb, Ray};
+pub use bvh::{Bvh, BvhNode, BvhNodeKind};
+pub use debug_output::*;
+pub use deferred::{DeferredRenderer, GBuffer, LightStats, RenderPath};
+pub use frustum::{Containment, CullStats, Frustum, FrustumLines, FRUSTUM_EDGES};
+pub use gl_ext::load_gl_extensions_with;
+pub use gl_state::{GlState, GlStats};
+pub use lod::{LodChain, LodLevel};
+pub use logging::{init_logging, TraceLogger};
+pub use mesh_processing::{MeshStats, VERTEX_CACHE_SIZE};
+pub use picking::{IdBuffer, MeshHit, RayHit};
+pub use post_process::{
+    ColorGrading, FilmGrain, LutData, LutError, MultisampleTarget, PostContext, PostEffect,
+    PostPass, PostStack, RenderTarget, ToneMapOperator, ToneMapping, Vignette,
 };
-use std::{fs::File, io::prelude::*, path::Path};
+pub use program_cache::ProgramCache;
+pub use render_queue::{BlendMode, DrawItem, QueueStats, RenderQueue};
+pub use scene_file::{MeshSource, MissingAsset, ObjectDescription, SceneDescription, SceneFileError};
+pub use simplify::SimplifiedMesh;
+pub use ssao::Ssao;
+pub use vertex_format::*;
+
 //Wrapper for opengl textures
 #[derive(Clone)]
 pub struct Texture2D<T>{
@@ -31,14 +88,14 @@ impl Texture2D<u8> {
                 panic!("Tried to load F32 image as U8");
             }
             LoadResult::Error(error) => {
-                println!("Error loading image: {}", error);
+                log::error!("Error loading image: {}", error);
                 return None;
             }
         }
 
         let mut texture = 0;
         glGenTextures(1, &mut texture);
-        glActiveTexture(texture_unit);
+        gl_state::active_texture(texture_unit);
         if texture == 0 {
             None
         } else {
@@ -47,11 +104,16 @@ impl Texture2D<u8> {
     }
 
     pub unsafe fn bind(&self) {
-        glBindTexture(GL_TEXTURE_2D, self.id);
+        gl_state::bind_texture(GL_TEXTURE_2D, self.id);
     }
 
     pub unsafe fn unbind(&self) {
-        glBindTexture(GL_TEXTURE_2D, 0);
+        gl_state::bind_texture(GL_TEXTURE_2D, 0);
+    }
+
+    //The texture has to have been bound once before it can be labeled
+    pub unsafe fn set_label(&self, label: &str) {
+        label_object(GL_TEXTURE, self.id, label);
     }
 
     pub unsafe fn set_data(
@@ -106,7 +168,7 @@ impl Texture2D<f32> {
 
         let mut texture = 0;
         glGenTextures(1, &mut texture);
-        glActiveTexture(texture_unit);
+        gl_state::active_texture(texture_unit);
         if texture == 0 {
             None
         } else {
@@ -115,11 +177,16 @@ impl Texture2D<f32> {
     }
 
     pub unsafe fn bind(&self) {
-        glBindTexture(GL_TEXTURE_2D, self.id);
+        gl_state::bind_texture(GL_TEXTURE_2D, self.id);
     }
 
     pub unsafe fn unbind(&self) {
-        glBindTexture(GL_TEXTURE_2D, 0);
+        gl_state::bind_texture(GL_TEXTURE_2D, 0);
+    }
+
+    //The texture has to have been bound once before it can be labeled
+    pub unsafe fn set_label(&self, label: &str) {
+        label_object(GL_TEXTURE, self.id, label);
     }
 
     pub unsafe fn set_data(
@@ -171,15 +238,19 @@ impl Buffer {
     }
 
     pub unsafe fn bind(&self) -> &Self {
-        glBindBuffer(self.1, self.0);
+        gl_state::bind_buffer(self.1, self.0);
         self
     }
 
     pub unsafe fn unbind(&self) -> &Self {
-        glBindBuffer(self.1, 0);
+        gl_state::bind_buffer(self.1, 0);
         self
     }
 
+    pub unsafe fn set_label(&self, label: &str) {
+        label_object(gl_ext::GL_BUFFER, self.0, label);
+    }
+
     pub unsafe fn set_data(&self, data: &[u8]) {
         glBufferData(
             self.1,
@@ -208,14 +279,24 @@ impl VertexArray {
     }
 
     pub unsafe fn unbind(&self) {
-        glBindVertexArray(0);
+        gl_state::bind_vertex_array(0);
     }
 
     pub unsafe fn bind(&self) {
-        glBindVertexArray(self.0);
+        gl_state::bind_vertex_array(self.0);
+    }
+
+    pub unsafe fn set_label(&self, label: &str) {
+        label_object(gl_ext::GL_VERTEX_ARRAY, self.0, label);
+    }
+
+    pub unsafe fn delete(&self) {
+        glDeleteVertexArrays(1, &self.0);
+        gl_state::forget_vertex_array(self.0);
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex = GL_VERTEX_SHADER as isize,
     Fragment = GL_FRAGMENT_SHADER as isize,
@@ -225,6 +306,9 @@ pub struct ShaderProgramBuilder {
     id: GLuint,
     uniforms: HashMap<String, GLint>,
     uniformblocks: HashMap<String, GLuint>,
+    sources: Vec<(ShaderType, String)>,
+    defines: Vec<(String, String)>,
+    cache: Option<ProgramCache>,
 }
 
 //Wrapper for opengl shader programs (uses builder pattern)
@@ -235,6 +319,15 @@ pub struct ShaderProgram(
 ); //Program, Uniforms, UniformBlocks
 
 impl ShaderProgram {
+    pub unsafe fn set_label(&self, label: &str) {
+        label_object(gl_ext::GL_PROGRAM, self.0, label);
+    }
+
+    pub unsafe fn delete(&self) {
+        glDeleteProgram(self.0);
+        gl_state::forget_program(self.0);
+    }
+
     pub unsafe fn create_uniform(&mut self, name: &CStr) {
         let uniform_location = glGetUniformLocation(self.0, name.as_ptr());
         self.1
@@ -256,10 +349,32 @@ impl ShaderProgram {
         );
     }
 
+    pub unsafe fn set_mat3(&self, name: &str, mat: &Matrix3<f32>) {
+        glUniformMatrix3fv(
+            self.1[name],
+            1,
+            GL_FALSE,
+            mat as *const Matrix3<f32> as *const f32,
+        );
+    }
+
+    pub unsafe fn set_vec2(&self, name: &str, vec: &Vector2<f32>) {
+        glUniform2f(self.1[name], vec.x, vec.y);
+    }
+
     pub unsafe fn set_vec3(&self, name: &str, vec: &cgmath::Vector3<f32>) {
         glUniform3f(self.1[name], vec.x, vec.y, vec.z);
     }
 
+    //Uploads a uniform array from its first element, name is the array's name without "[0]"
+    pub unsafe fn set_vec3_array(&self, name: &str, vecs: &[Vector3<f32>]) {
+        glUniform3fv(self.1[name], vecs.len() as GLsizei, vecs.as_ptr() as *const f32);
+    }
+
+    pub unsafe fn set_vec4_array(&self, name: &str, vecs: &[Vector4<f32>]) {
+        glUniform4fv(self.1[name], vecs.len() as GLsizei, vecs.as_ptr() as *const f32);
+    }
+
     pub unsafe fn set_vec4(&self, name: &str, vec: &cgmath::Vector4<f32>) {
         glUniform4f(self.1[name], vec.x, vec.y, vec.z, vec.w);
     }
@@ -280,22 +395,57 @@ impl ShaderProgramBuilder {
                 id: glCreateProgram(),
                 uniforms: HashMap::new(),
                 uniformblocks: HashMap::new(),
+                sources: Vec::new(),
+                defines: Vec::new(),
+                cache: None,
             }
         }
     }
-    //Compiles shader from source and attaches it to the program, as-well as deleting it after attachment
-    pub fn create_shader(&self, shader_type: ShaderType, shader_src: &str) -> &Self {
-        unsafe {
-            let shader = compile_shader(shader_type, shader_src).expect("Failed to compile shader");
-            glAttachShader(self.id, shader);
-            glDeleteShader(shader);
-        }
-
+    //Adds a shader source to the program. Sources are compiled when the program is linked
+    pub fn create_shader(&mut self, shader_type: ShaderType, shader_src: &str) -> &mut Self {
+        self.sources.push((shader_type, shader_src.to_string()));
+        self
+    }
+    //Adds a #define to every shader stage of the program
+    pub fn define(&mut self, name: &str, value: &str) -> &mut Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+    //Uses an on-disk program binary cache, skipping compilation when a matching binary exists
+    pub fn cache(&mut self, cache: ProgramCache) -> &mut Self {
+        self.cache = Some(cache);
         self
     }
     //Links the program and returns a ShaderProgram wrapper
     pub fn link(&self) -> Option<ShaderProgram> {
         unsafe {
+            let sources: Vec<(ShaderType, String)> = self
+                .sources
+                .iter()
+                .map(|(shader_type, src)| (*shader_type, preprocess_shader(src, &self.defines)))
+                .collect();
+
+            let cache = self.cache.as_ref().filter(|_| ProgramCache::is_supported());
+            let key = cache.map(|_| ProgramCache::key(&sources, &self.defines));
+
+            if let (Some(cache), Some(key)) = (cache, key) {
+                if cache.load(self.id, key) {
+                    return Some(self.program());
+                }
+                gl_ext::glProgramParameteri(
+                    self.id,
+                    gl_ext::GL_PROGRAM_BINARY_RETRIEVABLE_HINT,
+                    GL_TRUE as GLint,
+                );
+            }
+
+            //Compiles each shader and attaches it to the program, as-well as deleting it after attachment
+            for (shader_type, src) in sources.iter() {
+                let shader = compile_shader(*shader_type, src).expect("Failed to compile shader");
+                glAttachShader(self.id, shader);
+                glDeleteShader(shader);
+            }
+
             glLinkProgram(self.id);
 
             let mut success = 0;
@@ -312,22 +462,70 @@ impl ShaderProgramBuilder {
                 );
             }
 
-            Some(ShaderProgram(
-                self.id,
-                self.uniforms.clone(),
-                self.uniformblocks.clone(),
-            ))
+            if let (Some(cache), Some(key)) = (cache, key) {
+                cache.store(self.id, key);
+            }
+
+            Some(self.program())
+        }
+    }
+
+    fn program(&self) -> ShaderProgram {
+        ShaderProgram(self.id, self.uniforms.clone(), self.uniformblocks.clone())
+    }
+}
+
+//Inserts the defines after the #version line (which has to stay the first line of a GLSL source)
+pub fn preprocess_shader(shader_src: &str, defines: &[(String, String)]) -> String {
+    if defines.is_empty() {
+        return shader_src.to_string();
+    }
+    let define_lines: String = defines
+        .iter()
+        .map(|(name, value)| format!("#define {} {}\n", name, value))
+        .collect();
+
+    match shader_src.find("#version") {
+        Some(version_start) => {
+            let line_end = shader_src[version_start..]
+                .find('\n')
+                .map(|i| version_start + i + 1)
+                .unwrap_or(shader_src.len());
+            let mut result = shader_src[..line_end].to_string();
+            if !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str(&define_lines);
+            result.push_str(&shader_src[line_end..]);
+            result
         }
+        None => define_lines + shader_src,
     }
 }
 
+//Reads a shader, replacing `#include "file"` lines with that file's contents. Paths are relative to the
+//including file and includes can nest
 pub fn shader_from_file(path: &Path) -> String {
     let mut file = File::open(path).expect("Failed to open file");
     let mut contents = String::new();
     file.read_to_string(&mut contents)
         .expect("Failed to read file");
 
+    let directory = path.parent().unwrap_or(Path::new(""));
     contents
+        .lines()
+        .map(|line| {
+            let included = line
+                .trim()
+                .strip_prefix("#include")
+                .map(|name| name.trim().trim_matches('"'));
+            match included {
+                Some(name) => shader_from_file(&directory.join(name)),
+                None => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 //Compiles a shader from a string source and returns the shader id
@@ -356,8 +554,17 @@ unsafe fn compile_shader(shader_type: ShaderType, shader_src: &str) -> Option<u3
 }
 
 
-//Parse an obj file and return a mesh (HEAVILY WIP)
+//Parse an obj file and return a mesh (HEAVILY WIP), quantized with the default VertexPrecision
 pub fn mesh_from_obj(path: &Path) -> Mesh {
+    mesh_from_obj_with_precision(path, &VertexPrecision::default())
+}
+
+pub fn mesh_from_obj_with_precision(path: &Path, precision: &VertexPrecision) -> Mesh {
+    try_mesh_from_obj(path, precision).unwrap()
+}
+
+//Like mesh_from_obj_with_precision but returns load errors instead of panicking
+pub fn try_mesh_from_obj(path: &Path, precision: &VertexPrecision) -> Result<Mesh, tobj::LoadError> {
     let (models, _) = tobj::load_obj(
         path,
         &tobj::LoadOptions {
@@ -365,8 +572,10 @@ pub fn mesh_from_obj(path: &Path) -> Mesh {
             single_index: true,
             ..Default::default()
         },
-    )
-    .unwrap();
+    )?;
+    if models.is_empty() {
+        return Err(tobj::LoadError::GenericFailure);
+    }
     let mesh = &models[0].mesh;
 
     //Convert normals from Vec<f32> to Vec<[f32;3]>
@@ -388,14 +597,14 @@ pub fn mesh_from_obj(path: &Path) -> Mesh {
         .map(|chunk| TryInto::<[f32; 3]>::try_into(chunk).unwrap())
         .collect();
 
-    //Convert vertex_positions from Vec<[f32;3]> to Vec<[f32;8]> (since type Vertex is [f32;3+3+2])
-    //Range 0..3 is position, 3..6 is normal, 6..8 is texcoord
+    //Convert vertex_positions from Vec<[f32;3]> to Vec<Vertex>
+    //Range 0..3 is position, 3..6 is normal, 6..8 is texcoord, tangents are calculated in Mesh::setup
     let vertices: Vec<Vertex> = vertex_positions
         .into_iter()
         .zip(vertex_normals.into_iter())
         .zip(vertex_texcoords.into_iter())
         .map(|((chunk_pos, chunk_normal), chunk_tex)| {
-            let mut result = [0.0; 14];
+            let mut result = [0.0; VERTEX_SIZE];
             result[0..3].copy_from_slice(&chunk_pos);
             result[3..6].copy_from_slice(&chunk_normal);
             result[6..8].copy_from_slice(&chunk_tex);
@@ -411,187 +620,915 @@ pub fn mesh_from_obj(path: &Path) -> Mesh {
         .map(|chunk| TryInto::<[u32; 3]>::try_into(chunk).unwrap())
         .collect();
 
-    Mesh {
-        vertices,
-        indicies: vertex_indices,
-        tangents: Vec::new(),
-        bi_tangents: Vec::new(),
-        vao: None,
-        vbo: None,
-        ebo: None,
-    }
+    let mut mesh = Mesh::new(vertices, vertex_indices);
+    mesh.quantize(precision);
+    mesh.source = Some(MeshSource::File(path.to_path_buf()));
+    Ok(mesh)
 }
 
+//Handle to an object in a Scene, stays valid (and never points at another object) across removals
+pub type ObjectHandle = Handle<Object>;
+
 pub struct Scene {
-    pub objects: Vec<Object>,
+    pub objects: Arena<Object>,
+    //When false every object is drawn, stats are still gathered
+    pub culling: bool,
+    //Debug mode: while set, culling uses this frustum instead of the camera's so the camera can fly around
+    //and look at what gets culled
+    pub frozen_frustum: Option<Frustum>,
+    //Results of the last cull
+    pub cull_stats: CullStats,
+    pub visible_objects: Vec<ObjectHandle>,
+    //Spatial index over the objects' world AABBs by arena slot, see build_bvh. Kept up to date by
+    //update_model_matrices
+    pub bvh: Option<Bvh>,
+    pub lights: Vec<Light>,
+    //Lights reaching each object by arena slot, and how many objects each light reaches. See assign_lights
+    object_lights: Vec<Vec<usize>>,
+    light_reach: Vec<usize>,
+    //Equirectangular HDR image used for the background and image based lighting
+    pub environment_map: Option<PathBuf>,
+}
+
+//Objects that share a mesh and a material and can be drawn with one instanced draw call
+pub struct InstanceBatch {
+    pub mesh: Rc<Mesh>,
+    pub material: Rc<Material>,
+    pub objects: Vec<ObjectHandle>,
 }
 
 impl Scene {
     pub fn new() -> Self {
         Self {
-            objects: Vec::new(),
+            objects: Arena::new(),
+            culling: true,
+            frozen_frustum: None,
+            cull_stats: CullStats::default(),
+            visible_objects: Vec::new(),
+            bvh: None,
+            lights: Vec::new(),
+            object_lights: Vec::new(),
+            light_reach: Vec::new(),
+            environment_map: None,
+        }
+    }
+
+    pub fn add_object(&mut self, mut object: Object) -> ObjectHandle {
+        object.parent = None;
+        object.children.clear();
+        object.dirty = true;
+        let aabb = object.world_aabb;
+        let handle = self.objects.insert(object);
+        //A reused slot is already in the tree
+        if let Some(bvh) = self.bvh.as_mut().filter(|bvh| handle.slot() < bvh.len()) {
+            bvh.update(handle.slot(), aabb);
+        }
+        handle
+    }
+
+    //Removes the object along with everything below it in the hierarchy, their handles go stale.
+    //Returns the object itself, None if the handle was already stale
+    pub fn remove_object(&mut self, handle: ObjectHandle) -> Option<Object> {
+        if !self.objects.contains(handle) {
+            return None;
+        }
+        if let Some(parent) = self.objects[handle].parent {
+            self.objects[parent].children.retain(|&child| child != handle);
         }
+        let mut removed = self.descendants(handle);
+        removed.push(handle);
+        for &removed in removed.iter() {
+            if let Some(bvh) = self.bvh.as_mut().filter(|bvh| removed.slot() < bvh.len()) {
+                bvh.update(removed.slot(), Aabb::empty());
+            }
+        }
+        self.visible_objects
+            .retain(|visible| !removed.contains(visible));
+        let object = removed.pop().and_then(|handle| self.objects.remove(handle));
+        for handle in removed {
+            self.objects.remove(handle);
+        }
+        object
+    }
+
+    pub fn get(&self, handle: ObjectHandle) -> Option<&Object> {
+        self.objects.get(handle)
     }
 
-    pub fn add_object(&mut self, object: Object) {
-        self.objects.push(object);
+    pub fn get_mut(&mut self, handle: ObjectHandle) -> Option<&mut Object> {
+        self.objects.get_mut(handle)
     }
 
+    //Recomputes every object's transforms, use update_transforms to only update what changed
     pub fn update_model_matrices(&mut self) {
-        for mesh in self.objects.iter_mut() {
-            mesh.update_model_matrix();
+        for object in self.objects.values_mut() {
+            object.dirty = true;
         }
+        self.update_transforms();
     }
 
-    pub fn draw(&self) {
-        for object in self.objects.iter() {
-            object.mesh.draw();
+    pub(crate) fn refit_bvh(&mut self) {
+        if self.bvh.is_none() {
+            return;
+        }
+        let bounds = self.world_aabbs();
+        let bvh = self.bvh.as_mut().unwrap();
+        if bvh.len() != bounds.len() {
+            *bvh = Bvh::build(&bounds);
+        } else {
+            bvh.refit(&bounds);
+            if bvh.needs_rebuild() {
+                *bvh = Bvh::build(&bounds);
+            }
+        }
+    }
+
+    //World bounds by arena slot, free slots are empty
+    fn world_aabbs(&self) -> Vec<Aabb> {
+        (0..self.objects.slot_count())
+            .map(|slot| {
+                self.objects
+                    .handle_at(slot)
+                    .map_or(Aabb::empty(), |handle| self.objects[handle].world_aabb)
+            })
+            .collect()
+    }
+
+    //Builds the BVH from the current world bounds, culling and spatial queries use it from then on
+    pub fn build_bvh(&mut self) {
+        self.bvh = Some(Bvh::build(&self.world_aabbs()));
+    }
+
+    //The BVH, unless objects were added to new slots since it was last built or refitted
+    pub(crate) fn current_bvh(&self) -> Option<&Bvh> {
+        self.bvh
+            .as_ref()
+            .filter(|bvh| bvh.len() == self.objects.slot_count())
+    }
+
+    //Objects whose bounds touch the sphere, e.g. the objects a point light reaches
+    pub fn objects_in_sphere(&self, sphere: &BoundingSphere) -> Vec<ObjectHandle> {
+        let candidates: Vec<ObjectHandle> = match self.current_bvh() {
+            Some(bvh) => bvh
+                .query_sphere(sphere)
+                .into_iter()
+                .filter_map(|slot| self.objects.handle_at(slot))
+                .collect(),
+            None => self.objects.handles(),
+        };
+        let mut result: Vec<ObjectHandle> = candidates
+            .into_iter()
+            .filter(|&handle| {
+                let object = &self.objects[handle];
+                object.world_aabb.intersects_sphere(sphere) && object.world_sphere.intersects(sphere)
+            })
+            .collect();
+        result.sort_unstable();
+        result
+    }
+
+    //Finds the lights that reach each object: every directional light, then the point lights whose range
+    //touches its bounds, through objects_in_sphere so a BVH is used when there is one. Scene::queue calls
+    //it for programs with "light_count"
+    pub fn assign_lights(&mut self) {
+        let mut object_lights = vec![Vec::new(); self.objects.slot_count()];
+        let mut light_reach = vec![0; self.lights.len()];
+        for (index, light) in self.lights.iter().enumerate() {
+            let reached = match *light {
+                Light::Point {
+                    position, range, ..
+                } => self.objects_in_sphere(&BoundingSphere::new(position, range)),
+                Light::Directional { .. } => self.objects.handles(),
+            };
+            light_reach[index] = reached.len();
+            for handle in reached {
+                object_lights[handle.slot()].push(index);
+            }
+        }
+        //Directional lights first, they're the last to be dropped past MAX_FORWARD_LIGHTS
+        for lights in object_lights.iter_mut() {
+            lights.sort_by_key(|&index| matches!(self.lights[index], Light::Point { .. }));
+        }
+        self.object_lights = object_lights;
+        self.light_reach = light_reach;
+    }
+
+    //Indices into lights of the ones reaching the object, as of the last assign_lights
+    pub fn lights_for(&self, handle: ObjectHandle) -> &[usize] {
+        self.object_lights
+            .get(handle.slot())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    //Number of objects the light at index reached in the last assign_lights
+    pub fn light_reach(&self, index: usize) -> usize {
+        self.light_reach.get(index).copied().unwrap_or(0)
+    }
+
+    pub fn select_lods(&mut self, camera: &Camera) {
+        for object in self.objects.values_mut() {
+            object.select_lod(camera);
+        }
+    }
+
+    //Union of the objects' world bounds, as of their last update_model_matrix
+    pub fn bounds(&self) -> Aabb {
+        self.objects
+            .values()
+            .fold(Aabb::empty(), |bounds, object| bounds.union(&object.world_aabb))
+    }
+
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        self.objects
+            .values()
+            .map(|object| object.world_sphere)
+            .reduce(|a, b| a.union(&b))
+    }
+
+    pub fn freeze_frustum(&mut self, camera: &Camera) {
+        self.frozen_frustum = Some(Frustum::from_camera(camera));
+    }
+
+    pub fn unfreeze_frustum(&mut self) {
+        self.frozen_frustum = None;
+    }
+
+    //Tests every object's world bounds against the camera's (or the frozen) frustum,
+    //fills visible_objects and cull_stats. World bounds come from update_model_matrix
+    pub fn cull(&mut self, camera: &Camera) -> &[ObjectHandle] {
+        let frustum = self
+            .frozen_frustum
+            .unwrap_or_else(|| Frustum::from_camera(camera));
+        let mut stats = CullStats::default();
+        self.visible_objects.clear();
+
+        //With a BVH only the objects in nodes that reach into the frustum are visited at all
+        let bvh = self.current_bvh().filter(|_| self.culling);
+        if let Some(bvh) = bvh {
+            let mut slots = bvh.query_frustum(&frustum);
+            //Same order as iterating the arena, so both paths give the same list
+            slots.sort_unstable();
+            for slot in slots {
+                let (handle, object) = match self.objects.handle_at(slot) {
+                    Some(handle) => (handle, &self.objects[handle]),
+                    None => continue,
+                };
+                stats.tested += 1;
+                if frustum.intersects(&object.world_aabb, &object.world_sphere) {
+                    stats.visible += 1;
+                    stats.visible_triangles += object.mesh.indicies.len();
+                    self.visible_objects.push(handle);
+                }
+            }
+            //Everything the BVH skipped was culled too
+            let triangles: usize = self
+                .objects
+                .values()
+                .map(|object| object.mesh.indicies.len())
+                .sum();
+            stats.culled = self.objects.len() - stats.visible;
+            stats.culled_triangles = triangles - stats.visible_triangles;
+            self.cull_stats = stats;
+            return &self.visible_objects;
+        }
+
+        for (handle, object) in self.objects.iter() {
+            let triangles = object.mesh.indicies.len();
+            stats.tested += 1;
+            if !self.culling || frustum.intersects(&object.world_aabb, &object.world_sphere) {
+                stats.visible += 1;
+                stats.visible_triangles += triangles;
+                self.visible_objects.push(handle);
+            } else {
+                stats.culled += 1;
+                stats.culled_triangles += triangles;
+            }
         }
+        self.cull_stats = stats;
+        &self.visible_objects
+    }
+
+    //Uploads the first MAX_FORWARD_LIGHTS of the lights at indices to "light_count", "light_position" and
+    //"light_color", see Light::packed and lights_for
+    pub unsafe fn set_light_uniforms(&self, program: &ShaderProgram, indices: &[usize]) {
+        let (positions, colors): (Vec<Vector4<f32>>, Vec<Vector4<f32>>) = indices
+            .iter()
+            .take(MAX_FORWARD_LIGHTS)
+            .map(|&index| self.lights[index].packed())
+            .unzip();
+        program.set_int("light_count", positions.len() as i32);
+        if !positions.is_empty() {
+            program.set_vec4_array("light_position", &positions);
+            program.set_vec4_array("light_color", &colors);
+        }
+    }
+
+    //Uploads the camera uniforms ("V", "P" and "camera_pos") used by draw and draw_instanced
+    pub unsafe fn set_camera_uniforms(program: &ShaderProgram, camera: &Camera) {
+        program.set_mat4("V", &camera.get_view_matrix());
+        program.set_mat4("P", &camera.get_projection_matrix());
+        program.set_vec3("camera_pos", &camera.position);
+    }
+
+    //Draws the visible objects with program through a sorted RenderQueue: opaque objects grouped by
+    //material and mesh, then transparent ones back to front. The program needs the camera uniforms,
+    //"M", "N" (normal matrix) and the material uniforms
+    pub unsafe fn draw(&mut self, program: &ShaderProgram, camera: &Camera) {
+        let _group = DebugGroup::new("Scene::draw");
+        let mut queue = RenderQueue::new();
+        self.queue(&mut queue, program, camera);
+        queue.sort();
+        queue.draw(self, camera);
+    }
+
+    //Only the transparent objects, back to front. For drawing them over the deferred path's output
+    pub unsafe fn draw_transparent(&mut self, program: &ShaderProgram, camera: &Camera) {
+        let _group = DebugGroup::new("Scene::draw_transparent");
+        let mut queue = RenderQueue::new();
+        self.queue(&mut queue, program, camera);
+        queue.sort();
+        queue.draw_transparent(self, camera);
+    }
+
+    //Like draw but without transparent objects, they don't occlude anything. For depth and normal
+    //prepasses, the program needs the same uniforms as for draw
+    pub unsafe fn draw_opaque(&mut self, program: &ShaderProgram, camera: &Camera) {
+        let _group = DebugGroup::new("Scene::draw_opaque");
+        let mut queue = RenderQueue::new();
+        self.queue(&mut queue, program, camera);
+        queue.sort();
+        queue.draw_opaque(self, camera);
+    }
+
+    //Groups objects by shared mesh and material, in order of first appearance
+    pub fn batches(&self) -> Vec<InstanceBatch> {
+        self.batches_of(self.objects.handles())
+    }
+
+    fn batches_of(&self, objects: impl IntoIterator<Item = ObjectHandle>) -> Vec<InstanceBatch> {
+        let mut batches: Vec<InstanceBatch> = Vec::new();
+        for handle in objects {
+            let object = match self.objects.get(handle) {
+                Some(object) => object,
+                None => continue,
+            };
+            match batches.iter_mut().find(|batch| {
+                Rc::ptr_eq(&batch.mesh, &object.mesh) && Rc::ptr_eq(&batch.material, &object.material)
+            }) {
+                Some(batch) => batch.objects.push(handle),
+                None => batches.push(InstanceBatch {
+                    mesh: object.mesh.clone(),
+                    material: object.material.clone(),
+                    objects: vec![handle],
+                }),
+            }
+        }
+        batches
+    }
+
+    //Draws the visible opaque objects with one draw call per batch for meshes that have instancing enabled,
+    //other meshes fall back to one draw per object with the model matrix in "M" and normal matrix in "N".
+    //Transparent objects can't be batched, they need blending back to front, so they go through the
+    //RenderQueue one at a time afterwards.
+    //The program needs the camera uniforms, "M", "N" and "instanced" as-well as the material uniforms
+    pub unsafe fn draw_instanced(&mut self, program: &ShaderProgram, camera: &Camera) {
+        let _group = DebugGroup::new("Scene::draw_instanced");
+        let mut queue = RenderQueue::new();
+        self.queue(&mut queue, program, camera);
+        queue.sort();
+
+        gl_state::use_program(program.0);
+        Self::set_camera_uniforms(program, camera);
+        let lit = program.1.contains_key("light_count");
+        //The sorted queue has the opaque objects grouped by material and mesh already
+        for batch in self.batches_of(queue.opaque.iter().map(|item| item.object)) {
+            batch.material.blend_mode.apply();
+            batch.material.apply(program);
+            if batch.mesh.instance_vbo.is_some() {
+                //Every light reaching one of the instances
+                if lit {
+                    let mut lights: Vec<usize> = Vec::new();
+                    for &handle in batch.objects.iter() {
+                        for &index in self.lights_for(handle) {
+                            if !lights.contains(&index) {
+                                lights.push(index);
+                            }
+                        }
+                    }
+                    self.set_light_uniforms(program, &lights);
+                }
+                let instances: Vec<InstanceData> = batch
+                    .objects
+                    .iter()
+                    .map(|&handle| self.objects[handle].instance_data())
+                    .collect();
+                batch.mesh.set_instances(&instances);
+                program.set_int("instanced", 1);
+                batch.mesh.draw_instanced(instances.len());
+            } else {
+                program.set_int("instanced", 0);
+                for &handle in batch.objects.iter() {
+                    let object = &self.objects[handle];
+                    if lit {
+                        self.set_light_uniforms(program, self.lights_for(handle));
+                    }
+                    program.set_mat4("M", &object.model_matrix);
+                    program.set_mat3("N", &object.normal_matrix);
+                    batch.mesh.draw();
+                }
+            }
+        }
+        program.set_int("instanced", 0);
+        queue.draw_transparent(self, camera);
     }
 
     pub unsafe fn setup(&mut self) {
-        for object in self.objects.iter_mut() {
-            object.mesh.setup();
+        for object in self.objects.values_mut() {
+            if object.mesh.vao.is_none() {
+                object.mesh_mut().setup();
+            }
+        }
+    }
+}
+
+//Surface parameters for the PBR shader
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Material {
+    pub albedo: Vector3<f32>,
+    pub roughness: f32,
+    pub metallic: f32,
+    pub blend_mode: BlendMode,
+    //Opacity, multiplied with the per-instance color's alpha
+    pub alpha: f32,
+    //Fragments below this alpha are discarded, only used with BlendMode::AlphaTest
+    pub alpha_cutoff: f32,
+    //Light given off by the surface itself, linear and unaffected by lighting
+    pub emissive: Vector3<f32>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            albedo: Vector3::new(1.0, 1.0, 1.0),
+            roughness: 1.0,
+            metallic: 0.0,
+            blend_mode: BlendMode::Opaque,
+            alpha: 1.0,
+            alpha_cutoff: 0.5,
+            emissive: Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+//Light sources placed in a scene, color is linear and scaled by intensity
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Light {
+    Point {
+        position: Vector3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+        //Distance at which the light has faded out
+        range: f32,
+    },
+    Directional {
+        //Direction the light travels in
+        direction: Vector3<f32>,
+        color: Vector3<f32>,
+        intensity: f32,
+    },
+}
+
+//Lights the forward shader can take at once, the size of its light arrays
+pub const MAX_FORWARD_LIGHTS: usize = 16;
+
+impl Light {
+    //Packs the light into two vectors for the shaders: a position with w = 1 for point lights, or the
+    //direction the light travels with w = 0 for directional ones. Then the color times intensity, with
+    //the range in w (0 for directional lights)
+    pub fn packed(&self) -> (Vector4<f32>, Vector4<f32>) {
+        match *self {
+            Light::Point {
+                position,
+                color,
+                intensity,
+                range,
+            } => (position.extend(1.0), (color * intensity).extend(range)),
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => (
+                direction.normalize().extend(0.0),
+                (color * intensity).extend(0.0),
+            ),
+        }
+    }
+}
+
+impl Material {
+    //Uploads the material to the "albedo", "roughness" and "metallic" uniforms, and "alpha",
+    //"alpha_cutoff" and "emissive" if the program has them. The blend state is set separately by
+    //BlendMode::apply
+    pub unsafe fn apply(&self, program: &ShaderProgram) {
+        program.set_vec3("albedo", &self.albedo);
+        program.set_float("roughness", self.roughness);
+        program.set_float("metallic", self.metallic);
+        if program.1.contains_key("alpha") {
+            program.set_float("alpha", self.alpha);
+        }
+        if program.1.contains_key("alpha_cutoff") {
+            let cutoff = match self.blend_mode {
+                BlendMode::AlphaTest => self.alpha_cutoff,
+                _ => 0.0,
+            };
+            program.set_float("alpha_cutoff", cutoff);
+        }
+        if program.1.contains_key("emissive") {
+            program.set_vec3("emissive", &self.emissive);
         }
     }
 }
 
-//High level object that contains a mesh and a transform
+//High level object that contains a mesh and a transform.
+//Meshes and materials are reference counted so many objects can share (and be instanced from) one
 pub struct Object {
-    pub mesh: Mesh,
+    pub name: String,
+    pub mesh: Rc<Mesh>,
+    pub material: Rc<Material>,
+    //Local transform, relative to the parent (see scene_graph)
     pub position: Vector3<f32>,
     pub rotation: Quaternion<f32>,
     pub scale: Vector3<f32>,
-
+    pub color: Vector4<f32>,     //Per-instance tint
+    pub user_data: Vector4<f32>, //Per-instance custom data, Vertex.data in the shaders
+    //When set, select_lod swaps mesh for the chain's level that fits the object's size on screen
+    pub lods: Option<Rc<LodChain>>,
+    pub lod_level: usize,
+
+    parent: Option<ObjectHandle>,
+    children: Vec<ObjectHandle>,
+    //Set when the local transform changed and the world transform of the subtree needs recomputing
+    dirty: bool,
+
+    pub local_matrix: Matrix4<f32>,
+    //World transform, the parent's model matrix times local_matrix
     pub model_matrix: Matrix4<f32>,
+    //model_matrix before the last Scene::update_transforms, for per-object motion vectors
+    pub previous_model_matrix: Matrix4<f32>,
+    //Inverse transpose of model_matrix' upper 3x3, transforms normals correctly under non-uniform scale
+    pub normal_matrix: Matrix3<f32>,
+    //World space bounds of the current mesh, refreshed by update_model_matrix
+    pub world_aabb: Aabb,
+    pub world_sphere: BoundingSphere,
+    pub world_obb: Obb,
 }
 
 impl Object {
     pub fn new(mesh: Mesh) -> Self {
+        Self::with_shared(Rc::new(mesh), Rc::new(Material::default()))
+    }
+
+    pub fn with_shared(mesh: Rc<Mesh>, material: Rc<Material>) -> Self {
         Self {
+            name: String::new(),
+            world_aabb: mesh.aabb,
+            world_sphere: mesh.bounding_sphere,
+            world_obb: Obb::from_aabb(&mesh.aabb, &Matrix4::identity()),
             mesh,
+            material,
             position: Vector3::new(0.0, 0.0, 0.0),
             rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
             scale: Vector3::new(1.0, 1.0, 1.0),
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            user_data: Vector4::new(0.0, 0.0, 0.0, 0.0),
+            lods: None,
+            lod_level: 0,
+            parent: None,
+            children: Vec::new(),
+            dirty: true,
+            local_matrix: Matrix4::identity(),
             model_matrix: Matrix4::identity(),
+            previous_model_matrix: Matrix4::identity(),
+            normal_matrix: Matrix3::identity(),
         }
     }
 
+    pub fn with_lods(lods: Rc<LodChain>, material: Rc<Material>) -> Self {
+        let mut object = Self::with_shared(lods.levels[0].mesh.clone(), material);
+        object.lods = Some(lods);
+        object
+    }
+
+    //Picks the LOD level for the object's projected size, uses the model matrix so update it first
+    pub fn select_lod(&mut self, camera: &Camera) {
+        let lods = match &self.lods {
+            Some(lods) => lods.clone(),
+            None => return,
+        };
+        let sphere = BoundingSphere::new(lods.center, lods.radius).transformed(&self.model_matrix);
+        let screen_size = camera.projected_size(sphere.center, sphere.radius);
+        let level = lods.select(screen_size, self.lod_level);
+        if level != self.lod_level || !Rc::ptr_eq(&self.mesh, &lods.levels[level].mesh) {
+            self.lod_level = level;
+            self.mesh = lods.levels[level].mesh.clone();
+            self.update_world_bounds();
+        }
+    }
+
+    //Mutable access to the mesh, only possible while it isn't shared with other objects
+    pub fn mesh_mut(&mut self) -> &mut Mesh {
+        Rc::get_mut(&mut self.mesh)
+            .expect("Mesh is shared between objects, set it up before sharing it")
+    }
+
+    //Recomputes the matrices as if the object had no parent, objects in a hierarchy are updated by
+    //Scene::update_transforms
     pub fn update_model_matrix(&mut self) {
-        self.model_matrix = Matrix4::from_translation(self.position)
+        self.update_world_matrix(None);
+    }
+
+    pub(crate) fn update_world_matrix(&mut self, parent: Option<&Matrix4<f32>>) {
+        self.local_matrix = Matrix4::from_translation(self.position)
             * Matrix4::from(self.rotation)
             * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+        self.model_matrix = match parent {
+            Some(parent) => parent * self.local_matrix,
+            None => self.local_matrix,
+        };
+        let model = &self.model_matrix;
+        self.normal_matrix =
+            Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate())
+                .invert()
+                .map(|m| m.transpose())
+                .unwrap_or_else(Matrix3::identity);
+        self.dirty = false;
+        self.update_world_bounds();
+    }
+
+    fn update_world_bounds(&mut self) {
+        self.world_aabb = self.mesh.aabb.transformed(&self.model_matrix);
+        self.world_sphere = self.mesh.bounding_sphere.transformed(&self.model_matrix);
+        self.world_obb = Obb::from_aabb(&self.mesh.aabb, &self.model_matrix);
+    }
+
+    pub fn instance_data(&self) -> InstanceData {
+        InstanceData {
+            model: self.model_matrix.into(),
+            color: self.color.into(),
+            user_data: self.user_data.into(),
+        }
     }
 }
 
+//Per-instance vertex attributes, laid out to match INSTANCE_ATTRIBUTE_LOCATION onwards:
+//model matrix (4 locations), color, custom data. There's no normal matrix: with the extra vertex
+//attributes at 11-14 a mat3 wouldn't fit in the 16 locations GL guarantees, so the vertex shader derives
+//it from the model matrix
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct InstanceData {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+    pub user_data: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for InstanceData {}
+unsafe impl bytemuck::Pod for InstanceData {}
+
+//First attribute location used by InstanceData, after the five per-vertex attributes
+pub const INSTANCE_ATTRIBUTE_LOCATION: GLuint = 5;
+
 pub type VertIndicies = [u32; 3];
 
-pub type Vertex = [f32; 3 + 3 + 2 + 3 + 3]; //Position, Normal, TextureCoords, Tangent, BiTangent
+//Position, Normal, TextureCoords, Tangent (xyz + handedness sign in w), BiTangent (sign * cross(normal, tangent))
+pub type Vertex = [f32; VERTEX_SIZE];
+pub const VERTEX_SIZE: usize = 3 + 3 + 2 + 4 + 3;
 
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indicies: Vec<VertIndicies>,
     pub tangents: Vec<Vector3<f32>>,
     pub bi_tangents: Vec<Vector3<f32>>,
+    //Attributes that don't fit in Vertex (colors, second uv set, joints, weights...), one entry per vertex
+    pub extra_attributes: HashMap<VertexSemantic, Vec<[f32; 4]>>,
+    //GPU layout of the vertices, only the attributes listed here are uploaded
+    pub format: VertexFormat,
     pub vao: Option<VertexArray>,
-    pub vbo: Option<Buffer>,
+    pub vbos: Vec<Buffer>, //One per stream of the format
     pub ebo: Option<Buffer>,
+    pub index_format: IndexFormat,
+    pub instance_vbo: Option<Buffer>,
+    //Object space bounds, call update_bounds after moving vertices around by hand
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    //Where the mesh came from, so scenes can be saved (see scene_file). None for meshes built in code
+    pub source: Option<MeshSource>,
 }
 
 impl Mesh {
     pub fn new(v: Vec<Vertex>, i: Vec<VertIndicies>) -> Self {
         Self {
+            aabb: Aabb::from_vertices(&v),
+            bounding_sphere: BoundingSphere::from_vertices(&v),
             vertices: v,
             indicies: i,
             tangents: Vec::new(),
             bi_tangents: Vec::new(),
+            extra_attributes: HashMap::new(),
+            format: VertexFormat::standard(),
             vao: None,
-            vbo: None,
+            vbos: Vec::new(),
             ebo: None,
+            index_format: IndexFormat::U32,
+            instance_vbo: None,
+            source: None,
         }
     }
 
-    pub unsafe fn calculate_tangents(&mut self) {
-        //Calculate tangents and bi-tangents for each triangle
-        for i in (0..self.indicies.len()) {
-            let i0 = self.indicies[i][0] as usize;
-            let i1 = self.indicies[i][1] as usize;
-            let i2 = self.indicies[i][2] as usize;
-
-            let v0 = Vector3::new(
-                self.vertices[i0][0],
-                self.vertices[i0][1],
-                self.vertices[i0][2],
-            );
-            let v1 = Vector3::new(
-                self.vertices[i1][0],
-                self.vertices[i1][1],
-                self.vertices[i1][2],
-            );
-            let v2 = Vector3::new(
-                self.vertices[i2][0],
-                self.vertices[i2][1],
-                self.vertices[i2][2],
-            );
-
-            let normal = Vector3::new(
-                self.vertices[i0][3],
-                self.vertices[i0][4],
-                self.vertices[i0][5],
-            );
-
-            let uv0 = Vector2::new(self.vertices[i0][6], self.vertices[i0][7]);
-            let uv1 = Vector2::new(self.vertices[i1][6], self.vertices[i1][7]);
-            let uv2 = Vector2::new(self.vertices[i2][6], self.vertices[i2][7]);
-
-            let delta_pos1 = v1 - v0;
-            let delta_pos2 = v2 - v0;
+    pub(crate) fn with_source(mut self, source: MeshSource) -> Self {
+        self.source = Some(source);
+        self
+    }
 
-            let delta_uv1 = uv1 - uv0;
-            let delta_uv2 = uv2 - uv0;
+    //Builds a mesh from custom vertex structs, the mesh keeps their layout on the GPU
+    pub fn from_vertices<V: VertexLayout>(vertices: &[V], i: Vec<VertIndicies>) -> Self {
+        let format = V::vertex_format();
+        assert!(
+            format.stream_count() == 1 && format.strides[0] == std::mem::size_of::<V>(),
+            "VertexLayout formats must be interleaved with a stride of size_of::<V>()"
+        );
+        let (v, extra_attributes) = format.decode(&[cast_slice(vertices)]);
+        let mut mesh = Self::new(v, i);
+        mesh.extra_attributes = extra_attributes;
+        mesh.format = format;
+        mesh
+    }
 
-            let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-            let mut tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
-            let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r;
-            //Make tangent and bi-tangent orthogonal to the normal
-            tangent = (tangent - normal * normal.dot(tangent)).normalize();
+    pub fn update_bounds(&mut self) {
+        self.aabb = Aabb::from_vertices(&self.vertices);
+        self.bounding_sphere = BoundingSphere::from_vertices(&self.vertices);
+    }
 
-            //Put the tangent and bi-tangent in the vertices
-            self.vertices[i0][8] = tangent.x;
-            self.vertices[i0][9] = tangent.y;
-            self.vertices[i0][10] = tangent.z;
+    pub fn with_format(mut self, format: VertexFormat) -> Self {
+        self.format = format;
+        self
+    }
 
-            self.vertices[i1][8] = tangent.x;
-            self.vertices[i1][9] = tangent.y;
-            self.vertices[i1][10] = tangent.z;
+    //Switches the GPU layout to the given precision, has to be called before setup
+    pub fn quantize(&mut self, precision: &VertexPrecision) {
+        self.format = precision.format_for(self);
+    }
 
-            self.vertices[i2][8] = tangent.x;
-            self.vertices[i2][9] = tangent.y;
-            self.vertices[i2][10] = tangent.z;
+    //Bytes the vertex and index buffers take up on the GPU (excluding instance data)
+    pub fn gpu_size(&self) -> usize {
+        self.vertices.len() * self.format.vertex_size()
+            + self.indicies.len() * 3 * IndexFormat::for_vertex_count(self.vertices.len()).size()
+    }
 
-            self.vertices[i0][11] = bitangent.x;
-            self.vertices[i0][12] = bitangent.y;
-            self.vertices[i0][13] = bitangent.z;
+    //Tangent generation along the lines of MikkTSpace: per-face tangents are projected onto each corner's normal
+    //plane, weighted by the corner angle and summed per vertex, then Gram-Schmidt orthogonalized against the normal.
+    //Vertices shared by faces with opposite uv winding (mirrored uvs) are split first, so every face sees the same
+    //handedness at all of its corners. The sign goes in the tangent's w and the bitangent is stored as
+    //sign * cross(normal, tangent). Triangles with no area or degenerate uvs don't contribute, vertices that only
+    //touch those get an arbitrary tangent perpendicular to the normal
+    pub fn calculate_tangents(&mut self) {
+        let position = |v: &Vertex| Vector3::new(v[0], v[1], v[2]);
+        let normal = |v: &Vertex| Vector3::new(v[3], v[4], v[5]);
+        let uv = |v: &Vertex| Vector2::new(v[6], v[7]);
+
+        //Tangent and bitangent of each face, None for faces that can't have one
+        let faces: Vec<Option<(Vector3<f32>, Vector3<f32>)>> = self
+            .indicies
+            .iter()
+            .map(|triangle| {
+                let corners = triangle.map(|i| &self.vertices[i as usize]);
+                let p = corners.map(position);
+                let t = corners.map(uv);
+
+                let delta_pos1 = p[1] - p[0];
+                let delta_pos2 = p[2] - p[0];
+                let delta_uv1 = t[1] - t[0];
+                let delta_uv2 = t[2] - t[0];
+
+                let area = delta_pos1.cross(delta_pos2).magnitude();
+                let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+                //Relative to the uv extent so tiny (but valid) uv islands aren't thrown away
+                let uv_extent = delta_uv1.magnitude2().max(delta_uv2.magnitude2());
+                if area <= f32::EPSILON || det.abs() <= uv_extent * 1e-6 {
+                    return None;
+                }
+                Some((
+                    (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) / det,
+                    (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) / det,
+                ))
+            })
+            .collect();
+        self.split_mirrored_vertices(&faces);
+
+        let mut tangents = vec![Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+        let mut bitangents = vec![Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+
+        for (triangle, face) in self.indicies.iter().zip(faces.iter()) {
+            let (face_tangent, face_bitangent) = match face {
+                Some(face) => *face,
+                None => continue,
+            };
+            let corners = triangle.map(|i| &self.vertices[i as usize]);
+            let p = corners.map(position);
+
+            for corner in 0..3 {
+                let n = normal(corners[corner]);
+                let edge1 = p[(corner + 1) % 3] - p[corner];
+                let edge2 = p[(corner + 2) % 3] - p[corner];
+                if edge1.magnitude2() <= 0.0 || edge2.magnitude2() <= 0.0 {
+                    continue;
+                }
+                let angle = edge1.normalize().dot(edge2.normalize()).clamp(-1.0, 1.0).acos();
+
+                let index = triangle[corner] as usize;
+                let t = face_tangent - n * n.dot(face_tangent);
+                if t.magnitude2() > 0.0 {
+                    tangents[index] += t.normalize() * angle;
+                }
+                let b = face_bitangent - n * n.dot(face_bitangent);
+                if b.magnitude2() > 0.0 {
+                    bitangents[index] += b.normalize() * angle;
+                }
+            }
+        }
 
-            self.vertices[i1][11] = bitangent.x;
-            self.vertices[i1][12] = bitangent.y;
-            self.vertices[i1][13] = bitangent.z;
+        for (i, vertex) in self.vertices.iter_mut().enumerate() {
+            let n = normal(vertex);
+            let n = if n.magnitude2() > 0.0 {
+                n.normalize()
+            } else {
+                Vector3::new(0.0, 0.0, 1.0)
+            };
+
+            //Gram-Schmidt
+            let mut tangent = tangents[i] - n * n.dot(tangents[i]);
+            if tangent.magnitude2() <= f32::EPSILON {
+                tangent = any_perpendicular(n);
+            }
+            let tangent = tangent.normalize();
+            let sign = if n.cross(tangent).dot(bitangents[i]) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            let bitangent = n.cross(tangent) * sign;
+
+            vertex[8..11].copy_from_slice(&[tangent.x, tangent.y, tangent.z]);
+            vertex[11] = sign;
+            vertex[12..15].copy_from_slice(&[bitangent.x, bitangent.y, bitangent.z]);
+        }
+    }
 
-            self.vertices[i2][11] = bitangent.x;
-            self.vertices[i2][12] = bitangent.y;
-            self.vertices[i2][13] = bitangent.z;
+    //Gives corners whose face is mirrored relative to the rest of the vertex's faces their own copy of the vertex.
+    //A vertex keeps the handedness of its first face, extra attributes are copied along
+    fn split_mirrored_vertices(&mut self, faces: &[Option<(Vector3<f32>, Vector3<f32>)>]) {
+        let mut handedness: Vec<Option<bool>> = vec![None; self.vertices.len()];
+        //Copy of each vertex for the other handedness, made on first use
+        let mut mirrored: HashMap<u32, u32> = HashMap::new();
+
+        for (triangle, face) in self.indicies.iter_mut().zip(faces.iter()) {
+            let (face_tangent, face_bitangent) = match face {
+                Some(face) => *face,
+                None => continue,
+            };
+            for index in triangle.iter_mut() {
+                let vertex = &self.vertices[*index as usize];
+                let n = Vector3::new(vertex[3], vertex[4], vertex[5]);
+                let right_handed = n.cross(face_tangent).dot(face_bitangent) >= 0.0;
+                match handedness[*index as usize] {
+                    None => handedness[*index as usize] = Some(right_handed),
+                    Some(existing) if existing == right_handed => {}
+                    Some(_) => {
+                        let vertices = &mut self.vertices;
+                        let extra_attributes = &mut self.extra_attributes;
+                        *index = *mirrored.entry(*index).or_insert_with(|| {
+                            vertices.push(vertices[*index as usize]);
+                            for values in extra_attributes.values_mut() {
+                                if let Some(&value) = values.get(*index as usize) {
+                                    values.push(value);
+                                }
+                            }
+                            (vertices.len() - 1) as u32
+                        });
+                    }
+                }
+            }
         }
     }
 
     pub unsafe fn setup(&mut self) -> &Self {
-        self.calculate_tangents();
+        if self.format.has(VertexSemantic::Tangent) || self.format.has(VertexSemantic::Bitangent) {
+            self.calculate_tangents();
+        }
 
         self.vao = Some(VertexArray::new().expect("Failed to create vertex array"));
 
-        self.vbo = Some(
-            Buffer::new(GL_STATIC_DRAW, GL_ARRAY_BUFFER).expect("Failed to create vertex buffer"),
-        );
-
         self.ebo = Some(
             Buffer::new(GL_STATIC_DRAW, GL_ELEMENT_ARRAY_BUFFER)
                 .expect("Failed to create element buffer"),
@@ -599,73 +1536,104 @@ impl Mesh {
 
         //self.vao, and the other buffers, are an Option so we need to unwrap it
         self.vao.unwrap().bind();
-        self.vbo.unwrap().bind();
-
-        self.vbo
-            .unwrap()
-            .set_data(cast_slice(self.vertices.as_slice()));
 
+        self.vbos = self
+            .format
+            .encode(&self.vertices, &self.extra_attributes)
+            .iter()
+            .map(|stream| {
+                let vbo = Buffer::new(GL_STATIC_DRAW, GL_ARRAY_BUFFER)
+                    .expect("Failed to create vertex buffer");
+                vbo.bind();
+                vbo.set_data(stream);
+                vbo
+            })
+            .collect();
+
+        self.index_format = IndexFormat::for_vertex_count(self.vertices.len());
         self.ebo.unwrap().bind();
         self.ebo
             .unwrap()
-            .set_data(cast_slice(self.indicies.as_slice()));
-
-        //Calculate tangents and bi-tangents
-
-        glVertexAttribPointer(
-            0,
-            3,
-            GL_FLOAT,
-            GL_FALSE,
-            std::mem::size_of::<Vertex>().try_into().unwrap(),
-            std::ptr::null(),
-        );
-        glEnableVertexAttribArray(0);
+            .set_data(&self.index_format.encode(&self.indicies));
 
-        glVertexAttribPointer(
-            1,
-            3,
-            GL_FLOAT,
-            GL_FALSE,
-            std::mem::size_of::<Vertex>().try_into().unwrap(),
-            std::mem::size_of::<[f32; 3]>() as *const _,
-        );
-        glEnableVertexAttribArray(1);
+        self.format.setup_attributes(&self.vbos);
 
-        glVertexAttribPointer(
-            2,
-            2,
-            GL_FLOAT,
-            GL_FALSE,
-            std::mem::size_of::<Vertex>().try_into().unwrap(),
-            std::mem::size_of::<[f32; 6]>() as *const _,
-        );
-        glEnableVertexAttribArray(2);
+        self.vao.unwrap().unbind();
 
-        glVertexAttribPointer(
-            3,
-            3,
-            GL_FLOAT,
-            GL_FALSE,
-            std::mem::size_of::<Vertex>().try_into().unwrap(),
-            std::mem::size_of::<[f32; 8]>() as *const _,
-        );
-        glEnableVertexAttribArray(3);
+        self
+    }
+    //Creates the per-instance buffer and adds its attributes (with a divisor of 1) to the VAO.
+    //Must be called after setup
+    pub unsafe fn enable_instancing(&mut self) -> &Self {
+        let instance_vbo = Buffer::new(GL_DYNAMIC_DRAW, GL_ARRAY_BUFFER)
+            .expect("Failed to create instance buffer");
+        self.instance_vbo = Some(instance_vbo);
 
-        glVertexAttribPointer(
-            4,
-            3,
-            GL_FLOAT,
-            GL_FALSE,
-            std::mem::size_of::<Vertex>().try_into().unwrap(),
-            std::mem::size_of::<[f32; 11]>() as *const _,
-        );
-        glEnableVertexAttribArray(4);
+        self.vao.unwrap().bind();
+        instance_vbo.bind();
+
+        let stride: GLsizei = std::mem::size_of::<InstanceData>().try_into().unwrap();
+        //A mat4 attribute takes up four consecutive vec4 locations
+        for i in 0..6 {
+            let location = INSTANCE_ATTRIBUTE_LOCATION + i;
+            glVertexAttribPointer(
+                location,
+                4,
+                GL_FLOAT,
+                GL_FALSE,
+                stride,
+                (i as usize * std::mem::size_of::<[f32; 4]>()) as *const _,
+            );
+            glEnableVertexAttribArray(location);
+            glVertexAttribDivisor(location, 1);
+        }
 
         self.vao.unwrap().unbind();
+        instance_vbo.unbind();
 
         self
     }
+
+    //Replaces the per-instance data, enable_instancing has to have been called first
+    pub unsafe fn set_instances(&self, instances: &[InstanceData]) {
+        let instance_vbo = self
+            .instance_vbo
+            .expect("Instancing isn't enabled on this mesh");
+        instance_vbo.bind();
+        instance_vbo.set_data(cast_slice(instances));
+        instance_vbo.unbind();
+    }
+
+    pub fn draw_instanced(&self, instance_count: usize) {
+        unsafe {
+            self.vao.unwrap().bind();
+            glDrawElementsInstanced(
+                GL_TRIANGLES,
+                self.indicies.len() as i32 * 3,
+                self.index_format.gl_type(),
+                std::ptr::null(),
+                instance_count as i32,
+            );
+            gl_state::draw_call();
+        }
+    }
+
+    //Labels the mesh's GL objects (after setup) so they can be told apart in captures
+    pub unsafe fn set_label(&self, label: &str) {
+        if let Some(vao) = self.vao {
+            vao.set_label(&format!("{} VAO", label));
+        }
+        for (stream, vbo) in self.vbos.iter().enumerate() {
+            vbo.set_label(&format!("{} VBO {}", label, stream));
+        }
+        if let Some(ebo) = self.ebo {
+            ebo.set_label(&format!("{} EBO", label));
+        }
+        if let Some(instance_vbo) = self.instance_vbo {
+            instance_vbo.set_label(&format!("{} instances", label));
+        }
+    }
+    //Leaves the VAO bound, gl_state skips the bind when the next draw uses the same mesh
     pub fn draw(&self) {
         unsafe {
             //Print the combined lengths of the vertices and indicies
@@ -674,14 +1642,26 @@ impl Mesh {
             glDrawElements(
                 GL_TRIANGLES,
                 self.indicies.len() as i32 * 3,
-                GL_UNSIGNED_INT,
+                self.index_format.gl_type(),
                 std::ptr::null(),
             );
-            self.vao.unwrap().unbind();
+            gl_state::draw_call();
         }
     }
 }
 
+//Some unit vector perpendicular to n
+fn any_perpendicular(n: Vector3<f32>) -> Vector3<f32> {
+    let axis = if n.x.abs() < 0.9 {
+        Vector3::new(1.0, 0.0, 0.0)
+    } else {
+        Vector3::new(0.0, 1.0, 0.0)
+    };
+    n.cross(axis).normalize()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Camera {
     pub position: Vector3<f32>,
     pub target: Vector3<f32>,
@@ -689,6 +1669,15 @@ pub struct Camera {
     pub aspect: [f32; 2],
     pub near: f32,
     pub far: f32,
+    //Subpixel offset of the projection in normalized device coordinates, set each frame by TAA
+    #[serde(skip)]
+    pub jitter: Vector2<f32>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(Vector3::new(0.0, 0.0, 3.0), Vector3::new(0.0, 0.0, 0.0))
+    }
 }
 
 impl Camera {
@@ -700,6 +1689,7 @@ impl Camera {
             aspect: [4.0, 3.0],
             near: 0.1,
             far: 100.0,
+            jitter: Vector2::new(0.0, 0.0),
         }
     }
 
@@ -734,7 +1724,30 @@ impl Camera {
         Matrix4::look_at_rh(pos_p, target_p, up_p)
     }
 
+    //Backs the camera up along its view direction until the sphere fits the vertical field of view
+    pub fn frame(&mut self, sphere: &BoundingSphere) {
+        let direction = self.get_direction();
+        let distance = sphere.radius / (self.fov / 2.0).to_radians().sin();
+        self.target = sphere.center;
+        self.position = sphere.center - direction * distance;
+    }
+
+    //Diameter of a sphere on screen as a fraction of the screen height, infinite when the camera is inside it
+    pub fn projected_size(&self, center: Vector3<f32>, radius: f32) -> f32 {
+        let distance = (center - self.position).magnitude();
+        if distance <= radius {
+            return f32::INFINITY;
+        }
+        radius / (distance * (self.fov / 2.0).to_radians().tan())
+    }
+
+    //Includes the jitter, use this for rendering
     pub fn get_projection_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.jitter.extend(0.0)) * self.get_unjittered_projection_matrix()
+    }
+
+    //Without the jitter, for reprojecting between frames
+    pub fn get_unjittered_projection_matrix(&self) -> Matrix4<f32> {
         cgmath::PerspectiveFov {
             fovy: Deg(self.fov).into(),
             aspect: self.aspect[0] / self.aspect[1],
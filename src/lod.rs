@@ -0,0 +1,101 @@
+use crate::Mesh;
+use cgmath::{InnerSpace, Vector3};
+
+use std::rc::Rc;
+
+pub struct LodLevel {
+    pub mesh: Rc<Mesh>,
+    //Simplification error relative to the bounding box diagonal of the full detail mesh
+    pub error: f32,
+    //Largest projected size (fraction of the screen height) this level is used at
+    pub max_screen_size: f32,
+}
+
+//Progressively simplified versions of a mesh, level 0 is the full detail mesh
+pub struct LodChain {
+    pub levels: Vec<LodLevel>,
+    //Bounding sphere of the full detail mesh, in object space
+    pub center: Vector3<f32>,
+    pub radius: f32,
+    //Fraction of a threshold the projected size has to move past it before the level changes
+    pub hysteresis: f32,
+}
+
+impl LodChain {
+    //Generates up to level_count levels, each with about `reduction` times the triangles of the previous one.
+    //max_screen_error is the largest simplification error allowed on screen, as a fraction of the screen height
+    //(1.0 / 1080.0 is about a pixel at 1080p). Stops early when a level can't be simplified any further
+    pub fn generate(mesh: Mesh, level_count: usize, reduction: f32, max_screen_error: f32) -> Self {
+        let (center, radius) = (mesh.bounding_sphere.center, mesh.bounding_sphere.radius);
+        let diagonal = mesh.aabb.size().magnitude().max(f32::MIN_POSITIVE);
+
+        //Absolute errors, each level's error is relative to the level it was simplified from so they accumulate
+        let mut meshes = vec![mesh];
+        let mut errors = vec![0.0];
+        while meshes.len() < level_count {
+            let previous = meshes.last().unwrap();
+            let target = (previous.indicies.len() as f32 * reduction) as usize;
+            let simplified = previous.simplify(target, f32::MAX);
+            if simplified.mesh.indicies.len() as f32 > previous.indicies.len() as f32 * 0.95 {
+                break;
+            }
+            errors.push(errors.last().unwrap() + simplified.error * previous.aabb.size().magnitude());
+            meshes.push(simplified.mesh);
+        }
+
+        let levels = meshes
+            .into_iter()
+            .zip(errors)
+            .enumerate()
+            .map(|(index, (mesh, error))| {
+                //At a projected size s (diameter / screen height) the error covers error / diameter * s of the screen
+                let max_screen_size = if index == 0 || error <= 0.0 {
+                    f32::INFINITY
+                } else {
+                    max_screen_error * radius * 2.0 / error
+                };
+                LodLevel {
+                    mesh: Rc::new(mesh),
+                    error: error / diagonal,
+                    max_screen_size,
+                }
+            })
+            .collect();
+
+        Self {
+            levels,
+            center,
+            radius,
+            hysteresis: 0.15,
+        }
+    }
+
+    //Uploads every level, has to happen before the chain is shared with objects
+    pub unsafe fn setup(&mut self) {
+        for level in self.levels.iter_mut() {
+            Rc::get_mut(&mut level.mesh)
+                .expect("LOD meshes are shared, set the chain up before handing it to objects")
+                .setup();
+        }
+    }
+
+    //The coarsest level that is fine at screen_size, moving away from current only when screen_size is
+    //more than the hysteresis past the threshold
+    pub fn select(&self, screen_size: f32, current: usize) -> usize {
+        let coarsest = |scale: f32| {
+            self.levels
+                .iter()
+                .rposition(|level| screen_size <= level.max_screen_size * scale)
+                .unwrap_or(0)
+        };
+        let strict = coarsest(1.0 - self.hysteresis);
+        let loose = coarsest(1.0 + self.hysteresis);
+        if current > loose {
+            loose
+        } else if current < strict {
+            strict
+        } else {
+            current.min(self.levels.len() - 1)
+        }
+    }
+}
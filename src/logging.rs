@@ -0,0 +1,57 @@
+use colored::*;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+//Logger backend for the log facade, prints records as TRACE_<LEVEL>: message
+pub struct TraceLogger {
+    pub level: LevelFilter,
+}
+
+impl Log for TraceLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let prefix = "TRACE_";
+        let message = format!("{}", record.args());
+        match record.level() {
+            Level::Error => println!("{}{}: {}", prefix.red(), "ERROR".red(), message.white()),
+            Level::Warn => println!(
+                "{}{}: {}",
+                prefix.yellow(),
+                "WARNING".yellow(),
+                message.white()
+            ),
+            Level::Info => println!(
+                "{}{}: {}",
+                prefix.green(),
+                "INFO".green(),
+                message.white()
+            ),
+            Level::Debug => println!(
+                "{}{}: {}",
+                prefix.blue(),
+                "DEBUG".blue(),
+                message.white()
+            ),
+            Level::Trace => println!(
+                "{}{}: {}",
+                prefix.bright_black(),
+                "TRACE".bright_black(),
+                message.white()
+            ),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+//Installs the TraceLogger as the global logger, can only be called once
+pub fn init_logging(level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_boxed_logger(Box::new(TraceLogger { level }))?;
+    log::set_max_level(level);
+    Ok(())
+}
@@ -34,6 +34,7 @@ use std::{
     ffi::CString,
     fmt::{self, Display},
     path::Path,
+    rc::Rc,
     time::Instant,
 };
 
@@ -68,39 +69,6 @@ impl Default for PointLight {
 unsafe impl bytemuck::Zeroable for PointLight {}
 unsafe impl bytemuck::Pod for PointLight {}
 
-#[derive(Debug, Clone, Copy)]
-enum TraceLevel {
-    ERROR,
-    WARNING,
-    INFO,
-}
-
-fn trace(message: &str, level: TraceLevel) {
-    let prefix = "TRACE_";
-    let level_str = match level {
-        TraceLevel::ERROR => "ERROR",
-        TraceLevel::WARNING => "WARNING",
-        TraceLevel::INFO => "INFO",
-    };
-    match level {
-        TraceLevel::ERROR => println!("{}{}: {}", prefix.red(), level_str.red(), message.white()),
-        TraceLevel::WARNING => println!(
-            "{}{}: {}",
-            prefix.yellow(),
-            level_str.yellow(),
-            message.white()
-        ),
-        TraceLevel::INFO => println!(
-            "{}{}: {}",
-            prefix.green(),
-            level_str.green(),
-            message.white()
-        ),
-    }
-}
-
-
-
 // fn load_hdr_image(path: &Path) -> Vec<f32> {
 //     let mut f = std::fs::File::open(path).unwrap();
 //     let f = std::io::BufReader::new(f);
@@ -115,12 +83,17 @@ fn main() {
     let timer;
     let mut mouse_captured: bool = true;
     let mut movement: [bool; 4] = [false; 4]; //[forward, backward, left, right]
+    init_logging(log::LevelFilter::Info).expect("couldn't set up logging");
     let sdl = SDL::init(InitFlags::Everything).expect("couldn't start SDL");
 
     sdl.gl_set_attribute(SdlGlAttr::MajorVersion, 4).unwrap();
     sdl.gl_set_attribute(SdlGlAttr::MinorVersion, 3).unwrap();
     sdl.gl_set_attribute(SdlGlAttr::Profile, GlProfile::Core)
         .unwrap();
+    if cfg!(debug_assertions) {
+        sdl.gl_set_attribute(SdlGlAttr::Flags, ContextFlag::Debug)
+            .unwrap();
+    }
 
     let mut win = sdl
         .create_gl_window(
@@ -132,10 +105,18 @@ fn main() {
         )
         .expect("couldn't make a window and context");
 
-    trace("Window created", TraceLevel::INFO);
+    log::info!("Window created");
     win.set_swap_interval(SwapInterval::Vsync);
 
     let mut shader_program;
+    let material_textures: [(GLenum, GLuint); 5];
+    let mut post_stack;
+    let mut ssao;
+    let mut deferred;
+    let frustum_lines;
+    let mut render_path = RenderPath::Forward;
+    //Shows the occlusion buffer instead of the scene: None, Some(true) for raw, Some(false) for blurred
+    let mut ssao_debug_view: Option<bool> = None;
     let mut camera = Camera::new(Vector3::new(0.0, 1.0, 3.0), Vector3::new(0.0, 0.0, 0.0));
 
     timer = Instant::now();
@@ -147,16 +128,27 @@ fn main() {
 
     unsafe {
         load_gl_with(|f_name| win.get_proc_address(f_name));
+        load_gl_extensions_with(|f_name| win.get_proc_address(f_name));
+        enable_debug_output(DebugMessageFilter::noise_filtered());
         glClearColor(0.392, 0.584, 0.929, 1.0);
-        glEnable(GL_DEPTH_TEST);
+        gl_state::enable(GL_DEPTH_TEST);
         
-        sphere_object.mesh.setup();
-        plane_object.mesh.setup();
+        sphere_object.mesh_mut().setup();
+        plane_object.mesh_mut().setup();
+        sphere_object.mesh.set_label("monke");
+        plane_object.mesh.set_label("plane");
+        sphere_object.material = Rc::new(Material {
+            albedo: Vector3::new(1.0, 0.0, 0.0),
+            roughness: 1.0,
+            metallic: 1.0,
+            ..Default::default()
+        });
 
         let diffuse_map =
             Texture2D::<u8>::new(GL_TEXTURE0, "assets/textures/DiamondPlate008C_1K_Color.png")
                 .expect("Couldn't create texture");
         diffuse_map.bind();
+        diffuse_map.set_label("DiamondPlate008C_1K_Color");
         diffuse_map.set_wrap(GL_REPEAT);
         diffuse_map.set_filter(GL_LINEAR);
         diffuse_map.set_data(1024, 1024, GL_RGB, GL_RGB as GLint, GL_UNSIGNED_BYTE);
@@ -167,6 +159,7 @@ fn main() {
         )
         .expect("Couldn't create texture");
         roughness_map.bind();
+        roughness_map.set_label("DiamondPlate008C_1K_Roughness");
         roughness_map.set_wrap(GL_REPEAT);
         roughness_map.set_filter(GL_LINEAR);
         roughness_map.set_data(1024, 1024, GL_RED, GL_RED as GLint, GL_UNSIGNED_BYTE);
@@ -177,6 +170,7 @@ fn main() {
         )
         .expect("Couldn't create texture");
         normal_map.bind();
+        normal_map.set_label("DiamondPlate008C_1K_NormalGL");
         normal_map.set_wrap(GL_REPEAT);
         normal_map.set_filter(GL_LINEAR);
         normal_map.set_data(1024, 1024, GL_RGB, GL_RGB as GLint, GL_UNSIGNED_BYTE);
@@ -187,6 +181,7 @@ fn main() {
         )
         .expect("Couldn't create texture");
         metallic_map.bind();
+        metallic_map.set_label("DiamondPlate008C_1K_Metalness");
         metallic_map.set_wrap(GL_REPEAT);
         metallic_map.set_filter(GL_LINEAR);
         metallic_map.set_data(1024, 1024, GL_RED, GL_RED as GLint, GL_UNSIGNED_BYTE);
@@ -195,18 +190,25 @@ fn main() {
         let skybox_map = Texture2D::<f32>::new(GL_TEXTURE4, "assets/textures/hotel.hdr")
             .expect("Couldn't create texture");
         skybox_map.bind();
+        skybox_map.set_label("hotel.hdr");
         skybox_map.set_wrap(GL_CLAMP_TO_EDGE);
         skybox_map.set_filter(GL_LINEAR);
         skybox_map.set_data(2048, 2048, GL_RGB as GLint, GL_RGB, GL_UNSIGNED_BYTE);
+        //Post-processing uses the same units, so these get rebound every frame
+        material_textures = [
+            (GL_TEXTURE0, diffuse_map.id),
+            (GL_TEXTURE1, roughness_map.id),
+            (GL_TEXTURE2, normal_map.id),
+            (GL_TEXTURE3, metallic_map.id),
+            (GL_TEXTURE4, skybox_map.id),
+        ];
     
 
 
-        trace(
-            &format!("Assets loaded in {:?}", timer.elapsed()),
-            TraceLevel::INFO,
-        );
+        log::info!("Assets loaded in {:?}", timer.elapsed());
 
         shader_program = ShaderProgramBuilder::new()
+            .cache(ProgramCache::new(Path::new("cache/shaders")))
             .create_shader(
                 ShaderType::Vertex,
                 &shader_from_file(Path::new("assets/shaders/vertex_shader.vert")),
@@ -217,21 +219,69 @@ fn main() {
             )
             .link()
             .unwrap();
+        shader_program.set_label("PBR forward");
         shader_program.create_uniform(cstr!("M"));
+        shader_program.create_uniform(cstr!("N"));
         shader_program.create_uniform(cstr!("V"));
         shader_program.create_uniform(cstr!("P"));
         shader_program.create_uniform(cstr!("albedo"));
         shader_program.create_uniform(cstr!("roughness"));
         shader_program.create_uniform(cstr!("metallic"));
+        shader_program.create_uniform(cstr!("alpha"));
+        shader_program.create_uniform(cstr!("alpha_cutoff"));
         shader_program.create_uniform(cstr!("camera_pos"));
         shader_program.create_uniform(cstr!("diffuse_map"));
         shader_program.create_uniform(cstr!("roughness_map"));
         shader_program.create_uniform(cstr!("normal_map"));
         shader_program.create_uniform(cstr!("metallic_map"));
         shader_program.create_uniform(cstr!("equirectangular_map"));
-
-        glUseProgram(shader_program.0);
+        shader_program.create_uniform(cstr!("instanced"));
+        shader_program.create_uniform(cstr!("ao_map"));
+        shader_program.create_uniform(cstr!("ssao_enabled"));
+        shader_program.create_uniform(cstr!("emissive"));
+        shader_program.create_uniform(cstr!("light_count"));
+        shader_program.create_uniform(cstr!("light_position"));
+        shader_program.create_uniform(cstr!("light_color"));
+
+        gl_state::use_program(shader_program.0);
+
+        post_stack = PostStack::new(800, 600).expect("Couldn't create post-processing targets");
+        post_stack
+            .push(Taa::default())
+            .push(Bloom::default())
+            .push(ToneMapping::default())
+            .push(ColorGrading::default())
+            .push(Fxaa::default())
+            .push(Vignette::default())
+            .push(FilmGrain::default());
+        post_stack.set_enabled("color_grading", false);
+        //The window's own framebuffer only receives the finished image, so MSAA lives in the stack
+        post_stack.set_anti_aliasing(AntiAliasing::Msaa(4));
+
+        ssao = Ssao::new(800, 600).expect("Couldn't create SSAO targets");
+        deferred = DeferredRenderer::new(800, 600).expect("Couldn't create the G-buffer");
+        frustum_lines = FrustumLines::new().expect("Couldn't create the frustum lines");
+    }
+    let mut scene = Scene::new();
+    let monke = scene.add_object(sphere_object);
+    //The light the shader used to have hardcoded, with a ring of colored lights around it
+    scene.lights.push(Light::Point {
+        position: Vector3::new(0.0, 0.0, 1.0),
+        color: Vector3::new(1.0, 1.0, 1.0),
+        intensity: 2.0,
+        range: 10.0,
+    });
+    for i in 0..8 {
+        let angle = Deg(45.0 * i as f32);
+        scene.lights.push(Light::Point {
+            position: Vector3::new(2.0 * angle.cos(), 0.5, 2.0 * angle.sin()),
+            color: Vector3::new(angle.cos() * 0.5 + 0.5, 0.5, angle.sin() * 0.5 + 0.5),
+            intensity: 2.0,
+            range: 3.0,
+        });
     }
+    //Culling and light assignment go through the BVH, update_transforms keeps it fitted
+    scene.build_bvh();
     sdl.set_relative_mouse_mode(mouse_captured)
         .expect("Couldn't set relative mouse mode");
     let mut frame_start = sdl.get_ticks();
@@ -252,6 +302,64 @@ fn main() {
                         sdl.set_relative_mouse_mode(mouse_captured)
                             .expect("Couldn't set relative mouse mode");
                     }
+                    //Number keys toggle the post-processing effects in stack order
+                    let effect_keys = [
+                        Keycode::_1,
+                        Keycode::_2,
+                        Keycode::_3,
+                        Keycode::_4,
+                        Keycode::_5,
+                        Keycode::_6,
+                        Keycode::_7,
+                    ];
+                    if let Some(index) = effect_keys.iter().position(|&k| k == key.keycode) {
+                        if is_pressed && index < post_stack.passes.len() {
+                            let pass = &mut post_stack.passes[index];
+                            pass.enabled = !pass.enabled;
+                            log::info!("{} enabled: {}", pass.effect.name(), pass.enabled);
+                        }
+                    }
+                    if key.keycode == Keycode::M && is_pressed {
+                        let mut mode = post_stack.anti_aliasing().next();
+                        //The G-buffer isn't multisampled
+                        if render_path == RenderPath::Deferred && matches!(mode, AntiAliasing::Msaa(_)) {
+                            mode = mode.next();
+                        }
+                        unsafe { post_stack.set_anti_aliasing(mode) };
+                        log::info!("Anti-aliasing: {:?}", post_stack.anti_aliasing());
+                    }
+                    if key.keycode == Keycode::R && is_pressed {
+                        render_path = match render_path {
+                            RenderPath::Forward => RenderPath::Deferred,
+                            RenderPath::Deferred => RenderPath::Forward,
+                        };
+                        if render_path == RenderPath::Deferred && post_stack.msaa_samples() > 1 {
+                            log::info!("MSAA doesn't work with deferred shading, using FXAA");
+                            unsafe { post_stack.set_anti_aliasing(AntiAliasing::Fxaa) };
+                        }
+                        log::info!("Render path: {:?}", render_path);
+                    }
+                    if key.keycode == Keycode::F && is_pressed {
+                        //Culling keeps using the current frustum while the camera flies around it
+                        if scene.frozen_frustum.is_some() {
+                            scene.unfreeze_frustum();
+                        } else {
+                            scene.freeze_frustum(&camera);
+                        }
+                        log::info!("Frustum frozen: {}", scene.frozen_frustum.is_some());
+                    }
+                    if key.keycode == Keycode::O && is_pressed {
+                        ssao.enabled = !ssao.enabled;
+                        log::info!("SSAO enabled: {}", ssao.enabled);
+                    }
+                    if key.keycode == Keycode::P && is_pressed {
+                        //Cycles scene, raw occlusion and blurred occlusion
+                        ssao_debug_view = match ssao_debug_view {
+                            None => Some(true),
+                            Some(true) => Some(false),
+                            Some(false) => None,
+                        };
+                    }
                     if key.keycode == Keycode::W && is_pressed {
                         movement[0] = true;
                     } else if key.keycode == Keycode::W && !is_pressed {
@@ -307,7 +415,6 @@ fn main() {
         }
         let time = sdl.get_ticks() as f32 / 10.0_f32;
         let roughness_pingpong = ping_pong(time * 0.01, 0.0, 1.0);
-        let transform;
 
         if movement[0] {
             camera.set_position(camera.position + camera.get_direction() * delta_time * 3.0);
@@ -327,34 +434,65 @@ fn main() {
         }
 
         unsafe {
-            glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
-
-            transform = Matrix4::from_angle_y(Deg(time * 0.1));
-            shader_program.set_mat4("M", &transform);
-            shader_program.set_mat4("V", &camera.get_view_matrix());
-            shader_program.set_mat4("P", &camera.get_projection_matrix());
-            shader_program.set_vec3("camera_pos", &camera.position);
-            shader_program.set_vec3("albedo", &Vector3::new(1.0, 0.0, 0.0));
-            shader_program.set_float("metallic", 1.0);
-
+            scene.objects[monke].set_rotation(Quaternion::from_angle_y(Deg(time * 0.1)));
+            scene.update_transforms();
+            let taa_enabled = post_stack.is_enabled("taa");
+            match post_stack.get_mut::<Taa>() {
+                Some(taa) if taa_enabled => {
+                    taa.prepare(&mut camera, 800, 600);
+                    taa.render_object_motion(&mut scene, &camera, 800, 600);
+                }
+                _ => camera.jitter = Vector2::new(0.0, 0.0),
+            }
+            ssao.render(&mut scene, &camera);
 
-            shader_program.set_int("diffuse_map", 0);
-            shader_program.set_int("roughness_map", 1);
-            shader_program.set_int("normal_map", 2);
-            shader_program.set_int("metallic_map", 3);
+            let scene_pass = DebugGroup::new("Scene");
+            post_stack.begin();
+            for (unit, texture) in material_textures {
+                gl_state::bind_texture_unit(unit, GL_TEXTURE_2D, texture);
+            }
+            //Post-processing leaves its own program bound
+            for program in [&shader_program, deferred.geometry_program()] {
+                gl_state::use_program(program.0);
+                program.set_int("diffuse_map", 0);
+                program.set_int("roughness_map", 1);
+                program.set_int("normal_map", 2);
+                program.set_int("metallic_map", 3);
+            }
+            gl_state::use_program(shader_program.0);
             shader_program.set_int("equirectangular_map", 4);
-
-            shader_program.set_float("roughness", 1.0);
-
-            sphere_object.mesh.draw();
-
-            shader_program.set_vec3("albedo", &Vector3::new(1.0, 1.0, 1.0));
-            //plane_object.mesh.draw();
+            ssao.bind(&shader_program, GL_TEXTURE5);
+
+            match render_path {
+                RenderPath::Forward => scene.draw(&shader_program, &camera),
+                RenderPath::Deferred => deferred.draw(
+                    &mut scene,
+                    &camera,
+                    &mut post_stack,
+                    &shader_program,
+                    ssao.enabled.then_some(&ssao),
+                ),
+            }
+            if let Some(frustum) = &scene.frozen_frustum {
+                frustum_lines.draw(frustum, &camera, Vector3::new(1.0, 1.0, 0.0));
+            }
+            drop(scene_pass);
+            post_stack.end(0, time / 100.0);
+            if let Some(raw) = ssao_debug_view.filter(|_| ssao.enabled) {
+                ssao.draw_debug(0, 800, 600, raw);
+            }
         }
         win.swap_window();
+        let gl_stats = gl_state::end_frame();
         let msec = sdl.get_ticks() - frame_start;
         if msec > 0 {
-            win.set_title(&format!("{} - FPS: {}", WINDOW_TITLE, 1000 / msec));
+            win.set_title(&format!(
+                "{} - FPS: {} - Draws: {} - Binds skipped: {}",
+                WINDOW_TITLE,
+                1000 / msec,
+                gl_stats.draw_calls,
+                gl_stats.skipped
+            ));
         }
         last_frame = frame_start;
     }
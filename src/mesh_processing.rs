@@ -0,0 +1,419 @@
+//CPU side mesh clean-up and optimization. These work on Mesh::vertices/indicies, so they have to run before
+//Mesh::setup (or be followed by another setup) to have an effect on the GPU buffers
+use crate::{Mesh, VertIndicies, Vertex, VERTEX_SIZE};
+use cgmath::{InnerSpace, Vector3};
+
+use std::collections::HashMap;
+
+//Vertex and triangle counts before and after an operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshStats {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub triangles_before: usize,
+    pub triangles_after: usize,
+}
+
+impl MeshStats {
+    fn begin(mesh: &Mesh) -> Self {
+        Self {
+            vertices_before: mesh.vertices.len(),
+            vertices_after: mesh.vertices.len(),
+            triangles_before: mesh.indicies.len(),
+            triangles_after: mesh.indicies.len(),
+        }
+    }
+
+    fn end(mut self, mesh: &Mesh) -> Self {
+        self.vertices_after = mesh.vertices.len();
+        self.triangles_after = mesh.indicies.len();
+        self
+    }
+
+    //Negative when the operation had to split vertices
+    pub fn vertices_removed(&self) -> isize {
+        self.vertices_before as isize - self.vertices_after as isize
+    }
+
+    pub fn triangles_removed(&self) -> isize {
+        self.triangles_before as isize - self.triangles_after as isize
+    }
+}
+
+//Size of the simulated post-transform cache used by optimize_vertex_cache
+pub const VERTEX_CACHE_SIZE: usize = 32;
+
+fn position(v: &Vertex) -> Vector3<f32> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+fn face_normal(mesh: &Mesh, triangle: &VertIndicies) -> Vector3<f32> {
+    let p = triangle.map(|i| position(&mesh.vertices[i as usize]));
+    (p[1] - p[0]).cross(p[2] - p[0])
+}
+
+impl Mesh {
+    //Merges vertices whose attributes (all of them, so uv seams and hard edges survive) differ by at most epsilon
+    pub fn weld_vertices(&mut self, epsilon: f32) -> MeshStats {
+        let stats = MeshStats::begin(self);
+        let cell_size = epsilon.max(f32::MIN_POSITIVE) * 2.0;
+        let cell = |v: &Vertex| {
+            (
+                (v[0] / cell_size).floor() as i64,
+                (v[1] / cell_size).floor() as i64,
+                (v[2] / cell_size).floor() as i64,
+            )
+        };
+
+        let extras: Vec<&Vec<[f32; 4]>> = self.extra_attributes.values().collect();
+        let matches = |a: usize, b: usize| {
+            let va = &self.vertices[a];
+            let vb = &self.vertices[b];
+            (0..VERTEX_SIZE).all(|k| (va[k] - vb[k]).abs() <= epsilon)
+                && extras
+                    .iter()
+                    .all(|extra| match (extra.get(a), extra.get(b)) {
+                        (Some(ea), Some(eb)) => (0..4).all(|k| (ea[k] - eb[k]).abs() <= epsilon),
+                        (ea, eb) => ea.is_none() && eb.is_none(),
+                    })
+        };
+
+        //Spatial hash of the vertices kept so far, neighbouring cells are searched since
+        //two vertices within epsilon can fall on either side of a cell boundary
+        let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        let mut kept: Vec<usize> = Vec::new();
+        let mut remap: Vec<u32> = Vec::with_capacity(self.vertices.len());
+
+        for index in 0..self.vertices.len() {
+            let (x, y, z) = cell(&self.vertices[index]);
+            let mut found = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(candidates) = grid.get(&(x + dx, y + dy, z + dz)) {
+                            if let Some(&candidate) = candidates
+                                .iter()
+                                .find(|&&candidate| matches(kept[candidate], index))
+                            {
+                                found = Some(candidate);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+            remap.push(match found {
+                Some(candidate) => candidate as u32,
+                None => {
+                    kept.push(index);
+                    grid.entry((x, y, z)).or_default().push(kept.len() - 1);
+                    (kept.len() - 1) as u32
+                }
+            });
+        }
+
+        for triangle in self.indicies.iter_mut() {
+            *triangle = triangle.map(|i| remap[i as usize]);
+        }
+        self.select_vertices(&kept);
+        stats.end(self)
+    }
+
+    //Removes triangles that reference the same vertex twice or have (almost) no area, and any
+    //vertices no longer used by a triangle
+    pub fn remove_degenerate_triangles(&mut self) -> MeshStats {
+        let stats = MeshStats::begin(self);
+        let mut triangles = std::mem::take(&mut self.indicies);
+        triangles.retain(|triangle| {
+            triangle[0] != triangle[1]
+                && triangle[1] != triangle[2]
+                && triangle[0] != triangle[2]
+                && face_normal(self, triangle).magnitude2() > f32::EPSILON * f32::EPSILON
+        });
+        self.indicies = triangles;
+        self.remove_unused_vertices();
+        stats.end(self)
+    }
+
+    //Drops vertices that aren't referenced by any triangle
+    pub fn remove_unused_vertices(&mut self) -> MeshStats {
+        let stats = MeshStats::begin(self);
+        let mut used = vec![false; self.vertices.len()];
+        for &index in self.indicies.iter().flatten() {
+            used[index as usize] = true;
+        }
+        let kept: Vec<usize> = (0..self.vertices.len()).filter(|&i| used[i]).collect();
+        let mut remap = vec![0u32; self.vertices.len()];
+        for (new_index, &old_index) in kept.iter().enumerate() {
+            remap[old_index] = new_index as u32;
+        }
+        for triangle in self.indicies.iter_mut() {
+            *triangle = triangle.map(|i| remap[i as usize]);
+        }
+        self.select_vertices(&kept);
+        stats.end(self)
+    }
+
+    //Replaces the normals with angle weighted averages of the faces around each position.
+    //Faces whose normals are more than threshold_degrees apart don't smooth into each other, so hard edges
+    //stay hard. Vertices that end up with different normals on different faces are split
+    pub fn recompute_normals(&mut self, threshold_degrees: f32) -> MeshStats {
+        let stats = MeshStats::begin(self);
+        let cos_threshold = threshold_degrees.to_radians().cos();
+
+        let face_normals: Vec<Vector3<f32>> = self
+            .indicies
+            .iter()
+            .map(|triangle| {
+                let normal = face_normal(self, triangle);
+                if normal.magnitude2() > 0.0 {
+                    normal.normalize()
+                } else {
+                    normal
+                }
+            })
+            .collect();
+        //Interior angle of each triangle corner, weighting by angle keeps the result independent of how faces are triangulated
+        let corner_angles: Vec<[f32; 3]> = self
+            .indicies
+            .iter()
+            .map(|triangle| {
+                let p = triangle.map(|i| position(&self.vertices[i as usize]));
+                [0, 1, 2].map(|c| {
+                    let a = p[(c + 1) % 3] - p[c];
+                    let b = p[(c + 2) % 3] - p[c];
+                    if a.magnitude2() > 0.0 && b.magnitude2() > 0.0 {
+                        a.normalize().dot(b.normalize()).clamp(-1.0, 1.0).acos()
+                    } else {
+                        0.0
+                    }
+                })
+            })
+            .collect();
+
+        //Face corners around each position, bit exact positions are treated as the same point
+        let key = |v: &Vertex| (v[0].to_bits(), v[1].to_bits(), v[2].to_bits());
+        let mut corners_at: HashMap<(u32, u32, u32), Vec<(usize, usize)>> = HashMap::new();
+        for (face, triangle) in self.indicies.iter().enumerate() {
+            for (corner, &index) in triangle.iter().enumerate() {
+                corners_at
+                    .entry(key(&self.vertices[index as usize]))
+                    .or_default()
+                    .push((face, corner));
+            }
+        }
+
+        let mut assigned: Vec<Option<Vector3<f32>>> = vec![None; self.vertices.len()];
+        let mut splits: Vec<(usize, Vector3<f32>)> = Vec::new(); //(source vertex, normal) of new vertices
+        let mut split_lookup: HashMap<(usize, [u32; 3]), u32> = HashMap::new();
+
+        for face in 0..self.indicies.len() {
+            let own = face_normals[face];
+            for corner in 0..3 {
+                let index = self.indicies[face][corner] as usize;
+                let mut normal = Vector3::new(0.0, 0.0, 0.0);
+                for &(other, other_corner) in corners_at[&key(&self.vertices[index])].iter() {
+                    let other_normal = face_normals[other];
+                    if other == face || own.dot(other_normal) >= cos_threshold {
+                        normal += other_normal * corner_angles[other][other_corner];
+                    }
+                }
+                let normal = if normal.magnitude2() > 0.0 {
+                    normal.normalize()
+                } else {
+                    Vector3::new(0.0, 1.0, 0.0)
+                };
+
+                match assigned[index] {
+                    None => assigned[index] = Some(normal),
+                    Some(existing) if (existing - normal).magnitude2() <= 1e-10 => {}
+                    Some(_) => {
+                        let bits = [normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits()];
+                        let vertex_count = self.vertices.len();
+                        let new_index = *split_lookup.entry((index, bits)).or_insert_with(|| {
+                            splits.push((index, normal));
+                            (vertex_count + splits.len() - 1) as u32
+                        });
+                        self.indicies[face][corner] = new_index;
+                    }
+                }
+            }
+        }
+
+        for (index, normal) in assigned.iter().enumerate() {
+            if let Some(normal) = normal {
+                self.vertices[index][3..6].copy_from_slice(&[normal.x, normal.y, normal.z]);
+            }
+        }
+        for (source, normal) in splits {
+            let mut vertex = self.vertices[source];
+            vertex[3..6].copy_from_slice(&[normal.x, normal.y, normal.z]);
+            self.vertices.push(vertex);
+            for extra in self.extra_attributes.values_mut() {
+                if let Some(value) = extra.get(source).copied() {
+                    extra.push(value);
+                }
+            }
+        }
+        stats.end(self)
+    }
+
+    //Reorders triangles for the post-transform vertex cache (Tom Forsyth's linear-speed optimizer)
+    pub fn optimize_vertex_cache(&mut self) -> MeshStats {
+        let stats = MeshStats::begin(self);
+        const CACHE_DECAY_POWER: f32 = 1.5;
+        const LAST_TRIANGLE_SCORE: f32 = 0.75;
+        const VALENCE_BOOST_SCALE: f32 = 2.0;
+        const VALENCE_BOOST_POWER: f32 = 0.5;
+
+        let vertex_count = self.vertices.len();
+        let triangle_count = self.indicies.len();
+
+        let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+        for (triangle, indices) in self.indicies.iter().enumerate() {
+            for &index in indices.iter() {
+                vertex_triangles[index as usize].push(triangle);
+            }
+        }
+        let mut remaining: Vec<usize> = vertex_triangles.iter().map(|t| t.len()).collect();
+        let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+
+        let score = |cache_position: Option<usize>, remaining: usize| -> f32 {
+            if remaining == 0 {
+                return -1.0;
+            }
+            let mut score = match cache_position {
+                None => 0.0,
+                Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+                Some(position) => {
+                    let scaler = 1.0 / (VERTEX_CACHE_SIZE - 3) as f32;
+                    (1.0 - (position - 3) as f32 * scaler).powf(CACHE_DECAY_POWER)
+                }
+            };
+            score += VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER);
+            score
+        };
+
+        let mut vertex_score: Vec<f32> = (0..vertex_count)
+            .map(|v| score(None, remaining[v]))
+            .collect();
+        let mut triangle_score: Vec<f32> = self
+            .indicies
+            .iter()
+            .map(|t| t.iter().map(|&i| vertex_score[i as usize]).sum())
+            .collect();
+        let mut emitted = vec![false; triangle_count];
+        let mut cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+        let mut order: Vec<VertIndicies> = Vec::with_capacity(triangle_count);
+
+        let mut best: Option<usize> = None;
+        while order.len() < triangle_count {
+            //Fall back to a full scan when none of the cached vertices has triangles left
+            let triangle = match best {
+                Some(triangle) => triangle,
+                None => (0..triangle_count)
+                    .filter(|&t| !emitted[t])
+                    .max_by(|&a, &b| triangle_score[a].total_cmp(&triangle_score[b]))
+                    .unwrap(),
+            };
+            emitted[triangle] = true;
+            let indices = self.indicies[triangle];
+            order.push(indices);
+
+            for &index in indices.iter() {
+                let v = index as usize;
+                remaining[v] -= 1;
+                vertex_triangles[v].retain(|&t| t != triangle);
+                cache.retain(|&cached| cached != index);
+            }
+            //Most recently used vertices go to the front
+            for &index in indices.iter().rev() {
+                cache.insert(0, index);
+            }
+            let evicted: Vec<u32> = if cache.len() > VERTEX_CACHE_SIZE {
+                cache.split_off(VERTEX_CACHE_SIZE)
+            } else {
+                Vec::new()
+            };
+            for &index in evicted.iter() {
+                cache_position[index as usize] = None;
+            }
+            for (position, &index) in cache.iter().enumerate() {
+                cache_position[index as usize] = Some(position);
+            }
+
+            //Rescore the touched vertices and their triangles, picking the best candidate as we go
+            best = None;
+            let mut best_score = -1.0;
+            for &index in cache.iter().chain(evicted.iter()) {
+                let v = index as usize;
+                let new_score = score(cache_position[v], remaining[v]);
+                let delta = new_score - vertex_score[v];
+                vertex_score[v] = new_score;
+                for &t in vertex_triangles[v].iter() {
+                    triangle_score[t] += delta;
+                }
+            }
+            for &index in cache.iter() {
+                for &t in vertex_triangles[index as usize].iter() {
+                    if triangle_score[t] > best_score {
+                        best_score = triangle_score[t];
+                        best = Some(t);
+                    }
+                }
+            }
+        }
+
+        self.indicies = order;
+        stats.end(self)
+    }
+
+    //Renumbers vertices in the order the index buffer first uses them, so vertex fetches walk memory
+    //mostly forwards. Unreferenced vertices are dropped. Run after optimize_vertex_cache
+    pub fn optimize_vertex_fetch(&mut self) -> MeshStats {
+        let stats = MeshStats::begin(self);
+        let mut remap: Vec<Option<u32>> = vec![None; self.vertices.len()];
+        let mut order: Vec<usize> = Vec::with_capacity(self.vertices.len());
+        for triangle in self.indicies.iter_mut() {
+            for index in triangle.iter_mut() {
+                let old = *index as usize;
+                *index = *remap[old].get_or_insert_with(|| {
+                    order.push(old);
+                    (order.len() - 1) as u32
+                });
+            }
+        }
+        self.select_vertices(&order);
+        stats.end(self)
+    }
+
+    //Average cache miss ratio: vertex shader invocations per triangle with a FIFO cache of cache_size.
+    //0.5 is the best possible for large regular meshes, 3.0 the worst
+    pub fn acmr(&self, cache_size: usize) -> f32 {
+        if self.indicies.is_empty() {
+            return 0.0;
+        }
+        let mut cache: std::collections::VecDeque<u32> = std::collections::VecDeque::new();
+        let mut misses = 0;
+        for &index in self.indicies.iter().flatten() {
+            if !cache.contains(&index) {
+                misses += 1;
+                cache.push_back(index);
+                if cache.len() > cache_size {
+                    cache.pop_front();
+                }
+            }
+        }
+        misses as f32 / self.indicies.len() as f32
+    }
+
+    //Keeps only the given vertices (and their extra attributes), in that order.
+    //The indices must already refer to positions in `kept`. Dropped vertices can shrink the bounds
+    fn select_vertices(&mut self, kept: &[usize]) {
+        self.vertices = kept.iter().map(|&i| self.vertices[i]).collect();
+        for extra in self.extra_attributes.values_mut() {
+            *extra = kept.iter().filter_map(|&i| extra.get(i).copied()).collect();
+        }
+        self.update_bounds();
+    }
+}
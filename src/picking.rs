@@ -0,0 +1,341 @@
+use crate::debug_output::{label_object, DebugGroup};
+use crate::gl_state;
+use crate::{Camera, Frustum, Mesh, ObjectHandle, Ray, Scene, ShaderProgram};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector2, Vector3};
+use ogl33::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshHit {
+    pub triangle: usize,
+    //Along the ray, in units of the ray direction's length
+    pub distance: f32,
+    //Weights of the triangle's three vertices
+    pub barycentric: Vector3<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub object: ObjectHandle,
+    pub triangle: usize,
+    pub barycentric: Vector3<f32>,
+    pub distance: f32,
+    //World space position and interpolated, world space vertex normal
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
+}
+
+//Möller-Trumbore, two sided. Returns (distance, u, v) with the hit at (1 - u - v) * p0 + u * p1 + v * p2
+pub fn intersect_triangle(
+    ray: &Ray,
+    p0: Vector3<f32>,
+    p1: Vector3<f32>,
+    p2: Vector3<f32>,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON * edge1.magnitude() * edge2.magnitude() {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = ray.origin - p0;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = edge2.dot(q) * inverse;
+    if distance < 0.0 {
+        return None;
+    }
+    Some((distance, u, v))
+}
+
+impl Mesh {
+    //Closest triangle hit by the ray (in object space) within max_distance, tests every triangle
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<MeshHit> {
+        let position = |index: u32| {
+            let v = &self.vertices[index as usize];
+            Vector3::new(v[0], v[1], v[2])
+        };
+        let mut best: Option<MeshHit> = None;
+        for (triangle, indices) in self.indicies.iter().enumerate() {
+            let limit = best.map_or(max_distance, |hit| hit.distance);
+            if let Some((distance, u, v)) = intersect_triangle(
+                ray,
+                position(indices[0]),
+                position(indices[1]),
+                position(indices[2]),
+            ) {
+                if distance <= limit {
+                    best = Some(MeshHit {
+                        triangle,
+                        distance,
+                        barycentric: Vector3::new(1.0 - u - v, u, v),
+                    });
+                }
+            }
+        }
+        best
+    }
+}
+
+impl Camera {
+    //Ray from the camera through the center of a pixel, x and y are in pixels from the top left corner
+    //(as mouse coordinates are) of a width x height viewport
+    pub fn screen_ray(&self, x: f32, y: f32, width: f32, height: f32) -> Ray {
+        let ndc_x = 2.0 * (x + 0.5) / width - 1.0;
+        let ndc_y = 1.0 - 2.0 * (y + 0.5) / height;
+        let inverse = (self.get_projection_matrix() * self.get_view_matrix())
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        let unproject = |z: f32| {
+            let p = inverse * cgmath::Vector4::new(ndc_x, ndc_y, z, 1.0);
+            p.truncate() / p.w
+        };
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+        Ray::new(near, far - near)
+    }
+}
+
+impl Scene {
+    //Closest object triangle along the ray. Bounds are tested first (through the BVH when there is one),
+    //then the triangles of the objects whose bounds are hit, nearest bounds first
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<RayHit> {
+        let candidates: Vec<(ObjectHandle, f32)> = match self.current_bvh() {
+            Some(bvh) => bvh
+                .query_ray(ray, max_distance)
+                .into_iter()
+                .filter_map(|(slot, distance)| Some((self.objects.handle_at(slot)?, distance)))
+                .collect(),
+            None => {
+                let mut candidates: Vec<(ObjectHandle, f32)> = self
+                    .objects
+                    .iter()
+                    .filter_map(|(handle, object)| {
+                        object
+                            .world_aabb
+                            .intersect_ray(ray, max_distance)
+                            .map(|distance| (handle, distance))
+                    })
+                    .collect();
+                candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+                candidates
+            }
+        };
+
+        let mut best: Option<(ObjectHandle, MeshHit)> = None;
+        for (handle, entry) in candidates {
+            let limit = best.map_or(max_distance, |(_, hit)| hit.distance);
+            if entry > limit {
+                break;
+            }
+            let object = &self.objects[handle];
+            let inverse = match object.model_matrix.invert() {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            //Not normalized, so distances along the local ray are the same as along the world ray
+            let local = Ray {
+                origin: (inverse * ray.origin.extend(1.0)).truncate(),
+                direction: (inverse * ray.direction.extend(0.0)).truncate(),
+            };
+            if let Some(hit) = object.mesh.raycast(&local, limit) {
+                best = Some((handle, hit));
+            }
+        }
+
+        best.map(|(handle, hit)| {
+            let object = &self.objects[handle];
+            let mesh = &object.mesh;
+            let corners = mesh.indicies[hit.triangle].map(|i| &mesh.vertices[i as usize]);
+            let weights = [hit.barycentric.x, hit.barycentric.y, hit.barycentric.z];
+            let mut normal = Vector3::new(0.0, 0.0, 0.0);
+            let mut uv = Vector2::new(0.0, 0.0);
+            for (v, w) in corners.iter().zip(weights) {
+                normal += Vector3::new(v[3], v[4], v[5]) * w;
+                uv += Vector2::new(v[6], v[7]) * w;
+            }
+            let normal = object.normal_matrix * normal;
+            RayHit {
+                object: handle,
+                triangle: hit.triangle,
+                barycentric: hit.barycentric,
+                distance: hit.distance,
+                position: ray.at(hit.distance),
+                normal: if normal.magnitude2() > 0.0 {
+                    normal.normalize()
+                } else {
+                    normal
+                },
+                uv,
+            }
+        })
+    }
+}
+
+//Offscreen target the scene is drawn into with each object's arena slot + 1 as an unsigned integer color,
+//reading one texel back gives pixel exact picking. 0 means nothing was drawn there
+pub struct IdBuffer {
+    pub framebuffer: GLuint,
+    pub id_texture: GLuint,
+    pub depth_renderbuffer: GLuint,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl IdBuffer {
+    pub unsafe fn new(width: i32, height: i32) -> Option<Self> {
+        let mut framebuffer = 0;
+        glGenFramebuffers(1, &mut framebuffer);
+        let mut id_texture = 0;
+        glGenTextures(1, &mut id_texture);
+        let mut depth_renderbuffer = 0;
+        glGenRenderbuffers(1, &mut depth_renderbuffer);
+        if framebuffer == 0 || id_texture == 0 || depth_renderbuffer == 0 {
+            return None;
+        }
+
+        let mut id_buffer = Self {
+            framebuffer,
+            id_texture,
+            depth_renderbuffer,
+            width: 0,
+            height: 0,
+        };
+        id_buffer.resize(width, height);
+
+        glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+        glFramebufferTexture2D(
+            GL_FRAMEBUFFER,
+            GL_COLOR_ATTACHMENT0,
+            GL_TEXTURE_2D,
+            id_texture,
+            0,
+        );
+        glFramebufferRenderbuffer(
+            GL_FRAMEBUFFER,
+            GL_DEPTH_ATTACHMENT,
+            GL_RENDERBUFFER,
+            depth_renderbuffer,
+        );
+        let status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
+        glBindFramebuffer(GL_FRAMEBUFFER, 0);
+        if status != GL_FRAMEBUFFER_COMPLETE {
+            log::error!("ID buffer framebuffer is incomplete: 0x{:x}", status);
+            id_buffer.delete();
+            return None;
+        }
+
+        label_object(GL_FRAMEBUFFER, framebuffer, "ID buffer");
+        label_object(GL_TEXTURE, id_texture, "ID buffer ids");
+        label_object(GL_RENDERBUFFER, depth_renderbuffer, "ID buffer depth");
+        Some(id_buffer)
+    }
+
+    //Reallocates the attachments, call when the window size changes
+    pub unsafe fn resize(&mut self, width: i32, height: i32) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+
+        gl_state::bind_texture(GL_TEXTURE_2D, self.id_texture);
+        glTexImage2D(
+            GL_TEXTURE_2D,
+            0,
+            GL_R32UI as GLint,
+            self.width,
+            self.height,
+            0,
+            GL_RED_INTEGER,
+            GL_UNSIGNED_INT,
+            std::ptr::null(),
+        );
+        //Integer textures can't be filtered
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_NEAREST as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST as GLint);
+        gl_state::bind_texture(GL_TEXTURE_2D, 0);
+
+        glBindRenderbuffer(GL_RENDERBUFFER, self.depth_renderbuffer);
+        glRenderbufferStorage(
+            GL_RENDERBUFFER,
+            GL_DEPTH_COMPONENT24,
+            self.width,
+            self.height,
+        );
+        glBindRenderbuffer(GL_RENDERBUFFER, 0);
+    }
+
+    //Draws the objects in the camera's frustum into the buffer. The program needs the "M", "V", "P" and
+    //"object_id" uniforms, see assets/shaders/id_buffer.vert/frag. The previous framebuffer binding and
+    //viewport are restored afterwards
+    pub unsafe fn render(&self, scene: &Scene, program: &ShaderProgram, camera: &Camera) {
+        let _group = DebugGroup::new("IdBuffer::render");
+        let mut previous_framebuffer = 0;
+        glGetIntegerv(GL_DRAW_FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+        let mut viewport = [0; 4];
+        glGetIntegerv(GL_VIEWPORT, viewport.as_mut_ptr());
+
+        glBindFramebuffer(GL_FRAMEBUFFER, self.framebuffer);
+        glViewport(0, 0, self.width, self.height);
+        let clear = [0u32; 4];
+        glClearBufferuiv(GL_COLOR, 0, clear.as_ptr());
+        glClear(GL_DEPTH_BUFFER_BIT);
+        gl_state::enable(GL_DEPTH_TEST);
+
+        gl_state::use_program(program.0);
+        program.set_mat4("V", &camera.get_view_matrix());
+        program.set_mat4("P", &camera.get_projection_matrix());
+        let frustum = Frustum::from_camera(camera);
+        for (handle, object) in scene.objects.iter() {
+            if object.mesh.vao.is_none()
+                || !frustum.intersects(&object.world_aabb, &object.world_sphere)
+            {
+                continue;
+            }
+            program.set_mat4("M", &object.model_matrix);
+            program.set_int("object_id", handle.slot() as i32 + 1);
+            object.mesh.draw();
+        }
+
+        glBindFramebuffer(GL_FRAMEBUFFER, previous_framebuffer as GLuint);
+        glViewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+    }
+
+    //Object under the pixel (from the top left corner, like mouse coordinates) as of the last render.
+    //Render again after removing objects, their slots can be reused
+    pub unsafe fn pick(&self, scene: &Scene, x: i32, y: i32) -> Option<ObjectHandle> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        let mut previous_framebuffer = 0;
+        glGetIntegerv(GL_READ_FRAMEBUFFER_BINDING, &mut previous_framebuffer);
+        glBindFramebuffer(GL_READ_FRAMEBUFFER, self.framebuffer);
+        glReadBuffer(GL_COLOR_ATTACHMENT0);
+        let mut id: u32 = 0;
+        glReadPixels(
+            x,
+            self.height - 1 - y,
+            1,
+            1,
+            GL_RED_INTEGER,
+            GL_UNSIGNED_INT,
+            &mut id as *mut u32 as *mut c_void,
+        );
+        glBindFramebuffer(GL_READ_FRAMEBUFFER, previous_framebuffer as GLuint);
+        scene.objects.handle_at(id.checked_sub(1)? as usize)
+    }
+
+    pub unsafe fn delete(&self) {
+        glDeleteFramebuffers(1, &self.framebuffer);
+        glDeleteTextures(1, &self.id_texture);
+        gl_state::forget_texture(self.id_texture);
+        glDeleteRenderbuffers(1, &self.depth_renderbuffer);
+    }
+}
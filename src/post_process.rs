@@ -0,0 +1,974 @@
+//Post-processing: the scene renders into an HDR target, then an ordered list of full-screen effects
+//ping-pongs between two more targets, the last enabled effect writing to the output framebuffer
+use crate::debug_output::{label_object, DebugGroup};
+use crate::gl_state;
+use crate::{shader_from_file, ShaderProgram, ShaderProgramBuilder, ShaderType, VertexArray};
+use cgmath::Vector3;
+use cstr::cstr;
+use ogl33::*;
+
+use std::{any::Any, ffi::CStr, fmt, path::Path};
+
+//Framebuffer with a sampleable color texture and optionally a depth texture
+pub struct RenderTarget {
+    pub framebuffer: GLuint,
+    pub color_texture: GLuint,
+    pub depth_texture: Option<GLuint>,
+    pub internal_format: GLenum,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl RenderTarget {
+    pub unsafe fn new(
+        width: i32,
+        height: i32,
+        internal_format: GLenum,
+        with_depth: bool,
+    ) -> Option<Self> {
+        let mut framebuffer = 0;
+        glGenFramebuffers(1, &mut framebuffer);
+        let mut color_texture = 0;
+        glGenTextures(1, &mut color_texture);
+        let mut depth_texture = 0;
+        if with_depth {
+            glGenTextures(1, &mut depth_texture);
+        }
+        if framebuffer == 0 || color_texture == 0 || (with_depth && depth_texture == 0) {
+            return None;
+        }
+
+        let mut target = Self {
+            framebuffer,
+            color_texture,
+            depth_texture: with_depth.then_some(depth_texture),
+            internal_format,
+            width: 0,
+            height: 0,
+        };
+        target.resize(width, height);
+
+        glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+        glFramebufferTexture2D(
+            GL_FRAMEBUFFER,
+            GL_COLOR_ATTACHMENT0,
+            GL_TEXTURE_2D,
+            color_texture,
+            0,
+        );
+        if let Some(depth_texture) = target.depth_texture {
+            glFramebufferTexture2D(
+                GL_FRAMEBUFFER,
+                GL_DEPTH_ATTACHMENT,
+                GL_TEXTURE_2D,
+                depth_texture,
+                0,
+            );
+        }
+        let status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
+        glBindFramebuffer(GL_FRAMEBUFFER, 0);
+        if status != GL_FRAMEBUFFER_COMPLETE {
+            log::error!("Render target framebuffer is incomplete: 0x{:x}", status);
+            target.delete();
+            return None;
+        }
+        Some(target)
+    }
+
+    //Reallocates the textures, their contents are undefined afterwards
+    pub unsafe fn resize(&mut self, width: i32, height: i32) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+        let mut textures = vec![(self.color_texture, self.internal_format, GL_RGBA, GL_FLOAT)];
+        if let Some(depth_texture) = self.depth_texture {
+            textures.push((
+                depth_texture,
+                GL_DEPTH_COMPONENT24,
+                GL_DEPTH_COMPONENT,
+                GL_FLOAT,
+            ));
+        }
+        for (texture, internal_format, format, type_) in textures {
+            gl_state::bind_texture(GL_TEXTURE_2D, texture);
+            glTexImage2D(
+                GL_TEXTURE_2D,
+                0,
+                internal_format as GLint,
+                self.width,
+                self.height,
+                0,
+                format,
+                type_,
+                std::ptr::null(),
+            );
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as GLint);
+        }
+        gl_state::bind_texture(GL_TEXTURE_2D, 0);
+    }
+
+    //Binds the framebuffer and sets the viewport to cover it
+    pub unsafe fn bind(&self) {
+        glBindFramebuffer(GL_FRAMEBUFFER, self.framebuffer);
+        glViewport(0, 0, self.width, self.height);
+    }
+
+    pub unsafe fn set_label(&self, label: &str) {
+        label_object(GL_FRAMEBUFFER, self.framebuffer, label);
+        label_object(GL_TEXTURE, self.color_texture, &format!("{} color", label));
+        if let Some(depth_texture) = self.depth_texture {
+            label_object(GL_TEXTURE, depth_texture, &format!("{} depth", label));
+        }
+    }
+
+    pub unsafe fn delete(&self) {
+        glDeleteFramebuffers(1, &self.framebuffer);
+        glDeleteTextures(1, &self.color_texture);
+        gl_state::forget_texture(self.color_texture);
+        if let Some(depth_texture) = self.depth_texture {
+            glDeleteTextures(1, &depth_texture);
+            gl_state::forget_texture(depth_texture);
+        }
+    }
+}
+
+//Multisampled color and depth renderbuffers for MSAA, resolved into a RenderTarget of the same size
+pub struct MultisampleTarget {
+    pub framebuffer: GLuint,
+    pub color_renderbuffer: GLuint,
+    pub depth_renderbuffer: GLuint,
+    pub internal_format: GLenum,
+    pub samples: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+//Most samples the driver allows for multisampled framebuffers
+pub unsafe fn max_samples() -> i32 {
+    let mut samples = 0;
+    glGetIntegerv(GL_MAX_SAMPLES, &mut samples);
+    samples
+}
+
+impl MultisampleTarget {
+    pub unsafe fn new(
+        width: i32,
+        height: i32,
+        internal_format: GLenum,
+        samples: i32,
+    ) -> Option<Self> {
+        let mut framebuffer = 0;
+        glGenFramebuffers(1, &mut framebuffer);
+        let mut renderbuffers = [0; 2];
+        glGenRenderbuffers(2, renderbuffers.as_mut_ptr());
+        if framebuffer == 0 || renderbuffers.contains(&0) {
+            return None;
+        }
+
+        let mut target = Self {
+            framebuffer,
+            color_renderbuffer: renderbuffers[0],
+            depth_renderbuffer: renderbuffers[1],
+            internal_format,
+            samples,
+            width: 0,
+            height: 0,
+        };
+        target.resize(width, height);
+
+        glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+        glFramebufferRenderbuffer(
+            GL_FRAMEBUFFER,
+            GL_COLOR_ATTACHMENT0,
+            GL_RENDERBUFFER,
+            target.color_renderbuffer,
+        );
+        glFramebufferRenderbuffer(
+            GL_FRAMEBUFFER,
+            GL_DEPTH_ATTACHMENT,
+            GL_RENDERBUFFER,
+            target.depth_renderbuffer,
+        );
+        let status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
+        glBindFramebuffer(GL_FRAMEBUFFER, 0);
+        if status != GL_FRAMEBUFFER_COMPLETE {
+            log::error!("Multisampled framebuffer is incomplete: 0x{:x}", status);
+            target.delete();
+            return None;
+        }
+        Some(target)
+    }
+
+    //Reallocates the renderbuffers, their contents are undefined afterwards
+    pub unsafe fn resize(&mut self, width: i32, height: i32) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+        //Depth matches RenderTarget's so it can be resolved into it
+        for (renderbuffer, internal_format) in [
+            (self.color_renderbuffer, self.internal_format),
+            (self.depth_renderbuffer, GL_DEPTH_COMPONENT24),
+        ] {
+            glBindRenderbuffer(GL_RENDERBUFFER, renderbuffer);
+            glRenderbufferStorageMultisample(
+                GL_RENDERBUFFER,
+                self.samples,
+                internal_format,
+                self.width,
+                self.height,
+            );
+        }
+        glBindRenderbuffer(GL_RENDERBUFFER, 0);
+    }
+
+    pub unsafe fn bind(&self) {
+        glBindFramebuffer(GL_FRAMEBUFFER, self.framebuffer);
+        glViewport(0, 0, self.width, self.height);
+    }
+
+    //Averages the samples of the color into target, depth (which can't be averaged) takes one sample
+    pub unsafe fn resolve(&self, target: &RenderTarget) {
+        glBindFramebuffer(GL_READ_FRAMEBUFFER, self.framebuffer);
+        glBindFramebuffer(GL_DRAW_FRAMEBUFFER, target.framebuffer);
+        let mask = if target.depth_texture.is_some() {
+            GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT
+        } else {
+            GL_COLOR_BUFFER_BIT
+        };
+        glBlitFramebuffer(
+            0,
+            0,
+            self.width,
+            self.height,
+            0,
+            0,
+            target.width,
+            target.height,
+            mask,
+            GL_NEAREST,
+        );
+        glBindFramebuffer(GL_FRAMEBUFFER, 0);
+    }
+
+    pub unsafe fn set_label(&self, label: &str) {
+        label_object(GL_FRAMEBUFFER, self.framebuffer, label);
+        label_object(
+            GL_RENDERBUFFER,
+            self.color_renderbuffer,
+            &format!("{} color", label),
+        );
+        label_object(
+            GL_RENDERBUFFER,
+            self.depth_renderbuffer,
+            &format!("{} depth", label),
+        );
+    }
+
+    pub unsafe fn delete(&self) {
+        glDeleteFramebuffers(1, &self.framebuffer);
+        glDeleteRenderbuffers(1, &self.color_renderbuffer);
+        glDeleteRenderbuffers(1, &self.depth_renderbuffer);
+    }
+}
+
+//What an effect gets to work with. The output framebuffer is bound with its viewport when apply is called
+pub struct PostContext<'a> {
+    //Color texture written by the previous effect (or the scene)
+    pub input: GLuint,
+    //The HDR scene color and depth, as rendered
+    pub scene: &'a RenderTarget,
+    pub output_framebuffer: GLuint,
+    pub width: i32,
+    pub height: i32,
+    //Seconds, for animated effects
+    pub time: f32,
+    pub frame: u64,
+    fullscreen_vao: VertexArray,
+}
+
+impl PostContext<'_> {
+    //Rebinds the output, for effects that render intermediate passes into their own targets
+    pub unsafe fn bind_output(&self) {
+        glBindFramebuffer(GL_FRAMEBUFFER, self.output_framebuffer);
+        glViewport(0, 0, self.width, self.height);
+    }
+
+    pub unsafe fn draw_fullscreen(&self) {
+        self.fullscreen_vao.bind();
+        glDrawArrays(GL_TRIANGLES, 0, 3);
+        gl_state::draw_call();
+    }
+}
+
+pub trait PostEffect: Any {
+    //Used to look the effect up in the stack
+    fn name(&self) -> &'static str;
+
+    //Reads context.input (and whatever else it needs) and draws into the bound output
+    unsafe fn apply(&mut self, context: &PostContext);
+
+    //For effects with their own targets, called when the stack is resized
+    unsafe fn resize(&mut self, _width: i32, _height: i32) {}
+
+    //Frees the effect's GL objects, called by PostStack::delete. Anything built lazily is built again
+    //if the effect is applied afterwards
+    unsafe fn delete(&mut self) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+pub struct PostPass {
+    pub effect: Box<dyn PostEffect>,
+    pub enabled: bool,
+}
+
+//Builds a program from the full-screen vertex shader and one of the effect fragment shaders in
+//assets/shaders/post, "input_texture" is always created
+pub unsafe fn post_program(fragment: &str, uniforms: &[&CStr]) -> ShaderProgram {
+    let mut program = ShaderProgramBuilder::new()
+        .create_shader(
+            ShaderType::Vertex,
+            &shader_from_file(Path::new("assets/shaders/post/fullscreen.vert")),
+        )
+        .create_shader(
+            ShaderType::Fragment,
+            &shader_from_file(&Path::new("assets/shaders/post").join(fragment)),
+        )
+        .link()
+        .expect("Failed to link post-processing program");
+    program.set_label(fragment);
+    program.create_uniform(cstr!("input_texture"));
+    for uniform in uniforms {
+        program.create_uniform(uniform);
+    }
+    program
+}
+
+//Binds program with the context's input on texture unit 0
+unsafe fn bind_input(program: &ShaderProgram, context: &PostContext) {
+    gl_state::use_program(program.0);
+    gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, context.input);
+    program.set_int("input_texture", 0);
+}
+
+pub struct PostStack {
+    //Run in order, disabled passes are skipped
+    pub passes: Vec<PostPass>,
+    scene_target: RenderTarget,
+    //Rendered into instead of scene_target while MSAA is on, resolved at the start of end
+    multisample_target: Option<MultisampleTarget>,
+    ping_pong: [RenderTarget; 2],
+    copy_program: ShaderProgram,
+    fullscreen_vao: VertexArray,
+    frame: u64,
+}
+
+impl PostStack {
+    pub unsafe fn new(width: i32, height: i32) -> Option<Self> {
+        let scene_target = RenderTarget::new(width, height, GL_RGBA16F, true)?;
+        scene_target.set_label("Scene HDR");
+        let ping_pong = [
+            RenderTarget::new(width, height, GL_RGBA16F, false)?,
+            RenderTarget::new(width, height, GL_RGBA16F, false)?,
+        ];
+        ping_pong[0].set_label("Post ping");
+        ping_pong[1].set_label("Post pong");
+        Some(Self {
+            passes: Vec::new(),
+            scene_target,
+            multisample_target: None,
+            ping_pong,
+            copy_program: post_program("copy.frag", &[]),
+            fullscreen_vao: VertexArray::new()?,
+            frame: 0,
+        })
+    }
+
+    pub fn push(&mut self, effect: impl PostEffect) -> &mut Self {
+        self.passes.push(PostPass {
+            effect: Box::new(effect),
+            enabled: true,
+        });
+        self
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.passes
+            .iter()
+            .position(|pass| pass.effect.name() == name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostEffect>> {
+        let index = self.position(name)?;
+        Some(self.passes.remove(index).effect)
+    }
+
+    //Returns false if there's no effect with that name
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.position(name) {
+            Some(index) => {
+                self.passes[index].enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.position(name)
+            .is_some_and(|index| self.passes[index].enabled)
+    }
+
+    pub fn toggle(&mut self, name: &str) -> bool {
+        let enabled = !self.is_enabled(name);
+        self.set_enabled(name, enabled) && enabled
+    }
+
+    //First effect of type T, to change its settings
+    pub fn get_mut<T: PostEffect>(&mut self) -> Option<&mut T> {
+        self.passes
+            .iter_mut()
+            .find_map(|pass| pass.effect.as_any_mut().downcast_mut::<T>())
+    }
+
+    pub fn scene_target(&self) -> &RenderTarget {
+        &self.scene_target
+    }
+
+    //Number of MSAA samples, 0 when it's off
+    pub fn msaa_samples(&self) -> i32 {
+        self.multisample_target
+            .as_ref()
+            .map_or(0, |target| target.samples)
+    }
+
+    //Turns MSAA on with the given number of samples, clamped to what the driver supports. 0 or 1 turns
+    //it off. Returns the number of samples actually used
+    pub unsafe fn set_msaa_samples(&mut self, samples: i32) -> i32 {
+        let samples = samples.min(max_samples());
+        if samples == self.msaa_samples() || (samples <= 1 && self.multisample_target.is_none()) {
+            return self.msaa_samples();
+        }
+        if let Some(target) = self.multisample_target.take() {
+            target.delete();
+        }
+        if samples > 1 {
+            let (width, height) = (self.scene_target.width, self.scene_target.height);
+            self.multisample_target =
+                MultisampleTarget::new(width, height, self.scene_target.internal_format, samples);
+            match &self.multisample_target {
+                Some(target) => target.set_label("Scene HDR multisampled"),
+                None => log::error!("Couldn't create a framebuffer with {} samples", samples),
+            }
+        }
+        self.msaa_samples()
+    }
+
+    pub unsafe fn resize(&mut self, width: i32, height: i32) {
+        self.scene_target.resize(width, height);
+        if let Some(target) = &mut self.multisample_target {
+            target.resize(width, height);
+        }
+        for target in self.ping_pong.iter_mut() {
+            target.resize(width, height);
+        }
+        for pass in self.passes.iter_mut() {
+            pass.effect.resize(width, height);
+        }
+    }
+
+    //Binds the HDR scene target (the multisampled one with MSAA) and clears it, render the scene after this
+    pub unsafe fn begin(&self) {
+        match &self.multisample_target {
+            Some(target) => target.bind(),
+            None => self.scene_target.bind(),
+        }
+        gl_state::enable(GL_DEPTH_TEST);
+        gl_state::depth_mask(true);
+        glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+    }
+
+    //Runs the enabled effects on the scene, the last one drawing into output_framebuffer (0 for the
+    //window). Without any enabled effect the scene is copied over as-is
+    pub unsafe fn end(&mut self, output_framebuffer: GLuint, time: f32) {
+        let _group = DebugGroup::new("Post-processing");
+        if let Some(target) = &self.multisample_target {
+            let _group = DebugGroup::new("MSAA resolve");
+            target.resolve(&self.scene_target);
+        }
+        gl_state::disable(GL_DEPTH_TEST);
+        gl_state::disable(GL_BLEND);
+
+        let enabled: Vec<usize> = (0..self.passes.len())
+            .filter(|&index| self.passes[index].enabled)
+            .collect();
+        let (width, height) = (self.scene_target.width, self.scene_target.height);
+        let mut context = PostContext {
+            input: self.scene_target.color_texture,
+            scene: &self.scene_target,
+            output_framebuffer,
+            width,
+            height,
+            time,
+            frame: self.frame,
+            fullscreen_vao: self.fullscreen_vao,
+        };
+        if enabled.is_empty() {
+            context.bind_output();
+            bind_input(&self.copy_program, &context);
+            context.draw_fullscreen();
+        }
+        for (i, &index) in enabled.iter().enumerate() {
+            let last = i + 1 == enabled.len();
+            let target = &self.ping_pong[i % 2];
+            context.output_framebuffer = if last {
+                output_framebuffer
+            } else {
+                target.framebuffer
+            };
+            context.bind_output();
+            let pass = &mut self.passes[index];
+            let _group = DebugGroup::new(pass.effect.name());
+            pass.effect.apply(&context);
+            context.input = target.color_texture;
+        }
+        self.frame += 1;
+    }
+
+    pub unsafe fn delete(&mut self) {
+        self.scene_target.delete();
+        if let Some(target) = &self.multisample_target {
+            target.delete();
+        }
+        for target in self.ping_pong.iter() {
+            target.delete();
+        }
+        self.copy_program.delete();
+        self.fullscreen_vao.delete();
+        for pass in self.passes.iter_mut() {
+            pass.effect.delete();
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    Reinhard,
+    #[default]
+    Aces,
+    //No curve, just clamped
+    Clamp,
+}
+
+//Scales the HDR color by the exposure, maps it into 0..1 and gamma corrects it
+pub struct ToneMapping {
+    pub exposure: f32,
+    pub operator: ToneMapOperator,
+    pub gamma: f32,
+    program: Option<ShaderProgram>,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            operator: ToneMapOperator::default(),
+            gamma: 2.2,
+            program: None,
+        }
+    }
+}
+
+impl PostEffect for ToneMapping {
+    fn name(&self) -> &'static str {
+        "tone_mapping"
+    }
+
+    unsafe fn apply(&mut self, context: &PostContext) {
+        let program = self.program.get_or_insert_with(|| {
+            post_program(
+                "tone_mapping.frag",
+                &[
+                    cstr!("exposure"),
+                    cstr!("tone_map_operator"),
+                    cstr!("gamma"),
+                ],
+            )
+        });
+        bind_input(program, context);
+        program.set_float("exposure", self.exposure);
+        program.set_int("tone_map_operator", self.operator as i32);
+        program.set_float("gamma", self.gamma);
+        context.draw_fullscreen();
+    }
+
+    unsafe fn delete(&mut self) {
+        if let Some(program) = self.program.take() {
+            program.delete();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum LutError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Parse { line: usize, message: String },
+    //Well formed, but not a usable table
+    Invalid(String),
+}
+
+impl fmt::Display for LutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LutError::Io(error) => write!(f, "couldn't read LUT: {}", error),
+            LutError::Image(error) => write!(f, "couldn't load LUT image: {}", error),
+            LutError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LutError::Invalid(message) => write!(f, "invalid LUT: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for LutError {}
+
+//3D color lookup table, size^3 entries with red changing fastest, then green, then blue
+#[derive(Clone, Debug, PartialEq)]
+pub struct LutData {
+    pub size: usize,
+    pub data: Vec<[f32; 3]>,
+}
+
+impl LutData {
+    //Maps every color to itself
+    pub fn identity(size: usize) -> Self {
+        let size = size.max(2);
+        let step = 1.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push([r as f32 * step, g as f32 * step, b as f32 * step]);
+                }
+            }
+        }
+        Self { size, data }
+    }
+
+    //Adobe/Resolve .cube text format, only 3D tables over the default 0..1 domain
+    pub fn from_cube(text: &str) -> Result<Self, LutError> {
+        let mut size = None;
+        let mut data = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: &str| LutError::Parse {
+                line: number + 1,
+                message: message.to_string(),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("TITLE") {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("LUT_3D_SIZE") => {
+                    size = Some(
+                        words
+                            .next()
+                            .and_then(|word| word.parse::<usize>().ok())
+                            .filter(|&size| size >= 2)
+                            .ok_or_else(|| error("invalid LUT_3D_SIZE"))?,
+                    );
+                }
+                Some("LUT_1D_SIZE") => return Err(error("1D LUTs aren't supported")),
+                Some(keyword @ ("DOMAIN_MIN" | "DOMAIN_MAX")) => {
+                    let expected = if keyword == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    if !words.all(|word| word.parse::<f32>() == Ok(expected)) {
+                        return Err(error("only a 0..1 domain is supported"));
+                    }
+                }
+                _ => {
+                    let values: Vec<f32> = line
+                        .split_whitespace()
+                        .map(|word| word.parse::<f32>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| error("expected three numbers"))?;
+                    if values.len() != 3 {
+                        return Err(error("expected three numbers"));
+                    }
+                    data.push([values[0], values[1], values[2]]);
+                }
+            }
+        }
+        let size = size.ok_or_else(|| LutError::Invalid("missing LUT_3D_SIZE".to_string()))?;
+        if data.len() != size * size * size {
+            return Err(LutError::Invalid(format!(
+                "expected {} entries, found {}",
+                size * size * size,
+                data.len()
+            )));
+        }
+        Ok(Self { size, data })
+    }
+
+    //Unwrapped strip image, size slices of size x size side by side: red goes right within a slice,
+    //green goes down and blue picks the slice
+    pub fn from_strip(image: &image::RgbImage) -> Result<Self, LutError> {
+        let size = image.height() as usize;
+        if size < 2 || image.width() as usize != size * size {
+            return Err(LutError::Invalid(format!(
+                "a {}x{} image isn't a strip, the width has to be the height squared",
+                image.width(),
+                image.height()
+            )));
+        }
+        let mut data = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let pixel = image.get_pixel((b * size + r) as u32, g as u32);
+                    data.push(pixel.0.map(|c| c as f32 / 255.0));
+                }
+            }
+        }
+        Ok(Self { size, data })
+    }
+
+    //.cube files by extension, anything else is loaded as a strip image
+    pub fn load(path: &Path) -> Result<Self, LutError> {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "cube")
+        {
+            Self::from_cube(&std::fs::read_to_string(path).map_err(LutError::Io)?)
+        } else {
+            Self::from_strip(&image::open(path).map_err(LutError::Image)?.to_rgb8())
+        }
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> Vector3<f32> {
+        self.data[(b * self.size + g) * self.size + r].into()
+    }
+
+    //Trilinearly filtered lookup, what the color grading shader does on the GPU
+    pub fn sample(&self, color: Vector3<f32>) -> Vector3<f32> {
+        let max = (self.size - 1) as f32;
+        let position = color.map(|c| c.clamp(0.0, 1.0) * max);
+        let low = position.map(|c| (c.floor() as usize).min(self.size - 2));
+        let t = position - low.map(|c| c as f32);
+        let lerp = |a: Vector3<f32>, b: Vector3<f32>, t: f32| a + (b - a) * t;
+        let mut corners = [Vector3::new(0.0, 0.0, 0.0); 4];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let g = low.y + (i & 1);
+            let b = low.z + (i >> 1);
+            *corner = lerp(self.entry(low.x, g, b), self.entry(low.x + 1, g, b), t.x);
+        }
+        lerp(
+            lerp(corners[0], corners[1], t.y),
+            lerp(corners[2], corners[3], t.y),
+            t.z,
+        )
+    }
+}
+
+//Remaps colors through a 3D LUT, put it after tone mapping since the table covers 0..1
+pub struct ColorGrading {
+    //How much of the graded color is used, 0 leaves the image untouched
+    pub strength: f32,
+    lut: LutData,
+    texture: Option<GLuint>,
+    uploaded: bool,
+    program: Option<ShaderProgram>,
+}
+
+impl ColorGrading {
+    pub fn new(lut: LutData) -> Self {
+        Self {
+            strength: 1.0,
+            lut,
+            texture: None,
+            uploaded: false,
+            program: None,
+        }
+    }
+
+    pub fn lut(&self) -> &LutData {
+        &self.lut
+    }
+
+    //The new table is uploaded the next time the effect runs
+    pub fn set_lut(&mut self, lut: LutData) {
+        self.lut = lut;
+        self.uploaded = false;
+    }
+
+    unsafe fn upload(&mut self) -> GLuint {
+        let texture = *self.texture.get_or_insert_with(|| {
+            let mut texture = 0;
+            glGenTextures(1, &mut texture);
+            texture
+        });
+        if !self.uploaded {
+            gl_state::bind_texture_unit(GL_TEXTURE1, GL_TEXTURE_3D, texture);
+            let size = self.lut.size as GLsizei;
+            glTexImage3D(
+                GL_TEXTURE_3D,
+                0,
+                GL_RGB16F as GLint,
+                size,
+                size,
+                size,
+                0,
+                GL_RGB,
+                GL_FLOAT,
+                self.lut.data.as_ptr().cast(),
+            );
+            glTexParameteri(GL_TEXTURE_3D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
+            glTexParameteri(GL_TEXTURE_3D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
+            for wrap in [GL_TEXTURE_WRAP_S, GL_TEXTURE_WRAP_T, GL_TEXTURE_WRAP_R] {
+                glTexParameteri(GL_TEXTURE_3D, wrap, GL_CLAMP_TO_EDGE as GLint);
+            }
+            label_object(GL_TEXTURE, texture, "Color grading LUT");
+            self.uploaded = true;
+        }
+        texture
+    }
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self::new(LutData::identity(16))
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn name(&self) -> &'static str {
+        "color_grading"
+    }
+
+    unsafe fn apply(&mut self, context: &PostContext) {
+        let texture = self.upload();
+        let program = self.program.get_or_insert_with(|| {
+            post_program(
+                "color_grading.frag",
+                &[cstr!("lut"), cstr!("lut_size"), cstr!("strength")],
+            )
+        });
+        bind_input(program, context);
+        gl_state::bind_texture_unit(GL_TEXTURE1, GL_TEXTURE_3D, texture);
+        program.set_int("lut", 1);
+        program.set_float("lut_size", self.lut.size as f32);
+        program.set_float("strength", self.strength);
+        context.draw_fullscreen();
+    }
+
+    unsafe fn delete(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            glDeleteTextures(1, &texture);
+            gl_state::forget_texture(texture);
+        }
+        self.uploaded = false;
+        if let Some(program) = self.program.take() {
+            program.delete();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+//Darkens the image towards the corners
+pub struct Vignette {
+    pub intensity: f32,
+    //Distance from the center (1 is a corner) where darkening is complete
+    pub radius: f32,
+    //Width of the falloff inside radius
+    pub softness: f32,
+    program: Option<ShaderProgram>,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            intensity: 0.5,
+            radius: 1.0,
+            softness: 0.6,
+            program: None,
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn name(&self) -> &'static str {
+        "vignette"
+    }
+
+    unsafe fn apply(&mut self, context: &PostContext) {
+        let program = self.program.get_or_insert_with(|| {
+            post_program(
+                "vignette.frag",
+                &[cstr!("intensity"), cstr!("radius"), cstr!("softness")],
+            )
+        });
+        bind_input(program, context);
+        program.set_float("intensity", self.intensity);
+        program.set_float("radius", self.radius);
+        program.set_float("softness", self.softness);
+        context.draw_fullscreen();
+    }
+
+    unsafe fn delete(&mut self) {
+        if let Some(program) = self.program.take() {
+            program.delete();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+//Animated per-pixel noise, strongest in the darker parts of the image
+pub struct FilmGrain {
+    pub intensity: f32,
+    program: Option<ShaderProgram>,
+}
+
+impl Default for FilmGrain {
+    fn default() -> Self {
+        Self {
+            intensity: 0.05,
+            program: None,
+        }
+    }
+}
+
+impl PostEffect for FilmGrain {
+    fn name(&self) -> &'static str {
+        "film_grain"
+    }
+
+    unsafe fn apply(&mut self, context: &PostContext) {
+        let program = self.program.get_or_insert_with(|| {
+            post_program("film_grain.frag", &[cstr!("intensity"), cstr!("time")])
+        });
+        bind_input(program, context);
+        program.set_float("intensity", self.intensity);
+        program.set_float("time", context.time);
+        context.draw_fullscreen();
+    }
+
+    unsafe fn delete(&mut self) {
+        if let Some(program) = self.program.take() {
+            program.delete();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
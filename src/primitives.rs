@@ -0,0 +1,385 @@
+//Procedural meshes with normals, uvs and tangents. Winding is counter clockwise seen from the side the normals
+//point to, uv v goes up. Shared edges between parts are bit exact so the results are closed in position space
+use crate::{Mesh, MeshSource, VertIndicies, Vertex};
+use cgmath::{InnerSpace, Vector3};
+
+use std::{collections::HashMap, f32::consts::PI, f32::consts::TAU};
+
+fn vertex(position: [f32; 3], normal: [f32; 3], uv: [f32; 2]) -> Vertex {
+    let mut v = [0.0; crate::VERTEX_SIZE];
+    v[0..3].copy_from_slice(&position);
+    v[3..6].copy_from_slice(&normal);
+    v[6..8].copy_from_slice(&uv);
+    v
+}
+
+//Angle around the y axis for u in [0, 1], u == 1 wraps to exactly 0 so the seam column matches the first one
+fn turn(u: f32) -> f32 {
+    if u >= 1.0 {
+        0.0
+    } else {
+        u * TAU
+    }
+}
+
+//One row of a surface of revolution: distance from the y axis, height, normal in the (radial, y) plane and uv v
+struct ProfilePoint {
+    radius: f32,
+    height: f32,
+    normal: [f32; 2],
+    v: f32,
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<Vertex>,
+    indicies: Vec<VertIndicies>,
+}
+
+impl MeshBuilder {
+    //Quads between (columns + 1) * (rows + 1) vertices, front facing along d/du x d/dv
+    fn grid_indices(&mut self, base: usize, columns: usize, rows: usize) {
+        let index = |i: usize, j: usize| (base + j * (columns + 1) + i) as u32;
+        for j in 0..rows {
+            for i in 0..columns {
+                self.indicies
+                    .push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+                self.indicies
+                    .push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+            }
+        }
+    }
+
+    fn surface(
+        &mut self,
+        columns: usize,
+        rows: usize,
+        f: impl Fn(f32, f32) -> ([f32; 3], [f32; 3], [f32; 2]),
+    ) {
+        let base = self.vertices.len();
+        for j in 0..=rows {
+            for i in 0..=columns {
+                let (position, normal, uv) = f(i as f32 / columns as f32, j as f32 / rows as f32);
+                self.vertices.push(vertex(position, normal, uv));
+            }
+        }
+        self.grid_indices(base, columns, rows);
+    }
+
+    //Sweeps the profile (listed so that its normals are on the right hand side going from one point to the next)
+    //around the y axis. Rows with zero radius produce degenerate triangles that build removes
+    fn lathe(&mut self, profile: &[ProfilePoint], segments: usize) {
+        let base = self.vertices.len();
+        for point in profile.iter() {
+            for i in 0..=segments {
+                let u = i as f32 / segments as f32;
+                let (sin, cos) = turn(u).sin_cos();
+                self.vertices.push(vertex(
+                    [point.radius * cos, point.height, -point.radius * sin],
+                    [point.normal[0] * cos, point.normal[1], -point.normal[0] * sin],
+                    [u, point.v],
+                ));
+            }
+        }
+        self.grid_indices(base, segments, profile.len() - 1);
+    }
+
+    //Flat disk at height facing up or down
+    fn cap(&mut self, radius: f32, height: f32, up: bool, segments: usize) {
+        let base = self.vertices.len();
+        let ny = if up { 1.0 } else { -1.0 };
+        //Center to rim faces down, rim to center faces up
+        let rings: [f32; 2] = if up { [radius, 0.0] } else { [0.0, radius] };
+        for &ring in rings.iter() {
+            for i in 0..=segments {
+                let (sin, cos) = turn(i as f32 / segments as f32).sin_cos();
+                let (x, z) = (ring * cos, -ring * sin);
+                let uv = [0.5 + 0.5 * x / radius, 0.5 - 0.5 * z / radius * ny];
+                self.vertices.push(vertex([x, height, z], [0.0, ny, 0.0], uv));
+            }
+        }
+        self.grid_indices(base, segments, 1);
+    }
+
+    fn build(self) -> Mesh {
+        let mut mesh = Mesh::new(self.vertices, self.indicies);
+        mesh.remove_degenerate_triangles();
+        mesh.calculate_tangents();
+        mesh
+    }
+}
+
+//Profile point on a circle of the given radius around (0, center), angle measured from +y.
+//The poles are snapped so their radius is exactly 0
+fn arc_point(radius: f32, center: f32, angle: f32, v: f32) -> ProfilePoint {
+    let (mut sin, mut cos) = angle.sin_cos();
+    if angle <= 0.0 || angle >= PI {
+        sin = 0.0;
+        cos = cos.signum();
+    } else if angle == PI / 2.0 {
+        sin = 1.0;
+        cos = 0.0;
+    }
+    ProfilePoint {
+        radius: radius * sin,
+        height: center + radius * cos,
+        normal: [sin, cos],
+        v,
+    }
+}
+
+impl Mesh {
+    //Axis aligned cube centered on the origin, each face split into subdivisions x subdivisions quads
+    pub fn cube(size: f32, subdivisions: usize) -> Self {
+        let h = size * 0.5;
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+        let mut builder = MeshBuilder::default();
+        for (n, u_axis, v_axis) in faces {
+            builder.surface(subdivisions.max(1), subdivisions.max(1), |u, v| {
+                let (x, y) = ((2.0 * u - 1.0) * h, (2.0 * v - 1.0) * h);
+                let p = [0, 1, 2].map(|k| n[k] * h + u_axis[k] * x + v_axis[k] * y);
+                (p, n, [u, v])
+            });
+        }
+        builder.build().with_source(MeshSource::Cube { size, subdivisions })
+    }
+
+    //Latitude/longitude sphere, segments around the y axis and rings from pole to pole
+    pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> Self {
+        let rings = rings.max(2);
+        let profile: Vec<ProfilePoint> = (0..=rings)
+            .map(|j| {
+                let v = j as f32 / rings as f32;
+                arc_point(radius, 0.0, (1.0 - v) * PI, v)
+            })
+            .collect();
+        let mut builder = MeshBuilder::default();
+        builder.lathe(&profile, segments.max(3));
+        builder.build().with_source(MeshSource::UvSphere { radius, segments, rings })
+    }
+
+    //Subdivided icosahedron, evenly spread triangles without the pole pinching of uv_sphere.
+    //Vertices along the uv seam are duplicated so the texture doesn't wrap across a triangle
+    pub fn icosphere(radius: f32, subdivisions: usize) -> Self {
+        let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let mut positions: Vec<Vector3<f32>> = [
+            [-1.0, t, 0.0],
+            [1.0, t, 0.0],
+            [-1.0, -t, 0.0],
+            [1.0, -t, 0.0],
+            [0.0, -1.0, t],
+            [0.0, 1.0, t],
+            [0.0, -1.0, -t],
+            [0.0, 1.0, -t],
+            [t, 0.0, -1.0],
+            [t, 0.0, 1.0],
+            [-t, 0.0, -1.0],
+            [-t, 0.0, 1.0],
+        ]
+        .iter()
+        .map(|&p| Vector3::from(p).normalize())
+        .collect();
+        let mut faces: Vec<[usize; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+            let mut midpoint = |a: usize, b: usize, positions: &mut Vec<Vector3<f32>>| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    positions.push(((positions[a] + positions[b]) * 0.5).normalize());
+                    positions.len() - 1
+                })
+            };
+            faces = faces
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let ab = midpoint(a, b, &mut positions);
+                    let bc = midpoint(b, c, &mut positions);
+                    let ca = midpoint(c, a, &mut positions);
+                    [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        let uv = |p: Vector3<f32>| {
+            [
+                0.5 + (-p.z).atan2(p.x) / TAU,
+                0.5 + p.y.clamp(-1.0, 1.0).asin() / PI,
+            ]
+        };
+        let mut builder = MeshBuilder {
+            vertices: positions
+                .iter()
+                .map(|&p| vertex((p * radius).into(), p.into(), uv(p)))
+                .collect(),
+            indicies: Vec::new(),
+        };
+        //Triangles that straddle u = 0/1 get copies of their low u corners shifted past 1
+        let mut wrapped: HashMap<usize, u32> = HashMap::new();
+        for face in faces.iter() {
+            let us = face.map(|i| builder.vertices[i][6]);
+            let straddles = us.iter().cloned().fold(f32::MIN, f32::max)
+                - us.iter().cloned().fold(f32::MAX, f32::min)
+                > 0.5;
+            let triangle = face.map(|i| {
+                if straddles && builder.vertices[i][6] < 0.5 {
+                    *wrapped.entry(i).or_insert_with(|| {
+                        let mut v = builder.vertices[i];
+                        v[6] += 1.0;
+                        builder.vertices.push(v);
+                        (builder.vertices.len() - 1) as u32
+                    })
+                } else {
+                    i as u32
+                }
+            });
+            builder.indicies.push(triangle);
+        }
+        builder.build().with_source(MeshSource::Icosphere { radius, subdivisions })
+    }
+
+    //Plane in xz facing +y, centered on the origin
+    pub fn plane(width: f32, depth: f32, subdivisions: usize) -> Self {
+        let mut builder = MeshBuilder::default();
+        builder.surface(subdivisions.max(1), subdivisions.max(1), |u, v| {
+            (
+                [(u - 0.5) * width, 0.0, (0.5 - v) * depth],
+                [0.0, 1.0, 0.0],
+                [u, v],
+            )
+        });
+        builder.build().with_source(MeshSource::Plane { width, depth, subdivisions })
+    }
+
+    //Capped cylinder along y, centered on the origin
+    pub fn cylinder(radius: f32, height: f32, segments: usize, height_segments: usize) -> Self {
+        let h = height * 0.5;
+        let rows = height_segments.max(1);
+        let profile: Vec<ProfilePoint> = (0..=rows)
+            .map(|j| {
+                let v = j as f32 / rows as f32;
+                ProfilePoint {
+                    radius,
+                    height: if j == rows { h } else { -h + v * height },
+                    normal: [1.0, 0.0],
+                    v,
+                }
+            })
+            .collect();
+        let mut builder = MeshBuilder::default();
+        builder.lathe(&profile, segments.max(3));
+        builder.cap(radius, -h, false, segments.max(3));
+        builder.cap(radius, h, true, segments.max(3));
+        builder.build().with_source(MeshSource::Cylinder { radius, height, segments, height_segments })
+    }
+
+    //Cone along y with its base at -height / 2 and the apex at +height / 2
+    pub fn cone(radius: f32, height: f32, segments: usize, height_segments: usize) -> Self {
+        let h = height * 0.5;
+        let rows = height_segments.max(1);
+        let slope = Vector3::new(height, radius, 0.0).normalize();
+        let profile: Vec<ProfilePoint> = (0..=rows)
+            .map(|j| {
+                let v = j as f32 / rows as f32;
+                ProfilePoint {
+                    radius: if j == rows { 0.0 } else { radius * (1.0 - v) },
+                    height: if j == rows { h } else { -h + v * height },
+                    normal: [slope.x, slope.y],
+                    v,
+                }
+            })
+            .collect();
+        let mut builder = MeshBuilder::default();
+        builder.lathe(&profile, segments.max(3));
+        builder.cap(radius, -h, false, segments.max(3));
+        builder.build().with_source(MeshSource::Cone { radius, height, segments, height_segments })
+    }
+
+    //Torus around the y axis, major_segments around y and minor_segments around the tube
+    pub fn torus(
+        major_radius: f32,
+        minor_radius: f32,
+        major_segments: usize,
+        minor_segments: usize,
+    ) -> Self {
+        let mut builder = MeshBuilder::default();
+        builder.surface(major_segments.max(3), minor_segments.max(3), |u, v| {
+            let (sin_phi, cos_phi) = turn(u).sin_cos();
+            let (sin_psi, cos_psi) = turn(v).sin_cos();
+            let n = [cos_phi * cos_psi, sin_psi, -sin_phi * cos_psi];
+            let p = [
+                major_radius * cos_phi + minor_radius * n[0],
+                minor_radius * n[1],
+                -major_radius * sin_phi + minor_radius * n[2],
+            ];
+            (p, n, [u, v])
+        });
+        builder.build().with_source(MeshSource::Torus { major_radius, minor_radius, major_segments, minor_segments })
+    }
+
+    //Cylinder of the given height with hemispheres on both ends, total height is height + 2 * radius.
+    //rings is per hemisphere, v is distributed by arc length so the texture isn't stretched on the caps
+    pub fn capsule(radius: f32, height: f32, segments: usize, rings: usize) -> Self {
+        let rings = rings.max(1);
+        let h = height * 0.5;
+        let length = PI * radius + height;
+        let quarter = PI * 0.5 * radius;
+        let mut profile = Vec::with_capacity(rings * 2 + 2);
+        for k in 0..=rings {
+            let t = k as f32 / rings as f32;
+            profile.push(arc_point(radius, -h, PI - t * PI * 0.5, t * quarter / length));
+        }
+        for k in 0..=rings {
+            let t = k as f32 / rings as f32;
+            profile.push(arc_point(
+                radius,
+                h,
+                PI * 0.5 - t * PI * 0.5,
+                (quarter + height + t * quarter) / length,
+            ));
+        }
+        let mut builder = MeshBuilder::default();
+        builder.lathe(&profile, segments.max(3));
+        builder.build().with_source(MeshSource::Capsule { radius, height, segments, rings })
+    }
+
+    //Two triangles covering clip space at z = 0, for post-processing passes
+    pub fn fullscreen_quad() -> Self {
+        let mut builder = MeshBuilder::default();
+        builder.surface(1, 1, |u, v| {
+            (
+                [u * 2.0 - 1.0, v * 2.0 - 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [u, v],
+            )
+        });
+        builder.build()
+    }
+}
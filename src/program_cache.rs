@@ -0,0 +1,185 @@
+use crate::gl_ext::*;
+use crate::ShaderType;
+use ogl33::*;
+
+use std::{
+    ffi::CStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const CACHE_MAGIC: &[u8; 4] = b"GLPB";
+
+//On-disk cache of linked program binaries (glGetProgramBinary/glProgramBinary).
+//Each entry is a file named after the program key, containing the magic, the binary format and the binary itself.
+#[derive(Clone, Debug)]
+pub struct ProgramCache {
+    pub directory: PathBuf,
+}
+
+impl ProgramCache {
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+        }
+    }
+
+    //Program binaries need GL 4.1 or ARB_get_program_binary, and at least one binary format from the driver
+    pub unsafe fn is_supported() -> bool {
+        if !(is_loaded::glGetProgramBinary()
+            && is_loaded::glProgramBinary()
+            && is_loaded::glProgramParameteri())
+        {
+            return false;
+        }
+        let mut formats = 0;
+        glGetIntegerv(GL_NUM_PROGRAM_BINARY_FORMATS, &mut formats);
+        formats > 0
+    }
+
+    //Hashes the preprocessed sources, defines and the driver's vendor, renderer and version strings.
+    //A driver update changes the key, so stale binaries are never offered to the new driver.
+    pub unsafe fn key(sources: &[(ShaderType, String)], defines: &[(String, String)]) -> u64 {
+        let driver = [GL_VENDOR, GL_RENDERER, GL_VERSION].map(|name| driver_string(name));
+        Self::key_for_driver(&driver, sources, defines)
+    }
+
+    //The key without querying GL, driver holds the vendor, renderer and version strings
+    pub fn key_for_driver(
+        driver: &[String],
+        sources: &[(ShaderType, String)],
+        defines: &[(String, String)],
+    ) -> u64 {
+        let mut hash = Fnv1a::new();
+        for string in driver {
+            hash.write(string.as_bytes());
+            hash.write(&[0]);
+        }
+        for (name, value) in defines {
+            hash.write(name.as_bytes());
+            hash.write(&[0]);
+            hash.write(value.as_bytes());
+            hash.write(&[0]);
+        }
+        for (shader_type, source) in sources {
+            hash.write(&(*shader_type as u32).to_le_bytes());
+            hash.write(source.as_bytes());
+            hash.write(&[0]);
+        }
+        hash.finish()
+    }
+
+    pub fn entry_path(&self, key: u64) -> PathBuf {
+        self.directory.join(format!("{:016x}.bin", key))
+    }
+
+    //Reads the binary format and binary of an entry. A missing entry gives None, a corrupt one is also
+    //removed
+    pub fn read_entry(&self, key: u64) -> Option<(GLenum, Vec<u8>)> {
+        let path = self.entry_path(key);
+        let bytes = fs::read(&path).ok()?;
+        if bytes.len() < 8 || &bytes[0..4] != CACHE_MAGIC {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        let format = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        Some((format, bytes[8..].to_vec()))
+    }
+
+    //Writes an entry, creating the cache directory if needed
+    pub fn write_entry(&self, key: u64, format: GLenum, binary: &[u8]) -> std::io::Result<()> {
+        let mut bytes = Vec::with_capacity(8 + binary.len());
+        bytes.extend_from_slice(CACHE_MAGIC);
+        bytes.extend_from_slice(&format.to_le_bytes());
+        bytes.extend_from_slice(binary);
+        fs::create_dir_all(&self.directory).and_then(|_| fs::write(self.entry_path(key), bytes))
+    }
+
+    //Loads the cached binary for key into program. Returns false when there is no entry or the driver rejects it,
+    //in which case the entry is removed and the caller should compile from source.
+    pub unsafe fn load(&self, program: GLuint, key: u64) -> bool {
+        let path = self.entry_path(key);
+        let (format, binary) = match self.read_entry(key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        glProgramBinary(
+            program,
+            format,
+            binary.as_ptr() as *const c_void,
+            binary.len() as GLsizei,
+        );
+
+        let mut success = 0;
+        glGetProgramiv(program, GL_LINK_STATUS, &mut success);
+        if success == 0 {
+            log::warn!(
+                "Cached program binary {} was rejected by the driver, recompiling",
+                path.display()
+            );
+            let _ = fs::remove_file(&path);
+            return false;
+        }
+        true
+    }
+
+    //Writes the binary of an already linked program to the cache.
+    //The program should have been linked with GL_PROGRAM_BINARY_RETRIEVABLE_HINT set.
+    pub unsafe fn store(&self, program: GLuint, key: u64) {
+        let mut length = 0;
+        glGetProgramiv(program, GL_PROGRAM_BINARY_LENGTH, &mut length);
+        if length <= 0 {
+            return;
+        }
+
+        let mut binary: Vec<u8> = vec![0; length as usize];
+        let mut written = 0;
+        let mut format = 0;
+        glGetProgramBinary(
+            program,
+            length,
+            &mut written,
+            &mut format,
+            binary.as_mut_ptr() as *mut c_void,
+        );
+        binary.truncate(written as usize);
+
+        if let Err(error) = self.write_entry(key, format, &binary) {
+            log::warn!(
+                "Failed to write program binary {}: {}",
+                self.entry_path(key).display(),
+                error
+            );
+        }
+    }
+}
+
+unsafe fn driver_string(name: GLenum) -> String {
+    let s = glGetString(name);
+    if s.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(s as *const _).to_string_lossy().into_owned()
+    }
+}
+
+//FNV-1a, used instead of DefaultHasher because the keys are persisted and must be stable between builds
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,243 @@
+//Collects draw items for a frame and orders them to keep state changes down: opaque items grouped by
+//program, material and mesh (front to back within a group), then transparent items back to front
+use crate::debug_output::DebugGroup;
+use crate::gl_state;
+use crate::{Camera, Material, Mesh, ObjectHandle, Scene, ShaderProgram};
+use cgmath::InnerSpace;
+use ogl33::*;
+use serde::{Deserialize, Serialize};
+
+use std::rc::Rc;
+
+//How a material's surface is combined with what's already in the framebuffer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlendMode {
+    #[default]
+    Opaque,
+    //Opaque, but fragments with alpha below the material's alpha_cutoff are discarded
+    AlphaTest,
+    //Classic "over" blending by alpha
+    AlphaBlend,
+    //Adds to the framebuffer scaled by alpha, for glows and particles
+    Additive,
+}
+
+impl BlendMode {
+    //Transparent modes are drawn after everything opaque, sorted back to front without depth writes
+    pub fn is_transparent(self) -> bool {
+        matches!(self, BlendMode::AlphaBlend | BlendMode::Additive)
+    }
+
+    pub unsafe fn apply(self) {
+        match self {
+            BlendMode::Opaque | BlendMode::AlphaTest => {
+                gl_state::disable(GL_BLEND);
+                gl_state::depth_mask(true);
+            }
+            BlendMode::AlphaBlend => {
+                gl_state::enable(GL_BLEND);
+                gl_state::blend_func(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
+                gl_state::depth_mask(false);
+            }
+            BlendMode::Additive => {
+                gl_state::enable(GL_BLEND);
+                gl_state::blend_func(GL_SRC_ALPHA, GL_ONE);
+                gl_state::depth_mask(false);
+            }
+        }
+    }
+}
+
+pub struct DrawItem<'a> {
+    pub program: &'a ShaderProgram,
+    pub object: ObjectHandle,
+    pub mesh: Rc<Mesh>,
+    pub material: Rc<Material>,
+    //Distance in front of the camera, along its view direction
+    pub depth: f32,
+}
+
+//State changes the queue's current order causes, updated by sort
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    pub draw_calls: usize,
+    pub program_changes: usize,
+    pub material_changes: usize,
+    pub mesh_changes: usize,
+    pub blend_changes: usize,
+}
+
+#[derive(Default)]
+pub struct RenderQueue<'a> {
+    pub opaque: Vec<DrawItem<'a>>,
+    pub transparent: Vec<DrawItem<'a>>,
+    pub stats: QueueStats,
+}
+
+impl<'a> RenderQueue<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.opaque.len() + self.transparent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.opaque.clear();
+        self.transparent.clear();
+        self.stats = QueueStats::default();
+    }
+
+    pub fn push(&mut self, item: DrawItem<'a>) {
+        if item.material.blend_mode.is_transparent() {
+            self.transparent.push(item);
+        } else {
+            self.opaque.push(item);
+        }
+    }
+
+    //Items in the order they are drawn
+    pub fn items(&self) -> impl Iterator<Item = &DrawItem<'a>> + '_ {
+        self.opaque.iter().chain(self.transparent.iter())
+    }
+
+    pub fn sort(&mut self) {
+        //Pointers identify shared materials and meshes, the order between them doesn't matter
+        self.opaque.sort_by(|a, b| {
+            (a.program.0, Rc::as_ptr(&a.material), Rc::as_ptr(&a.mesh))
+                .cmp(&(b.program.0, Rc::as_ptr(&b.material), Rc::as_ptr(&b.mesh)))
+                .then(a.depth.total_cmp(&b.depth))
+        });
+        //Blending needs back to front, state changes can't be avoided here
+        self.transparent.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+
+        let mut stats = QueueStats::default();
+        let mut previous: Option<&DrawItem> = None;
+        for item in self.items() {
+            let (program, material, mesh, blend_mode) = match previous {
+                Some(p) => (
+                    p.program.0 != item.program.0,
+                    !Rc::ptr_eq(&p.material, &item.material),
+                    !Rc::ptr_eq(&p.mesh, &item.mesh),
+                    p.material.blend_mode != item.material.blend_mode,
+                ),
+                None => (true, true, true, true),
+            };
+            stats.draw_calls += 1;
+            stats.program_changes += program as usize;
+            stats.material_changes += material as usize;
+            stats.mesh_changes += mesh as usize;
+            stats.blend_changes += blend_mode as usize;
+            previous = Some(item);
+        }
+        self.stats = stats;
+    }
+
+    //Draws the items in their current order, call sort first. Each program gets the camera uniforms when
+    //it's bound, then every item sets "M", "N", "previous_M" and the lights reaching it (see
+    //Scene::lights_for) if the program has them and (when it changed) its material. Items whose object
+    //was removed since they were queued are skipped. Blending and depth writes are back to their opaque
+    //defaults afterwards
+    pub unsafe fn draw(&self, scene: &Scene, camera: &Camera) {
+        let _group = DebugGroup::new("RenderQueue::draw");
+        Self::draw_items(self.items(), scene, camera);
+    }
+
+    //Only the opaque (and alpha tested) items, for depth and normal prepasses
+    pub unsafe fn draw_opaque(&self, scene: &Scene, camera: &Camera) {
+        let _group = DebugGroup::new("RenderQueue::draw_opaque");
+        Self::draw_items(self.opaque.iter(), scene, camera);
+    }
+
+    //Only the transparent items, e.g. after a deferred lighting pass
+    pub unsafe fn draw_transparent(&self, scene: &Scene, camera: &Camera) {
+        let _group = DebugGroup::new("RenderQueue::draw_transparent");
+        Self::draw_items(self.transparent.iter(), scene, camera);
+    }
+
+    unsafe fn draw_items<'b>(
+        items: impl Iterator<Item = &'b DrawItem<'a>>,
+        scene: &Scene,
+        camera: &Camera,
+    ) where
+        'a: 'b,
+    {
+        let mut program: Option<GLuint> = None;
+        let mut material: Option<&Rc<Material>> = None;
+        let mut blend_mode: Option<BlendMode> = None;
+        let mut lights: Option<&[usize]> = None;
+        for item in items {
+            //Removed since it was queued
+            let object = match scene.objects.get(item.object) {
+                Some(object) => object,
+                None => continue,
+            };
+            if program != Some(item.program.0) {
+                gl_state::use_program(item.program.0);
+                Scene::set_camera_uniforms(item.program, camera);
+                if item.program.1.contains_key("instanced") {
+                    item.program.set_int("instanced", 0);
+                }
+                program = Some(item.program.0);
+                material = None;
+                lights = None;
+            }
+            if blend_mode != Some(item.material.blend_mode) {
+                item.material.blend_mode.apply();
+                blend_mode = Some(item.material.blend_mode);
+            }
+            if !material.is_some_and(|material| Rc::ptr_eq(material, &item.material)) {
+                item.material.apply(item.program);
+                material = Some(&item.material);
+            }
+            if item.program.1.contains_key("light_count") {
+                let object_lights = scene.lights_for(item.object);
+                if lights != Some(object_lights) {
+                    scene.set_light_uniforms(item.program, object_lights);
+                    lights = Some(object_lights);
+                }
+            }
+            item.program.set_mat4("M", &object.model_matrix);
+            item.program.set_mat3("N", &object.normal_matrix);
+            if item.program.1.contains_key("previous_M") {
+                item.program.set_mat4("previous_M", &object.previous_model_matrix);
+            }
+            item.mesh.draw();
+        }
+        if blend_mode.is_some_and(|mode| mode != BlendMode::Opaque) {
+            BlendMode::Opaque.apply();
+        }
+    }
+}
+
+impl Scene {
+    //Culls against the camera and queues every visible object to be drawn with program. Programs with
+    //"light_count" get the lights assigned to the objects first
+    pub fn queue<'a>(
+        &mut self,
+        queue: &mut RenderQueue<'a>,
+        program: &'a ShaderProgram,
+        camera: &Camera,
+    ) {
+        self.cull(camera);
+        if program.1.contains_key("light_count") {
+            self.assign_lights();
+        }
+        let direction = camera.get_direction();
+        for &handle in self.visible_objects.iter() {
+            let object = &self.objects[handle];
+            queue.push(DrawItem {
+                program,
+                object: handle,
+                mesh: object.mesh.clone(),
+                material: object.material.clone(),
+                depth: (object.world_sphere.center - camera.position).dot(direction),
+            });
+        }
+    }
+}
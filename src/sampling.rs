@@ -0,0 +1,13 @@
+//Low discrepancy sequences, shared by the effects that need well spread sample positions
+
+//Radical inverse of index in base, a low discrepancy sequence in 0..1
+pub fn halton(mut index: usize, base: usize) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
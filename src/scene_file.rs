@@ -0,0 +1,541 @@
+//Scenes as RON (or JSON, picked by the .json extension) files so they can be authored without recompiling.
+//Asset paths in the file are relative to the file itself
+use crate::{
+    try_mesh_from_obj, Camera, Light, Material, Mesh, Object, ObjectHandle, Scene, VertexPrecision,
+};
+use cgmath::{Deg, Euler, Quaternion, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+//How to get a mesh back: an asset file or one of the procedural primitives with its parameters
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MeshSource {
+    File(PathBuf),
+    Cube {
+        size: f32,
+        subdivisions: usize,
+    },
+    UvSphere {
+        radius: f32,
+        segments: usize,
+        rings: usize,
+    },
+    Icosphere {
+        radius: f32,
+        subdivisions: usize,
+    },
+    Plane {
+        width: f32,
+        depth: f32,
+        subdivisions: usize,
+    },
+    Cylinder {
+        radius: f32,
+        height: f32,
+        segments: usize,
+        height_segments: usize,
+    },
+    Cone {
+        radius: f32,
+        height: f32,
+        segments: usize,
+        height_segments: usize,
+    },
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+        major_segments: usize,
+        minor_segments: usize,
+    },
+    Capsule {
+        radius: f32,
+        height: f32,
+        segments: usize,
+        rings: usize,
+    },
+}
+
+impl MeshSource {
+    //Builds the mesh, File paths are taken as they are (resolve them first)
+    pub fn load(&self) -> Result<Mesh, SceneFileError> {
+        Ok(match *self {
+            MeshSource::File(ref path) => try_mesh_from_obj(path, &VertexPrecision::default())
+                .map_err(|error| SceneFileError::InvalidMesh {
+                    path: path.clone(),
+                    error,
+                })?,
+            MeshSource::Cube { size, subdivisions } => Mesh::cube(size, subdivisions),
+            MeshSource::UvSphere {
+                radius,
+                segments,
+                rings,
+            } => Mesh::uv_sphere(radius, segments, rings),
+            MeshSource::Icosphere {
+                radius,
+                subdivisions,
+            } => Mesh::icosphere(radius, subdivisions),
+            MeshSource::Plane {
+                width,
+                depth,
+                subdivisions,
+            } => Mesh::plane(width, depth, subdivisions),
+            MeshSource::Cylinder {
+                radius,
+                height,
+                segments,
+                height_segments,
+            } => Mesh::cylinder(radius, height, segments, height_segments),
+            MeshSource::Cone {
+                radius,
+                height,
+                segments,
+                height_segments,
+            } => Mesh::cone(radius, height, segments, height_segments),
+            MeshSource::Torus {
+                major_radius,
+                minor_radius,
+                major_segments,
+                minor_segments,
+            } => Mesh::torus(major_radius, minor_radius, major_segments, minor_segments),
+            MeshSource::Capsule {
+                radius,
+                height,
+                segments,
+                rings,
+            } => Mesh::capsule(radius, height, segments, rings),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MissingAsset {
+    pub path: PathBuf,
+    //Name of the object referencing it, or "environment_map"
+    pub referenced_by: String,
+}
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    Serialize(String),
+    //Every asset the scene needs that isn't there, so they can all be fixed in one go
+    MissingAssets(Vec<MissingAsset>),
+    InvalidMesh {
+        path: PathBuf,
+        error: tobj::LoadError,
+    },
+    UnknownMaterial {
+        object: String,
+        material: String,
+    },
+    //The object's mesh was built in code and can't be referenced from a file
+    NoMeshSource {
+        object: String,
+    },
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneFileError::Parse { path, message } => {
+                write!(f, "{}: parse error: {}", path.display(), message)
+            }
+            SceneFileError::Serialize(message) => {
+                write!(f, "couldn't serialize scene: {}", message)
+            }
+            SceneFileError::MissingAssets(assets) => {
+                write!(f, "scene references {} missing asset(s):", assets.len())?;
+                for asset in assets {
+                    write!(
+                        f,
+                        "\n  {} (used by {})",
+                        asset.path.display(),
+                        asset.referenced_by
+                    )?;
+                }
+                Ok(())
+            }
+            SceneFileError::InvalidMesh { path, error } => {
+                write!(f, "{}: couldn't load mesh: {}", path.display(), error)
+            }
+            SceneFileError::UnknownMaterial { object, material } => write!(
+                f,
+                "object \"{}\" uses material \"{}\" which isn't in the materials table",
+                object, material
+            ),
+            SceneFileError::NoMeshSource { object } => write!(
+                f,
+                "object \"{}\" has a mesh built in code, it has no source to save",
+                object
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+//The file's contents. Everything but the objects' meshes can be left out
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneDescription {
+    pub camera: Option<Camera>,
+    pub environment_map: Option<PathBuf>,
+    pub lights: Vec<Light>,
+    //Shared by name between objects
+    pub materials: BTreeMap<String, Material>,
+    pub objects: Vec<ObjectDescription>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ObjectDescription {
+    #[serde(default)]
+    pub name: String,
+    pub mesh: MeshSource,
+    //Key into SceneDescription::materials, the default material when left out
+    #[serde(default)]
+    pub material: Option<String>,
+    #[serde(default = "zero")]
+    pub position: Vector3<f32>,
+    //Euler angles in degrees
+    #[serde(default = "zero")]
+    pub rotation: Vector3<f32>,
+    #[serde(default = "one")]
+    pub scale: Vector3<f32>,
+    #[serde(default = "white")]
+    pub color: Vector4<f32>,
+    #[serde(default = "transparent")]
+    pub user_data: Vector4<f32>,
+    //Transforms are relative to this object's
+    #[serde(default)]
+    pub children: Vec<ObjectDescription>,
+}
+
+fn zero() -> Vector3<f32> {
+    Vector3::new(0.0, 0.0, 0.0)
+}
+
+fn one() -> Vector3<f32> {
+    Vector3::new(1.0, 1.0, 1.0)
+}
+
+fn white() -> Vector4<f32> {
+    Vector4::new(1.0, 1.0, 1.0, 1.0)
+}
+
+fn transparent() -> Vector4<f32> {
+    Vector4::new(0.0, 0.0, 0.0, 0.0)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+fn ron_options() -> ron::Options {
+    //Lets Option fields be written without Some(...)
+    ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+}
+
+//Directory the scene file's relative paths start from
+fn base_directory(path: &Path) -> PathBuf {
+    path.parent().map_or_else(PathBuf::new, Path::to_path_buf)
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+//path relative to base where possible, for writing portable scene files
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let path = absolute(path);
+    let base = absolute(base);
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base.components().collect();
+    let common = path_components
+        .iter()
+        .zip(base_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    //Different roots (drives on windows), nothing to be relative to
+    if common == 0 {
+        return path;
+    }
+    let mut result = PathBuf::new();
+    for _ in common..base_components.len() {
+        result.push("..");
+    }
+    for component in path_components[common..].iter() {
+        result.push(component);
+    }
+    result
+}
+
+impl SceneDescription {
+    pub fn read(path: &Path) -> Result<Self, SceneFileError> {
+        let text = fs::read_to_string(path).map_err(|error| SceneFileError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let parsed = if is_json(path) {
+            serde_json::from_str(&text).map_err(|error| error.to_string())
+        } else {
+            ron_options()
+                .from_str(&text)
+                .map_err(|error| error.to_string())
+        };
+        parsed.map_err(|message| SceneFileError::Parse {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SceneFileError> {
+        let text = if is_json(path) {
+            serde_json::to_string_pretty(self).map_err(|error| error.to_string())
+        } else {
+            let config = ron::ser::PrettyConfig::default()
+                .extensions(ron::extensions::Extensions::IMPLICIT_SOME);
+            ron_options()
+                .to_string_pretty(self, config)
+                .map_err(|error| error.to_string())
+        }
+        .map_err(SceneFileError::Serialize)?;
+        fs::write(path, text).map_err(|error| SceneFileError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    //Assets that don't exist, with relative paths resolved against base
+    pub fn missing_assets(&self, base: &Path) -> Vec<MissingAsset> {
+        let mut missing = Vec::new();
+        if let Some(map) = &self.environment_map {
+            if !base.join(map).is_file() {
+                missing.push(MissingAsset {
+                    path: base.join(map),
+                    referenced_by: "environment_map".to_string(),
+                });
+            }
+        }
+        let mut stack: Vec<&ObjectDescription> = self.objects.iter().collect();
+        while let Some(object) = stack.pop() {
+            if let MeshSource::File(path) = &object.mesh {
+                if !base.join(path).is_file() {
+                    missing.push(MissingAsset {
+                        path: base.join(path),
+                        referenced_by: format!("\"{}\"", object.name),
+                    });
+                }
+            }
+            stack.extend(object.children.iter());
+        }
+        missing
+    }
+}
+
+//Meshes and materials loaded so far, so objects referencing the same one share it
+#[derive(Default)]
+struct LoadCache {
+    meshes: Vec<(MeshSource, Rc<Mesh>)>,
+    materials: BTreeMap<String, Rc<Material>>,
+}
+
+impl Scene {
+    //Reads a scene file, the camera is None if the file doesn't have one
+    pub fn load(path: &Path) -> Result<(Scene, Option<Camera>), SceneFileError> {
+        let description = SceneDescription::read(path)?;
+        Self::from_description(&description, &base_directory(path))
+    }
+
+    //Builds the scene, relative asset paths are resolved against base. Checks all assets up front
+    pub fn from_description(
+        description: &SceneDescription,
+        base: &Path,
+    ) -> Result<(Scene, Option<Camera>), SceneFileError> {
+        let missing = description.missing_assets(base);
+        if !missing.is_empty() {
+            return Err(SceneFileError::MissingAssets(missing));
+        }
+
+        let mut scene = Scene::new();
+        scene.lights = description.lights.clone();
+        scene.environment_map = description
+            .environment_map
+            .as_ref()
+            .map(|map| base.join(map));
+        let mut cache = LoadCache {
+            meshes: Vec::new(),
+            materials: description
+                .materials
+                .iter()
+                .map(|(name, material)| (name.clone(), Rc::new(material.clone())))
+                .collect(),
+        };
+        let default_material = Rc::new(Material::default());
+        let mut stack: Vec<(&ObjectDescription, Option<ObjectHandle>)> = description
+            .objects
+            .iter()
+            .rev()
+            .map(|object| (object, None))
+            .collect();
+        while let Some((object, parent)) = stack.pop() {
+            let source = match &object.mesh {
+                MeshSource::File(path) => MeshSource::File(base.join(path)),
+                source => source.clone(),
+            };
+            let mesh = match cache.meshes.iter().find(|(cached, _)| *cached == source) {
+                Some((_, mesh)) => mesh.clone(),
+                None => {
+                    let mesh = Rc::new(source.load()?);
+                    cache.meshes.push((source, mesh.clone()));
+                    mesh
+                }
+            };
+            let material = match &object.material {
+                Some(name) => cache.materials.get(name).cloned().ok_or_else(|| {
+                    SceneFileError::UnknownMaterial {
+                        object: object.name.clone(),
+                        material: name.clone(),
+                    }
+                })?,
+                None => default_material.clone(),
+            };
+
+            let mut loaded = Object::with_shared(mesh, material);
+            loaded.name = object.name.clone();
+            loaded.position = object.position;
+            loaded.rotation = Quaternion::from(Euler::new(
+                Deg(object.rotation.x),
+                Deg(object.rotation.y),
+                Deg(object.rotation.z),
+            ));
+            loaded.scale = object.scale;
+            loaded.color = object.color;
+            loaded.user_data = object.user_data;
+            let handle = match parent {
+                Some(parent) => scene
+                    .add_child(parent, loaded)
+                    .expect("Parents are added before their children"),
+                None => scene.add_object(loaded),
+            };
+            stack.extend(
+                object
+                    .children
+                    .iter()
+                    .rev()
+                    .map(|child| (child, Some(handle))),
+            );
+        }
+        scene.update_transforms();
+        Ok((scene, description.camera.clone()))
+    }
+
+    pub fn save(&self, path: &Path, camera: Option<&Camera>) -> Result<(), SceneFileError> {
+        self.to_description(camera, &base_directory(path))?
+            .write(path)
+    }
+
+    //Describes the scene with asset paths relative to base. Materials get named material0, material1...
+    //in order of first use. LOD chains aren't saved, objects with one are saved with its full detail mesh
+    //since the simplified levels have no source
+    pub fn to_description(
+        &self,
+        camera: Option<&Camera>,
+        base: &Path,
+    ) -> Result<SceneDescription, SceneFileError> {
+        let mut materials: Vec<Rc<Material>> = Vec::new();
+        let mut describe = |handle: ObjectHandle| -> Result<ObjectDescription, SceneFileError> {
+            let object = &self.objects[handle];
+            let saved = match &object.lods {
+                Some(lods) => &lods.levels[0].mesh,
+                None => &object.mesh,
+            };
+            let mesh = match &saved.source {
+                Some(MeshSource::File(path)) => MeshSource::File(relative_to(path, base)),
+                Some(source) => source.clone(),
+                None => {
+                    return Err(SceneFileError::NoMeshSource {
+                        object: object.name.clone(),
+                    })
+                }
+            };
+            let material = match materials
+                .iter()
+                .position(|material| Rc::ptr_eq(material, &object.material))
+            {
+                Some(index) => index,
+                None => {
+                    materials.push(object.material.clone());
+                    materials.len() - 1
+                }
+            };
+            let rotation = Euler::from(object.rotation);
+            Ok(ObjectDescription {
+                name: object.name.clone(),
+                mesh,
+                material: Some(format!("material{}", material)),
+                position: object.position,
+                rotation: Vector3::new(
+                    Deg::from(rotation.x).0,
+                    Deg::from(rotation.y).0,
+                    Deg::from(rotation.z).0,
+                ),
+                scale: object.scale,
+                color: object.color,
+                user_data: object.user_data,
+                children: Vec::new(),
+            })
+        };
+
+        //Describe parents before children, then nest the children bottom up
+        let mut order: Vec<ObjectHandle> = Vec::new();
+        for root in self.roots() {
+            order.push(root);
+            order.extend(self.descendants(root));
+        }
+        let mut described: Vec<Option<ObjectDescription>> = Vec::with_capacity(order.len());
+        for &handle in order.iter() {
+            described.push(Some(describe(handle)?));
+        }
+        let position = |handle: ObjectHandle| order.iter().position(|&h| h == handle).unwrap();
+        for index in (0..order.len()).rev() {
+            if let Some(parent) = self.objects[order[index]].parent() {
+                let child = described[index].take().unwrap();
+                described[position(parent)]
+                    .as_mut()
+                    .unwrap()
+                    .children
+                    .insert(0, child);
+            }
+        }
+
+        Ok(SceneDescription {
+            camera: camera.cloned(),
+            environment_map: self
+                .environment_map
+                .as_ref()
+                .map(|map| relative_to(map, base)),
+            lights: self.lights.clone(),
+            materials: materials
+                .iter()
+                .enumerate()
+                .map(|(index, material)| (format!("material{}", index), (**material).clone()))
+                .collect(),
+            objects: described.into_iter().flatten().collect(),
+        })
+    }
+}
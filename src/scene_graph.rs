@@ -0,0 +1,218 @@
+use crate::{Object, ObjectHandle, Scene};
+use cgmath::{InnerSpace, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3};
+
+impl Object {
+    pub fn parent(&self) -> Option<ObjectHandle> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[ObjectHandle] {
+        &self.children
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    //Flags the object for Scene::update_transforms, needed after changing position/rotation/scale directly
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        self.position = position;
+        self.dirty = true;
+    }
+
+    pub fn set_rotation(&mut self, rotation: Quaternion<f32>) {
+        self.rotation = rotation;
+        self.dirty = true;
+    }
+
+    pub fn set_scale(&mut self, scale: Vector3<f32>) {
+        self.scale = scale;
+        self.dirty = true;
+    }
+
+    pub fn world_position(&self) -> Vector3<f32> {
+        self.model_matrix.w.truncate()
+    }
+
+    //Sets position, rotation and scale so the local matrix equals the given one. Shear (from non-uniform
+    //scales under rotations) can't be represented and is dropped
+    fn set_local_matrix(&mut self, matrix: &Matrix4<f32>) {
+        let columns = [
+            matrix.x.truncate(),
+            matrix.y.truncate(),
+            matrix.z.truncate(),
+        ];
+        let mut scale = Vector3::new(
+            columns[0].magnitude(),
+            columns[1].magnitude(),
+            columns[2].magnitude(),
+        );
+        //Mirroring shows up as a negative determinant, put it in the x scale
+        if Matrix3::from_cols(columns[0], columns[1], columns[2]).determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+        let axis = |i: usize| {
+            if scale[i] != 0.0 {
+                columns[i] / scale[i]
+            } else {
+                [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()][i]
+            }
+        };
+        self.position = matrix.w.truncate();
+        self.rotation = Quaternion::from(Matrix3::from_cols(axis(0), axis(1), axis(2))).normalize();
+        self.scale = scale;
+        self.dirty = true;
+    }
+}
+
+impl Scene {
+    //Adds an object under parent, its transform is relative to the parent's. Returns None (and drops the
+    //object) if parent is stale
+    pub fn add_child(&mut self, parent: ObjectHandle, object: Object) -> Option<ObjectHandle> {
+        if !self.objects.contains(parent) {
+            return None;
+        }
+        let handle = self.add_object(object);
+        self.objects[handle].parent = Some(parent);
+        self.objects[parent].children.push(handle);
+        Some(handle)
+    }
+
+    //Moves child under parent (or to the root with None) without moving it in the world, its local
+    //transform is recomputed from its current world transform. Returns false if either handle is stale or
+    //parent is the child itself or one of its descendants
+    pub fn set_parent(&mut self, child: ObjectHandle, parent: Option<ObjectHandle>) -> bool {
+        if !self.objects.contains(child) {
+            return false;
+        }
+        if let Some(parent) = parent {
+            if !self.objects.contains(parent)
+                || parent == child
+                || self.is_descendant(parent, child)
+            {
+                return false;
+            }
+        }
+        if self.objects[child].parent == parent {
+            return true;
+        }
+        self.update_transforms();
+
+        if let Some(old) = self.objects[child].parent {
+            self.objects[old].children.retain(|&c| c != child);
+        }
+        let world = self.objects[child].model_matrix;
+        let local = match parent {
+            Some(parent) => {
+                self.objects[parent].children.push(child);
+                self.objects[parent]
+                    .model_matrix
+                    .invert()
+                    .map_or(world, |inverse| inverse * world)
+            }
+            None => world,
+        };
+        let object = &mut self.objects[child];
+        object.parent = parent;
+        object.set_local_matrix(&local);
+        true
+    }
+
+    //Whether handle is somewhere below ancestor, false for stale handles
+    pub fn is_descendant(&self, handle: ObjectHandle, ancestor: ObjectHandle) -> bool {
+        let mut current = self.objects.get(handle).and_then(|object| object.parent);
+        while let Some(parent) = current {
+            if parent == ancestor {
+                return true;
+            }
+            current = self.objects.get(parent).and_then(|object| object.parent);
+        }
+        false
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = ObjectHandle> + '_ {
+        self.objects
+            .iter()
+            .filter(|(_, object)| object.parent.is_none())
+            .map(|(handle, _)| handle)
+    }
+
+    //Everything below handle, depth first with parents before their children. Empty for a stale handle
+    pub fn descendants(&self, handle: ObjectHandle) -> Vec<ObjectHandle> {
+        let mut result = Vec::new();
+        let mut stack: Vec<ObjectHandle> = match self.objects.get(handle) {
+            Some(object) => object.children.iter().rev().copied().collect(),
+            None => return result,
+        };
+        while let Some(current) = stack.pop() {
+            result.push(current);
+            stack.extend(self.objects[current].children.iter().rev());
+        }
+        result
+    }
+
+    //First object with the name, in slot order
+    pub fn find(&self, name: &str) -> Option<ObjectHandle> {
+        self.objects
+            .iter()
+            .find(|(_, object)| object.name == name)
+            .map(|(handle, _)| handle)
+    }
+
+    pub fn find_child(&self, parent: ObjectHandle, name: &str) -> Option<ObjectHandle> {
+        self.objects
+            .get(parent)?
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.objects[child].name == name)
+    }
+
+    //Follows a "/" separated path of names from the roots, e.g. "robot/arm/hand"
+    pub fn find_path(&self, path: &str) -> Option<ObjectHandle> {
+        let mut names = path.split('/').filter(|name| !name.is_empty());
+        let first = names.next()?;
+        let mut current = self
+            .roots()
+            .find(|&handle| self.objects[handle].name == first)?;
+        for name in names {
+            current = self.find_child(current, name)?;
+        }
+        Some(current)
+    }
+
+    //Recomputes the world transforms of dirty objects and everything below them, clean subtrees are
+    //skipped. Every object's previous_model_matrix gets the transform from before the call, so call it
+    //once per frame. Returns how many objects were updated
+    pub fn update_transforms(&mut self) -> usize {
+        let mut stack: Vec<(ObjectHandle, bool)> =
+            self.roots().map(|handle| (handle, false)).collect();
+        let mut updated = 0;
+        while let Some((handle, parent_changed)) = stack.pop() {
+            let object = &mut self.objects[handle];
+            object.previous_model_matrix = object.model_matrix;
+            let changed = parent_changed || object.dirty;
+            if changed {
+                let parent = self.objects[handle]
+                    .parent
+                    .map(|parent| self.objects[parent].model_matrix);
+                self.objects[handle].update_world_matrix(parent.as_ref());
+                updated += 1;
+            }
+            stack.extend(
+                self.objects[handle]
+                    .children
+                    .iter()
+                    .map(|&child| (child, changed)),
+            );
+        }
+        if updated > 0 {
+            self.refit_bvh();
+        }
+        updated
+    }
+}
@@ -0,0 +1,331 @@
+//Quadric error metric simplification (Garland & Heckbert) with half-edge collapses, so every remaining vertex is one
+//of the original vertices and keeps its attributes as-is. Vertices on borders, UV/normal seams (positions shared by
+//several vertices) and non-manifold edges are never removed, which keeps those outlines intact
+use crate::{Mesh, VertIndicies, Vertex};
+use cgmath::{InnerSpace, Vector3};
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+pub struct SimplifiedMesh {
+    pub mesh: Mesh,
+    //Largest distance a collapse moved the surface, relative to the diagonal of the mesh's bounding box
+    pub error: f32,
+}
+
+//Symmetric 4x4 plane quadric plus the accumulated area, so errors can be normalized back to a distance
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+    a: [f64; 10],
+    weight: f64,
+}
+
+impl Quadric {
+    fn from_plane(n: Vector3<f64>, d: f64, weight: f64) -> Self {
+        let (a, b, c) = (n.x, n.y, n.z);
+        Self {
+            a: [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|x| x * weight),
+            weight,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.a.iter_mut().zip(other.a.iter()) {
+            *a += b;
+        }
+        self.weight += other.weight;
+    }
+
+    //Area weighted mean squared distance of p to the planes
+    fn error(&self, p: Vector3<f64>) -> f64 {
+        let q = &self.a;
+        let (x, y, z) = (p.x, p.y, p.z);
+        let e = q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9];
+        if self.weight > 0.0 {
+            e.max(0.0) / self.weight
+        } else {
+            0.0
+        }
+    }
+}
+
+struct Collapse {
+    cost: f64,
+    from: u32,
+    to: u32,
+    from_version: u32,
+    to_version: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+//Reversed, so the BinaryHeap pops the cheapest collapse first
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn position(v: &Vertex) -> Vector3<f64> {
+    Vector3::new(v[0] as f64, v[1] as f64, v[2] as f64)
+}
+
+impl Mesh {
+    //Collapses edges until the mesh has at most target_triangles triangles or the next collapse would move the surface
+    //further than max_error (relative to the bounding box diagonal). Locked vertices can stop it short of the target.
+    //Only the CPU side is produced, the result needs a setup before drawing
+    pub fn simplify(&self, target_triangles: usize, max_error: f32) -> SimplifiedMesh {
+        let vertex_count = self.vertices.len();
+        let mut triangles: Vec<VertIndicies> = self.indicies.clone();
+        let mut alive = vec![true; triangles.len()];
+        let mut alive_count = triangles.len();
+
+        let extent = {
+            let mut min = Vector3::new(f64::MAX, f64::MAX, f64::MAX);
+            let mut max = Vector3::new(f64::MIN, f64::MIN, f64::MIN);
+            for v in self.vertices.iter() {
+                let p = position(v);
+                min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+                max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+            }
+            if self.vertices.is_empty() {
+                1.0
+            } else {
+                (max - min).magnitude().max(f64::MIN_POSITIVE)
+            }
+        };
+
+        //Vertices are grouped by (bit exact) position, attribute seams show up as groups with several vertices
+        let mut position_ids: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut group_of: Vec<usize> = Vec::with_capacity(vertex_count);
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (index, v) in self.vertices.iter().enumerate() {
+            let key = [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()];
+            let group = *position_ids.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].push(index);
+            group_of.push(group);
+        }
+
+        let mut locked: Vec<bool> = groups.iter().map(|vertices| vertices.len() > 1).collect();
+        let mut edge_uses: HashMap<(usize, usize), usize> = HashMap::new();
+        for triangle in triangles.iter() {
+            for corner in 0..3 {
+                let a = group_of[triangle[corner] as usize];
+                let b = group_of[triangle[(corner + 1) % 3] as usize];
+                *edge_uses.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        for (&(a, b), &uses) in edge_uses.iter() {
+            if uses != 2 {
+                locked[a] = true;
+                locked[b] = true;
+            }
+        }
+
+        let mut quadrics = vec![Quadric::default(); groups.len()];
+        let mut triangles_of: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+        for (t, triangle) in triangles.iter().enumerate() {
+            let p = triangle.map(|i| position(&self.vertices[i as usize]));
+            let cross = (p[1] - p[0]).cross(p[2] - p[0]);
+            let area = cross.magnitude() * 0.5;
+            if area > 0.0 {
+                let n = cross / (area * 2.0);
+                let plane = Quadric::from_plane(n, -n.dot(p[0]), area);
+                for &index in triangle.iter() {
+                    quadrics[group_of[index as usize]].add(&plane);
+                }
+            }
+            for &index in triangle.iter() {
+                triangles_of[index as usize].push(t);
+            }
+        }
+
+        let mut versions = vec![0u32; groups.len()];
+        let mut heap = BinaryHeap::new();
+        let push_edges = |heap: &mut BinaryHeap<Collapse>,
+                          triangle: &VertIndicies,
+                          quadrics: &[Quadric],
+                          versions: &[u32]| {
+            for corner in 0..3 {
+                let a = triangle[corner];
+                let b = triangle[(corner + 1) % 3];
+                for (from, to) in [(a, b), (b, a)] {
+                    let (from_group, to_group) = (group_of[from as usize], group_of[to as usize]);
+                    if locked[from_group] || from_group == to_group {
+                        continue;
+                    }
+                    let mut q = quadrics[from_group];
+                    q.add(&quadrics[to_group]);
+                    heap.push(Collapse {
+                        cost: q.error(position(&self.vertices[to as usize])),
+                        from,
+                        to,
+                        from_version: versions[from_group],
+                        to_version: versions[to_group],
+                    });
+                }
+            }
+        };
+        for triangle in triangles.iter() {
+            push_edges(&mut heap, triangle, &quadrics, &versions);
+        }
+
+        let group_triangles = |group: usize, triangles_of: &[Vec<usize>], alive: &[bool]| {
+            let mut result: Vec<usize> = groups[group]
+                .iter()
+                .flat_map(|&v| triangles_of[v].iter().copied())
+                .filter(|&t| alive[t])
+                .collect();
+            result.sort_unstable();
+            result.dedup();
+            result
+        };
+
+        let mut error: f64 = 0.0;
+        while alive_count > target_triangles {
+            let collapse = match heap.pop() {
+                Some(collapse) => collapse,
+                None => break,
+            };
+            let (from, to) = (collapse.from as usize, collapse.to as usize);
+            let (from_group, to_group) = (group_of[from], group_of[to]);
+            if versions[from_group] != collapse.from_version
+                || versions[to_group] != collapse.to_version
+            {
+                continue;
+            }
+            let collapse_error = collapse.cost.sqrt() / extent;
+            if collapse_error > max_error as f64 {
+                break;
+            }
+
+            let around_from = group_triangles(from_group, &triangles_of, &alive);
+            let around_to = group_triangles(to_group, &triangles_of, &alive);
+
+            //Link condition: the only positions next to both ends may be the tips of the two triangles on the edge,
+            //anything else would pinch the surface into a non-manifold edge
+            let neighbours = |around: &[usize], own: usize| {
+                let mut result: Vec<usize> = around
+                    .iter()
+                    .flat_map(|&t| triangles[t].iter().map(|&i| group_of[i as usize]))
+                    .filter(|&g| g != own)
+                    .collect();
+                result.sort_unstable();
+                result.dedup();
+                result
+            };
+            let to_neighbours = neighbours(&around_to, to_group);
+            let shared = neighbours(&around_from, from_group)
+                .iter()
+                .filter(|g| to_neighbours.binary_search(g).is_ok())
+                .count();
+            if shared > 2 {
+                continue;
+            }
+
+            //Reject collapses that flip or squash one of the triangles that survive it
+            let target = position(&self.vertices[to]);
+            let flips = around_from.iter().any(|&t| {
+                let triangle = triangles[t];
+                if triangle.iter().any(|&i| group_of[i as usize] == to_group) {
+                    return false;
+                }
+                let p = triangle.map(|i| position(&self.vertices[i as usize]));
+                let moved = triangle.map(|i| {
+                    if i as usize == from {
+                        target
+                    } else {
+                        position(&self.vertices[i as usize])
+                    }
+                });
+                let before = (p[1] - p[0]).cross(p[2] - p[0]);
+                let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+                after.magnitude2() <= 0.0
+                    || before.magnitude2() <= 0.0
+                    || before.normalize().dot(after.normalize()) < 0.25
+            });
+            if flips {
+                continue;
+            }
+
+            for &t in around_from.iter() {
+                let triangle = &mut triangles[t];
+                for index in triangle.iter_mut() {
+                    if *index as usize == from {
+                        *index = to as u32;
+                    }
+                }
+                let g = triangle.map(|i| group_of[i as usize]);
+                if g[0] == g[1] || g[1] == g[2] || g[0] == g[2] {
+                    alive[t] = false;
+                    alive_count -= 1;
+                } else {
+                    triangles_of[to].push(t);
+                }
+            }
+            triangles_of[from].clear();
+
+            let q = quadrics[from_group];
+            quadrics[to_group].add(&q);
+            versions[from_group] += 1;
+            versions[to_group] += 1;
+            error = error.max(collapse_error);
+
+            for t in group_triangles(to_group, &triangles_of, &alive) {
+                push_edges(&mut heap, &triangles[t], &quadrics, &versions);
+            }
+        }
+
+        let mut mesh = Mesh::new(
+            self.vertices.clone(),
+            triangles
+                .iter()
+                .zip(alive.iter())
+                .filter(|(_, &alive)| alive)
+                .map(|(triangle, _)| *triangle)
+                .collect(),
+        );
+        mesh.extra_attributes = self.extra_attributes.clone();
+        mesh.format = self.format.clone();
+        mesh.remove_unused_vertices();
+        SimplifiedMesh {
+            mesh,
+            error: error as f32,
+        }
+    }
+}
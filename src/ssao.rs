@@ -0,0 +1,340 @@
+//Screen space ambient occlusion: a prepass renders depth and view space normals, then every pixel
+//checks a hemisphere of samples around its surface against the depth buffer. The result is blurred
+//with a depth aware filter and read by the forward shader to darken its ambient light
+use crate::debug_output::{label_object, DebugGroup};
+use crate::gl_state;
+use crate::post_process::{post_program, RenderTarget};
+use crate::sampling::halton;
+use crate::{
+    shader_from_file, Camera, Scene, ShaderProgram, ShaderProgramBuilder, ShaderType, VertexArray,
+};
+use cgmath::{Matrix4, SquareMatrix, Vector2, Vector3};
+use cstr::cstr;
+use ogl33::*;
+
+use std::f32::consts::TAU;
+use std::path::Path;
+
+//Size of the sample array in ssao.frag
+pub const MAX_SAMPLES: usize = 64;
+//The noise texture is tiled over the screen, the blur hides the pattern
+const NOISE_SIZE: i32 = 4;
+
+pub struct Ssao {
+    //When false render does nothing and bind tells the shader to skip the lookup
+    pub enabled: bool,
+    //World space radius of the sampled hemisphere
+    pub radius: f32,
+    //Samples per pixel, up to MAX_SAMPLES
+    pub sample_count: usize,
+    //Depth offset against self occlusion on flat surfaces
+    pub bias: f32,
+    //Exponent applied to the result, higher darkens the creases more
+    pub power: f32,
+    //Blur taps on each side of a pixel, 0 turns the blur off
+    pub blur_radius: i32,
+    //How quickly the blur stops at depth edges, 0 is a plain gaussian
+    pub blur_sharpness: f32,
+    prepass: RenderTarget,
+    raw: RenderTarget,
+    blurred: [RenderTarget; 2],
+    noise_texture: GLuint,
+    kernel: Vec<Vector3<f32>>,
+    prepass_program: ShaderProgram,
+    ssao_program: ShaderProgram,
+    blur_program: ShaderProgram,
+    debug_program: ShaderProgram,
+    fullscreen_vao: VertexArray,
+}
+
+impl Ssao {
+    pub unsafe fn new(width: i32, height: i32) -> Option<Self> {
+        let prepass = RenderTarget::new(width, height, GL_RGBA16F, true)?;
+        prepass.set_label("SSAO prepass");
+        let raw = RenderTarget::new(width, height, GL_R16F, false)?;
+        raw.set_label("SSAO raw");
+        let blurred = [
+            RenderTarget::new(width, height, GL_R16F, false)?,
+            RenderTarget::new(width, height, GL_R16F, false)?,
+        ];
+        blurred[0].set_label("SSAO blur horizontal");
+        blurred[1].set_label("SSAO blurred");
+
+        let mut prepass_program = ShaderProgramBuilder::new()
+            .create_shader(
+                ShaderType::Vertex,
+                &shader_from_file(Path::new("assets/shaders/vertex_shader.vert")),
+            )
+            .create_shader(
+                ShaderType::Fragment,
+                &shader_from_file(Path::new("assets/shaders/normal_prepass.frag")),
+            )
+            .link()?;
+        prepass_program.set_label("Normal prepass");
+        //Everything Scene::draw sets, the ones the prepass doesn't use just have no location
+        for uniform in [
+            cstr!("M"),
+            cstr!("N"),
+            cstr!("V"),
+            cstr!("P"),
+            cstr!("camera_pos"),
+            cstr!("instanced"),
+            cstr!("albedo"),
+            cstr!("roughness"),
+            cstr!("metallic"),
+            cstr!("alpha"),
+            cstr!("alpha_cutoff"),
+        ] {
+            prepass_program.create_uniform(uniform);
+        }
+
+        let ssao = Self {
+            enabled: true,
+            radius: 0.5,
+            sample_count: 16,
+            bias: 0.025,
+            power: 1.5,
+            blur_radius: 4,
+            blur_sharpness: 20.0,
+            prepass,
+            raw,
+            blurred,
+            noise_texture: Self::create_noise_texture()?,
+            kernel: Vec::new(),
+            prepass_program,
+            ssao_program: post_program(
+                "ssao.frag",
+                &[
+                    cstr!("normal_texture"),
+                    cstr!("noise_texture"),
+                    cstr!("samples"),
+                    cstr!("sample_count"),
+                    cstr!("radius"),
+                    cstr!("bias"),
+                    cstr!("power"),
+                    cstr!("noise_scale"),
+                    cstr!("P"),
+                    cstr!("inverse_P"),
+                ],
+            ),
+            blur_program: post_program(
+                "ssao_blur.frag",
+                &[
+                    cstr!("depth_texture"),
+                    cstr!("direction"),
+                    cstr!("blur_radius"),
+                    cstr!("sharpness"),
+                    cstr!("inverse_P"),
+                ],
+            ),
+            debug_program: post_program("ssao_debug.frag", &[]),
+            fullscreen_vao: VertexArray::new()?,
+        };
+        Some(ssao)
+    }
+
+    //Sample offsets in the unit hemisphere around +z, cosine weighted and packed towards the center
+    //where nearby occluders matter most. Deterministic so the noise pattern doesn't change between runs
+    pub fn kernel(sample_count: usize) -> Vec<Vector3<f32>> {
+        (0..sample_count)
+            .map(|i| {
+                let (u, v) = (halton(i + 1, 2), halton(i + 1, 3));
+                let (sin, cos) = (TAU * v).sin_cos();
+                let r = u.sqrt();
+                let direction = Vector3::new(r * cos, r * sin, (1.0 - u).sqrt());
+                let t = (i + 1) as f32 / sample_count as f32;
+                direction * (0.1 + 0.9 * t * t)
+            })
+            .collect()
+    }
+
+    //Random rotations of the kernel around the normal
+    unsafe fn create_noise_texture() -> Option<GLuint> {
+        let noise: Vec<[f32; 3]> = (0..NOISE_SIZE * NOISE_SIZE)
+            .map(|i| {
+                let (sin, cos) = (TAU * halton(i as usize + 1, 5)).sin_cos();
+                [cos, sin, 0.0]
+            })
+            .collect();
+        let mut texture = 0;
+        glGenTextures(1, &mut texture);
+        if texture == 0 {
+            return None;
+        }
+        gl_state::bind_texture(GL_TEXTURE_2D, texture);
+        glTexImage2D(
+            GL_TEXTURE_2D,
+            0,
+            GL_RGB16F as GLint,
+            NOISE_SIZE,
+            NOISE_SIZE,
+            0,
+            GL_RGB,
+            GL_FLOAT,
+            noise.as_ptr().cast(),
+        );
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_NEAREST as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_REPEAT as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_REPEAT as GLint);
+        gl_state::bind_texture(GL_TEXTURE_2D, 0);
+        label_object(GL_TEXTURE, texture, "SSAO noise");
+        Some(texture)
+    }
+
+    //Final occlusion, blurred unless blur_radius is 0. Same size as the render target
+    pub fn texture(&self) -> GLuint {
+        if self.blur_radius > 0 {
+            self.blurred[1].color_texture
+        } else {
+            self.raw.color_texture
+        }
+    }
+
+    //Occlusion straight from the sampling pass, noisy
+    pub fn raw_texture(&self) -> GLuint {
+        self.raw.color_texture
+    }
+
+    pub fn prepass_target(&self) -> &RenderTarget {
+        &self.prepass
+    }
+
+    pub unsafe fn resize(&mut self, width: i32, height: i32) {
+        self.prepass.resize(width, height);
+        self.raw.resize(width, height);
+        for target in self.blurred.iter_mut() {
+            target.resize(width, height);
+        }
+    }
+
+    //Renders the prepass and the occlusion, call before the forward pass. Leaves the occlusion target
+    //bound, bind the scene target afterwards
+    pub unsafe fn render(&mut self, scene: &mut Scene, camera: &Camera) {
+        if !self.enabled {
+            return;
+        }
+        let _group = DebugGroup::new("SSAO");
+        let sample_count = self.sample_count.clamp(1, MAX_SAMPLES);
+        if self.kernel.len() != sample_count {
+            self.kernel = Self::kernel(sample_count);
+        }
+        let depth_texture = self
+            .prepass
+            .depth_texture
+            .expect("SSAO prepass has a depth texture");
+
+        {
+            let _group = DebugGroup::new("Normal prepass");
+            self.prepass.bind();
+            gl_state::enable(GL_DEPTH_TEST);
+            gl_state::depth_mask(true);
+            glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+            scene.draw_opaque(&self.prepass_program, camera);
+        }
+
+        gl_state::disable(GL_DEPTH_TEST);
+        gl_state::disable(GL_BLEND);
+        let projection = camera.get_projection_matrix();
+        let inverse_projection = projection.invert().unwrap_or_else(Matrix4::identity);
+        let (width, height) = (self.raw.width, self.raw.height);
+
+        {
+            let _group = DebugGroup::new("Occlusion");
+            let program = &self.ssao_program;
+            self.raw.bind();
+            gl_state::use_program(program.0);
+            gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, depth_texture);
+            gl_state::bind_texture_unit(GL_TEXTURE1, GL_TEXTURE_2D, self.prepass.color_texture);
+            gl_state::bind_texture_unit(GL_TEXTURE2, GL_TEXTURE_2D, self.noise_texture);
+            program.set_int("input_texture", 0);
+            program.set_int("normal_texture", 1);
+            program.set_int("noise_texture", 2);
+            program.set_vec3_array("samples", &self.kernel);
+            program.set_int("sample_count", sample_count as i32);
+            program.set_float("radius", self.radius);
+            program.set_float("bias", self.bias);
+            program.set_float("power", self.power);
+            program.set_vec2(
+                "noise_scale",
+                &Vector2::new(
+                    width as f32 / NOISE_SIZE as f32,
+                    height as f32 / NOISE_SIZE as f32,
+                ),
+            );
+            program.set_mat4("P", &projection);
+            program.set_mat4("inverse_P", &inverse_projection);
+            self.draw_fullscreen();
+        }
+
+        if self.blur_radius > 0 {
+            let _group = DebugGroup::new("Bilateral blur");
+            let program = &self.blur_program;
+            gl_state::use_program(program.0);
+            gl_state::bind_texture_unit(GL_TEXTURE1, GL_TEXTURE_2D, depth_texture);
+            program.set_int("input_texture", 0);
+            program.set_int("depth_texture", 1);
+            program.set_int("blur_radius", self.blur_radius);
+            program.set_float("sharpness", self.blur_sharpness);
+            program.set_mat4("inverse_P", &inverse_projection);
+            let passes = [
+                (
+                    self.raw.color_texture,
+                    Vector2::new(1.0 / width as f32, 0.0),
+                ),
+                (
+                    self.blurred[0].color_texture,
+                    Vector2::new(0.0, 1.0 / height as f32),
+                ),
+            ];
+            for (target, (source, direction)) in self.blurred.iter().zip(passes) {
+                target.bind();
+                gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, source);
+                program.set_vec2("direction", &direction);
+                self.draw_fullscreen();
+            }
+        }
+        gl_state::enable(GL_DEPTH_TEST);
+    }
+
+    //Binds the occlusion on unit for a program with "ao_map" and "ssao_enabled" uniforms
+    pub unsafe fn bind(&self, program: &ShaderProgram, unit: GLenum) {
+        gl_state::bind_texture_unit(unit, GL_TEXTURE_2D, self.texture());
+        program.set_int("ao_map", (unit - GL_TEXTURE0) as i32);
+        program.set_int("ssao_enabled", self.enabled as i32);
+    }
+
+    //Debug view: draws the occlusion as grayscale over the whole framebuffer, raw or blurred
+    pub unsafe fn draw_debug(&self, framebuffer: GLuint, width: i32, height: i32, raw: bool) {
+        let _group = DebugGroup::new("SSAO debug view");
+        glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+        glViewport(0, 0, width, height);
+        gl_state::disable(GL_DEPTH_TEST);
+        gl_state::use_program(self.debug_program.0);
+        let texture = if raw {
+            self.raw_texture()
+        } else {
+            self.texture()
+        };
+        gl_state::bind_texture_unit(GL_TEXTURE0, GL_TEXTURE_2D, texture);
+        self.debug_program.set_int("input_texture", 0);
+        self.draw_fullscreen();
+        gl_state::enable(GL_DEPTH_TEST);
+    }
+
+    unsafe fn draw_fullscreen(&self) {
+        self.fullscreen_vao.bind();
+        glDrawArrays(GL_TRIANGLES, 0, 3);
+        gl_state::draw_call();
+    }
+
+    pub unsafe fn delete(&self) {
+        self.prepass.delete();
+        self.raw.delete();
+        for target in self.blurred.iter() {
+            target.delete();
+        }
+        glDeleteTextures(1, &self.noise_texture);
+        gl_state::forget_texture(self.noise_texture);
+    }
+}
@@ -0,0 +1,787 @@
+use crate::{Buffer, Mesh, VertIndicies, Vertex, VERTEX_SIZE};
+use ogl33::*;
+
+use half::f16;
+use std::collections::HashMap;
+
+//What a vertex attribute means, used to pull its values out of a Mesh when building GPU buffers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexSemantic {
+    Position,
+    Normal,
+    TexCoord0,
+    Tangent,
+    Bitangent,
+    Color,
+    TexCoord1,
+    Joints,
+    Weights,
+    Custom(u8),
+}
+
+impl VertexSemantic {
+    //Range of the semantic inside the standard Vertex, None for semantics stored in Mesh::extra_attributes
+    pub fn vertex_range(self) -> Option<std::ops::Range<usize>> {
+        match self {
+            VertexSemantic::Position => Some(0..3),
+            VertexSemantic::Normal => Some(3..6),
+            VertexSemantic::TexCoord0 => Some(6..8),
+            VertexSemantic::Tangent => Some(8..12),
+            VertexSemantic::Bitangent => Some(12..15),
+            _ => None,
+        }
+    }
+
+    //Value used for components the mesh has no data for
+    pub fn default_value(self) -> [f32; 4] {
+        match self {
+            VertexSemantic::Color => [1.0, 1.0, 1.0, 1.0],
+            _ => [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+//Storage type of a vertex attribute.
+//Int2101010Rev and the octahedral types are packed: they always take 4 (or 2) bytes and are always normalized
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeType {
+    F32,
+    F16,
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    Int2101010Rev, //xyz as 10 bit snorm, w as 2 bit snorm (e.g. tangent handedness)
+    Octahedral16,  //Unit vector folded onto an octahedron, 2x snorm16. Needs octahedral_decode in the shader
+    Octahedral8,   //Same as Octahedral16 but 2x snorm8
+}
+
+impl AttributeType {
+    pub fn gl_type(self) -> GLenum {
+        match self {
+            AttributeType::F32 => GL_FLOAT,
+            AttributeType::F16 => GL_HALF_FLOAT,
+            AttributeType::I8 | AttributeType::Octahedral8 => GL_BYTE,
+            AttributeType::U8 => GL_UNSIGNED_BYTE,
+            AttributeType::I16 | AttributeType::Octahedral16 => GL_SHORT,
+            AttributeType::U16 => GL_UNSIGNED_SHORT,
+            AttributeType::I32 => GL_INT,
+            AttributeType::U32 => GL_UNSIGNED_INT,
+            AttributeType::Int2101010Rev => GL_INT_2_10_10_10_REV,
+        }
+    }
+
+    //Size of one component in bytes, packed types report the size of the whole attribute
+    pub fn size(self) -> usize {
+        match self {
+            AttributeType::I8 | AttributeType::U8 => 1,
+            AttributeType::F16 | AttributeType::I16 | AttributeType::U16 => 2,
+            AttributeType::Octahedral8 => 2,
+            AttributeType::F32 | AttributeType::I32 | AttributeType::U32 => 4,
+            AttributeType::Int2101010Rev | AttributeType::Octahedral16 => 4,
+        }
+    }
+
+    pub fn is_packed(self) -> bool {
+        matches!(
+            self,
+            AttributeType::Int2101010Rev | AttributeType::Octahedral16 | AttributeType::Octahedral8
+        )
+    }
+
+    pub fn is_integer(self) -> bool {
+        !matches!(self, AttributeType::F32 | AttributeType::F16) && !self.is_packed()
+    }
+
+    //Number of components GL reads for an attribute with the given logical component count
+    pub fn gl_components(self, components: usize) -> GLint {
+        match self {
+            AttributeType::Int2101010Rev => 4,
+            AttributeType::Octahedral16 | AttributeType::Octahedral8 => 2,
+            _ => components as GLint,
+        }
+    }
+
+    //Size in bytes of an attribute with the given logical component count
+    pub fn attribute_size(self, components: usize) -> usize {
+        if self.is_packed() {
+            self.size()
+        } else {
+            self.size() * components
+        }
+    }
+
+    fn write(self, value: &[f32; 4], components: usize, normalized: bool, out: &mut [u8]) {
+        match self {
+            AttributeType::Int2101010Rev => {
+                let snorm = |v: f32, max: f32| (v.clamp(-1.0, 1.0) * max).round() as i32;
+                let packed = (snorm(value[0], 511.0) & 0x3ff)
+                    | (snorm(value[1], 511.0) & 0x3ff) << 10
+                    | (snorm(value[2], 511.0) & 0x3ff) << 20
+                    | (snorm(value[3], 1.0) & 0x3) << 30;
+                out.copy_from_slice(&packed.to_le_bytes());
+            }
+            AttributeType::Octahedral16 | AttributeType::Octahedral8 => {
+                let encoded = octahedral_encode([value[0], value[1], value[2]]);
+                let component_size = self.size() / 2;
+                for (component, v) in encoded.iter().enumerate() {
+                    let at = component * component_size;
+                    let component_type = if self == AttributeType::Octahedral16 {
+                        AttributeType::I16
+                    } else {
+                        AttributeType::I8
+                    };
+                    component_type.write_component(*v, true, &mut out[at..at + component_size]);
+                }
+            }
+            _ => {
+                let component_size = self.size();
+                for (component, v) in value.iter().enumerate().take(components) {
+                    let at = component * component_size;
+                    self.write_component(*v, normalized, &mut out[at..at + component_size]);
+                }
+            }
+        }
+    }
+
+    fn read(self, components: usize, normalized: bool, bytes: &[u8]) -> [f32; 4] {
+        let mut value = [0.0; 4];
+        match self {
+            AttributeType::Int2101010Rev => {
+                let packed = i32::from_le_bytes(bytes.try_into().unwrap());
+                //Shift each field up to the sign bit and back down to sign extend it
+                let field = |shift: u32, bits: u32| (packed << (32 - shift - bits)) >> (32 - bits);
+                value[0] = (field(0, 10) as f32 / 511.0).max(-1.0);
+                value[1] = (field(10, 10) as f32 / 511.0).max(-1.0);
+                value[2] = (field(20, 10) as f32 / 511.0).max(-1.0);
+                value[3] = (field(30, 2) as f32).max(-1.0);
+            }
+            AttributeType::Octahedral16 | AttributeType::Octahedral8 => {
+                let component_size = self.size() / 2;
+                let component_type = if self == AttributeType::Octahedral16 {
+                    AttributeType::I16
+                } else {
+                    AttributeType::I8
+                };
+                let x = component_type.read_component(true, &bytes[0..component_size]);
+                let y = component_type.read_component(true, &bytes[component_size..2 * component_size]);
+                let decoded = octahedral_decode([x, y]);
+                value[..3].copy_from_slice(&decoded);
+            }
+            _ => {
+                let component_size = self.size();
+                for (component, v) in value.iter_mut().enumerate().take(components) {
+                    let at = component * component_size;
+                    *v = self.read_component(normalized, &bytes[at..at + component_size]);
+                }
+            }
+        }
+        value
+    }
+
+    fn write_component(self, value: f32, normalized: bool, out: &mut [u8]) {
+        fn quantize(value: f32, normalized: bool, min: f32, max: f32) -> f32 {
+            if normalized {
+                (value.clamp(min.max(-1.0), 1.0) * max).round()
+            } else {
+                value.round().clamp(min, max)
+            }
+        }
+        match self {
+            AttributeType::F32 => out.copy_from_slice(&value.to_le_bytes()),
+            AttributeType::F16 => out.copy_from_slice(&f16::from_f32(value).to_le_bytes()),
+            AttributeType::I8 => {
+                out.copy_from_slice(&(quantize(value, normalized, -128.0, 127.0) as i8).to_le_bytes())
+            }
+            AttributeType::U8 => {
+                out.copy_from_slice(&(quantize(value, normalized, 0.0, 255.0) as u8).to_le_bytes())
+            }
+            AttributeType::I16 => out.copy_from_slice(
+                &(quantize(value, normalized, -32768.0, 32767.0) as i16).to_le_bytes(),
+            ),
+            AttributeType::U16 => out.copy_from_slice(
+                &(quantize(value, normalized, 0.0, 65535.0) as u16).to_le_bytes(),
+            ),
+            AttributeType::I32 => out.copy_from_slice(&(value.round() as i32).to_le_bytes()),
+            AttributeType::U32 => out.copy_from_slice(&(value.round() as u32).to_le_bytes()),
+            _ => unreachable!("Packed types are written as a whole"),
+        }
+    }
+
+    fn read_component(self, normalized: bool, bytes: &[u8]) -> f32 {
+        //Signed normalized values map both -128 and -127 to -1.0, as GL does
+        let scale = |value: f32, max: f32| {
+            if normalized {
+                (value / max).max(-1.0)
+            } else {
+                value
+            }
+        };
+        match self {
+            AttributeType::F32 => f32::from_le_bytes(bytes.try_into().unwrap()),
+            AttributeType::F16 => f16::from_le_bytes(bytes.try_into().unwrap()).to_f32(),
+            AttributeType::I8 => scale(i8::from_le_bytes(bytes.try_into().unwrap()) as f32, 127.0),
+            AttributeType::U8 => scale(bytes[0] as f32, 255.0),
+            AttributeType::I16 => {
+                scale(i16::from_le_bytes(bytes.try_into().unwrap()) as f32, 32767.0)
+            }
+            AttributeType::U16 => {
+                scale(u16::from_le_bytes(bytes.try_into().unwrap()) as f32, 65535.0)
+            }
+            AttributeType::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+            AttributeType::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+            _ => unreachable!("Packed types are read as a whole"),
+        }
+    }
+}
+
+//Maps a unit vector onto the [-1,1]^2 square (octahedral normal encoding)
+pub fn octahedral_encode(n: [f32; 3]) -> [f32; 2] {
+    let l1 = n[0].abs() + n[1].abs() + n[2].abs();
+    if l1 == 0.0 {
+        return [0.0, 0.0];
+    }
+    let (x, y) = (n[0] / l1, n[1] / l1);
+    if n[2] >= 0.0 {
+        [x, y]
+    } else {
+        //Fold the lower hemisphere over the diagonals
+        let sign = |v: f32| if v >= 0.0 { 1.0 } else { -1.0 };
+        [(1.0 - y.abs()) * sign(x), (1.0 - x.abs()) * sign(y)]
+    }
+}
+
+pub fn octahedral_decode(e: [f32; 2]) -> [f32; 3] {
+    let z = 1.0 - e[0].abs() - e[1].abs();
+    let t = (-z).max(0.0);
+    let x = e[0] + if e[0] >= 0.0 { -t } else { t };
+    let y = e[1] + if e[1] >= 0.0 { -t } else { t };
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+//GLSL counterpart of octahedral_decode, for shaders that read Octahedral16/Octahedral8 attributes
+pub const OCTAHEDRAL_DECODE_GLSL: &str = "
+vec3 octahedral_decode(vec2 e) {
+    vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+    float t = max(-n.z, 0.0);
+    n.xy += vec2(n.x >= 0.0 ? -t : t, n.y >= 0.0 ? -t : t);
+    return normalize(n);
+}
+";
+
+//A named vertex attribute. stream and offset are filled in by VertexFormat
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexAttribute {
+    pub name: String,
+    pub semantic: VertexSemantic,
+    pub location: GLuint,
+    pub attribute_type: AttributeType,
+    pub components: usize,
+    pub normalized: bool,
+    pub stream: usize,
+    pub offset: usize,
+}
+
+impl VertexAttribute {
+    pub fn new(
+        name: &str,
+        semantic: VertexSemantic,
+        location: GLuint,
+        attribute_type: AttributeType,
+        components: usize,
+    ) -> Self {
+        assert!((1..=4).contains(&components), "Vertex attributes have 1 to 4 components");
+        Self {
+            name: name.to_string(),
+            semantic,
+            location,
+            attribute_type,
+            components,
+            normalized: false,
+            stream: 0,
+            offset: 0,
+        }
+    }
+
+    //Integer data is read as floats in [0,1] (unsigned) or [-1,1] (signed) by the shader
+    pub fn normalized(mut self) -> Self {
+        self.normalized = true;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.attribute_type.attribute_size(self.components)
+    }
+}
+
+//Describes how vertex data is laid out in one or more buffers (streams) and which attribute locations it feeds
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexFormat {
+    pub attributes: Vec<VertexAttribute>,
+    pub strides: Vec<usize>, //Stride of each stream in bytes
+}
+
+//Attribute locations used by the shaders
+pub const POSITION_LOCATION: GLuint = 0;
+pub const NORMAL_LOCATION: GLuint = 1;
+pub const TEXCOORD0_LOCATION: GLuint = 2;
+pub const TANGENT_LOCATION: GLuint = 3;
+pub const BITANGENT_LOCATION: GLuint = 4;
+pub const COLOR_LOCATION: GLuint = 11;
+pub const TEXCOORD1_LOCATION: GLuint = 12;
+pub const JOINTS_LOCATION: GLuint = 13;
+pub const WEIGHTS_LOCATION: GLuint = 14;
+
+impl VertexFormat {
+    //All attributes one after another in a single buffer.
+    //Attributes start on 4 byte boundaries, which GL implementations expect for good (or any) performance
+    pub fn interleaved(attributes: Vec<VertexAttribute>) -> Self {
+        let mut offset = 0;
+        let attributes: Vec<VertexAttribute> = attributes
+            .into_iter()
+            .map(|mut attribute| {
+                attribute.stream = 0;
+                attribute.offset = offset;
+                offset = align4(offset + attribute.size());
+                attribute
+            })
+            .collect();
+        Self {
+            attributes,
+            strides: vec![offset],
+        }
+    }
+
+    //Every attribute in its own buffer
+    pub fn separate(attributes: Vec<VertexAttribute>) -> Self {
+        let strides = attributes
+            .iter()
+            .map(|attribute| align4(attribute.size()))
+            .collect();
+        let attributes = attributes
+            .into_iter()
+            .enumerate()
+            .map(|(stream, mut attribute)| {
+                attribute.stream = stream;
+                attribute.offset = 0;
+                attribute
+            })
+            .collect();
+        Self {
+            attributes,
+            strides,
+        }
+    }
+
+    //A single stream with explicit offsets, for vertex structs with padding (use std::mem::offset_of!)
+    pub fn with_offsets(stride: usize, attributes: Vec<(usize, VertexAttribute)>) -> Self {
+        let attributes = attributes
+            .into_iter()
+            .map(|(offset, mut attribute)| {
+                assert!(
+                    offset + attribute.size() <= stride,
+                    "Vertex attribute {} doesn't fit in the stride",
+                    attribute.name
+                );
+                attribute.stream = 0;
+                attribute.offset = offset;
+                attribute
+            })
+            .collect();
+        Self {
+            attributes,
+            strides: vec![stride],
+        }
+    }
+
+    //Full precision position, normal, uv and tangent (with handedness in w).
+    //The bitangent isn't uploaded, shaders rebuild it as tangent.w * cross(normal, tangent.xyz)
+    pub fn standard() -> Self {
+        Self::interleaved(vec![
+            VertexAttribute::new("pos", VertexSemantic::Position, POSITION_LOCATION, AttributeType::F32, 3),
+            VertexAttribute::new("normal", VertexSemantic::Normal, NORMAL_LOCATION, AttributeType::F32, 3),
+            VertexAttribute::new("uv", VertexSemantic::TexCoord0, TEXCOORD0_LOCATION, AttributeType::F32, 2),
+            VertexAttribute::new("tangent", VertexSemantic::Tangent, TANGENT_LOCATION, AttributeType::F32, 4),
+        ])
+    }
+
+    //Position, normal and uv only, for meshes that are never normal mapped
+    pub fn without_tangents() -> Self {
+        Self::interleaved(vec![
+            VertexAttribute::new("pos", VertexSemantic::Position, POSITION_LOCATION, AttributeType::F32, 3),
+            VertexAttribute::new("normal", VertexSemantic::Normal, NORMAL_LOCATION, AttributeType::F32, 3),
+            VertexAttribute::new("uv", VertexSemantic::TexCoord0, TEXCOORD0_LOCATION, AttributeType::F32, 2),
+        ])
+    }
+
+    pub fn stream_count(&self) -> usize {
+        self.strides.len()
+    }
+
+    pub fn has(&self, semantic: VertexSemantic) -> bool {
+        self.attribute(semantic).is_some()
+    }
+
+    pub fn attribute(&self, semantic: VertexSemantic) -> Option<&VertexAttribute> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.semantic == semantic)
+    }
+
+    //Size of one vertex across all streams
+    pub fn vertex_size(&self) -> usize {
+        self.strides.iter().sum()
+    }
+
+    //Packs the vertices (and any extra attributes) into one byte buffer per stream
+    pub fn encode(
+        &self,
+        vertices: &[Vertex],
+        extra_attributes: &HashMap<VertexSemantic, Vec<[f32; 4]>>,
+    ) -> Vec<Vec<u8>> {
+        let mut streams: Vec<Vec<u8>> = self
+            .strides
+            .iter()
+            .map(|stride| vec![0; stride * vertices.len()])
+            .collect();
+
+        for attribute in self.attributes.iter() {
+            let stride = self.strides[attribute.stream];
+            let stream = &mut streams[attribute.stream];
+            let extra = extra_attributes.get(&attribute.semantic);
+
+            for (index, vertex) in vertices.iter().enumerate() {
+                let mut value = attribute.semantic.default_value();
+                match attribute.semantic.vertex_range() {
+                    Some(range) => {
+                        let len = range.len();
+                        value[..len].copy_from_slice(&vertex[range]);
+                    }
+                    None => {
+                        if let Some(data) = extra.and_then(|extra| extra.get(index)) {
+                            value = *data;
+                        }
+                    }
+                }
+                //Packed types store directions, so only the direction of xyz is kept
+                if attribute.attribute_type.is_packed() {
+                    let length = (value[0] * value[0] + value[1] * value[1] + value[2] * value[2]).sqrt();
+                    if length > 0.0 {
+                        value[0] /= length;
+                        value[1] /= length;
+                        value[2] /= length;
+                    }
+                }
+
+                let start = index * stride + attribute.offset;
+                attribute.attribute_type.write(
+                    &value,
+                    attribute.components,
+                    attribute.normalized,
+                    &mut stream[start..start + attribute.size()],
+                );
+            }
+        }
+
+        streams
+    }
+
+    //Inverse of encode, semantics without a place in Vertex end up in the returned extra attributes
+    pub fn decode(
+        &self,
+        streams: &[&[u8]],
+    ) -> (Vec<Vertex>, HashMap<VertexSemantic, Vec<[f32; 4]>>) {
+        let vertex_count = streams
+            .iter()
+            .zip(self.strides.iter())
+            .map(|(stream, stride)| stream.len() / stride)
+            .min()
+            .unwrap_or(0);
+
+        let mut vertices: Vec<Vertex> = vec![[0.0; VERTEX_SIZE]; vertex_count];
+        let mut extra_attributes = HashMap::new();
+
+        for attribute in self.attributes.iter() {
+            let stride = self.strides[attribute.stream];
+            let stream = streams[attribute.stream];
+            let mut extra: Vec<[f32; 4]> = Vec::new();
+
+            for (index, vertex) in vertices.iter_mut().enumerate() {
+                let start = index * stride + attribute.offset;
+                let mut value = attribute.attribute_type.read(
+                    attribute.components,
+                    attribute.normalized,
+                    &stream[start..start + attribute.size()],
+                );
+                let default = attribute.semantic.default_value();
+                value[attribute.components..].copy_from_slice(&default[attribute.components..]);
+
+                match attribute.semantic.vertex_range() {
+                    Some(range) => {
+                        let len = range.len();
+                        vertex[range].copy_from_slice(&value[..len]);
+                    }
+                    None => extra.push(value),
+                }
+            }
+
+            if attribute.semantic.vertex_range().is_none() {
+                extra_attributes.insert(attribute.semantic, extra);
+            }
+        }
+
+        (vertices, extra_attributes)
+    }
+
+    //Points the attributes at their stream buffers, the VAO has to be bound.
+    //Integer attributes that aren't normalized use glVertexAttribIPointer so the shader sees ints
+    pub unsafe fn setup_attributes(&self, buffers: &[Buffer]) {
+        assert_eq!(
+            buffers.len(),
+            self.stream_count(),
+            "Vertex format needs one buffer per stream"
+        );
+        for attribute in self.attributes.iter() {
+            buffers[attribute.stream].bind();
+            let stride: GLsizei = self.strides[attribute.stream].try_into().unwrap();
+            if attribute.attribute_type.is_integer() && !attribute.normalized {
+                glVertexAttribIPointer(
+                    attribute.location,
+                    attribute.attribute_type.gl_components(attribute.components),
+                    attribute.attribute_type.gl_type(),
+                    stride,
+                    attribute.offset as *const _,
+                );
+            } else {
+                let normalized = attribute.normalized || attribute.attribute_type.is_packed();
+                glVertexAttribPointer(
+                    attribute.location,
+                    attribute.attribute_type.gl_components(attribute.components),
+                    attribute.attribute_type.gl_type(),
+                    if normalized { GL_TRUE } else { GL_FALSE },
+                    stride,
+                    attribute.offset as *const _,
+                );
+            }
+            glEnableVertexAttribArray(attribute.location);
+        }
+    }
+}
+
+fn align4(size: usize) -> usize {
+    (size + 3) & !3
+}
+
+//Type of the element buffer. Mesh::setup picks U16 whenever the vertex count allows it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+    U16,
+    U32,
+}
+
+impl IndexFormat {
+    pub fn for_vertex_count(vertex_count: usize) -> Self {
+        if vertex_count <= u16::MAX as usize + 1 {
+            IndexFormat::U16
+        } else {
+            IndexFormat::U32
+        }
+    }
+
+    pub fn gl_type(self) -> GLenum {
+        match self {
+            IndexFormat::U16 => GL_UNSIGNED_SHORT,
+            IndexFormat::U32 => GL_UNSIGNED_INT,
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            IndexFormat::U16 => 2,
+            IndexFormat::U32 => 4,
+        }
+    }
+
+    pub fn encode(self, indices: &[VertIndicies]) -> Vec<u8> {
+        match self {
+            IndexFormat::U16 => indices
+                .iter()
+                .flatten()
+                .flat_map(|&index| {
+                    u16::try_from(index)
+                        .expect("Index doesn't fit in a u16")
+                        .to_le_bytes()
+                })
+                .collect(),
+            IndexFormat::U32 => bytemuck::cast_slice(indices).to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionPrecision {
+    F32,
+    F16, //Only for models whose coordinates stay well inside +-65504 and don't need sub-millimeter detail far from the origin
+}
+
+//Used for normals and tangents
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirectionPrecision {
+    F32,
+    Int2101010Rev,
+    Octahedral16, //The shader has to decode these, see OCTAHEDRAL_DECODE_GLSL
+    Octahedral8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TexCoordPrecision {
+    F32,
+    F16,
+    Unorm16, //Only for texture coordinates in [0,1]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorPrecision {
+    F32,
+    Unorm8,
+}
+
+//How much precision each kind of attribute keeps on the GPU, turned into a VertexFormat by format_for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexPrecision {
+    pub positions: PositionPrecision,
+    pub directions: DirectionPrecision,
+    pub tex_coords: TexCoordPrecision,
+    pub colors: ColorPrecision,
+    pub tangents: bool, //Whether tangents are uploaded at all
+}
+
+//Packs normals and colors, which shaders read back transparently, and keeps positions and uvs at full precision
+impl Default for VertexPrecision {
+    fn default() -> Self {
+        Self {
+            positions: PositionPrecision::F32,
+            directions: DirectionPrecision::Int2101010Rev,
+            tex_coords: TexCoordPrecision::F32,
+            colors: ColorPrecision::Unorm8,
+            tangents: true,
+        }
+    }
+}
+
+impl VertexPrecision {
+    //Matches the layout of the standard Vertex
+    pub fn full() -> Self {
+        Self {
+            positions: PositionPrecision::F32,
+            directions: DirectionPrecision::F32,
+            tex_coords: TexCoordPrecision::F32,
+            colors: ColorPrecision::F32,
+            tangents: true,
+        }
+    }
+
+    //Smallest layout that needs no shader changes: half positions and uvs, packed directions
+    pub fn compact() -> Self {
+        Self {
+            positions: PositionPrecision::F16,
+            directions: DirectionPrecision::Int2101010Rev,
+            tex_coords: TexCoordPrecision::F16,
+            colors: ColorPrecision::Unorm8,
+            tangents: true,
+        }
+    }
+
+    //Interleaved format holding the attributes the mesh actually has data for
+    pub fn format_for(&self, mesh: &Mesh) -> VertexFormat {
+        let direction = |name: &str, semantic: VertexSemantic, location: GLuint| match self.directions {
+            DirectionPrecision::F32 => VertexAttribute::new(name, semantic, location, AttributeType::F32, 3),
+            DirectionPrecision::Int2101010Rev => {
+                VertexAttribute::new(name, semantic, location, AttributeType::Int2101010Rev, 3)
+            }
+            DirectionPrecision::Octahedral16 => {
+                VertexAttribute::new(name, semantic, location, AttributeType::Octahedral16, 3)
+            }
+            DirectionPrecision::Octahedral8 => {
+                VertexAttribute::new(name, semantic, location, AttributeType::Octahedral8, 3)
+            }
+        };
+        let tex_coord = |name: &str, semantic: VertexSemantic, location: GLuint| match self.tex_coords {
+            TexCoordPrecision::F32 => VertexAttribute::new(name, semantic, location, AttributeType::F32, 2),
+            TexCoordPrecision::F16 => VertexAttribute::new(name, semantic, location, AttributeType::F16, 2),
+            TexCoordPrecision::Unorm16 => {
+                VertexAttribute::new(name, semantic, location, AttributeType::U16, 2).normalized()
+            }
+        };
+
+        let position_type = match self.positions {
+            PositionPrecision::F32 => AttributeType::F32,
+            PositionPrecision::F16 => AttributeType::F16,
+        };
+        let mut attributes = vec![
+            VertexAttribute::new("pos", VertexSemantic::Position, POSITION_LOCATION, position_type, 3),
+            direction("normal", VertexSemantic::Normal, NORMAL_LOCATION),
+            tex_coord("uv", VertexSemantic::TexCoord0, TEXCOORD0_LOCATION),
+        ];
+        if self.tangents {
+            //Octahedral encoding has no room for the handedness sign, so tangents fall back to Int2101010Rev
+            attributes.push(match self.directions {
+                DirectionPrecision::F32 => {
+                    VertexAttribute::new("tangent", VertexSemantic::Tangent, TANGENT_LOCATION, AttributeType::F32, 4)
+                }
+                _ => VertexAttribute::new(
+                    "tangent",
+                    VertexSemantic::Tangent,
+                    TANGENT_LOCATION,
+                    AttributeType::Int2101010Rev,
+                    4,
+                ),
+            });
+        }
+        if mesh.extra_attributes.contains_key(&VertexSemantic::Color) {
+            attributes.push(match self.colors {
+                ColorPrecision::F32 => {
+                    VertexAttribute::new("color", VertexSemantic::Color, COLOR_LOCATION, AttributeType::F32, 4)
+                }
+                ColorPrecision::Unorm8 => {
+                    VertexAttribute::new("color", VertexSemantic::Color, COLOR_LOCATION, AttributeType::U8, 4)
+                        .normalized()
+                }
+            });
+        }
+        if mesh.extra_attributes.contains_key(&VertexSemantic::TexCoord1) {
+            attributes.push(tex_coord("uv1", VertexSemantic::TexCoord1, TEXCOORD1_LOCATION));
+        }
+        if mesh.extra_attributes.contains_key(&VertexSemantic::Joints) {
+            attributes.push(VertexAttribute::new(
+                "joints",
+                VertexSemantic::Joints,
+                JOINTS_LOCATION,
+                AttributeType::U16,
+                4,
+            ));
+        }
+        if mesh.extra_attributes.contains_key(&VertexSemantic::Weights) {
+            attributes.push(
+                VertexAttribute::new("weights", VertexSemantic::Weights, WEIGHTS_LOCATION, AttributeType::U8, 4)
+                    .normalized(),
+            );
+        }
+        VertexFormat::interleaved(attributes)
+    }
+}
+
+//Implemented by Pod vertex structs so they can be turned into a Mesh with Mesh::from_vertices.
+//The format must be interleaved with a stride of size_of::<Self>()
+pub trait VertexLayout: bytemuck::Pod {
+    fn vertex_format() -> VertexFormat;
+}
+
+impl VertexLayout for Vertex {
+    fn vertex_format() -> VertexFormat {
+        VertexFormat::standard()
+    }
+}
@@ -0,0 +1,65 @@
+use cgmath::{Vector2, Vector3, Vector4};
+use OpenGL_Renderer::*;
+
+#[test]
+fn modes_cycle_back_to_none() {
+    let mut mode = AntiAliasing::default();
+    let mut seen = vec![mode];
+    for _ in 0..3 {
+        mode = mode.next();
+        seen.push(mode);
+    }
+    assert_eq!(
+        seen,
+        [
+            AntiAliasing::None,
+            AntiAliasing::Msaa(4),
+            AntiAliasing::Fxaa,
+            AntiAliasing::Taa
+        ]
+    );
+    assert_eq!(mode.next(), AntiAliasing::None);
+}
+
+#[test]
+fn jitter_covers_the_pixel() {
+    let offsets: Vec<Vector2<f32>> = (0..8).map(|i| Taa::jitter_offset(i, 8)).collect();
+    for (i, offset) in offsets.iter().enumerate() {
+        assert!(
+            offset.x.abs() <= 0.5 && offset.y.abs() <= 0.5,
+            "{:?}",
+            offset
+        );
+        assert!(!offsets[..i].contains(offset), "{:?} repeats", offset);
+    }
+    let mean = offsets.iter().fold(Vector2::new(0.0, 0.0), |a, &b| a + b) / 8.0;
+    assert!(mean.x.abs() < 0.1 && mean.y.abs() < 0.1, "{:?}", mean);
+    //The sequence wraps around
+    assert_eq!(Taa::jitter_offset(8, 8), offsets[0]);
+}
+
+#[test]
+fn jitter_shifts_the_projection_by_subpixels() {
+    let mut camera = Camera::new(Vector3::new(0.0, 0.0, 3.0), Vector3::new(0.0, 0.0, 0.0));
+    let point = Vector4::new(0.3, -0.2, 0.0, 1.0);
+    let ndc = |camera: &Camera| {
+        let clip = camera.get_projection_matrix() * camera.get_view_matrix() * point;
+        Vector2::new(clip.x / clip.w, clip.y / clip.w)
+    };
+    let still = ndc(&camera);
+    assert_eq!(
+        camera.get_projection_matrix(),
+        camera.get_unjittered_projection_matrix()
+    );
+
+    let mut taa = Taa::default();
+    let (width, height) = (800, 600);
+    for _ in 0..8 {
+        taa.prepare(&mut camera, width, height);
+        let shift = ndc(&camera) - still;
+        //Half a pixel is 1 / size in normalized device coordinates
+        assert!(shift.x.abs() <= 1.0 / width as f32 + 1e-6, "{:?}", shift);
+        assert!(shift.y.abs() <= 1.0 / height as f32 + 1e-6, "{:?}", shift);
+        assert!((shift - camera.jitter).x.abs() < 1e-5 && (shift - camera.jitter).y.abs() < 1e-5);
+    }
+}
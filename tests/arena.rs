@@ -0,0 +1,149 @@
+use cgmath::Vector3;
+use OpenGL_Renderer::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn handles_go_stale_on_removal() {
+    let mut arena: Arena<&str> = Arena::new();
+    let a = arena.insert("a");
+    let b = arena.insert("b");
+    assert_eq!(arena.len(), 2);
+    assert_eq!(arena[a], "a");
+    assert_eq!(arena.get(b), Some(&"b"));
+
+    assert_eq!(arena.remove(a), Some("a"));
+    assert_eq!(arena.remove(a), None);
+    assert!(!arena.contains(a));
+    assert_eq!(arena.len(), 1);
+
+    //The slot is reused, the old handle doesn't see the new value
+    let c = arena.insert("c");
+    assert_eq!(c.slot(), a.slot());
+    assert_ne!(c, a);
+    assert_eq!(arena.get(a), None);
+    assert_eq!(arena.get_mut(a), None);
+    assert_eq!(arena[c], "c");
+    assert_eq!(arena.slot_count(), 2);
+    assert_eq!(arena.handle_at(c.slot()), Some(c));
+
+    assert_eq!(
+        arena.iter().map(|(handle, _)| handle).collect::<Vec<_>>(),
+        vec![c, b]
+    );
+    arena.retain(|_, value| *value != "b");
+    assert_eq!(arena.values().copied().collect::<Vec<_>>(), vec!["c"]);
+    arena.clear();
+    assert!(arena.is_empty());
+    assert!(!arena.contains(c));
+    assert_eq!(arena.handle_at(0), None);
+}
+
+fn scene() -> (Scene, Vec<ObjectHandle>) {
+    let (mut scene, handles) = scene_of(
+        Mesh::cube(1.0, 1),
+        (0..20).map(|i| Vector3::new(i as f32 * 2.0 - 20.0, 0.0, -10.0)),
+    );
+    for (i, &handle) in handles.iter().enumerate() {
+        scene.objects[handle].name = format!("cube{}", i);
+    }
+    (scene, handles)
+}
+
+#[test]
+fn removal_mid_iteration() {
+    let (mut scene, handles) = scene();
+    //Removing objects while walking a snapshot of the handles, including ones not visited yet
+    for handle in scene.objects.handles() {
+        let index = match scene.get(handle) {
+            Some(object) => object.name[4..].parse::<usize>().unwrap(),
+            None => continue,
+        };
+        if index % 3 == 0 {
+            scene.remove_object(handle);
+            if index + 1 < handles.len() {
+                scene.remove_object(handles[index + 1]);
+            }
+        }
+    }
+    for (index, &handle) in handles.iter().enumerate() {
+        assert_eq!(scene.get(handle).is_some(), index % 3 == 2, "{}", index);
+    }
+    assert_eq!(scene.objects.len(), 6);
+    assert_eq!(scene.find("cube2"), Some(handles[2]));
+    assert_eq!(scene.find("cube3"), None);
+
+    //New objects fill the freed slots but old handles stay dead
+    let new = scene.add_object(Object::new(Mesh::cube(1.0, 1)));
+    assert!(handles.iter().any(|handle| handle.slot() == new.slot()));
+    assert!(!handles.contains(&new));
+    assert_eq!(scene.objects.slot_count(), 20);
+}
+
+#[test]
+fn removal_updates_hierarchy_and_queries() {
+    let (mut scene, handles) = scene();
+    scene.build_bvh();
+    let camera = Camera::new(Vector3::new(0.0, 0.0, 10.0), Vector3::new(0.0, 0.0, -10.0));
+    let visible = scene.cull(&camera).to_vec();
+    assert!(visible.contains(&handles[10]));
+
+    let child = scene
+        .add_child(handles[10], Object::new(Mesh::cube(1.0, 1)))
+        .unwrap();
+    let grandchild = scene
+        .add_child(child, Object::new(Mesh::cube(1.0, 1)))
+        .unwrap();
+    scene.update_transforms();
+    assert!(scene.remove_object(handles[10]).is_some());
+    assert!(scene.get(child).is_none() && scene.get(grandchild).is_none());
+    assert!(scene.remove_object(handles[10]).is_none());
+
+    //Neither culling nor ray casts return removed objects, with or without the BVH
+    let ray = Ray::new(Vector3::new(0.0, 0.0, 10.0), Vector3::new(0.0, 0.0, -1.0));
+    for _ in 0..2 {
+        let visible = scene.cull(&camera).to_vec();
+        assert_eq!(visible.len(), scene.cull_stats.visible);
+        assert!(!visible.contains(&handles[10]));
+        assert!(visible.iter().all(|&handle| scene.get(handle).is_some()));
+        assert!(scene.raycast(&ray, 100.0).is_none());
+        let sphere = BoundingSphere::new(Vector3::new(-1.0, 0.0, -10.0), 0.75);
+        assert_eq!(scene.objects_in_sphere(&sphere), vec![handles[9]]);
+        scene.bvh = None;
+    }
+
+    //Removing a child detaches it from its parent
+    let child = scene
+        .add_child(handles[4], Object::new(Mesh::cube(1.0, 1)))
+        .unwrap();
+    scene.remove_object(child);
+    assert!(scene.objects[handles[4]].children().is_empty());
+}
+
+#[test]
+fn hierarchy_calls_reject_stale_handles() {
+    let (mut scene, handles) = scene();
+    let child = scene
+        .add_child(handles[0], Object::new(Mesh::cube(1.0, 1)))
+        .unwrap();
+    scene.remove_object(handles[1]);
+    let stale = handles[1];
+    let count = scene.objects.len();
+
+    //Nothing is added under a stale parent, so no orphan is left behind
+    assert!(scene
+        .add_child(stale, Object::new(Mesh::cube(1.0, 1)))
+        .is_none());
+    assert_eq!(scene.objects.len(), count);
+
+    assert!(!scene.set_parent(child, Some(stale)));
+    assert!(!scene.set_parent(stale, Some(handles[2])));
+    assert!(!scene.set_parent(stale, None));
+    assert_eq!(scene.objects[child].parent(), Some(handles[0]));
+
+    assert!(!scene.is_descendant(stale, handles[0]));
+    assert!(!scene.is_descendant(child, stale));
+    assert!(scene.descendants(stale).is_empty());
+    assert_eq!(scene.find_child(stale, "cube0"), None);
+}
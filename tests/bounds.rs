@@ -0,0 +1,133 @@
+use cgmath::{Deg, InnerSpace, Matrix4, Quaternion, Rotation3, Vector3};
+use OpenGL_Renderer::*;
+
+use std::{path::Path, rc::Rc};
+
+mod common;
+use common::*;
+
+fn positions(mesh: &Mesh) -> Vec<Vector3<f32>> {
+    mesh.vertices
+        .iter()
+        .map(|v| Vector3::new(v[0], v[1], v[2]))
+        .collect()
+}
+
+#[test]
+fn mesh_bounds_enclose_vertices() {
+    let meshes = [
+        Mesh::cube(2.0, 1),
+        Mesh::uv_sphere(1.0, 24, 12),
+        Mesh::torus(1.0, 0.25, 24, 12),
+        mesh_from_obj(Path::new("assets/models/monke.obj")),
+        mesh_from_obj(Path::new("assets/models/sphere.obj")),
+    ];
+    for mesh in meshes.iter() {
+        assert!(!mesh.aabb.is_empty());
+        for p in positions(mesh) {
+            assert!(mesh.aabb.contains(p));
+            assert!((p - mesh.bounding_sphere.center).magnitude() <= mesh.bounding_sphere.radius * 1.0001);
+        }
+        //Ritter's sphere stays close to the box's circumscribed sphere or better
+        assert!(mesh.bounding_sphere.radius <= mesh.aabb.size().magnitude() * 0.5 * 1.05);
+    }
+
+    let cube = &meshes[0];
+    assert!(close(cube.aabb.min, Vector3::new(-1.0, -1.0, -1.0)));
+    assert!(close(cube.aabb.max, Vector3::new(1.0, 1.0, 1.0)));
+    let sphere = &meshes[1];
+    assert!(close(sphere.bounding_sphere.center, Vector3::new(0.0, 0.0, 0.0)));
+    assert!((sphere.bounding_sphere.radius - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn bounds_follow_mesh_processing() {
+    let mut mesh = Mesh::cube(2.0, 1);
+    mesh.vertices.push([0.0; VERTEX_SIZE].map(|_| 10.0));
+    mesh.update_bounds();
+    assert_eq!(mesh.aabb.max, Vector3::new(10.0, 10.0, 10.0));
+    mesh.remove_unused_vertices();
+    assert!(close(mesh.aabb.max, Vector3::new(1.0, 1.0, 1.0)));
+}
+
+#[test]
+fn object_world_bounds() {
+    let mut object = Object::new(Mesh::cube(2.0, 1));
+    assert!(close(object.world_aabb.max, Vector3::new(1.0, 1.0, 1.0)));
+
+    object.position = Vector3::new(5.0, 0.0, 0.0);
+    object.rotation = Quaternion::from_angle_y(Deg(45.0));
+    object.scale = Vector3::new(1.0, 2.0, 1.0);
+    object.update_model_matrix();
+
+    let diagonal = 2.0f32.sqrt();
+    assert!(close(object.world_aabb.min, Vector3::new(5.0 - diagonal, -2.0, -diagonal)));
+    assert!(close(object.world_aabb.max, Vector3::new(5.0 + diagonal, 2.0, diagonal)));
+    assert!(close(object.world_sphere.center, Vector3::new(5.0, 0.0, 0.0)));
+    assert!((object.world_sphere.radius - object.mesh.bounding_sphere.radius * 2.0).abs() < 1e-4);
+
+    //The OBB stays tight: its corners are exactly the transformed cube corners
+    let obb = object.world_obb;
+    assert!(close(obb.half_extents, Vector3::new(1.0, 2.0, 1.0)));
+    for corner in object.mesh.aabb.corners() {
+        let world = (object.model_matrix * corner.extend(1.0)).truncate();
+        assert!(obb.corners().iter().any(|&c| close(c, world)));
+        assert!(obb.contains(world * 0.999 + obb.center * 0.001));
+        assert!(object.world_aabb.contains(world * 0.999 + obb.center * 0.001));
+    }
+    assert!(!obb.contains(Vector3::new(5.0 + 1.2, 0.0, 1.2)));
+    assert!(object.world_aabb.contains(Vector3::new(5.0 + 1.2, 0.0, 1.2)));
+}
+
+#[test]
+fn aabb_transform_matches_corners() {
+    let aabb = Aabb::new(Vector3::new(-1.0, 0.0, 2.0), Vector3::new(3.0, 1.0, 4.0));
+    let matrix = Matrix4::from_translation(Vector3::new(1.0, -2.0, 3.0))
+        * Matrix4::from(Quaternion::from_axis_angle(
+            Vector3::new(1.0, 1.0, 0.0).normalize(),
+            Deg(30.0),
+        ))
+        * Matrix4::from_nonuniform_scale(2.0, 0.5, 1.0);
+    let expected = Aabb::from_points(
+        aabb.corners()
+            .iter()
+            .map(|c| (matrix * c.extend(1.0)).truncate()),
+    );
+    let transformed = aabb.transformed(&matrix);
+    assert!(close(transformed.min, expected.min));
+    assert!(close(transformed.max, expected.max));
+    assert!(Aabb::empty().transformed(&matrix).is_empty());
+}
+
+#[test]
+fn scene_bounds_cover_objects() {
+    let mut scene = Scene::new();
+    assert!(scene.bounds().is_empty());
+    assert!(scene.bounding_sphere().is_none());
+
+    let mesh = Rc::new(Mesh::uv_sphere(1.0, 16, 8));
+    let material = Rc::new(Material::default());
+    for x in [-4.0, 0.0, 6.0] {
+        let mut object = Object::with_shared(mesh.clone(), material.clone());
+        object.position = Vector3::new(x, 1.0, 0.0);
+        scene.add_object(object);
+    }
+    scene.update_model_matrices();
+
+    let bounds = scene.bounds();
+    assert!((bounds.min.x - -5.0).abs() < 1e-4 && (bounds.max.x - 7.0).abs() < 1e-4);
+    assert!((bounds.min.y - 0.0).abs() < 1e-4 && (bounds.max.y - 2.0).abs() < 1e-4);
+
+    let sphere = scene.bounding_sphere().unwrap();
+    for object in scene.objects.values() {
+        let s = object.world_sphere;
+        assert!((s.center - sphere.center).magnitude() + s.radius <= sphere.radius + 1e-4);
+    }
+
+    //Framing puts the whole scene inside the field of view
+    let mut camera = Camera::new(Vector3::new(0.0, 0.0, 10.0), Vector3::new(0.0, 0.0, 0.0));
+    camera.frame(&sphere);
+    let distance = (camera.position - sphere.center).magnitude();
+    assert!((sphere.radius / distance - (camera.fov / 2.0).to_radians().sin()).abs() < 1e-5);
+    assert!(close(camera.get_direction(), Vector3::new(0.0, 0.0, -1.0)));
+}
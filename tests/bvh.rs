@@ -0,0 +1,264 @@
+use cgmath::{InnerSpace, Vector3};
+use OpenGL_Renderer::*;
+
+mod common;
+use common::*;
+
+//Small deterministic generator so the tests don't need a rand dependency
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next()
+    }
+
+    fn point(&mut self, extent: f32) -> Vector3<f32> {
+        Vector3::new(
+            self.range(-extent, extent),
+            self.range(-extent, extent),
+            self.range(-extent, extent),
+        )
+    }
+}
+
+fn random_boxes(rng: &mut Lcg, count: usize) -> Vec<Aabb> {
+    (0..count)
+        .map(|_| {
+            let center = rng.point(50.0);
+            let half = Vector3::new(rng.range(0.1, 3.0), rng.range(0.1, 3.0), rng.range(0.1, 3.0));
+            Aabb::new(center - half, center + half)
+        })
+        .collect()
+}
+
+fn sorted(mut items: Vec<usize>) -> Vec<usize> {
+    items.sort_unstable();
+    items
+}
+
+fn brute_force(bounds: &[Aabb], test: impl Fn(&Aabb) -> bool) -> Vec<usize> {
+    (0..bounds.len()).filter(|&i| test(&bounds[i])).collect()
+}
+
+fn check_queries(bvh: &Bvh, bounds: &[Aabb], rng: &mut Lcg) {
+    for _ in 0..20 {
+        let center = rng.point(50.0);
+        let half = Vector3::new(10.0, 5.0, 8.0);
+        let query = Aabb::new(center - half, center + half);
+        assert_eq!(
+            sorted(bvh.query_aabb(&query)),
+            brute_force(bounds, |aabb| query.intersects(aabb))
+        );
+
+        let sphere = BoundingSphere::new(rng.point(50.0), rng.range(1.0, 20.0));
+        assert_eq!(
+            sorted(bvh.query_sphere(&sphere)),
+            brute_force(bounds, |aabb| aabb.intersects_sphere(&sphere))
+        );
+
+        let ray = Ray::new(rng.point(60.0), rng.point(1.0));
+        let hits = bvh.query_ray(&ray, 200.0);
+        assert_eq!(
+            sorted(hits.iter().map(|hit| hit.0).collect()),
+            brute_force(bounds, |aabb| aabb.intersect_ray(&ray, 200.0).is_some())
+        );
+        assert!(hits.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+        let point = rng.point(50.0);
+        let nearest = bvh.nearest(point, 5);
+        let mut expected: Vec<f32> = bounds.iter().map(|aabb| aabb.distance2(point).sqrt()).collect();
+        expected.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(nearest.len(), bounds.len().min(5));
+        for (i, &(item, distance)) in nearest.iter().enumerate() {
+            assert!((distance - expected[i]).abs() < 1e-4);
+            assert!((bounds[item].distance2(point).sqrt() - distance).abs() < 1e-4);
+        }
+
+        let camera = Camera::new(rng.point(40.0), rng.point(40.0));
+        let frustum = Frustum::from_camera(&camera);
+        assert_eq!(
+            sorted(bvh.query_frustum(&frustum)),
+            brute_force(bounds, |aabb| frustum.classify_aabb(aabb) != Containment::Outside)
+        );
+    }
+}
+
+#[test]
+fn queries_match_brute_force() {
+    let mut rng = Lcg(7);
+    for count in [0, 1, 3, 50, 1000] {
+        let bounds = random_boxes(&mut rng, count);
+        let bvh = Bvh::build(&bounds);
+        assert_eq!(bvh.len(), count);
+        assert_eq!(sorted(bvh.items.clone()), (0..count).collect::<Vec<_>>());
+        if count > 0 {
+            check_queries(&bvh, &bounds, &mut rng);
+        } else {
+            assert!(bvh.query_sphere(&BoundingSphere::new(Vector3::new(0.0, 0.0, 0.0), 100.0)).is_empty());
+            assert!(bvh.nearest(Vector3::new(0.0, 0.0, 0.0), 3).is_empty());
+        }
+    }
+}
+
+#[test]
+fn nodes_contain_their_children() {
+    let mut rng = Lcg(3);
+    let bounds = random_boxes(&mut rng, 500);
+    let bvh = Bvh::build(&bounds);
+    for (index, node) in bvh.nodes.iter().enumerate() {
+        match node.kind {
+            BvhNodeKind::Leaf { first, count } => {
+                assert!(count > 0 && count <= 4);
+                for &item in bvh.items[first..first + count].iter() {
+                    assert_eq!(node.aabb.union(&bounds[item]), node.aabb);
+                }
+            }
+            BvhNodeKind::Internal { left, right } => {
+                assert!(left > index && right > index);
+                assert_eq!(bvh.nodes[left].parent, Some(index));
+                assert_eq!(node.aabb, bvh.nodes[left].aabb.union(&bvh.nodes[right].aabb));
+            }
+        }
+    }
+}
+
+#[test]
+fn refit_and_update_track_movement() {
+    let mut rng = Lcg(11);
+    let mut bounds = random_boxes(&mut rng, 300);
+    let mut bvh = Bvh::build(&bounds);
+    assert!(!bvh.needs_rebuild());
+
+    for aabb in bounds.iter_mut() {
+        let offset = rng.point(5.0);
+        *aabb = Aabb::new(aabb.min + offset, aabb.max + offset);
+    }
+    bvh.refit(&bounds);
+    check_queries(&bvh, &bounds, &mut rng);
+
+    let moved = Aabb::new(Vector3::new(200.0, 0.0, 0.0), Vector3::new(201.0, 1.0, 1.0));
+    bounds[42] = moved;
+    bvh.update(42, moved);
+    assert!(bvh.nodes[0].aabb.contains(Vector3::new(201.0, 1.0, 1.0)));
+    assert_eq!(bvh.query_aabb(&moved), vec![42]);
+    check_queries(&bvh, &bounds, &mut rng);
+
+    //Scattering everything makes the old tree a bad fit
+    for aabb in bounds.iter_mut() {
+        let offset = rng.point(200.0);
+        *aabb = Aabb::new(aabb.min + offset, aabb.max + offset);
+    }
+    bvh.refit(&bounds);
+    assert!(bvh.needs_rebuild());
+    assert!(!Bvh::build(&bounds).needs_rebuild());
+}
+
+fn scattered_scene(rng: &mut Lcg) -> Scene {
+    let positions: Vec<Vector3<f32>> = (0..400).map(|_| rng.point(60.0)).collect();
+    scene_of(Mesh::icosphere(1.0, 1), positions).0
+}
+
+#[test]
+fn scene_culling_with_bvh_matches_linear() {
+    let mut rng = Lcg(5);
+    let mut scene = scattered_scene(&mut rng);
+    let camera = Camera::new(Vector3::new(0.0, 0.0, 70.0), Vector3::new(0.0, 0.0, 0.0));
+
+    let linear = scene.cull(&camera).to_vec();
+    let linear_stats = scene.cull_stats;
+    scene.build_bvh();
+    assert_eq!(scene.cull(&camera), linear.as_slice());
+    let stats = scene.cull_stats;
+    //Everything but the number of tests agrees
+    assert_eq!(
+        CullStats {
+            tested: linear_stats.tested,
+            ..stats
+        },
+        linear_stats
+    );
+    //Objects in nodes outside the frustum are skipped without being tested
+    assert!(stats.tested < linear_stats.tested);
+    assert!(stats.tested >= stats.visible);
+
+    //Moving objects refits the tree through update_model_matrices
+    for object in scene.objects.values_mut() {
+        object.position += Vector3::new(0.0, 0.0, -30.0);
+    }
+    scene.update_model_matrices();
+    let moved = scene.cull(&camera).to_vec();
+    let bvh = scene.bvh.take();
+    assert_eq!(scene.cull(&camera), moved.as_slice());
+    scene.bvh = bvh;
+
+    //Adding an object rebuilds it
+    scene.add_object(Object::new(Mesh::cube(1.0, 1)));
+    scene.update_model_matrices();
+    assert_eq!(scene.bvh.as_ref().unwrap().len(), scene.objects.len());
+}
+
+#[test]
+fn scene_objects_in_sphere() {
+    let mut rng = Lcg(9);
+    let mut scene = scattered_scene(&mut rng);
+    let light = BoundingSphere::new(Vector3::new(0.0, 0.0, 0.0), 25.0);
+    let linear = scene.objects_in_sphere(&light);
+    scene.build_bvh();
+    assert_eq!(scene.objects_in_sphere(&light), linear);
+    assert!(!linear.is_empty());
+    for &index in linear.iter() {
+        let center = scene.objects[index].world_sphere.center;
+        assert!(center.magnitude() <= 25.0 + 1.0 + 1e-4);
+    }
+}
+
+#[test]
+fn lights_are_assigned_to_the_objects_they_reach() {
+    let mut rng = Lcg(13);
+    let mut scene = scattered_scene(&mut rng);
+    scene.lights.push(Light::Point {
+        position: Vector3::new(0.0, 0.0, 0.0),
+        color: Vector3::new(1.0, 1.0, 1.0),
+        intensity: 1.0,
+        range: 20.0,
+    });
+    scene.lights.push(Light::Directional {
+        direction: Vector3::new(0.0, -1.0, 0.0),
+        color: Vector3::new(1.0, 1.0, 1.0),
+        intensity: 1.0,
+    });
+    //Too far from everything to reach an object
+    scene.lights.push(Light::Point {
+        position: Vector3::new(500.0, 0.0, 0.0),
+        color: Vector3::new(1.0, 1.0, 1.0),
+        intensity: 1.0,
+        range: 5.0,
+    });
+    scene.assign_lights();
+    let linear: Vec<Vec<usize>> = scene
+        .objects
+        .handles()
+        .into_iter()
+        .map(|handle| scene.lights_for(handle).to_vec())
+        .collect();
+    scene.build_bvh();
+    scene.assign_lights();
+
+    let reached = BoundingSphere::new(Vector3::new(0.0, 0.0, 0.0), 20.0);
+    for (handle, linear) in scene.objects.handles().into_iter().zip(linear) {
+        let lights = scene.lights_for(handle);
+        assert_eq!(lights, linear.as_slice());
+        //The directional light comes first
+        assert_eq!(lights[0], 1);
+        let touches = scene.objects[handle].world_sphere.intersects(&reached);
+        assert_eq!(lights.contains(&0), touches, "{:?}", handle);
+    }
+    assert_eq!(scene.light_reach(1), scene.objects.len());
+    assert!(scene.light_reach(0) > 0);
+    assert_eq!(scene.light_reach(2), 0);
+}
@@ -0,0 +1,61 @@
+//Helpers shared by the integration tests, each test file includes this with `mod common;`
+#![allow(dead_code)]
+
+use cgmath::{InnerSpace, Vector3};
+use OpenGL_Renderer::*;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+//Loads a model from assets/models with full precision vertices
+pub fn load(name: &str) -> Mesh {
+    mesh_from_obj_with_precision(
+        &Path::new("assets/models").join(name),
+        &VertexPrecision::full(),
+    )
+}
+
+pub fn position(mesh: &Mesh, index: u32) -> Vector3<f32> {
+    let v = mesh.vertices[index as usize];
+    Vector3::new(v[0], v[1], v[2])
+}
+
+pub fn normal(mesh: &Mesh, index: u32) -> Vector3<f32> {
+    let v = mesh.vertices[index as usize];
+    Vector3::new(v[3], v[4], v[5])
+}
+
+pub fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    (a - b).magnitude() < 1e-4
+}
+
+//Objects sharing one mesh and the default material at positions, with their world transforms updated
+pub fn scene_of(
+    mesh: Mesh,
+    positions: impl IntoIterator<Item = Vector3<f32>>,
+) -> (Scene, Vec<ObjectHandle>) {
+    let mut scene = Scene::new();
+    let mesh = Rc::new(mesh);
+    let material = Rc::new(Material::default());
+    let handles = positions
+        .into_iter()
+        .map(|position| {
+            let mut object = Object::with_shared(mesh.clone(), material.clone());
+            object.position = position;
+            scene.add_object(object)
+        })
+        .collect();
+    scene.update_model_matrices();
+    (scene, handles)
+}
+
+//Fresh directory per test so they can run in parallel
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
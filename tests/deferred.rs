@@ -0,0 +1,66 @@
+use cgmath::{Vector3, Vector4};
+use OpenGL_Renderer::deferred::visible_lights;
+use OpenGL_Renderer::*;
+
+use std::path::Path;
+
+fn point(x: f32, y: f32, z: f32, range: f32) -> Light {
+    Light::Point {
+        position: Vector3::new(x, y, z),
+        color: Vector3::new(1.0, 0.5, 0.25),
+        intensity: 2.0,
+        range,
+    }
+}
+
+#[test]
+fn lights_are_packed_for_the_shaders() {
+    let (position, color) = point(1.0, 2.0, 3.0, 5.0).packed();
+    assert_eq!(position, Vector4::new(1.0, 2.0, 3.0, 1.0));
+    assert_eq!(color, Vector4::new(2.0, 1.0, 0.5, 5.0));
+
+    let sun = Light::Directional {
+        direction: Vector3::new(0.0, -2.0, 0.0),
+        color: Vector3::new(1.0, 1.0, 1.0),
+        intensity: 3.0,
+    };
+    let (direction, color) = sun.packed();
+    assert_eq!(direction, Vector4::new(0.0, -1.0, 0.0, 0.0));
+    assert_eq!(color, Vector4::new(3.0, 3.0, 3.0, 0.0));
+}
+
+#[test]
+fn lights_outside_the_frustum_are_culled() {
+    //Looking down -z from the origin
+    let camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    let frustum = Frustum::from_camera(&camera);
+    let lights = [
+        point(0.0, 0.0, -5.0, 1.0),
+        //Behind the camera
+        point(0.0, 0.0, 5.0, 1.0),
+        //Behind, but its range reaches into view
+        point(0.0, 0.0, 5.0, 6.0),
+        Light::Directional {
+            direction: Vector3::new(0.0, -1.0, 0.0),
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+        },
+        //Far off to the side
+        point(100.0, 0.0, -5.0, 10.0),
+    ];
+    assert_eq!(visible_lights(&lights, &frustum), vec![0, 2, 3]);
+}
+
+#[test]
+fn both_paths_share_the_brdf() {
+    for shader in ["fragment_shader.frag", "deferred_light.frag"] {
+        let source = shader_from_file(&Path::new("assets/shaders").join(shader));
+        assert!(
+            !source.lines().any(|line| line.starts_with("#include")),
+            "{}",
+            shader
+        );
+        assert!(source.contains("vec3 brdf(pbr_material mat"), "{}", shader);
+        assert!(source.starts_with("#version 430 core"), "{}", shader);
+    }
+}
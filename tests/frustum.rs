@@ -0,0 +1,143 @@
+use cgmath::{InnerSpace, Vector3};
+use OpenGL_Renderer::*;
+
+mod common;
+use common::*;
+
+fn camera() -> Camera {
+    //Looking down -z from the origin, 45 degree vertical fov, 4:3, near 0.1, far 100
+    Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0))
+}
+
+fn sphere(x: f32, y: f32, z: f32, radius: f32) -> (Aabb, BoundingSphere) {
+    let center = Vector3::new(x, y, z);
+    let extent = Vector3::new(radius, radius, radius);
+    (
+        Aabb::new(center - extent, center + extent),
+        BoundingSphere::new(center, radius),
+    )
+}
+
+#[test]
+fn planes_match_camera() {
+    let frustum = Frustum::from_camera(&camera());
+    for plane in frustum.planes.iter() {
+        assert!((plane.truncate().magnitude() - 1.0).abs() < 1e-5);
+    }
+    assert!(frustum.contains_point(Vector3::new(0.0, 0.0, -1.0)));
+    assert!(frustum.contains_point(Vector3::new(0.0, 0.0, -99.0)));
+    assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, 1.0)));
+    assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, -0.05)));
+    assert!(!frustum.contains_point(Vector3::new(0.0, 0.0, -101.0)));
+
+    //At distance 10 the half height is 10 * tan(22.5), the half width 4/3 of that
+    let half_height = 10.0 * 22.5f32.to_radians().tan();
+    let half_width = half_height * 4.0 / 3.0;
+    assert!(frustum.contains_point(Vector3::new(0.0, half_height * 0.99, -10.0)));
+    assert!(!frustum.contains_point(Vector3::new(0.0, half_height * 1.01, -10.0)));
+    assert!(frustum.contains_point(Vector3::new(-half_width * 0.99, 0.0, -10.0)));
+    assert!(!frustum.contains_point(Vector3::new(-half_width * 1.01, 0.0, -10.0)));
+
+    //The corners lie on the near and far planes
+    for (i, corner) in frustum.corners.iter().enumerate() {
+        let expected = if i < 4 { -0.1 } else { -100.0 };
+        assert!((corner.z - expected).abs() < expected.abs() * 1e-3);
+    }
+}
+
+#[test]
+fn edges_join_neighbouring_corners() {
+    let frustum = Frustum::from_camera(&camera());
+    for [a, b] in FRUSTUM_EDGES {
+        assert_eq!((a ^ b).count_ones(), 1, "{} {}", a, b);
+    }
+    for corner in 0..8 {
+        let count = FRUSTUM_EDGES.iter().flatten().filter(|&&c| c == corner).count();
+        assert_eq!(count, 3);
+    }
+    let vertices = frustum.edge_vertices();
+    assert_eq!(vertices.len(), 24);
+    assert_eq!(vertices[0], frustum.corners[0]);
+    assert_eq!(vertices[23], frustum.corners[7]);
+}
+
+#[test]
+fn classification() {
+    let frustum = Frustum::from_camera(&camera());
+    let (aabb, bounds) = sphere(0.0, 0.0, -10.0, 1.0);
+    assert_eq!(frustum.classify_sphere(&bounds), Containment::Inside);
+    assert_eq!(frustum.classify_aabb(&aabb), Containment::Inside);
+
+    let (aabb, bounds) = sphere(0.0, 0.0, 10.0, 1.0);
+    assert_eq!(frustum.classify_sphere(&bounds), Containment::Outside);
+    assert_eq!(frustum.classify_aabb(&aabb), Containment::Outside);
+
+    //Straddling the near plane
+    let (aabb, bounds) = sphere(0.0, 0.0, 0.0, 1.0);
+    assert_eq!(frustum.classify_sphere(&bounds), Containment::Intersecting);
+    assert_eq!(frustum.classify_aabb(&aabb), Containment::Intersecting);
+
+    //Off to the side
+    let (aabb, bounds) = sphere(30.0, 0.0, -10.0, 1.0);
+    assert!(!frustum.intersects(&aabb, &bounds));
+    assert_eq!(frustum.classify_aabb(&Aabb::empty()), Containment::Outside);
+}
+
+fn grid_scene() -> Scene {
+    let positions = (-10..=10)
+        .flat_map(|x| (-10..=10).map(move |z| Vector3::new(x as f32 * 4.0, 0.0, z as f32 * 4.0)));
+    scene_of(Mesh::cube(1.0, 1), positions).0
+}
+
+#[test]
+fn scene_culling_stats() {
+    let mut scene = grid_scene();
+    let camera = camera();
+    let visible = scene.cull(&camera).to_vec();
+    let stats = scene.cull_stats;
+
+    assert_eq!(stats.tested, 21 * 21);
+    assert_eq!(stats.visible + stats.culled, stats.tested);
+    assert_eq!(stats.visible, visible.len());
+    assert!(stats.visible > 0 && stats.culled > stats.visible);
+    assert_eq!(stats.visible_triangles, stats.visible * 12);
+    assert_eq!(stats.culled_triangles, stats.culled * 12);
+
+    //Everything behind the camera is culled, everything left visible is in front of it
+    let frustum = Frustum::from_camera(&camera);
+    for (handle, object) in scene.objects.iter() {
+        let is_visible = visible.contains(&handle);
+        assert_eq!(is_visible, frustum.intersects(&object.world_aabb, &object.world_sphere));
+        if object.position.z > 1.0 {
+            assert!(!is_visible);
+        }
+    }
+
+    scene.culling = false;
+    scene.cull(&camera);
+    assert_eq!(scene.cull_stats.visible, 21 * 21);
+    assert_eq!(scene.cull_stats.culled, 0);
+}
+
+#[test]
+fn frozen_frustum_keeps_culling_result() {
+    let mut scene = grid_scene();
+    let mut camera = camera();
+    scene.cull(&camera);
+    let before = scene.visible_objects.clone();
+
+    scene.freeze_frustum(&camera);
+    camera.position = Vector3::new(0.0, 0.0, 0.0);
+    camera.target = Vector3::new(0.0, 0.0, 1.0);
+    scene.cull(&camera);
+    assert_eq!(scene.visible_objects, before);
+
+    scene.unfreeze_frustum();
+    scene.cull(&camera);
+    assert_ne!(scene.visible_objects, before);
+    assert!(scene
+        .visible_objects
+        .iter()
+        .all(|&index| scene.objects[index].position.z > -2.0));
+    assert!(Vector3::new(0.0, 0.0, 1.0).dot(camera.get_direction()) > 0.99);
+}
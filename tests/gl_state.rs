@@ -0,0 +1,82 @@
+use ogl33::*;
+use OpenGL_Renderer::*;
+
+#[test]
+fn redundant_binds_are_skipped() {
+    let mut state = GlState::new();
+    assert!(state.set_program(3));
+    assert!(!state.set_program(3));
+    assert!(state.set_program(4));
+
+    assert!(state.set_vertex_array(1));
+    assert!(!state.set_vertex_array(1));
+    assert!(state.set_buffer(GL_ARRAY_BUFFER, 7));
+    assert!(!state.set_buffer(GL_ARRAY_BUFFER, 7));
+    assert!(state.set_buffer(GL_ELEMENT_ARRAY_BUFFER, 8));
+    assert!(!state.set_buffer(GL_ELEMENT_ARRAY_BUFFER, 8));
+    //The element buffer belongs to the VAO, switching VAOs makes it unknown, array buffers aren't affected
+    assert!(state.set_vertex_array(2));
+    assert!(state.set_buffer(GL_ELEMENT_ARRAY_BUFFER, 8));
+    assert!(!state.set_buffer(GL_ARRAY_BUFFER, 7));
+
+    assert!(state.set_depth_mask(false));
+    assert!(!state.set_depth_mask(false));
+    assert!(state.set_capability(GL_BLEND, true));
+    assert!(!state.set_capability(GL_BLEND, true));
+    assert!(state.set_capability(GL_DEPTH_TEST, true));
+    assert!(state.set_blend_func(GL_SRC_ALPHA, GL_ONE));
+    assert!(!state.set_blend_func(GL_SRC_ALPHA, GL_ONE));
+    assert!(state.set_blend_func(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA));
+    state.draw_call();
+    state.draw_call();
+
+    let stats = state.end_frame();
+    assert_eq!(stats.program_binds, 2);
+    assert_eq!(stats.vertex_array_binds, 2);
+    assert_eq!(stats.buffer_binds, 3);
+    assert_eq!(stats.state_changes, 5);
+    assert_eq!(stats.draw_calls, 2);
+    assert_eq!(stats.skipped, 8);
+    assert_eq!(state.stats, GlStats::default());
+
+    //Forgetting everything makes the next calls go through again
+    state.invalidate();
+    assert!(state.set_program(4));
+    assert!(state.set_capability(GL_BLEND, true));
+}
+
+#[test]
+fn textures_are_tracked_per_unit() {
+    let mut state = GlState::new();
+    //Without a known active unit nothing can be skipped
+    assert!(state.set_texture(GL_TEXTURE_2D, 5));
+    assert!(state.set_texture(GL_TEXTURE_2D, 5));
+
+    assert!(state.set_active_texture(GL_TEXTURE0));
+    assert!(state.set_texture(GL_TEXTURE_2D, 5));
+    assert!(!state.set_texture(GL_TEXTURE_2D, 5));
+    assert!(state.set_texture(GL_TEXTURE_CUBE_MAP, 5));
+    assert!(state.set_active_texture(GL_TEXTURE1));
+    assert!(state.set_texture(GL_TEXTURE_2D, 5));
+    assert!(state.set_active_texture(GL_TEXTURE0));
+    assert!(!state.set_texture(GL_TEXTURE_2D, 5));
+
+    //A deleted texture's name can come back as a different texture
+    state.forget_texture(5);
+    assert!(state.set_texture(GL_TEXTURE_2D, 5));
+
+    state.forget_buffer(9);
+    assert!(state.set_buffer(GL_ARRAY_BUFFER, 9));
+    state.forget_buffer(9);
+    assert!(state.set_buffer(GL_ARRAY_BUFFER, 9));
+
+    assert!(state.set_program(3));
+    state.forget_program(4);
+    assert!(!state.set_program(3));
+    state.forget_program(3);
+    assert!(state.set_program(3));
+
+    assert!(state.set_vertex_array(2));
+    state.forget_vertex_array(2);
+    assert!(state.set_vertex_array(2));
+}
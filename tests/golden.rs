@@ -0,0 +1,198 @@
+//Golden image tests render through the real shaders and need a GL 4.3 context, so they are ignored by
+//default. Run them with `cargo test --test golden -- --ignored`, set UPDATE_GOLDEN=1 to rewrite the
+//reference images after an intended change
+use beryllium::*;
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation3, Vector3};
+use cstr::cstr;
+use ogl33::*;
+use OpenGL_Renderer::*;
+
+use std::path::{Path, PathBuf};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+
+//Outputs the interpolated world space normal so the vertex stage can be checked directly
+const NORMAL_FRAGMENT: &str = "#version 430 core
+in Vertex{
+    vec3 pos;
+    vec3 norm;
+    vec2 uv;
+    mat3 TBN;
+    vec4 color;
+    vec4 data;
+} i;
+out vec4 o_color;
+void main(){
+    o_color = vec4(normalize(i.norm)*0.5+0.5, 1.0);
+}";
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new("tests/golden").join(format!("{}.png", name))
+}
+
+//Offscreen RGBA8 target with depth, left bound
+unsafe fn render_target() {
+    let mut framebuffer = 0;
+    glGenFramebuffers(1, &mut framebuffer);
+    glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+    let mut renderbuffers = [0; 2];
+    glGenRenderbuffers(2, renderbuffers.as_mut_ptr());
+    for (renderbuffer, format, attachment) in [
+        (renderbuffers[0], GL_RGBA8, GL_COLOR_ATTACHMENT0),
+        (renderbuffers[1], GL_DEPTH_COMPONENT24, GL_DEPTH_ATTACHMENT),
+    ] {
+        glBindRenderbuffer(GL_RENDERBUFFER, renderbuffer);
+        glRenderbufferStorage(GL_RENDERBUFFER, format, WIDTH as i32, HEIGHT as i32);
+        glFramebufferRenderbuffer(GL_FRAMEBUFFER, attachment, GL_RENDERBUFFER, renderbuffer);
+    }
+    assert_eq!(
+        glCheckFramebufferStatus(GL_FRAMEBUFFER),
+        GL_FRAMEBUFFER_COMPLETE
+    );
+    glViewport(0, 0, WIDTH as i32, HEIGHT as i32);
+}
+
+//Rows top to bottom, like image files
+unsafe fn read_pixels() -> image::RgbaImage {
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 4) as usize];
+    glPixelStorei(GL_PACK_ALIGNMENT, 1);
+    glReadPixels(
+        0,
+        0,
+        WIDTH as i32,
+        HEIGHT as i32,
+        GL_RGBA,
+        GL_UNSIGNED_BYTE,
+        pixels.as_mut_ptr().cast(),
+    );
+    let image = image::RgbaImage::from_raw(WIDTH, HEIGHT, pixels).unwrap();
+    image::imageops::flip_vertical(&image)
+}
+
+//Compares against the stored image, allowing for small driver differences in rasterization. A missing
+//reference is a failure, it's only written with UPDATE_GOLDEN set
+fn assert_matches_golden(name: &str, image: &image::RgbaImage) {
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image.save(&path).unwrap();
+        eprintln!("Wrote golden image {}", path.display());
+        return;
+    }
+    let failed = golden_path(&format!("{}_failed", name));
+    if !path.exists() {
+        image.save(&failed).unwrap();
+        panic!(
+            "There is no golden image {}, the render was saved to {}. Run with UPDATE_GOLDEN=1 to accept it",
+            path.display(),
+            failed.display()
+        );
+    }
+    let golden = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(golden.dimensions(), image.dimensions());
+    let different = golden
+        .pixels()
+        .zip(image.pixels())
+        .filter(|(a, b)| a.0.iter().zip(b.0).any(|(&a, b)| a.abs_diff(b) > 3))
+        .count();
+    if different * 200 > (WIDTH * HEIGHT) as usize {
+        image.save(&failed).unwrap();
+        panic!(
+            "{} pixels differ from {}, the render was saved to {}",
+            different,
+            path.display(),
+            failed.display()
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn non_uniformly_scaled_normals() {
+    let sdl = SDL::init(InitFlags::Everything).expect("couldn't start SDL");
+    sdl.gl_set_attribute(SdlGlAttr::MajorVersion, 4).unwrap();
+    sdl.gl_set_attribute(SdlGlAttr::MinorVersion, 3).unwrap();
+    sdl.gl_set_attribute(SdlGlAttr::Profile, GlProfile::Core)
+        .unwrap();
+    let win = sdl
+        .create_gl_window(
+            "golden",
+            WindowPosition::Centered,
+            WIDTH,
+            HEIGHT,
+            WindowFlags::OpenGL,
+        )
+        .expect("couldn't make a window and context");
+
+    let mut scene = Scene::new();
+    let mut object = Object::new(Mesh::uv_sphere(1.0, 48, 24));
+    object.rotation = Quaternion::from_angle_z(Deg(30.0));
+    object.scale = Vector3::new(1.6, 0.5, 1.0);
+    scene.add_object(object);
+    scene.update_transforms();
+    let mut camera = Camera::new(Vector3::new(0.0, 0.0, 4.0), Vector3::new(0.0, 0.0, 0.0));
+    camera.aspect = [WIDTH as f32, HEIGHT as f32];
+
+    let image = unsafe {
+        load_gl_with(|f_name| win.get_proc_address(f_name));
+        render_target();
+        glEnable(GL_DEPTH_TEST);
+        glClearColor(0.0, 0.0, 0.0, 0.0);
+        glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+        scene.setup();
+
+        let mut program = ShaderProgramBuilder::new()
+            .create_shader(
+                ShaderType::Vertex,
+                &shader_from_file(Path::new("assets/shaders/vertex_shader.vert")),
+            )
+            .create_shader(ShaderType::Fragment, NORMAL_FRAGMENT)
+            .link()
+            .unwrap();
+        for name in [
+            cstr!("M"),
+            cstr!("N"),
+            cstr!("V"),
+            cstr!("P"),
+            cstr!("camera_pos"),
+            cstr!("albedo"),
+            cstr!("roughness"),
+            cstr!("metallic"),
+            cstr!("alpha"),
+            cstr!("alpha_cutoff"),
+            cstr!("instanced"),
+        ] {
+            program.create_uniform(name);
+        }
+        scene.draw(&program, &camera);
+        glFinish();
+        read_pixels()
+    };
+
+    //Independently of the golden image, the rendered normals have to agree with the ones the CPU
+    //ray cast computes. Grazing pixels are skipped, their coverage depends on rasterization rules
+    for y in (4..HEIGHT).step_by(8) {
+        for x in (4..WIDTH).step_by(8) {
+            let pixel = image.get_pixel(x, y);
+            let ray = camera.screen_ray(x as f32, y as f32, WIDTH as f32, HEIGHT as f32);
+            match scene.raycast(&ray, 100.0) {
+                Some(hit) if hit.normal.dot(-ray.direction.normalize()) > 0.3 => {
+                    let rendered = Vector3::new(pixel[0], pixel[1], pixel[2])
+                        .map(|c| c as f32 / 255.0 * 2.0 - 1.0);
+                    assert!(
+                        (rendered - hit.normal).magnitude() < 0.05,
+                        "normal at {},{} is {:?}, expected {:?}",
+                        x,
+                        y,
+                        rendered,
+                        hit.normal
+                    );
+                }
+                Some(_) => {}
+                None => assert_eq!(pixel[3], 0, "background at {},{} was drawn", x, y),
+            }
+        }
+    }
+    assert_matches_golden("non_uniform_scale", &image);
+}
@@ -0,0 +1,125 @@
+use cgmath::Vector3;
+use OpenGL_Renderer::*;
+
+use std::collections::HashMap;
+
+mod common;
+use common::*;
+
+//Positions that have to survive simplification: shared by several vertices (seams) or on an open edge
+fn locked_positions(mesh: &Mesh) -> Vec<[u32; 3]> {
+    let key = |index: u32| {
+        let v = mesh.vertices[index as usize];
+        [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()]
+    };
+    let mut vertices_at: HashMap<[u32; 3], Vec<u32>> = HashMap::new();
+    for index in 0..mesh.vertices.len() as u32 {
+        vertices_at.entry(key(index)).or_default().push(index);
+    }
+    let mut edges: HashMap<([u32; 3], [u32; 3]), usize> = HashMap::new();
+    for triangle in mesh.indicies.iter() {
+        for corner in 0..3 {
+            let (a, b) = (key(triangle[corner]), key(triangle[(corner + 1) % 3]));
+            *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+    let mut locked: Vec<[u32; 3]> = vertices_at
+        .iter()
+        .filter(|(_, vertices)| vertices.len() > 1)
+        .map(|(position, _)| *position)
+        .chain(
+            edges
+                .iter()
+                .filter(|(_, &uses)| uses != 2)
+                .flat_map(|(&(a, b), _)| [a, b]),
+        )
+        .collect();
+    locked.sort();
+    locked.dedup();
+    locked
+}
+
+fn positions(mesh: &Mesh) -> Vec<[u32; 3]> {
+    mesh.vertices
+        .iter()
+        .map(|v| [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()])
+        .collect()
+}
+
+#[test]
+fn simplify_reaches_target_and_keeps_seams() {
+    for name in ["monke.obj", "sphere.obj"] {
+        let mesh = load(name);
+        let target = mesh.indicies.len() / 2;
+        let simplified = mesh.simplify(target, f32::MAX);
+
+        //Each collapse removes one or two triangles, so it can undershoot by one. Locked seam and border
+        //vertices may stop it short of the target, allow 5% for that
+        let triangles = simplified.mesh.indicies.len();
+        assert!(triangles + 1 >= target, "{}: {} below {}", name, triangles, target);
+        assert!(triangles <= target + target / 20, "{}: {} above {}", name, triangles, target);
+        assert!(simplified.mesh.vertices.len() < mesh.vertices.len(), "{}", name);
+        assert!(simplified.error > 0.0 && simplified.error < 0.1, "{}: {}", name, simplified.error);
+
+        let remaining = positions(&simplified.mesh);
+        for position in locked_positions(&mesh) {
+            assert!(remaining.contains(&position), "{}: seam/border vertex removed", name);
+        }
+        for &index in simplified.mesh.indicies.iter().flatten() {
+            assert!((index as usize) < simplified.mesh.vertices.len());
+        }
+    }
+}
+
+#[test]
+fn simplify_respects_error_bound() {
+    let mesh = load("sphere.obj");
+    let untouched = mesh.simplify(0, 0.0);
+    assert_eq!(untouched.mesh.indicies.len(), mesh.indicies.len());
+    assert_eq!(untouched.error, 0.0);
+
+    let bounded = mesh.simplify(0, 0.01);
+    assert!(bounded.error <= 0.01);
+    assert!(bounded.mesh.indicies.len() < mesh.indicies.len());
+}
+
+#[test]
+fn lod_chain_levels_get_coarser() {
+    let chain = LodChain::generate(load("monke.obj"), 4, 0.5, 1.0 / 1080.0);
+    assert!(chain.levels.len() > 1);
+    assert!(chain.radius > 0.0);
+    assert_eq!(chain.levels[0].max_screen_size, f32::INFINITY);
+    for pair in chain.levels.windows(2) {
+        assert!(pair[1].mesh.indicies.len() < pair[0].mesh.indicies.len());
+        assert!(pair[1].error >= pair[0].error);
+        assert!(pair[1].max_screen_size <= pair[0].max_screen_size);
+    }
+}
+
+#[test]
+fn lod_selection_has_hysteresis() {
+    let chain = LodChain::generate(load("monke.obj"), 3, 0.5, 1.0 / 1080.0);
+    let threshold = chain.levels[1].max_screen_size;
+
+    //Far away the coarsest level is used, up close the full detail one
+    assert_eq!(chain.select(0.0, 0), chain.levels.len() - 1);
+    assert_eq!(chain.select(f32::INFINITY, chain.levels.len() - 1), 0);
+
+    //Just above the threshold level 1 stays, just below level 0 stays
+    assert_eq!(chain.select(threshold * 1.05, 1), 1);
+    assert_eq!(chain.select(threshold * 0.95, 0), 0);
+    //Far enough past it the level changes
+    assert_eq!(chain.select(threshold * 1.3, 1), 0);
+    assert!(chain.select(threshold * 0.7, 0) >= 1);
+}
+
+#[test]
+fn camera_projected_size_shrinks_with_distance() {
+    let mut camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    let near = camera.projected_size(Vector3::new(0.0, 0.0, -5.0), 1.0);
+    let far = camera.projected_size(Vector3::new(0.0, 0.0, -10.0), 1.0);
+    assert!((near - 2.0 * far).abs() < 1e-5);
+    camera.fov = 90.0;
+    assert!((camera.projected_size(Vector3::new(0.0, 0.0, -4.0), 1.0) - 0.25).abs() < 1e-5);
+    assert_eq!(camera.projected_size(Vector3::new(0.0, 0.0, -0.5), 1.0), f32::INFINITY);
+}
@@ -0,0 +1,191 @@
+use cgmath::InnerSpace;
+use OpenGL_Renderer::*;
+
+mod common;
+use common::*;
+
+//Gives every triangle corner its own vertex, like an exporter that doesn't index
+fn unindexed(mesh: &Mesh) -> Mesh {
+    let mut vertices = Vec::new();
+    let mut indicies = Vec::new();
+    for triangle in mesh.indicies.iter() {
+        let base = vertices.len() as u32;
+        for &index in triangle.iter() {
+            vertices.push(mesh.vertices[index as usize]);
+        }
+        indicies.push([base, base + 1, base + 2]);
+    }
+    Mesh::new(vertices, indicies)
+}
+
+fn sorted_triangles(mesh: &Mesh) -> Vec<[[u32; 3]; 3]> {
+    let mut triangles: Vec<[[u32; 3]; 3]> = mesh
+        .indicies
+        .iter()
+        .map(|t| {
+            t.map(|i| position(mesh, i).into())
+                .map(|p: [f32; 3]| p.map(f32::to_bits))
+        })
+        .collect();
+    triangles.sort();
+    triangles
+}
+
+#[test]
+fn weld_restores_indexed_meshes() {
+    for name in ["Cube.obj", "sphere.obj"] {
+        let mut original = load(name);
+        original.weld_vertices(1e-5);
+        let mut mesh = unindexed(&original);
+        let stats = mesh.weld_vertices(1e-5);
+
+        assert_eq!(stats.vertices_before, original.indicies.len() * 3);
+        assert_eq!(stats.vertices_after, original.vertices.len(), "{}", name);
+        assert!(stats.vertices_removed() > 0);
+        assert_eq!(stats.triangles_removed(), 0);
+        assert_eq!(sorted_triangles(&mesh), sorted_triangles(&original));
+    }
+}
+
+#[test]
+fn weld_keeps_seams() {
+    //tobj leaves a few exact duplicates in the cube, but the corners only share positions,
+    //so each of the 6 faces has to keep its own 4 vertices
+    let mut mesh = load("Cube.obj");
+    let stats = mesh.weld_vertices(1e-5);
+    assert_eq!(stats.vertices_before, 28);
+    assert_eq!(stats.vertices_after, 24);
+    assert_eq!(stats.vertices_removed(), 4);
+}
+
+#[test]
+fn remove_degenerate_triangles() {
+    for name in ["Cube.obj", "sphere.obj"] {
+        let mut mesh = load(name);
+        let triangles = mesh.indicies.len();
+        let vertices = mesh.vertices.len();
+
+        //A collapsed triangle, a zero area one and an orphaned vertex
+        let first = mesh.indicies[0];
+        mesh.indicies.push([first[0], first[0], first[1]]);
+        let v = mesh.vertices[first[0] as usize];
+        mesh.vertices.push(v);
+        mesh.vertices.push(v);
+        mesh.indicies
+            .push([first[0], vertices as u32, vertices as u32 + 1]);
+
+        let stats = mesh.remove_degenerate_triangles();
+        assert_eq!(stats.triangles_removed(), 2, "{}", name);
+        assert_eq!(stats.vertices_removed(), 2, "{}", name);
+        assert_eq!(mesh.indicies.len(), triangles);
+        assert_eq!(mesh.vertices.len(), vertices);
+    }
+}
+
+#[test]
+fn recompute_normals_keeps_cube_hard() {
+    let mut mesh = unindexed(&load("Cube.obj"));
+    mesh.weld_vertices(1e-5);
+    let stats = mesh.recompute_normals(30.0);
+    assert_eq!(stats.vertices_removed(), 0);
+
+    for triangle in mesh.indicies.iter() {
+        let p = triangle.map(|i| position(&mesh, i));
+        let face = (p[1] - p[0]).cross(p[2] - p[0]).normalize();
+        for &index in triangle.iter() {
+            assert!((normal(&mesh, index) - face).magnitude() < 1e-4);
+        }
+    }
+}
+
+#[test]
+fn recompute_normals_splits_cube_corners() {
+    //Positions only: every corner is shared by three faces and has to be split
+    let mut mesh = load("Cube.obj");
+    for vertex in mesh.vertices.iter_mut() {
+        vertex[3..8].fill(0.0);
+    }
+    mesh.weld_vertices(1e-5);
+    assert_eq!(mesh.vertices.len(), 8);
+
+    let stats = mesh.recompute_normals(30.0);
+    assert_eq!(stats.vertices_after, 24);
+    assert_eq!(stats.vertices_removed(), -16);
+
+    //Without a threshold the corners stay shared and point diagonally outwards
+    let mut smooth = load("Cube.obj");
+    for vertex in smooth.vertices.iter_mut() {
+        vertex[3..8].fill(0.0);
+    }
+    smooth.weld_vertices(1e-5);
+    smooth.recompute_normals(180.0);
+    assert_eq!(smooth.vertices.len(), 8);
+    for index in 0..8 {
+        let n = normal(&smooth, index);
+        let p = position(&smooth, index).normalize();
+        assert!((n - p).magnitude() < 1e-4);
+    }
+}
+
+#[test]
+fn recompute_normals_matches_authored_sphere() {
+    let authored = load("sphere.obj");
+    let mut mesh = load("sphere.obj");
+    let stats = mesh.recompute_normals(60.0);
+    assert_eq!(stats.vertices_removed(), 0);
+    for index in 0..mesh.vertices.len() as u32 {
+        let n = normal(&mesh, index);
+        assert!((n.magnitude() - 1.0).abs() < 1e-4);
+        assert!(n.dot(normal(&authored, index).normalize()) > 0.99);
+    }
+}
+
+#[test]
+fn vertex_cache_optimization_lowers_acmr() {
+    for name in ["Cube.obj", "sphere.obj"] {
+        let mut mesh = load(name);
+        let mut reference = sorted_triangles(&mesh);
+
+        //Start from a deliberately bad order so there is something to win on both meshes
+        let count = mesh.indicies.len();
+        let mut shuffled = Vec::with_capacity(count);
+        let mut i = 0;
+        for _ in 0..count {
+            i = (i + 7919) % count;
+            shuffled.push(mesh.indicies[i]);
+        }
+        mesh.indicies = shuffled;
+        let before = mesh.acmr(VERTEX_CACHE_SIZE);
+
+        let stats = mesh.optimize_vertex_cache();
+        let after = mesh.acmr(VERTEX_CACHE_SIZE);
+        assert_eq!(stats.vertices_removed(), 0);
+        assert_eq!(stats.triangles_removed(), 0);
+        assert!(after <= before, "{}: {} -> {}", name, before, after);
+
+        reference.sort();
+        assert_eq!(sorted_triangles(&mesh), reference);
+    }
+}
+
+#[test]
+fn vertex_fetch_optimization_is_sequential() {
+    for name in ["Cube.obj", "sphere.obj"] {
+        let mut mesh = load(name);
+        mesh.optimize_vertex_cache();
+        let reference = sorted_triangles(&mesh);
+        let stats = mesh.optimize_vertex_fetch();
+        assert_eq!(stats.vertices_removed(), 0);
+
+        //Every index is either already seen or the next new vertex
+        let mut next = 0;
+        for &index in mesh.indicies.iter().flatten() {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+        assert_eq!(next as usize, mesh.vertices.len());
+        assert_eq!(sorted_triangles(&mesh), reference);
+    }
+}
@@ -0,0 +1,136 @@
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation3, Vector3};
+use OpenGL_Renderer::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn screen_ray_goes_through_pixels() {
+    let camera = Camera::new(Vector3::new(1.0, 2.0, 10.0), Vector3::new(1.0, 2.0, 0.0));
+    let (width, height) = (800.0, 600.0);
+
+    //The center of the viewport looks straight down the camera direction
+    let ray = camera.screen_ray(width / 2.0 - 0.5, height / 2.0 - 0.5, width, height);
+    assert!(ray.direction.dot(camera.get_direction()) > 0.9999);
+    assert!((ray.direction.magnitude() - 1.0).abs() < 1e-5);
+
+    //The ray starts on the near plane and the top left pixel points up and to the left
+    assert!(((camera.position - ray.origin).dot(camera.get_direction()) + 0.1).abs() < 1e-3);
+    let corner = camera.screen_ray(0.0, 0.0, width, height);
+    assert!(corner.direction.dot(camera.get_right()) < 0.0);
+    assert!(corner.direction.dot(camera.get_up()) > 0.0);
+
+    //Points along the ray project back onto the pixel
+    let point = corner.at(20.0);
+    let clip = camera.get_projection_matrix() * camera.get_view_matrix() * point.extend(1.0);
+    let x = (clip.x / clip.w + 1.0) * 0.5 * width;
+    let y = (1.0 - clip.y / clip.w) * 0.5 * height;
+    assert!((x - 0.5).abs() < 1e-2 && (y - 0.5).abs() < 1e-2);
+}
+
+#[test]
+fn mesh_raycast_finds_closest_triangle() {
+    let cube = Mesh::cube(2.0, 1);
+    let ray = Ray::new(Vector3::new(0.3, -0.2, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let hit = cube.raycast(&ray, 100.0).unwrap();
+    assert!((hit.distance - 4.0).abs() < 1e-4);
+    assert!((hit.barycentric.x + hit.barycentric.y + hit.barycentric.z - 1.0).abs() < 1e-5);
+
+    //The barycentrics reproduce the hit point
+    let mut point = Vector3::new(0.0, 0.0, 0.0);
+    for (corner, weight) in cube.indicies[hit.triangle].iter().zip([
+        hit.barycentric.x,
+        hit.barycentric.y,
+        hit.barycentric.z,
+    ]) {
+        let v = cube.vertices[*corner as usize];
+        point += Vector3::new(v[0], v[1], v[2]) * weight;
+    }
+    assert!(close(point, ray.at(hit.distance)));
+
+    assert!(cube.raycast(&ray, 3.9).is_none());
+    let miss = Ray::new(Vector3::new(3.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(cube.raycast(&miss, 100.0).is_none());
+}
+
+fn scene() -> (Scene, ObjectHandle) {
+    let (mut scene, handles) = scene_of(
+        Mesh::cube(1.0, 2),
+        (-5..=5).flat_map(|x| {
+            (-5..=5).map(move |y| Vector3::new(x as f32 * 3.0, y as f32 * 3.0, -(x + y) as f32))
+        }),
+    );
+    for handle in handles {
+        let object = &mut scene.objects[handle];
+        object.rotation = Quaternion::from_angle_y(Deg(object.position.x / 3.0 * 10.0));
+        object.scale = Vector3::new(1.0, 2.0, 0.5);
+    }
+    let mut floor = Object::new(Mesh::plane(10.0, 10.0, 4));
+    floor.position = Vector3::new(0.0, -20.0, 0.0);
+    floor.scale = Vector3::new(4.0, 1.0, 4.0);
+    let floor = scene.add_object(floor);
+    scene.update_model_matrices();
+    (scene, floor)
+}
+
+#[test]
+fn scene_raycast_reports_hit_details() {
+    let (scene, floor) = scene();
+
+    //Straight down onto the floor, between the cubes
+    let ray = Ray::new(Vector3::new(1.5, 0.0, 7.5), Vector3::new(0.0, -1.0, 0.0));
+    let hit = scene.raycast(&ray, 100.0).unwrap();
+    assert_eq!(hit.object, floor);
+    assert!((hit.distance - 20.0).abs() < 1e-3);
+    assert!(close(hit.position, Vector3::new(1.5, -20.0, 7.5)));
+    assert!(close(hit.normal, Vector3::new(0.0, 1.0, 0.0)));
+
+    //The UVs span the floor linearly
+    let other = scene
+        .raycast(
+            &Ray::new(Vector3::new(-7.5, 0.0, -2.5), Vector3::new(0.0, -1.0, 0.0)),
+            100.0,
+        )
+        .unwrap();
+    assert_eq!(other.object, floor);
+    let du = (other.uv - hit.uv).x.abs() + (other.uv - hit.uv).y.abs();
+    assert!((du - (9.0 + 10.0) / 40.0).abs() < 1e-3);
+
+    //Into the front of the center cube, which is scaled by 0.5 on z
+    let camera = Camera::new(Vector3::new(0.0, 0.0, 10.0), Vector3::new(0.0, 0.0, 0.0));
+    let hit = scene
+        .raycast(&camera.screen_ray(399.5, 299.5, 800.0, 600.0), 100.0)
+        .unwrap();
+    assert!(close(
+        scene.objects[hit.object].position,
+        Vector3::new(0.0, 0.0, 0.0)
+    ));
+    assert!((hit.position.z - 0.25).abs() < 1e-3);
+    assert!(close(hit.normal, Vector3::new(0.0, 0.0, 1.0)));
+    assert!(hit.triangle < scene.objects[hit.object].mesh.indicies.len());
+
+    assert!(scene.raycast(&ray, 10.0).is_none());
+    let up = Ray::new(Vector3::new(0.0, 30.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    assert!(scene.raycast(&up, 100.0).is_none());
+}
+
+#[test]
+fn scene_raycast_with_bvh_matches_linear() {
+    let (mut scene, _) = scene();
+    let camera = Camera::new(Vector3::new(4.0, 6.0, 25.0), Vector3::new(0.0, -5.0, 0.0));
+    let rays: Vec<Ray> = (0..40)
+        .flat_map(|x| (0..30).map(move |y| (x, y)))
+        .map(|(x, y)| camera.screen_ray(x as f32 * 20.0, y as f32 * 20.0, 800.0, 600.0))
+        .collect();
+    let linear: Vec<Option<RayHit>> = rays.iter().map(|ray| scene.raycast(ray, 200.0)).collect();
+    assert!(linear.iter().filter(|hit| hit.is_some()).count() > 100);
+
+    scene.build_bvh();
+    for (ray, expected) in rays.iter().zip(linear.iter()) {
+        let hit = scene.raycast(ray, 200.0);
+        assert_eq!(hit.map(|hit| hit.object), expected.map(|hit| hit.object));
+        if let (Some(hit), Some(expected)) = (hit, expected) {
+            assert!((hit.distance - expected.distance).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use cgmath::Vector3;
+use OpenGL_Renderer::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn identity_lut_maps_colors_to_themselves() {
+    let lut = LutData::identity(8);
+    assert_eq!(lut.data.len(), 8 * 8 * 8);
+    for color in [
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(1.0, 1.0, 1.0),
+        Vector3::new(0.3, 0.71, 0.05),
+        Vector3::new(0.999, 0.5, 0.25),
+    ] {
+        assert!(close(lut.sample(color), color), "{:?}", color);
+    }
+    //Out of range input is clamped
+    assert!(close(
+        lut.sample(Vector3::new(2.0, -1.0, 0.5)),
+        Vector3::new(1.0, 0.0, 0.5)
+    ));
+}
+
+#[test]
+fn cube_files_are_parsed() {
+    //Swaps red and blue, with red changing fastest in the file
+    let mut text = String::from("# comment\nTITLE \"swap\"\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\n\n");
+    for b in 0..2 {
+        for g in 0..2 {
+            for r in 0..2 {
+                text += &format!("{} {} {}\n", b, g, r);
+            }
+        }
+    }
+    let lut = LutData::from_cube(&text).unwrap();
+    assert_eq!(lut.size, 2);
+    assert!(close(
+        lut.sample(Vector3::new(0.2, 0.4, 0.9)),
+        Vector3::new(0.9, 0.4, 0.2)
+    ));
+
+    let errors = [
+        ("LUT_3D_SIZE 2\n0 0 0\n", "expected 8 entries"),
+        ("LUT_3D_SIZE 2\n0 0\n", "line 2"),
+        ("LUT_1D_SIZE 16\n", "1D"),
+        ("DOMAIN_MAX 2 2 2\n", "domain"),
+        ("0 0 0\n", "LUT_3D_SIZE"),
+    ];
+    for (text, message) in errors {
+        let error = LutData::from_cube(text).unwrap_err().to_string();
+        assert!(error.contains(message), "{}", error);
+    }
+}
+
+#[test]
+fn strip_images_match_identity() {
+    let size = 4;
+    let step = 255.0 / (size - 1) as f32;
+    let strip = image::RgbImage::from_fn(size * size, size, |x, y| {
+        let (r, g, b) = (x % size, y, x / size);
+        image::Rgb([r, g, b].map(|c| (c as f32 * step).round() as u8))
+    });
+    let lut = LutData::from_strip(&strip).unwrap();
+    assert_eq!(lut, LutData::identity(size as usize));
+    assert!(LutData::from_strip(&image::RgbImage::new(10, 4)).is_err());
+}
+
+#[test]
+fn bloom_mips_halve_until_one_pixel() {
+    assert_eq!(
+        Bloom::mip_sizes(800, 600, 4),
+        vec![(400, 300), (200, 150), (100, 75), (50, 37)]
+    );
+    //Odd and thin sizes round down but never reach zero
+    assert_eq!(Bloom::mip_sizes(5, 2, 10), vec![(2, 1), (1, 1)]);
+    assert!(Bloom::mip_sizes(1, 1, 6).is_empty());
+}
+
+#[test]
+fn bloom_threshold_has_a_soft_knee() {
+    let mut bloom = Bloom::default();
+    bloom.threshold = 1.0;
+    bloom.knee = 0.5;
+    assert_eq!(bloom.contribution(0.4), 0.0);
+    assert_eq!(bloom.contribution(0.0), 0.0);
+    //Well above the knee it's the part over the threshold
+    assert!((bloom.contribution(4.0) - 0.75).abs() < 1e-4);
+    //Inside the knee some light passes, and the curve meets the hard threshold at its top
+    assert!(bloom.contribution(0.8) > 0.0);
+    assert!(bloom.contribution(0.8) < bloom.contribution(1.2));
+    let top = 1.5;
+    assert!((bloom.contribution(top - 1e-3) - bloom.contribution(top + 1e-3)).abs() < 1e-2);
+
+    bloom.knee = 0.0;
+    assert_eq!(bloom.contribution(0.99), 0.0);
+    assert!(bloom.contribution(1.01) > 0.0);
+}
@@ -0,0 +1,196 @@
+use cgmath::{InnerSpace, Vector3};
+use OpenGL_Renderer::*;
+
+use std::collections::{HashMap, HashSet};
+
+mod common;
+use common::*;
+
+//Topology in position space: (vertices, undirected edges, open edges). Panics on a directed edge that
+//shows up twice (inconsistent winding) or an edge shared by more than two triangles
+fn topology(mesh: &Mesh) -> (usize, usize, usize) {
+    let key = |index: u32| {
+        let p = position(mesh, index) * 1e4;
+        [p.x.round() as i64, p.y.round() as i64, p.z.round() as i64]
+    };
+    let mut directed = HashSet::new();
+    let mut undirected: HashMap<([i64; 3], [i64; 3]), usize> = HashMap::new();
+    let mut vertices = HashSet::new();
+    for triangle in mesh.indicies.iter() {
+        for corner in 0..3 {
+            let (a, b) = (key(triangle[corner]), key(triangle[(corner + 1) % 3]));
+            assert_ne!(a, b, "degenerate edge");
+            assert!(directed.insert((a, b)), "edge {:?} -> {:?} used twice", a, b);
+            *undirected.entry((a.min(b), a.max(b))).or_default() += 1;
+            vertices.insert(a);
+        }
+    }
+    assert!(undirected.values().all(|&uses| uses <= 2), "non-manifold edge");
+    let open = undirected.values().filter(|&&uses| uses == 1).count();
+    (vertices.len(), undirected.len(), open)
+}
+
+fn assert_closed(mesh: &Mesh, genus: i64) {
+    let (v, e, open) = topology(mesh);
+    assert_eq!(open, 0);
+    let f = mesh.indicies.len() as i64;
+    assert_eq!(v as i64 - e as i64 + f, 2 - 2 * genus);
+}
+
+//Winding agrees with the vertex normals and tangents are unit length and perpendicular to them
+fn assert_consistent(mesh: &Mesh) {
+    assert!(!mesh.indicies.is_empty());
+    for triangle in mesh.indicies.iter() {
+        let p = triangle.map(|i| position(mesh, i));
+        let face = (p[1] - p[0]).cross(p[2] - p[0]);
+        assert!(face.magnitude() > 0.0);
+        let average: Vector3<f32> = triangle.iter().map(|&i| normal(mesh, i)).sum();
+        assert!(face.dot(average) > 0.0, "winding disagrees with normals");
+    }
+    for (index, v) in mesh.vertices.iter().enumerate() {
+        let n = normal(mesh, index as u32);
+        let t = Vector3::new(v[8], v[9], v[10]);
+        assert!((n.magnitude() - 1.0).abs() < 1e-4);
+        assert!((t.magnitude() - 1.0).abs() < 1e-3);
+        assert!(n.dot(t).abs() < 1e-3);
+        assert!(v[11] == 1.0 || v[11] == -1.0);
+    }
+}
+
+//Normals point away from the given center
+fn assert_outward(mesh: &Mesh, center: impl Fn(Vector3<f32>) -> Vector3<f32>) {
+    for index in 0..mesh.vertices.len() as u32 {
+        let p = position(mesh, index);
+        assert!(normal(mesh, index).dot(p - center(p)) > 0.0, "inward normal at {:?}", p);
+    }
+}
+
+fn origin(_: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(0.0, 0.0, 0.0)
+}
+
+#[test]
+fn cube() {
+    for subdivisions in [1, 3] {
+        let mesh = Mesh::cube(2.0, subdivisions);
+        assert_eq!(mesh.indicies.len(), 6 * 2 * subdivisions * subdivisions);
+        assert_closed(&mesh, 0);
+        assert_consistent(&mesh);
+        assert_outward(&mesh, origin);
+        for index in 0..mesh.vertices.len() as u32 {
+            let n = normal(&mesh, index);
+            assert!((position(&mesh, index).dot(n) - 1.0).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn uv_sphere() {
+    for (segments, rings) in [(3, 2), (16, 8), (32, 17)] {
+        let mesh = Mesh::uv_sphere(1.5, segments, rings);
+        assert_eq!(mesh.indicies.len(), 2 * segments * (rings - 1));
+        assert_closed(&mesh, 0);
+        assert_consistent(&mesh);
+        assert_outward(&mesh, origin);
+        for index in 0..mesh.vertices.len() as u32 {
+            assert!((position(&mesh, index).magnitude() - 1.5).abs() < 1e-5);
+        }
+    }
+}
+
+#[test]
+fn icosphere() {
+    for subdivisions in 0..4 {
+        let mesh = Mesh::icosphere(1.0, subdivisions);
+        assert_eq!(mesh.indicies.len(), 20 * 4usize.pow(subdivisions as u32));
+        assert_closed(&mesh, 0);
+        assert_consistent(&mesh);
+        assert_outward(&mesh, origin);
+        //Away from the poles, where u is meaningless, no triangle may span more than half the texture
+        for triangle in mesh.indicies.iter() {
+            if triangle.iter().any(|&i| normal(&mesh, i).y.abs() > 0.8) {
+                continue;
+            }
+            let us = triangle.map(|i| mesh.vertices[i as usize][6]);
+            let spread = us.iter().cloned().fold(f32::MIN, f32::max)
+                - us.iter().cloned().fold(f32::MAX, f32::min);
+            assert!(spread < 0.5, "triangle wraps around the uv seam");
+        }
+    }
+}
+
+#[test]
+fn plane() {
+    for subdivisions in [1, 4] {
+        let mesh = Mesh::plane(2.0, 3.0, subdivisions);
+        let (v, _, open) = topology(&mesh);
+        assert_eq!(v, (subdivisions + 1) * (subdivisions + 1));
+        assert_eq!(open, 4 * subdivisions);
+        assert_consistent(&mesh);
+        for index in 0..mesh.vertices.len() as u32 {
+            assert_eq!(normal(&mesh, index), Vector3::new(0.0, 1.0, 0.0));
+        }
+    }
+}
+
+#[test]
+fn cylinder() {
+    for (segments, height_segments) in [(3, 1), (24, 4)] {
+        let mesh = Mesh::cylinder(0.5, 2.0, segments, height_segments);
+        assert_closed(&mesh, 0);
+        assert_consistent(&mesh);
+        assert_outward(&mesh, origin);
+    }
+}
+
+#[test]
+fn cone() {
+    for (segments, height_segments) in [(3, 1), (24, 3)] {
+        let mesh = Mesh::cone(1.0, 2.0, segments, height_segments);
+        assert_closed(&mesh, 0);
+        assert_consistent(&mesh);
+        assert_outward(&mesh, origin);
+    }
+}
+
+#[test]
+fn torus() {
+    for (major, minor) in [(3, 3), (32, 12)] {
+        let mesh = Mesh::torus(1.0, 0.25, major, minor);
+        assert_eq!(mesh.indicies.len(), 2 * major * minor);
+        assert_closed(&mesh, 1);
+        assert_consistent(&mesh);
+        //Away from the center line of the tube
+        assert_outward(&mesh, |p| Vector3::new(p.x, 0.0, p.z).normalize());
+    }
+}
+
+#[test]
+fn capsule() {
+    for (segments, rings) in [(3, 1), (24, 6)] {
+        let mesh = Mesh::capsule(0.5, 1.0, segments, rings);
+        assert_closed(&mesh, 0);
+        assert_consistent(&mesh);
+        assert_outward(&mesh, |p| Vector3::new(0.0, p.y.clamp(-0.5, 0.5), 0.0));
+        for index in 0..mesh.vertices.len() as u32 {
+            let p = position(&mesh, index);
+            let axis = Vector3::new(0.0, p.y.clamp(-0.5, 0.5), 0.0);
+            assert!(((p - axis).magnitude() - 0.5).abs() < 1e-5);
+        }
+    }
+}
+
+#[test]
+fn fullscreen_quad() {
+    let mesh = Mesh::fullscreen_quad();
+    assert_eq!(mesh.indicies.len(), 2);
+    let (v, _, open) = topology(&mesh);
+    assert_eq!((v, open), (4, 4));
+    assert_consistent(&mesh);
+    for index in 0..4 {
+        let p = position(&mesh, index);
+        let uv = &mesh.vertices[index as usize][6..8];
+        assert_eq!([p.x.abs(), p.y.abs(), p.z], [1.0, 1.0, 0.0]);
+        assert_eq!([uv[0] * 2.0 - 1.0, uv[1] * 2.0 - 1.0], [p.x, p.y]);
+    }
+}
@@ -0,0 +1,126 @@
+use OpenGL_Renderer::*;
+
+use std::fs;
+
+mod common;
+use common::*;
+
+fn defines(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn driver() -> Vec<String> {
+    vec![
+        "Vendor".to_string(),
+        "Renderer".to_string(),
+        "4.6.0".to_string(),
+    ]
+}
+
+#[test]
+fn defines_go_after_version_line() {
+    let source = "#version 430 core\nvoid main() {}\n";
+    let result = preprocess_shader(source, &defines(&[("MAX_LIGHTS", "16"), ("SHADOWS", "1")]));
+    assert_eq!(
+        result,
+        "#version 430 core\n#define MAX_LIGHTS 16\n#define SHADOWS 1\nvoid main() {}\n"
+    );
+
+    //Comments before #version and a version line without a newline
+    let result = preprocess_shader("//Header\n#version 430", &defines(&[("A", "2")]));
+    assert_eq!(result, "//Header\n#version 430\n#define A 2\n");
+
+    assert_eq!(
+        preprocess_shader("void main() {}", &defines(&[("A", "2")])),
+        "#define A 2\nvoid main() {}"
+    );
+    assert_eq!(preprocess_shader(source, &[]), source);
+}
+
+#[test]
+fn key_is_stable_and_covers_inputs() {
+    let sources = vec![
+        (
+            ShaderType::Vertex,
+            "#version 430\nvoid main() {}".to_string(),
+        ),
+        (
+            ShaderType::Fragment,
+            "#version 430\nvoid main() {}".to_string(),
+        ),
+    ];
+    let key = ProgramCache::key_for_driver(&driver(), &sources, &[]);
+    //Persisted between runs, so the hash mustn't depend on the build
+    assert_eq!(key, 0xb6ed26649f5b2d59);
+    assert_eq!(
+        ProgramCache::new(std::path::Path::new("cache"))
+            .entry_path(key)
+            .file_name()
+            .unwrap()
+            .len(),
+        "0123456789abcdef.bin".len()
+    );
+
+    let mut other_driver = driver();
+    other_driver[2] = "4.6.1".to_string();
+    assert_ne!(
+        key,
+        ProgramCache::key_for_driver(&other_driver, &sources, &[])
+    );
+    assert_ne!(
+        key,
+        ProgramCache::key_for_driver(&driver(), &sources, &defines(&[("A", "1")]))
+    );
+    let mut swapped = sources.clone();
+    swapped.swap(0, 1);
+    assert_ne!(key, ProgramCache::key_for_driver(&driver(), &swapped, &[]));
+}
+
+#[test]
+fn key_changes_with_included_file() {
+    let dir = temp_dir("program_cache_include");
+    fs::write(dir.join("common.glsl"), "float scale() { return 1.0; }").unwrap();
+    fs::write(
+        dir.join("shader.frag"),
+        "#version 430\n#include \"common.glsl\"\nvoid main() {}",
+    )
+    .unwrap();
+    let key = || {
+        let source = shader_from_file(&dir.join("shader.frag"));
+        ProgramCache::key_for_driver(&driver(), &[(ShaderType::Fragment, source)], &[])
+    };
+
+    let before = key();
+    assert_eq!(before, key());
+    fs::write(dir.join("common.glsl"), "float scale() { return 2.0; }").unwrap();
+    assert_ne!(before, key());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn entries_round_trip_and_corrupt_ones_are_removed() {
+    let dir = temp_dir("program_cache_entries");
+    //Created on first write
+    let cache = ProgramCache::new(&dir.join("shaders"));
+    assert_eq!(cache.read_entry(1), None);
+
+    let binary: Vec<u8> = (0..=255).collect();
+    cache.write_entry(1, 0x8e21, &binary).unwrap();
+    assert_eq!(cache.read_entry(1), Some((0x8e21, binary)));
+    cache.write_entry(2, 7, &[]).unwrap();
+    assert_eq!(cache.read_entry(2), Some((7, Vec::new())));
+
+    let bytes = fs::read(cache.entry_path(1)).unwrap();
+    assert_eq!(&bytes[0..4], b"GLPB");
+    assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 0x8e21);
+
+    for corrupt in [&b"GLP"[..], &b"XXXX\x01\0\0\0binary"[..]] {
+        fs::write(cache.entry_path(3), corrupt).unwrap();
+        assert_eq!(cache.read_entry(3), None);
+        assert!(!cache.entry_path(3).exists());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
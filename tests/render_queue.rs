@@ -0,0 +1,104 @@
+use cgmath::Vector3;
+use OpenGL_Renderer::*;
+
+use std::{collections::HashMap, rc::Rc};
+
+mod common;
+use common::*;
+
+fn material(blend_mode: BlendMode) -> Rc<Material> {
+    Rc::new(Material {
+        blend_mode,
+        ..Default::default()
+    })
+}
+
+//Objects on a line going away from the camera, materials and meshes interleaved
+fn scene() -> (Scene, Camera, Vec<ObjectHandle>) {
+    let (mut scene, handles) = scene_of(
+        Mesh::cube(1.0, 1),
+        (0..16).map(|i| Vector3::new(0.0, 0.0, -2.0 - i as f32)),
+    );
+    let meshes = [
+        Rc::new(Mesh::cube(1.0, 1)),
+        Rc::new(Mesh::uv_sphere(0.5, 8, 4)),
+    ];
+    let materials = [
+        material(BlendMode::Opaque),
+        material(BlendMode::AlphaTest),
+        material(BlendMode::AlphaBlend),
+        material(BlendMode::Additive),
+    ];
+    for (i, &handle) in handles.iter().enumerate() {
+        let object = &mut scene.objects[handle];
+        object.mesh = meshes[i % 2].clone();
+        object.material = materials[i % 4].clone();
+    }
+    scene.update_model_matrices();
+    let camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+    (scene, camera, handles)
+}
+
+#[test]
+fn opaque_grouped_by_state_and_front_to_back() {
+    let (mut scene, camera, handles) = scene();
+    assert_eq!(scene.cull(&camera).len(), handles.len());
+    let program = ShaderProgram(1, HashMap::new(), HashMap::new());
+    let mut queue = RenderQueue::new();
+    scene.queue(&mut queue, &program, &camera);
+    assert_eq!(queue.len(), 16);
+    assert_eq!(queue.opaque.len(), 8);
+    assert!(queue
+        .opaque
+        .iter()
+        .all(|item| !item.material.blend_mode.is_transparent()));
+
+    queue.sort();
+    //Same material and mesh end up next to each other, nearest first within each group
+    for pair in queue.opaque.windows(2) {
+        if Rc::ptr_eq(&pair[0].material, &pair[1].material)
+            && Rc::ptr_eq(&pair[0].mesh, &pair[1].mesh)
+        {
+            assert!(pair[0].depth <= pair[1].depth);
+        }
+    }
+    let mut seen: Vec<*const Material> = Vec::new();
+    for item in queue.opaque.iter() {
+        let material = Rc::as_ptr(&item.material);
+        if seen.last() != Some(&material) {
+            assert!(!seen.contains(&material), "material drawn in two runs");
+            seen.push(material);
+        }
+    }
+    assert_eq!(queue.stats.draw_calls, 16);
+    assert_eq!(queue.stats.program_changes, 1);
+}
+
+#[test]
+fn transparent_back_to_front_after_opaque() {
+    let (mut scene, camera, _) = scene();
+    let program = ShaderProgram(1, HashMap::new(), HashMap::new());
+    let mut queue = RenderQueue::new();
+    scene.queue(&mut queue, &program, &camera);
+    queue.sort();
+
+    let items: Vec<&DrawItem> = queue.items().collect();
+    let first_transparent = items
+        .iter()
+        .position(|item| item.material.blend_mode.is_transparent())
+        .unwrap();
+    assert_eq!(first_transparent, 8);
+    assert!(items[first_transparent..]
+        .iter()
+        .all(|item| item.material.blend_mode.is_transparent()));
+    for pair in queue.transparent.windows(2) {
+        assert!(pair[0].depth >= pair[1].depth);
+    }
+    //Blend and additive alternate along the line, so every transparent draw switches blend state
+    assert_eq!(queue.stats.blend_changes, 2 + 8);
+    assert_eq!(queue.stats.material_changes, 2 + 8);
+
+    queue.clear();
+    assert!(queue.is_empty());
+    assert_eq!(queue.stats, QueueStats::default());
+}
@@ -0,0 +1,272 @@
+use cgmath::{Deg, InnerSpace, Quaternion, Rotation3, Vector3};
+use OpenGL_Renderer::*;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+mod common;
+use common::*;
+
+fn example_scene() -> (Scene, Camera) {
+    let mut scene = Scene::new();
+    let steel = Rc::new(Material {
+        albedo: Vector3::new(0.6, 0.6, 0.65),
+        roughness: 0.3,
+        metallic: 1.0,
+        ..Default::default()
+    });
+    let cube = Rc::new(mesh_from_obj(Path::new("assets/models/Cube.obj")));
+
+    let mut table = Object::with_shared(Rc::new(Mesh::cube(2.0, 1)), steel.clone());
+    table.name = "table".to_string();
+    table.position = Vector3::new(1.0, 0.5, -2.0);
+    table.rotation = Quaternion::from_angle_y(Deg(30.0));
+    let table = scene.add_object(table);
+    for i in 0..2 {
+        let mut object = Object::with_shared(cube.clone(), steel.clone());
+        object.name = format!("box{}", i);
+        object.position = Vector3::new(i as f32, 1.5, 0.0);
+        object.scale = Vector3::new(0.25, 0.25, 0.25);
+        object.color = cgmath::Vector4::new(1.0, 0.0, 0.0, 1.0);
+        let handle = scene.add_child(table, object).unwrap();
+        if i == 1 {
+            let mut lid = Object::new(Mesh::torus(1.0, 0.25, 12, 6));
+            lid.name = "lid".to_string();
+            lid.position = Vector3::new(0.0, 2.0, 0.0);
+            scene.add_child(handle, lid).unwrap();
+        }
+    }
+    let mut floor = Object::new(Mesh::plane(10.0, 10.0, 2));
+    floor.name = "floor".to_string();
+    scene.add_object(floor);
+
+    scene.lights = vec![
+        Light::Point {
+            position: Vector3::new(0.0, 3.0, 0.0),
+            color: Vector3::new(1.0, 0.9, 0.8),
+            intensity: 20.0,
+            range: 15.0,
+        },
+        Light::Directional {
+            direction: Vector3::new(0.0, -1.0, 0.2),
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 2.0,
+        },
+    ];
+    scene.environment_map = Some(PathBuf::from(
+        "assets/textures/DiamondPlate008C_1K_Color.png",
+    ));
+    scene.update_transforms();
+    let mut camera = Camera::new(Vector3::new(0.0, 2.0, 6.0), Vector3::new(0.0, 0.5, 0.0));
+    camera.fov = 60.0;
+    (scene, camera)
+}
+
+fn assert_same_scene(a: &Scene, b: &Scene) {
+    assert_eq!(a.objects.len(), b.objects.len());
+    for (_, object) in a.objects.iter() {
+        let other = &b.objects[b.find(&object.name).unwrap()];
+        assert!(
+            close(object.world_position(), other.world_position()),
+            "{}",
+            object.name
+        );
+        assert!(close(object.scale, other.scale));
+        assert!((object.rotation.dot(other.rotation).abs() - 1.0).abs() < 1e-5);
+        assert_eq!(*object.material, *other.material);
+        assert_eq!(object.color, other.color);
+        assert_eq!(object.mesh.indicies.len(), other.mesh.indicies.len());
+        assert_eq!(
+            object.parent().map(|parent| &a.objects[parent].name),
+            other.parent().map(|parent| &b.objects[parent].name)
+        );
+    }
+    assert_eq!(a.lights, b.lights);
+    assert_eq!(
+        fs::canonicalize(a.environment_map.as_ref().unwrap()).unwrap(),
+        fs::canonicalize(b.environment_map.as_ref().unwrap()).unwrap()
+    );
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    let dir = temp_dir("scene_file_round_trip");
+    let (scene, camera) = example_scene();
+    for file in ["level.ron", "level.json"] {
+        let path = dir.join(file);
+        scene.save(&path, Some(&camera)).unwrap();
+        let (loaded, loaded_camera) = Scene::load(&path).unwrap();
+        assert_same_scene(&scene, &loaded);
+        assert_eq!(loaded_camera, Some(camera.clone()));
+
+        //Shared meshes and materials stay shared
+        let box0 = &loaded.objects[loaded.find("box0").unwrap()];
+        let box1 = &loaded.objects[loaded.find("box1").unwrap()];
+        let table = &loaded.objects[loaded.find("table").unwrap()];
+        assert!(Rc::ptr_eq(&box0.mesh, &box1.mesh));
+        assert!(Rc::ptr_eq(&box0.material, &table.material));
+        assert_eq!(loaded.find_path("table/box1/lid"), loaded.find("lid"));
+
+        //Asset paths are written relative to the scene file
+        let description = SceneDescription::read(&path).unwrap();
+        let mut objects: Vec<&ObjectDescription> = description.objects.iter().collect();
+        let mut files = Vec::new();
+        while let Some(object) = objects.pop() {
+            if let MeshSource::File(file) = &object.mesh {
+                files.push(file.clone());
+            }
+            objects.extend(object.children.iter());
+        }
+        assert!(files.iter().any(|file| file.ends_with("Cube.obj")));
+        files.extend(description.environment_map.clone());
+        assert!(files.len() >= 2);
+        for file in files {
+            assert!(file.is_relative(), "{} isn't relative", file.display());
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn hand_written_scene_uses_defaults() {
+    let dir = temp_dir("scene_file_hand_written");
+    fs::create_dir_all(dir.join("models")).unwrap();
+    fs::copy("assets/models/Cube.obj", dir.join("models/crate.obj")).unwrap();
+    let text = r#"(
+        camera: (position: (x: 0.0, y: 1.0, z: 5.0), target: (x: 0.0, y: 0.0, z: 0.0)),
+        materials: {
+            "red": (albedo: (x: 1.0, y: 0.0, z: 0.0)),
+        },
+        objects: [
+            (name: "crate", mesh: File("models/crate.obj"), material: "red", rotation: (x: 0.0, y: 90.0, z: 0.0)),
+            (mesh: Icosphere(radius: 0.5, subdivisions: 2), position: (x: 2.0, y: 0.0, z: 0.0), children: [
+                (name: "moon", mesh: UvSphere(radius: 0.1, segments: 8, rings: 4), position: (x: 1.0, y: 0.0, z: 0.0)),
+            ]),
+        ],
+    )"#;
+    let path = dir.join("hand_written.ron");
+    fs::write(&path, text).unwrap();
+    let (scene, camera) = Scene::load(&path).unwrap();
+
+    let camera = camera.unwrap();
+    assert_eq!(camera.position, Vector3::new(0.0, 1.0, 5.0));
+    assert_eq!(camera.fov, Camera::default().fov);
+    assert!(scene.lights.is_empty() && scene.environment_map.is_none());
+
+    let crate_object = &scene.objects[scene.find("crate").unwrap()];
+    assert_eq!(crate_object.material.albedo, Vector3::new(1.0, 0.0, 0.0));
+    assert_eq!(
+        crate_object.material.roughness,
+        Material::default().roughness
+    );
+    assert_eq!(crate_object.scale, Vector3::new(1.0, 1.0, 1.0));
+    assert!(close(
+        crate_object.rotation * Vector3::unit_x(),
+        -Vector3::unit_z()
+    ));
+    assert!(!crate_object.mesh.indicies.is_empty());
+
+    let moon = &scene.objects[scene.find("moon").unwrap()];
+    assert!(close(moon.world_position(), Vector3::new(3.0, 0.0, 0.0)));
+    assert_eq!(*moon.material, Material::default());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn objects_showing_a_lod_save_the_full_detail_mesh() {
+    let mut scene = Scene::new();
+    let lods = Rc::new(LodChain::generate(
+        Mesh::uv_sphere(1.0, 32, 16),
+        3,
+        0.5,
+        1.0,
+    ));
+    let mut object = Object::with_lods(lods.clone(), Rc::new(Material::default()));
+    object.lod_level = 1;
+    object.mesh = lods.levels[1].mesh.clone();
+    assert!(object.mesh.source.is_none());
+    scene.add_object(object);
+
+    let description = scene.to_description(None, Path::new(".")).unwrap();
+    assert_eq!(description.objects.len(), 1);
+    assert_eq!(
+        description.objects[0].mesh,
+        MeshSource::UvSphere {
+            radius: 1.0,
+            segments: 32,
+            rings: 16
+        }
+    );
+}
+
+#[test]
+fn errors_are_reported() {
+    let dir = temp_dir("scene_file_errors");
+    let path = dir.join("broken.ron");
+    fs::write(
+        &path,
+        r#"(
+            environment_map: "sky.hdr",
+            objects: [
+                (name: "a", mesh: File("missing/a.obj"), children: [(name: "b", mesh: File("b.obj"))]),
+                (name: "c", mesh: Cube(size: 1.0, subdivisions: 1)),
+            ],
+        )"#,
+    )
+    .unwrap();
+    let error = Scene::load(&path).err().unwrap();
+    match &error {
+        SceneFileError::MissingAssets(missing) => {
+            assert_eq!(missing.len(), 3);
+            assert!(missing
+                .iter()
+                .any(|asset| asset.path == dir.join("missing/a.obj")));
+            assert!(missing
+                .iter()
+                .any(|asset| asset.referenced_by == "environment_map"));
+        }
+        error => panic!("unexpected error {}", error),
+    }
+    let message = error.to_string();
+    assert!(
+        message.contains("b.obj") && message.contains("\"b\""),
+        "{}",
+        message
+    );
+
+    fs::write(
+        &path,
+        r#"(objects: [(name: "c", mesh: Cube(size: 1.0, subdivisions: 1), material: "gold")])"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        Scene::load(&path),
+        Err(SceneFileError::UnknownMaterial { .. })
+    ));
+
+    fs::write(&path, "(objects: [(mesh: Cube(size: 1.0))])").unwrap();
+    match Scene::load(&path) {
+        Err(error @ SceneFileError::Parse { .. }) => {
+            assert!(error.to_string().contains("broken.ron"))
+        }
+        _ => panic!("expected a parse error"),
+    }
+    assert!(matches!(
+        Scene::load(&dir.join("nothing_here.ron")),
+        Err(SceneFileError::Io { .. })
+    ));
+
+    //Meshes built from raw vertices have nothing to point the file at
+    let mut scene = Scene::new();
+    let mut object = Object::new(Mesh::new(Mesh::cube(1.0, 1).vertices, Vec::new()));
+    object.name = "custom".to_string();
+    scene.add_object(object);
+    assert!(matches!(
+        scene.save(&path, None),
+        Err(SceneFileError::NoMeshSource { .. })
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
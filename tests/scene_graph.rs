@@ -0,0 +1,204 @@
+use cgmath::{Deg, InnerSpace, Matrix4, Quaternion, Rotation3, Vector3};
+use OpenGL_Renderer::*;
+
+use std::rc::Rc;
+
+mod common;
+use common::*;
+
+fn matrices_close(a: &Matrix4<f32>, b: &Matrix4<f32>) -> bool {
+    let a: &[f32; 16] = a.as_ref();
+    let b: &[f32; 16] = b.as_ref();
+    a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-4)
+}
+
+fn named(name: &str, mesh: &Rc<Mesh>) -> Object {
+    let mut object = Object::with_shared(mesh.clone(), Rc::new(Material::default()));
+    object.name = name.to_string();
+    object
+}
+
+//robot -> arm -> hand, plus an unrelated crate
+fn robot() -> (Scene, [ObjectHandle; 4]) {
+    let mesh = Rc::new(Mesh::cube(1.0, 1));
+    let mut scene = Scene::new();
+    let mut robot = named("robot", &mesh);
+    robot.position = Vector3::new(10.0, 0.0, 0.0);
+    robot.rotation = Quaternion::from_angle_y(Deg(90.0));
+    let robot = scene.add_object(robot);
+
+    let mut arm = named("arm", &mesh);
+    arm.position = Vector3::new(0.0, 2.0, 0.0);
+    arm.scale = Vector3::new(2.0, 2.0, 2.0);
+    let arm = scene.add_child(robot, arm).unwrap();
+
+    let mut hand = named("hand", &mesh);
+    hand.position = Vector3::new(1.0, 0.0, 0.0);
+    let hand = scene.add_child(arm, hand).unwrap();
+
+    let mut other = named("crate", &mesh);
+    other.position = Vector3::new(-5.0, 0.0, 0.0);
+    let other = scene.add_object(other);
+    scene.update_transforms();
+    (scene, [robot, arm, hand, other])
+}
+
+#[test]
+fn world_transforms_compose_down_the_hierarchy() {
+    let (scene, [robot, arm, hand, other]) = robot();
+    assert_eq!(scene.objects[hand].parent(), Some(arm));
+    assert_eq!(scene.objects[robot].children(), &[arm]);
+    assert_eq!(scene.roots().collect::<Vec<_>>(), vec![robot, other]);
+    assert_eq!(scene.descendants(robot), vec![arm, hand]);
+    assert!(scene.is_descendant(hand, robot));
+    assert!(!scene.is_descendant(robot, hand));
+
+    //The hand is 1 unit along the arm's x, scaled by 2, rotated 90 degrees about y onto -z
+    assert!(close(
+        scene.objects[arm].world_position(),
+        Vector3::new(10.0, 2.0, 0.0)
+    ));
+    assert!(close(
+        scene.objects[hand].world_position(),
+        Vector3::new(10.0, 2.0, -2.0)
+    ));
+    let expected = scene.objects[robot].model_matrix
+        * scene.objects[arm].local_matrix
+        * scene.objects[hand].local_matrix;
+    assert!(matrices_close(&scene.objects[hand].model_matrix, &expected));
+
+    //World bounds follow the world transform
+    assert!(scene.objects[hand]
+        .world_aabb
+        .contains(Vector3::new(10.0, 2.0, -2.0)));
+    assert!(!scene.objects[hand]
+        .world_aabb
+        .contains(Vector3::new(1.0, 0.0, 0.0)));
+}
+
+#[test]
+fn only_dirty_subtrees_are_updated() {
+    let (mut scene, [robot, arm, hand, other]) = robot();
+    assert_eq!(scene.update_transforms(), 0);
+
+    scene.objects[hand].set_position(Vector3::new(2.0, 0.0, 0.0));
+    assert!(scene.objects[hand].is_dirty());
+    assert_eq!(scene.update_transforms(), 1);
+    assert!(!scene.objects[hand].is_dirty());
+    assert!(close(
+        scene.objects[hand].world_position(),
+        Vector3::new(10.0, 2.0, -4.0)
+    ));
+
+    //Moving the root carries its whole subtree along, the unrelated crate stays untouched
+    scene.objects[robot].set_position(Vector3::new(0.0, 0.0, 0.0));
+    assert_eq!(scene.update_transforms(), 3);
+    assert!(close(
+        scene.objects[hand].world_position(),
+        Vector3::new(0.0, 2.0, -4.0)
+    ));
+    assert!(close(
+        scene.objects[other].world_position(),
+        Vector3::new(-5.0, 0.0, 0.0)
+    ));
+
+    scene.objects[arm].scale = Vector3::new(1.0, 1.0, 1.0);
+    scene.objects[arm].mark_dirty();
+    assert_eq!(scene.update_transforms(), 2);
+
+    //update_model_matrices recomputes everything, dirty or not
+    scene.update_model_matrices();
+    assert_eq!(scene.update_transforms(), 0);
+}
+
+#[test]
+fn previous_model_matrix_lags_one_update() {
+    let (mut scene, [robot, _, hand, other]) = robot();
+    let before = scene.objects[hand].model_matrix;
+    scene.objects[robot].set_position(Vector3::new(0.0, 0.0, 0.0));
+    scene.update_transforms();
+    assert!(matrices_close(
+        &scene.objects[hand].previous_model_matrix,
+        &before
+    ));
+    assert!(!matrices_close(
+        &scene.objects[hand].previous_model_matrix,
+        &scene.objects[hand].model_matrix
+    ));
+
+    //Untouched objects and ones that stopped moving have no motion
+    scene.update_transforms();
+    for handle in [hand, other] {
+        assert!(matrices_close(
+            &scene.objects[handle].previous_model_matrix,
+            &scene.objects[handle].model_matrix
+        ));
+    }
+}
+
+#[test]
+fn reparenting_keeps_world_transform() {
+    let (mut scene, [robot, arm, hand, other]) = robot();
+    scene.objects[other].rotation = Quaternion::from_angle_x(Deg(30.0));
+    scene.objects[other].scale = Vector3::new(0.5, 0.5, 0.5);
+    scene.objects[other].mark_dirty();
+    scene.update_transforms();
+
+    let before = scene.objects[hand].model_matrix;
+    assert!(scene.set_parent(hand, Some(other)));
+    scene.update_transforms();
+    assert_eq!(scene.objects[hand].parent(), Some(other));
+    assert!(scene.objects[arm].children().is_empty());
+    assert_eq!(scene.objects[other].children(), &[hand]);
+    assert!(matrices_close(&scene.objects[hand].model_matrix, &before));
+
+    //And back to the root
+    assert!(scene.set_parent(hand, None));
+    scene.update_transforms();
+    assert!(scene.objects[hand].parent().is_none());
+    assert!(matrices_close(&scene.objects[hand].model_matrix, &before));
+    assert!(matrices_close(&scene.objects[hand].local_matrix, &before));
+
+    //Cycles are refused
+    assert!(!scene.set_parent(robot, Some(arm)));
+    assert!(!scene.set_parent(arm, Some(arm)));
+    assert!(scene.objects[robot].parent().is_none());
+}
+
+#[test]
+fn lookup_by_name() {
+    let (scene, [robot, arm, hand, other]) = robot();
+    assert_eq!(scene.find("hand"), Some(hand));
+    assert_eq!(scene.find("crate"), Some(other));
+    assert_eq!(scene.find("leg"), None);
+    assert_eq!(scene.find_child(robot, "arm"), Some(arm));
+    assert_eq!(scene.find_child(robot, "hand"), None);
+    assert_eq!(scene.find_path("robot/arm/hand"), Some(hand));
+    assert_eq!(scene.find_path("robot/hand"), None);
+    assert_eq!(scene.find_path("arm"), None);
+}
+
+#[test]
+fn normal_matrix_follows_hierarchy() {
+    let mut scene = Scene::new();
+    let mut parent = Object::new(Mesh::cube(1.0, 1));
+    parent.scale = Vector3::new(4.0, 1.0, 1.0);
+    let parent = scene.add_object(parent);
+    let mut child = Object::new(Mesh::cube(1.0, 1));
+    child.rotation = Quaternion::from_angle_z(Deg(45.0));
+    child.scale = Vector3::new(1.0, 0.5, 2.0);
+    let child = scene.add_child(parent, child).unwrap();
+    scene.update_transforms();
+
+    //A surface spanned by two tangents keeps its normal perpendicular to both after the transform,
+    //transforming the normal by the model matrix itself doesn't
+    let object = &scene.objects[child];
+    let (t, b) = (Vector3::new(1.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+    let n = t.cross(b);
+    let model = &object.model_matrix;
+    let world = |v: Vector3<f32>| (model * v.extend(0.0)).truncate();
+    let normal = (object.normal_matrix * n).normalize();
+    assert!(normal.dot(world(t)).abs() < 1e-4);
+    assert!(normal.dot(world(b)).abs() < 1e-4);
+    assert!(world(n).normalize().dot(world(t)).abs() > 0.1);
+}
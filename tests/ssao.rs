@@ -0,0 +1,31 @@
+use cgmath::InnerSpace;
+use OpenGL_Renderer::*;
+
+#[test]
+fn kernel_stays_in_the_hemisphere() {
+    let kernel = Ssao::kernel(32);
+    assert_eq!(kernel.len(), 32);
+    for sample in kernel.iter() {
+        assert!(sample.z > 0.0, "{:?}", sample);
+        assert!(sample.magnitude() <= 1.0 + 1e-5, "{:?}", sample);
+        assert!(sample.magnitude() >= 0.1 - 1e-5, "{:?}", sample);
+    }
+    //Samples get further out along the kernel so most of them are close to the surface
+    let near = kernel[..16].iter().map(|s| s.magnitude()).sum::<f32>();
+    let far = kernel[16..].iter().map(|s| s.magnitude()).sum::<f32>();
+    assert!(near < far);
+    //The same on every run
+    assert_eq!(kernel, Ssao::kernel(32));
+}
+
+#[test]
+fn kernel_covers_every_direction() {
+    //Opposite quadrants of the hemisphere should both be sampled, the kernel isn't lopsided
+    let kernel = Ssao::kernel(16);
+    let mean = kernel
+        .iter()
+        .fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |a, &b| a + b)
+        / kernel.len() as f32;
+    assert!(mean.x.abs() < 0.1 && mean.y.abs() < 0.1, "{:?}", mean);
+    assert!(mean.z > 0.1);
+}
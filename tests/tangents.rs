@@ -0,0 +1,116 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+use OpenGL_Renderer::*;
+
+fn position(v: &Vertex) -> Vector3<f32> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+fn normal(v: &Vertex) -> Vector3<f32> {
+    Vector3::new(v[3], v[4], v[5]).normalize()
+}
+
+fn tangent(v: &Vertex) -> Vector3<f32> {
+    Vector3::new(v[8], v[9], v[10])
+}
+
+//Handedness of the face's uv mapping: whether the uv v direction is on the left of u seen from the normal
+fn face_sign(mesh: &Mesh, triangle: &VertIndicies) -> Option<f32> {
+    let [a, b, c] = triangle.map(|i| &mesh.vertices[i as usize]);
+    let uv = |v: &Vertex| Vector2::new(v[6], v[7]);
+    let (e1, e2) = (position(b) - position(a), position(c) - position(a));
+    let (d1, d2) = (uv(b) - uv(a), uv(c) - uv(a));
+    let det = d1.x * d2.y - d1.y * d2.x;
+    if det.abs() < 1e-8 || e1.cross(e2).magnitude() < 1e-8 {
+        return None;
+    }
+    let t = (e1 * d2.y - e2 * d1.y) / det;
+    let b = (e2 * d1.x - e1 * d2.x) / det;
+    Some(if normal(a).cross(t).dot(b) < 0.0 {
+        -1.0
+    } else {
+        1.0
+    })
+}
+
+fn assert_tangent_frame(name: &str, mesh: &Mesh) {
+    for (i, v) in mesh.vertices.iter().enumerate() {
+        let (n, t) = (normal(v), tangent(v));
+        assert!((t.magnitude() - 1.0).abs() < 1e-4, "{} vertex {}", name, i);
+        assert!(
+            n.dot(t).abs() < 1e-4,
+            "{} vertex {} tangent isn't orthogonal",
+            name,
+            i
+        );
+        assert!(
+            v[11] == 1.0 || v[11] == -1.0,
+            "{} vertex {} w {}",
+            name,
+            i,
+            v[11]
+        );
+        let bitangent = Vector3::new(v[12], v[13], v[14]);
+        assert!((bitangent - n.cross(t) * v[11]).magnitude() < 1e-4);
+    }
+    for triangle in mesh.indicies.iter() {
+        let signs = triangle.map(|i| mesh.vertices[i as usize][11]);
+        assert!(
+            signs.iter().all(|&w| w == signs[0]),
+            "{} {:?}",
+            name,
+            triangle
+        );
+        if let Some(sign) = face_sign(mesh, triangle) {
+            assert_eq!(signs[0], sign, "{} {:?}", name, triangle);
+        }
+    }
+}
+
+#[test]
+fn primitives_have_orthogonal_consistent_tangents() {
+    assert_tangent_frame("plane", &Mesh::plane(2.0, 3.0, 4));
+    assert_tangent_frame("cube", &Mesh::cube(1.0, 2));
+    assert_tangent_frame("uv_sphere", &Mesh::uv_sphere(1.0, 16, 8));
+}
+
+#[test]
+fn mirrored_uvs_split_shared_vertices() {
+    //Two quads sharing the middle edge, the right one has its uvs mirrored in u
+    let vertex = |x: f32, y: f32, u: f32, v: f32| {
+        let mut vertex = [0.0; VERTEX_SIZE];
+        vertex[0..8].copy_from_slice(&[x, y, 0.0, 0.0, 0.0, 1.0, u, v]);
+        vertex
+    };
+    let vertices = vec![
+        vertex(-1.0, 0.0, 0.0, 0.0),
+        vertex(0.0, 0.0, 1.0, 0.0),
+        vertex(0.0, 1.0, 1.0, 1.0),
+        vertex(-1.0, 1.0, 0.0, 1.0),
+        vertex(1.0, 0.0, 0.0, 0.0),
+        vertex(1.0, 1.0, 0.0, 1.0),
+    ];
+    let indicies = vec![[0, 1, 2], [2, 3, 0], [1, 4, 5], [5, 2, 1]];
+    let mut mesh = Mesh::new(vertices, indicies);
+    mesh.extra_attributes.insert(
+        vertex_format::VertexSemantic::Color,
+        (0..6).map(|i| [i as f32, 0.0, 0.0, 1.0]).collect(),
+    );
+    mesh.calculate_tangents();
+
+    //The two shared vertices on the seam get a copy each
+    assert_eq!(mesh.vertices.len(), 8);
+    assert_eq!(
+        mesh.extra_attributes[&vertex_format::VertexSemantic::Color].len(),
+        8
+    );
+    assert_tangent_frame("mirrored", &mesh);
+    assert_eq!(mesh.vertices[0][11], 1.0);
+    assert_eq!(mesh.vertices[4][11], -1.0);
+    //Both halves still point the tangent along +u
+    assert!(tangent(&mesh.vertices[0]).x > 0.99);
+    assert!(tangent(&mesh.vertices[4]).x < -0.99);
+
+    //Nothing left to split the second time
+    mesh.calculate_tangents();
+    assert_eq!(mesh.vertices.len(), 8);
+}
@@ -0,0 +1,388 @@
+use OpenGL_Renderer::vertex_format::*;
+use OpenGL_Renderer::*;
+
+use std::collections::HashMap;
+
+//A vertex struct with its own layout: packed color and no normal or tangent
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct ColoredVertex {
+    pos: [f32; 3],
+    color: [u8; 4],
+    uv: [f32; 2],
+}
+
+unsafe impl bytemuck::Zeroable for ColoredVertex {}
+unsafe impl bytemuck::Pod for ColoredVertex {}
+
+impl VertexLayout for ColoredVertex {
+    fn vertex_format() -> VertexFormat {
+        VertexFormat::interleaved(vec![
+            VertexAttribute::new(
+                "pos",
+                VertexSemantic::Position,
+                POSITION_LOCATION,
+                AttributeType::F32,
+                3,
+            ),
+            VertexAttribute::new(
+                "color",
+                VertexSemantic::Color,
+                COLOR_LOCATION,
+                AttributeType::U8,
+                4,
+            )
+            .normalized(),
+            VertexAttribute::new(
+                "uv",
+                VertexSemantic::TexCoord0,
+                TEXCOORD0_LOCATION,
+                AttributeType::F32,
+                2,
+            ),
+        ])
+    }
+}
+
+fn vertex(i: usize) -> Vertex {
+    let f = i as f32;
+    let mut vertex = [0.0; VERTEX_SIZE];
+    vertex[0..3].copy_from_slice(&[f, f + 0.5, -f]);
+    vertex[3..6].copy_from_slice(&[0.0, 1.0, 0.0]);
+    vertex[6..8].copy_from_slice(&[f * 0.25, 1.0 - f * 0.25]);
+    vertex[8..12].copy_from_slice(&[1.0, 0.0, 0.0, -1.0]);
+    vertex
+}
+
+fn colors(count: usize) -> HashMap<VertexSemantic, Vec<[f32; 4]>> {
+    let colors = (0..count)
+        .map(|i| [i as f32 / 4.0, 0.5, 1.0, 1.0])
+        .collect();
+    HashMap::from([(VertexSemantic::Color, colors)])
+}
+
+fn full_format() -> Vec<VertexAttribute> {
+    vec![
+        VertexAttribute::new(
+            "pos",
+            VertexSemantic::Position,
+            POSITION_LOCATION,
+            AttributeType::F32,
+            3,
+        ),
+        VertexAttribute::new(
+            "normal",
+            VertexSemantic::Normal,
+            NORMAL_LOCATION,
+            AttributeType::F32,
+            3,
+        ),
+        VertexAttribute::new(
+            "uv",
+            VertexSemantic::TexCoord0,
+            TEXCOORD0_LOCATION,
+            AttributeType::F32,
+            2,
+        ),
+        VertexAttribute::new(
+            "tangent",
+            VertexSemantic::Tangent,
+            TANGENT_LOCATION,
+            AttributeType::F32,
+            4,
+        ),
+        VertexAttribute::new(
+            "color",
+            VertexSemantic::Color,
+            COLOR_LOCATION,
+            AttributeType::F32,
+            4,
+        ),
+    ]
+}
+
+#[test]
+fn layouts_place_attributes() {
+    let interleaved = VertexFormat::interleaved(full_format());
+    assert_eq!(interleaved.strides, vec![(3 + 3 + 2 + 4 + 4) * 4]);
+    let offsets: Vec<usize> = interleaved.attributes.iter().map(|a| a.offset).collect();
+    assert_eq!(offsets, vec![0, 12, 24, 32, 48]);
+    assert!(interleaved.attributes.iter().all(|a| a.stream == 0));
+
+    let separate = VertexFormat::separate(full_format());
+    assert_eq!(separate.strides, vec![12, 12, 8, 16, 16]);
+    assert_eq!(separate.vertex_size(), interleaved.vertex_size());
+    for (stream, attribute) in separate.attributes.iter().enumerate() {
+        assert_eq!((attribute.stream, attribute.offset), (stream, 0));
+    }
+
+    //Attributes start on 4 byte boundaries
+    let unaligned = VertexFormat::interleaved(vec![
+        VertexAttribute::new("a", VertexSemantic::Custom(0), 15, AttributeType::U8, 3),
+        VertexAttribute::new("b", VertexSemantic::Custom(1), 16, AttributeType::F16, 3),
+        VertexAttribute::new("c", VertexSemantic::Custom(2), 17, AttributeType::F32, 1),
+    ]);
+    let offsets: Vec<usize> = unaligned.attributes.iter().map(|a| a.offset).collect();
+    assert_eq!(offsets, vec![0, 4, 12]);
+    assert_eq!(unaligned.strides, vec![16]);
+
+    let with_offsets = VertexFormat::with_offsets(
+        32,
+        vec![
+            (
+                16,
+                VertexAttribute::new("pos", VertexSemantic::Position, 0, AttributeType::F32, 3),
+            ),
+            (
+                0,
+                VertexAttribute::new("uv", VertexSemantic::TexCoord0, 2, AttributeType::F32, 2),
+            ),
+        ],
+    );
+    assert_eq!(with_offsets.strides, vec![32]);
+    assert_eq!(
+        with_offsets
+            .attribute(VertexSemantic::Position)
+            .unwrap()
+            .offset,
+        16
+    );
+    assert!(!with_offsets.has(VertexSemantic::Normal));
+}
+
+#[test]
+fn encode_decode_round_trip() {
+    let vertices: Vec<Vertex> = (0..4).map(vertex).collect();
+    let extra = colors(vertices.len());
+    for format in [
+        VertexFormat::interleaved(full_format()),
+        VertexFormat::separate(full_format()),
+    ] {
+        let streams = format.encode(&vertices, &extra);
+        assert_eq!(streams.len(), format.stream_count());
+        for (stream, stride) in streams.iter().zip(format.strides.iter()) {
+            assert_eq!(stream.len(), stride * vertices.len());
+        }
+
+        let streams: Vec<&[u8]> = streams.iter().map(|stream| stream.as_slice()).collect();
+        let (decoded, decoded_extra) = format.decode(&streams);
+        //The bitangent isn't stored, everything else comes back exactly
+        for (a, b) in vertices.iter().zip(decoded.iter()) {
+            assert_eq!(a[..12], b[..12]);
+        }
+        assert_eq!(decoded_extra, extra);
+    }
+
+    //Attributes the mesh has no data for get their default value
+    let format = VertexFormat::interleaved(full_format());
+    let streams = format.encode(&vertices, &HashMap::new());
+    let (_, decoded_extra) = format.decode(&[&streams[0]]);
+    assert!(decoded_extra[&VertexSemantic::Color]
+        .iter()
+        .all(|&color| color == [1.0; 4]));
+}
+
+#[test]
+fn from_vertices_keeps_custom_layout() {
+    let vertices = [
+        ColoredVertex {
+            pos: [0.0, 0.0, 0.0],
+            color: [255, 0, 0, 255],
+            uv: [0.0, 0.0],
+        },
+        ColoredVertex {
+            pos: [1.0, 0.0, 0.0],
+            color: [0, 255, 0, 128],
+            uv: [1.0, 0.0],
+        },
+        ColoredVertex {
+            pos: [0.0, 1.0, 0.0],
+            color: [0, 0, 255, 0],
+            uv: [0.0, 1.0],
+        },
+    ];
+    let mesh = Mesh::from_vertices(&vertices, vec![[0, 1, 2]]);
+    assert_eq!(mesh.format, ColoredVertex::vertex_format());
+    assert_eq!(
+        mesh.format.strides,
+        vec![std::mem::size_of::<ColoredVertex>()]
+    );
+    assert_eq!(mesh.vertices.len(), 3);
+    for (vertex, original) in mesh.vertices.iter().zip(vertices.iter()) {
+        assert_eq!(vertex[0..3], original.pos);
+        assert_eq!(vertex[6..8], original.uv);
+    }
+    let colors = &mesh.extra_attributes[&VertexSemantic::Color];
+    assert_eq!(colors[1], [0.0, 1.0, 0.0, 128.0 / 255.0]);
+
+    //Encoding with the mesh's own format gives back the original bytes
+    let streams = mesh.format.encode(&mesh.vertices, &mesh.extra_attributes);
+    assert_eq!(streams[0], bytemuck::cast_slice::<_, u8>(&vertices));
+}
+
+//Unit vectors spread evenly over the sphere, including the poles and the octahedron's edges
+fn directions() -> Vec<[f32; 3]> {
+    let count = 500;
+    let mut directions: Vec<[f32; 3]> = (0..count)
+        .map(|i| {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let r = (1.0 - y * y).sqrt();
+            let phi = i as f32 * 2.399_963;
+            [r * phi.cos(), y, r * phi.sin()]
+        })
+        .collect();
+    directions.extend([
+        [1.0, 0.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+        [0.6, 0.0, -0.8],
+    ]);
+    directions
+}
+
+fn angle(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    dot.clamp(-1.0, 1.0).acos()
+}
+
+#[test]
+fn octahedral_round_trip() {
+    for n in directions() {
+        let encoded = octahedral_encode(n);
+        assert!(encoded.iter().all(|v| (-1.0..=1.0).contains(v)));
+        assert!(angle(n, octahedral_decode(encoded)) < 1e-3);
+    }
+    assert_eq!(octahedral_encode([0.0, 0.0, 0.0]), [0.0, 0.0]);
+}
+
+//Largest error of each attribute after a round trip through the precision's format
+fn round_trip_error(precision: VertexPrecision, attribute_type: AttributeType) -> [f32; 4] {
+    let vertices: Vec<Vertex> = directions()
+        .iter()
+        .enumerate()
+        .map(|(i, n)| {
+            let mut vertex = [0.0; VERTEX_SIZE];
+            let f = i as f32 / 10.0;
+            vertex[0..3].copy_from_slice(&[f, -f * 0.5, 100.0 - f]);
+            vertex[3..6].copy_from_slice(n);
+            vertex[6..8].copy_from_slice(&[(i % 17) as f32 / 16.0, (i % 5) as f32 / 4.0]);
+            //Any vector perpendicular to the normal
+            let t = if n[0].abs() < 0.9 {
+                [0.0, n[2], -n[1]]
+            } else {
+                [-n[2], 0.0, n[0]]
+            };
+            let length = (t[0] * t[0] + t[1] * t[1] + t[2] * t[2]).sqrt();
+            let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+            vertex[8..12].copy_from_slice(&[t[0] / length, t[1] / length, t[2] / length, sign]);
+            vertex
+        })
+        .collect();
+    let mut mesh = Mesh::new(vertices.clone(), Vec::new());
+    mesh.quantize(&precision);
+    assert_eq!(
+        mesh.format
+            .attribute(VertexSemantic::Normal)
+            .unwrap()
+            .attribute_type,
+        attribute_type
+    );
+
+    let streams = mesh.format.encode(&mesh.vertices, &mesh.extra_attributes);
+    let (decoded, _) = mesh.format.decode(&[&streams[0]]);
+    let mut error = [0.0f32; 4];
+    for (a, b) in vertices.iter().zip(decoded.iter()) {
+        let direction = |v: &Vertex, range: std::ops::Range<usize>| -> [f32; 3] {
+            let length = v[range.clone()].iter().map(|x| x * x).sum::<f32>().sqrt();
+            [
+                v[range.start] / length,
+                v[range.start + 1] / length,
+                v[range.start + 2] / length,
+            ]
+        };
+        let position = (0..3)
+            .map(|i| (a[i] - b[i]).abs() / a[i].abs().max(1.0))
+            .fold(0.0, f32::max);
+        let uv = (6..8).map(|i| (a[i] - b[i]).abs()).fold(0.0, f32::max);
+        error[0] = error[0].max(position);
+        error[1] = error[1].max(angle(direction(a, 3..6), direction(b, 3..6)));
+        error[2] = error[2].max(uv);
+        error[3] = error[3].max(angle(direction(a, 8..11), direction(b, 8..11)));
+        assert_eq!(a[11], b[11], "Tangent handedness is kept exactly");
+    }
+    error
+}
+
+#[test]
+fn quantization_stays_within_bounds() {
+    //Directions come back exactly, acos of a dot product of 1 is only accurate to about 5e-4
+    let [position, normal, uv, tangent] =
+        round_trip_error(VertexPrecision::full(), AttributeType::F32);
+    assert_eq!((position, uv), (0.0, 0.0));
+    assert!(normal < 1e-3 && tangent < 1e-3);
+
+    //Half floats keep 11 significant bits
+    let [position, normal, uv, tangent] =
+        round_trip_error(VertexPrecision::compact(), AttributeType::Int2101010Rev);
+    assert!(position <= 1.0 / 2048.0, "position error {}", position);
+    assert!(uv <= 1.0 / 2048.0, "uv error {}", uv);
+    //10 bit snorm, about a tenth of a degree
+    assert!(normal < 0.004, "normal error {}", normal);
+    assert!(tangent < 0.004, "tangent error {}", tangent);
+
+    for (directions, attribute_type, bound) in [
+        (
+            DirectionPrecision::Octahedral16,
+            AttributeType::Octahedral16,
+            1e-3,
+        ),
+        (
+            DirectionPrecision::Octahedral8,
+            AttributeType::Octahedral8,
+            0.03,
+        ),
+    ] {
+        let precision = VertexPrecision {
+            directions,
+            tex_coords: TexCoordPrecision::Unorm16,
+            ..VertexPrecision::full()
+        };
+        let [position, normal, uv, _] = round_trip_error(precision, attribute_type);
+        assert_eq!(position, 0.0);
+        assert!(normal < bound, "{:?} error {}", directions, normal);
+        assert!(uv <= 0.5 / 65535.0 + 1e-7, "uv error {}", uv);
+    }
+}
+
+#[test]
+fn compact_formats_are_smaller() {
+    let mesh = Mesh::new(vec![[0.0; VERTEX_SIZE]; 4], vec![[0, 1, 2], [2, 3, 0]]);
+    let full = VertexPrecision::full().format_for(&mesh).vertex_size();
+    let default = VertexPrecision::default().format_for(&mesh).vertex_size();
+    let compact = VertexPrecision::compact().format_for(&mesh).vertex_size();
+    assert_eq!(full, (3 + 3 + 2 + 4) * 4);
+    assert_eq!(default, 12 + 4 + 8 + 4);
+    assert_eq!(compact, 8 + 4 + 4 + 4);
+    assert_eq!(mesh.gpu_size(), 4 * mesh.format.vertex_size() + 6 * 2);
+}
+
+#[test]
+fn index_format_switches_at_u16_range() {
+    assert_eq!(IndexFormat::for_vertex_count(0), IndexFormat::U16);
+    assert_eq!(IndexFormat::for_vertex_count(65536), IndexFormat::U16);
+    assert_eq!(IndexFormat::for_vertex_count(65537), IndexFormat::U32);
+
+    let indices = [[0, 1, 65535], [65535, 2, 3]];
+    let u16_bytes = IndexFormat::U16.encode(&indices);
+    assert_eq!(u16_bytes.len(), 6 * IndexFormat::U16.size());
+    assert_eq!(&u16_bytes[0..6], &[0, 0, 1, 0, 0xff, 0xff]);
+    let u32_bytes = IndexFormat::U32.encode(&[[0, 1, 65536]]);
+    assert_eq!(u32_bytes, vec![0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0]);
+}
+
+#[test]
+#[should_panic(expected = "Index doesn't fit in a u16")]
+fn u16_indices_reject_large_values() {
+    IndexFormat::U16.encode(&[[0, 1, 65536]]);
+}